@@ -0,0 +1,132 @@
+//! The `#[prefab_component]` attribute, re-exported by `legion-prefab`. Use that crate rather than
+//! depending on this one directly.
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, spanned::Spanned, AttributeArgs, DeriveInput, Lit, Meta, NestedMeta};
+
+/// Makes a type usable as a prefab component:
+///
+/// ```ignore
+/// #[prefab_component(uuid = "f5780013-bae4-49f0-ac0e-a108ff52fec0")]
+/// struct Position2D {
+///     position: Vec<f32>,
+/// }
+/// ```
+///
+/// expands to
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize, SerdeDiff, TypeUuid, Clone, Default)]
+/// #[uuid = "f5780013-bae4-49f0-ac0e-a108ff52fec0"]
+/// struct Position2D {
+///     position: Vec<f32>,
+/// }
+///
+/// legion_prefab::register_component_type!(Position2D);
+/// ```
+///
+/// The crate still needs `serde`, `serde-diff` and `type-uuid` as dependencies for the derives.
+/// Other derives and attributes on the type are kept, so don't derive `Clone` or `Default` again.
+///
+/// The UUID can also be given with a `#[uuid = "..."]` attribute on the type. If it's left out,
+/// one is generated from the crate and type names. Prefab files store that UUID, so renaming the
+/// type or the crate breaks existing prefabs. Give an explicit UUID for anything that's saved.
+#[proc_macro_attribute]
+pub fn prefab_component(
+    args: TokenStream,
+    input: TokenStream,
+) -> TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(args, input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand(
+    args: AttributeArgs,
+    input: DeriveInput,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let mut uuid = None;
+    for arg in args {
+        match arg {
+            NestedMeta::Meta(Meta::NameValue(ref name_value))
+                if name_value.path.is_ident("uuid") =>
+            {
+                match &name_value.lit {
+                    Lit::Str(value) => uuid = Some(value.value()),
+                    lit => return Err(syn::Error::new(lit.span(), "expected a UUID string")),
+                }
+            }
+            arg => return Err(syn::Error::new(arg.span(), "expected `uuid = \"...\"`")),
+        }
+    }
+
+    let ident = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            "prefab components can't be generic, each component type needs its own UUID",
+        ));
+    }
+
+    let has_uuid_attr = input.attrs.iter().any(|attr| attr.path.is_ident("uuid"));
+    let uuid_attr = match (uuid, has_uuid_attr) {
+        (Some(_), true) => {
+            return Err(syn::Error::new(
+                ident.span(),
+                "the UUID is given both as an argument and with a #[uuid] attribute",
+            ))
+        }
+        (Some(uuid), false) => quote!(#[uuid = #uuid]),
+        (None, true) => quote!(),
+        (None, false) => {
+            let uuid = generated_uuid(&ident.to_string());
+            quote!(#[uuid = #uuid])
+        }
+    };
+
+    Ok(quote! {
+        #[derive(
+            ::serde::Serialize,
+            ::serde::Deserialize,
+            ::serde_diff::SerdeDiff,
+            ::type_uuid::TypeUuid,
+            Clone,
+            Default,
+        )]
+        #uuid_attr
+        #input
+
+        ::legion_prefab::register_component_type!(#ident);
+    })
+}
+
+// A UUID made from hashing the crate and type names, so it's the same on every build
+fn generated_uuid(type_name: &str) -> String {
+    let crate_name = std::env::var("CARGO_PKG_NAME").unwrap_or_default();
+    let name = format!("{}::{}", crate_name, type_name);
+
+    // Two 64 bit FNV-1a hashes with different offsets
+    let fnv1a = |offset: u64| {
+        name.bytes().fold(offset, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+    };
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&fnv1a(0xcbf2_9ce4_8422_2325).to_be_bytes());
+    bytes[8..].copy_from_slice(&fnv1a(0x6c62_272e_07bb_0142).to_be_bytes());
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
@@ -0,0 +1,101 @@
+//! Compares how long cooked prefabs take to load in each `CookedFormat`. Run with
+//! `cargo run --release --example cooked_load`.
+use legion::*;
+use legion_prefab::{CookedFormat, CookedPrefab, PodComponent, PrefabResources, UuidEntityBimap};
+use serde::{Deserialize, Serialize};
+use serde_diff::SerdeDiff;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use type_uuid::TypeUuid;
+
+#[derive(TypeUuid, Serialize, Deserialize, SerdeDiff, Clone, Copy, Default)]
+#[uuid = "4f9b3b43-3ed2-4b8b-9b6a-2a7c3e5b1f10"]
+#[repr(C)]
+struct Position {
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
+unsafe impl PodComponent for Position {}
+
+legion_prefab::register_component_type!(Position, pod);
+
+#[derive(TypeUuid, Serialize, Deserialize, SerdeDiff, Clone, Copy, Default)]
+#[uuid = "0d2e8c5a-7c4e-4d55-8f0b-6b1e9a3c2d41"]
+struct Velocity {
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
+legion_prefab::register_component_type!(Velocity);
+
+#[derive(TypeUuid, Serialize, Deserialize, SerdeDiff, Clone, Default)]
+#[uuid = "a7c61d3e-2b9f-4e1a-9d48-5f3b7e0c8a92"]
+struct Name {
+    name: String,
+}
+
+legion_prefab::register_component_type!(Name);
+
+const ENTITY_COUNT: usize = 100_000;
+const ITERATIONS: u32 = 10;
+
+fn cooked_prefab() -> CookedPrefab {
+    let mut world = World::default();
+    world.extend((0..ENTITY_COUNT).map(|i| {
+        let f = i as f32;
+        (
+            Position { x: f, y: f, z: f },
+            Velocity {
+                x: 1.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        )
+    }));
+    world.extend((0..ENTITY_COUNT / 10).map(|i| {
+        (
+            Position::default(),
+            Name {
+                name: format!("entity {}", i),
+            },
+        )
+    }));
+
+    CookedPrefab {
+        world,
+        entities: UuidEntityBimap::new(),
+        parameters: vec![],
+        resources: PrefabResources::new(),
+        blobs: HashMap::new(),
+    }
+}
+
+fn main() {
+    let cooked_prefab = cooked_prefab();
+
+    for &format in &[
+        CookedFormat::Bincode,
+        CookedFormat::Columnar,
+        CookedFormat::Fast,
+    ] {
+        let bytes = legion_prefab::write_cooked_prefab(&cooked_prefab, format).unwrap();
+
+        let mut total = Duration::default();
+        for _ in 0..ITERATIONS {
+            let start = Instant::now();
+            let loaded = legion_prefab::read_cooked_prefab(&bytes, format).unwrap();
+            total += start.elapsed();
+            assert_eq!(loaded.world.len(), cooked_prefab.world.len());
+        }
+
+        println!(
+            "{:?}: {} bytes, {:?} per load",
+            format,
+            bytes.len(),
+            total / ITERATIONS
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use crate::format::source::{AsyncPrefabSource, PrefabSource};
+use crate::format::{ComponentTypeUuid, PrefabUuid};
+use crate::{
+    cook_prefab, prefab_cook_order, ComponentRegistration, CookedPrefab, ParameterError, Prefab,
+    PrefabCookOrderError, PrefabFormatDeserializer, PrefabSerdeContext,
+};
+use legion::storage::ComponentTypeId;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+#[derive(Debug)]
+pub enum LoadPrefabError<E> {
+    /// Fetching the source of a prefab failed
+    Fetch(PrefabUuid, E),
+    /// The source of a prefab could not be deserialized
+    Deserialize(PrefabUuid, ron::de::Error),
+    /// The source fetched for a prefab contains a prefab with a different ID
+    IdMismatch {
+        requested: PrefabUuid,
+        found: PrefabUuid,
+    },
+    /// The prefab references itself, directly or through other prefabs
+    CyclicReference(PrefabUuid),
+}
+
+/// A prefab along with every prefab it references, directly or indirectly
+pub struct LoadedPrefabs {
+    pub prefabs: HashMap<PrefabUuid, Prefab>,
+    /// The order prefabs must be cooked in, referenced prefabs before the prefabs that reference
+    /// them. The root prefab is last.
+    pub cook_order: Vec<PrefabUuid>,
+}
+
+impl LoadedPrefabs {
+    /// The root prefab that was requested
+    pub fn root(&self) -> &Prefab {
+        &self.prefabs[self.cook_order.last().unwrap()]
+    }
+
+    /// A lookup suitable for passing to `cook_prefab` along with `cook_order`
+    pub fn prefab_lookup(&self) -> HashMap<PrefabUuid, &Prefab> {
+        self.prefabs.iter().map(|(k, v)| (*k, v)).collect()
+    }
+
+    /// Cooks the root prefab with everything it references
+    pub fn cook<S: BuildHasher, T: BuildHasher>(
+        &self,
+        registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+        registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, T>,
+    ) -> Result<CookedPrefab, ParameterError> {
+        cook_prefab(
+            registered_components,
+            registered_components_by_uuid,
+            &self.cook_order,
+            &self.prefab_lookup(),
+        )
+    }
+}
+
+/// Loads a RON prefab and every prefab it references, fetching their source from `source`. Each
+/// prefab is fetched once. Prefab variants are loaded too, see `prefab_format::deserialize_variant`.
+pub fn load_prefab<T, S>(
+    root: PrefabUuid,
+    context: PrefabSerdeContext<'_, T>,
+    mut source: S,
+) -> Result<LoadedPrefabs, LoadPrefabError<S::Error>>
+where
+    T: BuildHasher,
+    S: PrefabSource,
+{
+    let mut prefabs = HashMap::new();
+    let mut pending = vec![root];
+    while let Some(prefab_id) = pending.pop() {
+        if prefabs.contains_key(&prefab_id) {
+            continue;
+        }
+
+        let prefab_source = source
+            .fetch_prefab(prefab_id)
+            .map_err(|e| LoadPrefabError::Fetch(prefab_id, e))?;
+        let prefab = deserialize_fetched_prefab(prefab_id, &prefab_source, context)?;
+        pending.extend(prefab.prefab_meta.prefab_refs.keys().cloned());
+        prefabs.insert(prefab_id, prefab);
+    }
+
+    order_loaded_prefabs(root, prefabs)
+}
+
+/// Like `load_prefab`, but fetching may await, i.e. on a request to an asset server, so this can
+/// run inside an async asset pipeline without blocking a worker thread. Closures returning a
+/// future can be passed as the source. Prefabs are fetched one at a time in the order they are
+/// discovered.
+pub async fn load_prefab_async<T, S>(
+    root: PrefabUuid,
+    context: PrefabSerdeContext<'_, T>,
+    mut source: S,
+) -> Result<LoadedPrefabs, LoadPrefabError<S::Error>>
+where
+    T: BuildHasher,
+    S: AsyncPrefabSource,
+{
+    let mut prefabs = HashMap::new();
+    let mut pending = vec![root];
+    while let Some(prefab_id) = pending.pop() {
+        if prefabs.contains_key(&prefab_id) {
+            continue;
+        }
+
+        let prefab_source = source
+            .fetch_prefab(prefab_id)
+            .await
+            .map_err(|e| LoadPrefabError::Fetch(prefab_id, e))?;
+        let prefab = deserialize_fetched_prefab(prefab_id, &prefab_source, context)?;
+        pending.extend(prefab.prefab_meta.prefab_refs.keys().cloned());
+        prefabs.insert(prefab_id, prefab);
+    }
+
+    order_loaded_prefabs(root, prefabs)
+}
+
+fn deserialize_fetched_prefab<T: BuildHasher, E>(
+    prefab_id: PrefabUuid,
+    source: &[u8],
+    context: PrefabSerdeContext<T>,
+) -> Result<Prefab, LoadPrefabError<E>> {
+    let prefab = deserialize_prefab(source, context)
+        .map_err(|e| LoadPrefabError::Deserialize(prefab_id, e))?;
+    if prefab.prefab_id() != prefab_id {
+        return Err(LoadPrefabError::IdMismatch {
+            requested: prefab_id,
+            found: prefab.prefab_id(),
+        });
+    }
+    Ok(prefab)
+}
+
+fn order_loaded_prefabs<E>(
+    root: PrefabUuid,
+    prefabs: HashMap<PrefabUuid, Prefab>,
+) -> Result<LoadedPrefabs, LoadPrefabError<E>> {
+    let cook_order = prefab_cook_order(&root, |prefab_id| {
+        prefabs
+            .get(prefab_id)
+            .map(|prefab| prefab.prefab_meta.prefab_refs.keys().cloned().collect())
+    })
+    .map_err(|e| match e {
+        PrefabCookOrderError::CyclicReference(prefab_id) => {
+            LoadPrefabError::CyclicReference(prefab_id)
+        }
+        // Every referenced prefab was loaded above
+        PrefabCookOrderError::MissingPrefab(_) => unreachable!(),
+    })?;
+
+    Ok(LoadedPrefabs {
+        prefabs,
+        cook_order,
+    })
+}
+
+fn deserialize_prefab<T: BuildHasher>(
+    source: &[u8],
+    context: PrefabSerdeContext<T>,
+) -> Result<Prefab, ron::de::Error> {
+    let mut de = ron::de::Deserializer::from_bytes(source)?;
+    let prefab_deser = PrefabFormatDeserializer::new(context);
+    // Variants are loaded as prefabs that extend their base, which is then loaded like any
+    // referenced prefab
+    if std::str::from_utf8(source).map_or(false, prefab_format::is_variant_ron) {
+        prefab_format::deserialize_variant(&mut de, &prefab_deser)?;
+    } else {
+        prefab_format::deserialize(&mut de, &prefab_deser)?;
+    }
+    de.end()?;
+    Ok(prefab_deser.prefab())
+}
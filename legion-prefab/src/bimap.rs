@@ -0,0 +1,174 @@
+use crate::format::EntityUuid;
+use legion::Entity;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::iter::FromIterator;
+
+/// A one-to-one mapping between entity UUIDs and the entities they were cooked or spawned as, with
+/// constant time lookup in both directions
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UuidEntityBimap {
+    uuid_to_entity: HashMap<EntityUuid, Entity>,
+    entity_to_uuid: HashMap<Entity, EntityUuid>,
+}
+
+impl UuidEntityBimap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        UuidEntityBimap {
+            uuid_to_entity: HashMap::with_capacity(capacity),
+            entity_to_uuid: HashMap::with_capacity(capacity),
+        }
+    }
+
+    /// Maps `uuid` to `entity`. Any existing mappings of either of them are removed.
+    pub fn insert(
+        &mut self,
+        uuid: EntityUuid,
+        entity: Entity,
+    ) {
+        self.remove_uuid(&uuid);
+        self.remove_entity(&entity);
+        self.uuid_to_entity.insert(uuid, entity);
+        self.entity_to_uuid.insert(entity, uuid);
+    }
+
+    pub fn entity(
+        &self,
+        uuid: &EntityUuid,
+    ) -> Option<Entity> {
+        self.uuid_to_entity.get(uuid).cloned()
+    }
+
+    pub fn uuid(
+        &self,
+        entity: &Entity,
+    ) -> Option<EntityUuid> {
+        self.entity_to_uuid.get(entity).cloned()
+    }
+
+    pub fn contains_uuid(
+        &self,
+        uuid: &EntityUuid,
+    ) -> bool {
+        self.uuid_to_entity.contains_key(uuid)
+    }
+
+    pub fn contains_entity(
+        &self,
+        entity: &Entity,
+    ) -> bool {
+        self.entity_to_uuid.contains_key(entity)
+    }
+
+    pub fn remove_uuid(
+        &mut self,
+        uuid: &EntityUuid,
+    ) -> Option<Entity> {
+        let entity = self.uuid_to_entity.remove(uuid)?;
+        self.entity_to_uuid.remove(&entity);
+        Some(entity)
+    }
+
+    pub fn remove_entity(
+        &mut self,
+        entity: &Entity,
+    ) -> Option<EntityUuid> {
+        let uuid = self.entity_to_uuid.remove(entity)?;
+        self.uuid_to_entity.remove(&uuid);
+        Some(uuid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.uuid_to_entity.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.uuid_to_entity.is_empty()
+    }
+
+    pub fn iter(&self) -> std::collections::hash_map::Iter<EntityUuid, Entity> {
+        self.uuid_to_entity.iter()
+    }
+
+    pub fn uuids(&self) -> impl Iterator<Item = &EntityUuid> {
+        self.uuid_to_entity.keys()
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = &Entity> {
+        self.entity_to_uuid.keys()
+    }
+
+    pub fn uuid_to_entity(&self) -> &HashMap<EntityUuid, Entity> {
+        &self.uuid_to_entity
+    }
+
+    pub fn entity_to_uuid(&self) -> &HashMap<Entity, EntityUuid> {
+        &self.entity_to_uuid
+    }
+}
+
+impl std::ops::Index<&EntityUuid> for UuidEntityBimap {
+    type Output = Entity;
+
+    fn index(
+        &self,
+        uuid: &EntityUuid,
+    ) -> &Entity {
+        &self.uuid_to_entity[uuid]
+    }
+}
+
+impl<'a> IntoIterator for &'a UuidEntityBimap {
+    type Item = (&'a EntityUuid, &'a Entity);
+    type IntoIter = std::collections::hash_map::Iter<'a, EntityUuid, Entity>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.uuid_to_entity.iter()
+    }
+}
+
+impl FromIterator<(EntityUuid, Entity)> for UuidEntityBimap {
+    fn from_iter<I: IntoIterator<Item = (EntityUuid, Entity)>>(iter: I) -> Self {
+        let mut bimap = UuidEntityBimap::new();
+        for (uuid, entity) in iter {
+            bimap.insert(uuid, entity);
+        }
+        bimap
+    }
+}
+
+impl<S: BuildHasher> From<HashMap<EntityUuid, Entity, S>> for UuidEntityBimap {
+    fn from(uuid_to_entity: HashMap<EntityUuid, Entity, S>) -> Self {
+        uuid_to_entity.into_iter().collect()
+    }
+}
+
+// Only the UUID -> Entity direction is stored. Entity UUIDs are map keys, so they need to be
+// strings in formats like JSON
+impl Serialize for UuidEntityBimap {
+    fn serialize<S>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        prefab_format::uuid_bytes::map::serialize(&self.uuid_to_entity, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for UuidEntityBimap {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let uuid_to_entity: HashMap<EntityUuid, Entity> =
+            prefab_format::uuid_bytes::map::deserialize(deserializer)?;
+        Ok(uuid_to_entity.into())
+    }
+}
@@ -0,0 +1,169 @@
+use crate::format::ComponentTypeUuid;
+use crate::ComponentRegistration;
+use legion::world::Entity;
+use legion::{IntoQuery, World};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use type_uuid::TypeUuid;
+
+/// A marker component that stands for a group of components, i.e. a `CharacterBundle` for the
+/// transform, health and inventory of a character. Tools add the whole group by adding the marker,
+/// and cooking replaces the marker with the components of the bundle, see
+/// `expand_component_bundles`.
+#[derive(Clone)]
+pub struct ComponentBundle {
+    uuid: ComponentTypeUuid,
+    type_name: &'static str,
+    components: Vec<ComponentTypeUuid>,
+}
+
+impl ComponentBundle {
+    /// A bundle without components, for the marker type `B`. The marker must also be registered
+    /// as a component type, which `register_component_bundle!` does.
+    pub fn of<B: TypeUuid + 'static>() -> Self {
+        ComponentBundle {
+            uuid: B::UUID,
+            type_name: std::any::type_name::<B>(),
+            components: vec![],
+        }
+    }
+
+    pub fn with_component<C: TypeUuid>(mut self) -> Self {
+        self.components.push(C::UUID);
+        self
+    }
+
+    /// The UUID of the marker component
+    pub fn uuid(&self) -> &ComponentTypeUuid {
+        &self.uuid
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// The component types the bundle expands to, in the order they are added
+    pub fn components(&self) -> &[ComponentTypeUuid] {
+        &self.components
+    }
+}
+
+inventory::collect!(ComponentBundle);
+
+pub fn iter_component_bundles() -> impl Iterator<Item = &'static ComponentBundle> {
+    inventory::iter::<ComponentBundle>.into_iter()
+}
+
+/// Registers a marker component type as a bundle of other component types, and the marker itself
+/// as a component type. The marker must be a zero-sized type that can be registered with
+/// `register_component_type!`:
+///
+/// ```ignore
+/// #[derive(TypeUuid, Serialize, Deserialize, SerdeDiff, Clone, Default)]
+/// #[uuid = "..."]
+/// struct CharacterBundle;
+///
+/// register_component_bundle!(CharacterBundle, [Transform, Health, Inventory]);
+/// ```
+#[macro_export]
+macro_rules! register_component_bundle {
+    ($bundle_type:ty, [$($component_type:ty),* $(,)?]) => {
+        $crate::register_component_bundle!(legion_prefab; $bundle_type, [$($component_type),*]);
+    };
+    ($krate:ident; $bundle_type:ty, [$($component_type:ty),* $(,)?]) => {
+        $crate::register_component_type!($krate; $bundle_type);
+        const _: () = {
+            $crate::inventory::submit!{
+                #![crate = $krate]
+                $crate::ComponentBundle::of::<$bundle_type>()
+                    $(.with_component::<$component_type>())*
+            }
+        };
+    };
+}
+
+/// Replaces the bundle markers in a world with the components of their bundles. Components an
+/// entity already has keep their value, and the others are added with their default value.
+/// Bundles that contain other bundles are expanded all the way.
+pub fn expand_component_bundles<S: BuildHasher>(
+    world: &mut World,
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+) {
+    let bundles: HashMap<ComponentTypeUuid, &ComponentBundle> = iter_component_bundles()
+        .map(|bundle| (bundle.uuid, bundle))
+        .collect();
+    if bundles.is_empty() {
+        return;
+    }
+
+    let entities: Vec<Entity> = <Entity>::query().iter(world).copied().collect();
+    for entity in entities {
+        for bundle in bundles.values() {
+            expand_bundle(
+                world,
+                entity,
+                bundle,
+                &bundles,
+                registered_components_by_uuid,
+                &mut vec![],
+            );
+        }
+    }
+}
+
+// `expanding` holds the bundles being expanded, so a bundle that (indirectly) contains itself
+// isn't added back
+fn expand_bundle<S: BuildHasher>(
+    world: &mut World,
+    entity: Entity,
+    bundle: &ComponentBundle,
+    bundles: &HashMap<ComponentTypeUuid, &ComponentBundle>,
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+    expanding: &mut Vec<ComponentTypeUuid>,
+) {
+    let marker = &registered_components_by_uuid[&bundle.uuid];
+    if !has_component(marker, world, entity) {
+        return;
+    }
+    marker.remove_from_entity(world, entity);
+
+    expanding.push(bundle.uuid);
+    for component_type in &bundle.components {
+        if expanding.contains(component_type) {
+            continue;
+        }
+
+        let registration = &registered_components_by_uuid[component_type];
+        if !has_component(registration, world, entity) {
+            registration.add_default_to_entity(world, entity);
+        }
+
+        if let Some(nested) = bundles.get(component_type) {
+            expand_bundle(
+                world,
+                entity,
+                nested,
+                bundles,
+                registered_components_by_uuid,
+                expanding,
+            );
+        }
+    }
+    expanding.pop();
+}
+
+fn has_component(
+    registration: &ComponentRegistration,
+    world: &World,
+    entity: Entity,
+) -> bool {
+    world
+        .entry_ref(entity)
+        .map(|entry| {
+            entry
+                .archetype()
+                .layout()
+                .has_component_by_id(registration.component_type_id())
+        })
+        .unwrap_or(false)
+}
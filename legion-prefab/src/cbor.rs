@@ -0,0 +1,34 @@
+//! Helpers for loading and saving legion prefabs as CBOR
+use crate::{
+    CookedPrefab, Prefab, PrefabFormatDeserializer, PrefabFormatSerializer, PrefabSerdeContext,
+};
+use std::hash::BuildHasher;
+
+/// Loads a prefab from source data in CBOR prefab format
+pub fn prefab_from_slice<T: BuildHasher>(
+    bytes: &[u8],
+    context: PrefabSerdeContext<T>,
+) -> Result<Prefab, serde_cbor::Error> {
+    let prefab_deser = PrefabFormatDeserializer::new(context);
+    prefab_format::cbor::from_slice(bytes, &prefab_deser)?;
+    Ok(prefab_deser.prefab())
+}
+
+/// Saves a prefab as source data in CBOR prefab format
+pub fn prefab_to_vec<T: BuildHasher>(
+    prefab: &Prefab,
+    context: PrefabSerdeContext<T>,
+) -> Result<Vec<u8>, serde_cbor::Error> {
+    let prefab_ser = PrefabFormatSerializer::new(context, prefab);
+    prefab_format::cbor::to_vec(&prefab_ser, prefab.prefab_id())
+}
+
+/// Loads a cooked prefab from CBOR
+pub fn cooked_prefab_from_slice(bytes: &[u8]) -> Result<CookedPrefab, serde_cbor::Error> {
+    serde_cbor::from_slice(bytes)
+}
+
+/// Saves a cooked prefab as CBOR
+pub fn cooked_prefab_to_vec(cooked_prefab: &CookedPrefab) -> Result<Vec<u8>, serde_cbor::Error> {
+    serde_cbor::to_vec(cooked_prefab)
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use crate::ComponentRegistration;
 use legion::storage::{
     ComponentTypeId, Component, ComponentStorage, Components, EntityLayout, Archetype,
@@ -16,11 +16,187 @@ use legion::world::EntityHasher;
 #[derive(Copy, Clone)]
 pub struct CopyCloneImpl<'a, S: BuildHasher> {
     components: &'a HashMap<ComponentTypeId, ComponentRegistration, S>,
+    skip_unregistered: bool,
 }
 
 impl<'a, S: BuildHasher> CopyCloneImpl<'a, S> {
+    /// Panics when cloning a component whose type is not in `components`
     pub fn new(components: &'a HashMap<ComponentTypeId, ComponentRegistration, S>) -> Self {
-        Self { components }
+        Self {
+            components,
+            skip_unregistered: false,
+        }
+    }
+
+    /// Leaves out components whose type is not in `components` instead of panicking
+    pub fn new_skipping_unregistered(
+        components: &'a HashMap<ComponentTypeId, ComponentRegistration, S>
+    ) -> Self {
+        Self {
+            components,
+            skip_unregistered: true,
+        }
+    }
+
+    /// Whether components of the type can be cloned, rather than being left out or panicking
+    pub fn can_clone(
+        &self,
+        component_type: &ComponentTypeId,
+    ) -> bool {
+        self.components.contains_key(component_type)
+    }
+
+    fn registration(
+        &self,
+        component_type: &ComponentTypeId,
+    ) -> Option<&'a ComponentRegistration> {
+        let registration = self.components.get(component_type);
+        if registration.is_none() && !self.skip_unregistered {
+            panic!(
+                "cannot clone component of unregistered type {:?}",
+                component_type
+            );
+        }
+        registration
+    }
+}
+
+/// What `clone_world` and `clone_entities` do with components whose type isn't registered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnregisteredComponentPolicy {
+    /// Clone the entities without those components, logging a warning for each type
+    SkipWithWarning,
+    /// Don't clone anything and return `CloneWorldError::UnregisteredComponentTypes`
+    Error,
+}
+
+#[derive(Debug)]
+pub enum CloneWorldError {
+    /// The source world has components of these types, which aren't registered
+    UnregisteredComponentTypes(Vec<ComponentTypeId>),
+}
+
+/// Clones every entity in `src` into `dst`, copying components with the registrations in
+/// `registered_components`. Returns the `dst` entity each `src` entity was cloned to.
+pub fn clone_world<S: BuildHasher>(
+    src: &World,
+    dst: &mut World,
+    registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+    unregistered_policy: UnregisteredComponentPolicy,
+) -> Result<HashMap<Entity, Entity, EntityHasher>, CloneWorldError> {
+    check_unregistered_components(
+        src,
+        Entity::query().iter(src).copied(),
+        registered_components,
+        unregistered_policy,
+    )?;
+
+    let mut clone_impl = CopyCloneImpl::new_skipping_unregistered(registered_components);
+    Ok(dst.clone_from(src, &legion::query::any(), &mut clone_impl))
+}
+
+/// Clones only the given entities from `src` into `dst`, i.e. to duplicate a selection or to
+/// stage entities in another world for editing. Returns the `dst` entity each `src` entity was
+/// cloned to. Entities that aren't in `src` are ignored.
+pub fn clone_entities<S: BuildHasher>(
+    src: &World,
+    entities: &[Entity],
+    dst: &mut World,
+    registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+    unregistered_policy: UnregisteredComponentPolicy,
+) -> Result<HashMap<Entity, Entity, EntityHasher>, CloneWorldError> {
+    clone_entities_with_children(
+        src,
+        entities,
+        dst,
+        registered_components,
+        unregistered_policy,
+        |_, _| vec![],
+    )
+}
+
+/// Like `clone_entities`, but also clones the descendants of the given entities. `children`
+/// returns the direct children of an entity, since the hierarchy is defined by the application's
+/// own components.
+pub fn clone_entities_with_children<S, F>(
+    src: &World,
+    entities: &[Entity],
+    dst: &mut World,
+    registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+    unregistered_policy: UnregisteredComponentPolicy,
+    mut children: F,
+) -> Result<HashMap<Entity, Entity, EntityHasher>, CloneWorldError>
+where
+    S: BuildHasher,
+    F: FnMut(&World, Entity) -> Vec<Entity>,
+{
+    // Every entity to clone, each listed once, in the order they were given with descendants
+    // following their ancestors
+    let mut selected = vec![];
+    let mut visited = HashSet::new();
+    let mut pending: Vec<Entity> = entities.iter().rev().copied().collect();
+    while let Some(entity) = pending.pop() {
+        if !src.contains(entity) || !visited.insert(entity) {
+            continue;
+        }
+
+        selected.push(entity);
+        pending.extend(children(src, entity).into_iter().rev());
+    }
+
+    check_unregistered_components(
+        src,
+        selected.iter().copied(),
+        registered_components,
+        unregistered_policy,
+    )?;
+
+    let mut clone_impl = CopyCloneImpl::new_skipping_unregistered(registered_components);
+    let mut mappings = HashMap::default();
+    for entity in selected {
+        let dst_entity = dst.clone_from_single(src, entity, &mut clone_impl);
+        mappings.insert(entity, dst_entity);
+    }
+
+    Ok(mappings)
+}
+
+// Applies the unregistered component policy to the component types of the given entities
+fn check_unregistered_components<S: BuildHasher, I: Iterator<Item = Entity>>(
+    world: &World,
+    entities: I,
+    registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+    unregistered_policy: UnregisteredComponentPolicy,
+) -> Result<(), CloneWorldError> {
+    let mut unregistered = vec![];
+    for entity in entities {
+        let entry = world.entry_ref(entity).unwrap();
+        for component_type in entry.archetype().layout().component_types() {
+            if !registered_components.contains_key(component_type)
+                && !unregistered.contains(component_type)
+            {
+                unregistered.push(*component_type);
+            }
+        }
+    }
+
+    if unregistered.is_empty() {
+        return Ok(());
+    }
+
+    match unregistered_policy {
+        UnregisteredComponentPolicy::SkipWithWarning => {
+            for component_type in &unregistered {
+                log::warn!(
+                    "skipped cloning components of unregistered type {:?}",
+                    component_type
+                );
+            }
+            Ok(())
+        }
+        UnregisteredComponentPolicy::Error => {
+            Err(CloneWorldError::UnregisteredComponentTypes(unregistered))
+        }
     }
 }
 
@@ -35,8 +211,9 @@ impl<'a, S: BuildHasher> legion::world::Merger for CopyCloneImpl<'a, S> {
     ) -> EntityLayout {
         let mut dest_layout = EntityLayout::default();
         for component_type in source_layout.component_types() {
-            let comp_reg = &self.components[component_type];
-            comp_reg.register_component(&mut dest_layout);
+            if let Some(comp_reg) = self.registration(component_type) {
+                comp_reg.register_component(&mut dest_layout);
+            }
         }
 
         dest_layout
@@ -50,9 +227,15 @@ impl<'a, S: BuildHasher> legion::world::Merger for CopyCloneImpl<'a, S> {
         dst: &mut ArchetypeWriter,
     ) {
         for src_type in src_arch.layout().component_types() {
-            let comp_reg = &self.components[src_type];
-            unsafe {
-                comp_reg.clone_components(src_entity_range.clone(), src_arch, src_components, dst);
+            if let Some(comp_reg) = self.registration(src_type) {
+                unsafe {
+                    comp_reg.clone_components(
+                        src_entity_range.clone(),
+                        src_arch,
+                        src_components,
+                        dst,
+                    );
+                }
             }
         }
     }
@@ -0,0 +1,50 @@
+use crate::format::ComponentTypeUuid;
+use crate::registration::ComponentRegistration;
+use std::collections::HashSet;
+
+/// The component types a consumer of world data may see, i.e. a client that must not receive
+/// server-only components. Pass it to `serialize_world_masked` or the delta/replication APIs in
+/// legion-transaction, which leave masked out component types out entirely.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ComponentMask {
+    /// Every component type
+    All,
+    /// Only these component types
+    Only(HashSet<ComponentTypeUuid>),
+    /// Every component type except these
+    AllExcept(HashSet<ComponentTypeUuid>),
+}
+
+impl Default for ComponentMask {
+    fn default() -> Self {
+        ComponentMask::All
+    }
+}
+
+impl ComponentMask {
+    pub fn only<I: IntoIterator<Item = ComponentTypeUuid>>(component_types: I) -> Self {
+        ComponentMask::Only(component_types.into_iter().collect())
+    }
+
+    pub fn all_except<I: IntoIterator<Item = ComponentTypeUuid>>(component_types: I) -> Self {
+        ComponentMask::AllExcept(component_types.into_iter().collect())
+    }
+
+    pub fn includes(
+        &self,
+        component_type: &ComponentTypeUuid,
+    ) -> bool {
+        match self {
+            ComponentMask::All => true,
+            ComponentMask::Only(component_types) => component_types.contains(component_type),
+            ComponentMask::AllExcept(component_types) => !component_types.contains(component_type),
+        }
+    }
+
+    pub fn includes_registration(
+        &self,
+        registration: &ComponentRegistration,
+    ) -> bool {
+        self.includes(registration.uuid())
+    }
+}
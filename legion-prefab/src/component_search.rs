@@ -0,0 +1,59 @@
+use crate::format::ron_patch::{RonPatchError, RonPrefabDocument};
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid};
+use std::path::{Path, PathBuf};
+
+/// A place where a component type appears in a prefab file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentUsage {
+    pub path: PathBuf,
+    pub prefab: PrefabUuid,
+    /// The entity with the component. For overrides, this is an entity of the referenced prefab.
+    pub entity: EntityUuid,
+    /// The referenced (or extended) prefab if the component appears in an override rather than on
+    /// an entity of the prefab
+    pub prefab_ref: Option<PrefabUuid>,
+}
+
+#[derive(Debug)]
+pub enum ComponentUsageError {
+    Io(PathBuf, std::io::Error),
+    Scan(PathBuf, RonPatchError),
+}
+
+/// Finds every entity and override in a set of RON prefab files that uses a component type, i.e.
+/// to find all uses of a deprecated component. Files are scanned without loading component data,
+/// so the component type doesn't need to be registered.
+pub fn find_component_usages<P: AsRef<Path>, I: IntoIterator<Item = P>>(
+    prefab_paths: I,
+    component_type: &ComponentTypeUuid,
+) -> Result<Vec<ComponentUsage>, ComponentUsageError> {
+    let mut usages = vec![];
+    for path in prefab_paths {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| ComponentUsageError::Io(path.to_path_buf(), e))?;
+        let doc = RonPrefabDocument::parse(&source)
+            .map_err(|e| ComponentUsageError::Scan(path.to_path_buf(), e))?;
+
+        let usage = |entity, prefab_ref| ComponentUsage {
+            path: path.to_path_buf(),
+            prefab: doc.prefab_id(),
+            entity,
+            prefab_ref,
+        };
+        for entity in doc.entities() {
+            if doc.component_types(&entity).contains(component_type) {
+                usages.push(usage(entity, None));
+            }
+        }
+        for prefab_ref in doc.base_prefab().into_iter().chain(doc.prefab_refs()) {
+            for (entity, component_types) in doc.prefab_ref_overrides(&prefab_ref) {
+                if component_types.contains(component_type) {
+                    usages.push(usage(entity, Some(prefab_ref)));
+                }
+            }
+        }
+    }
+
+    Ok(usages)
+}
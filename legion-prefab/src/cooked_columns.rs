@@ -0,0 +1,177 @@
+//! A binary layout for cooked prefabs that is fast to load. The world is written per archetype,
+//! with each component type of an archetype stored as one length-prefixed column of bincode.
+//! Components registered with `pod` are a single byte copy per column, and everything else is
+//! decoded a column at a time rather than dispatching per entity.
+//!
+//! The columns are stored in a table ahead of the rest of the prefab, so they can all be decoded
+//! before the world is assembled, and on several threads with `read_cooked_columns_parallel`.
+//!
+//! The layout depends on the component types, so it is meant for cooked output that is rebuilt
+//! from prefab source data, not for storing anything long term.
+use crate::format::bytes::ByteBuf;
+use crate::format::{ComponentTypeUuid, EntityUuid};
+use crate::registration::DecodedColumn;
+use crate::world_serde::SharedEntityUuidMapper;
+use crate::{CookedPrefab, UuidEntityBimap};
+use bincode::config::DefaultOptions;
+use bincode::Options;
+use legion::{Entity, IntoQuery};
+use serde::de::Error;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+
+const MAGIC: &[u8; 8] = b"PFCOLUMN";
+const VERSION: u32 = 2;
+
+// Everything that is decoded before the world is read
+#[derive(Serialize, Deserialize)]
+struct ColumnTable {
+    // The entities of the world in the order it stores them, allocated before any column is
+    // decoded so that entities are the same however the columns are decoded
+    entities: Vec<EntityUuid>,
+    columns: Vec<EncodedColumn>,
+}
+
+/// One component slice of the world, as bincode
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EncodedColumn {
+    pub component_type: ComponentTypeUuid,
+    pub data: ByteBuf,
+}
+
+/// The columns of a world, decoded and waiting to be added to it, with the entities that were
+/// allocated for them
+pub(crate) struct DecodedColumns {
+    pub entity_map: UuidEntityBimap,
+    pub allocator: legion::world::Allocate,
+    pub columns: Vec<Option<DecodedColumn>>,
+}
+
+#[derive(Debug)]
+pub enum CookedColumnsError {
+    /// The data doesn't start with the columnar header
+    NotColumnar,
+    /// The data was written by a different version of the layout and must be cooked again
+    UnsupportedVersion(u32),
+    Bincode(bincode::Error),
+}
+
+impl From<bincode::Error> for CookedColumnsError {
+    fn from(error: bincode::Error) -> Self {
+        CookedColumnsError::Bincode(error)
+    }
+}
+
+/// Saves a cooked prefab in the columnar layout
+pub fn write_cooked_columns(cooked_prefab: &CookedPrefab) -> Result<Vec<u8>, CookedColumnsError> {
+    let column_table = RefCell::new(vec![]);
+    let mut prefab = Vec::new();
+    let mut serializer = bincode::Serializer::new(&mut prefab, DefaultOptions::new());
+    cooked_prefab.serialize_with_layout(&mut serializer, Some(&column_table))?;
+
+    let entities = <Entity>::query()
+        .iter(&cooked_prefab.world)
+        .filter_map(|entity| cooked_prefab.entities.uuid(entity))
+        .collect();
+    let column_table = ColumnTable {
+        entities,
+        columns: column_table.into_inner(),
+    };
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    DefaultOptions::new().serialize_into(&mut bytes, &column_table)?;
+    bytes.extend_from_slice(&prefab);
+    Ok(bytes)
+}
+
+/// Loads a cooked prefab written by `write_cooked_columns`
+pub fn read_cooked_columns(bytes: &[u8]) -> Result<CookedPrefab, CookedColumnsError> {
+    read_columns(bytes, |columns, decode| {
+        columns.iter().map(decode).collect()
+    })
+}
+
+/// Like `read_cooked_columns`, but decodes the columns concurrently on the rayon thread pool
+/// before assembling the world, which cuts the load time of big worlds. Entities and their order
+/// are the same as with `read_cooked_columns`, except for entities that are only referenced by
+/// components, which are allocated in whichever order the threads get to them.
+#[cfg(feature = "parallel")]
+pub fn read_cooked_columns_parallel(bytes: &[u8]) -> Result<CookedPrefab, CookedColumnsError> {
+    use rayon::prelude::*;
+    read_columns(bytes, |columns, decode| {
+        columns.par_iter().map(decode).collect()
+    })
+}
+
+// Reads the column table, decodes the columns with decode_all and then reads the rest of the
+// prefab. decode_all calls the given fn for each column and collects the results in order
+fn read_columns<F>(
+    bytes: &[u8],
+    decode_all: F,
+) -> Result<CookedPrefab, CookedColumnsError>
+where
+    F: FnOnce(
+        &[EncodedColumn],
+        &(dyn Fn(&EncodedColumn) -> bincode::Result<DecodedColumn> + Sync),
+    ) -> bincode::Result<Vec<DecodedColumn>>,
+{
+    if bytes.len() < MAGIC.len() + 4 || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(CookedColumnsError::NotColumnar);
+    }
+
+    let mut version = [0; 4];
+    version.copy_from_slice(&bytes[MAGIC.len()..MAGIC.len() + 4]);
+    let version = u32::from_le_bytes(version);
+    if version != VERSION {
+        return Err(CookedColumnsError::UnsupportedVersion(version));
+    }
+
+    let mut deserializer =
+        bincode::Deserializer::from_slice(&bytes[MAGIC.len() + 4..], DefaultOptions::new());
+    let column_table = ColumnTable::deserialize(&mut deserializer)?;
+
+    let mut entity_map = UuidEntityBimap::with_capacity(column_table.entities.len());
+    let mut allocator = legion::world::Allocate::new();
+    for entity_uuid in column_table.entities {
+        entity_map.insert(entity_uuid, allocator.next().unwrap());
+    }
+
+    let columns = {
+        let mapper = SharedEntityUuidMapper {
+            state: std::sync::Mutex::new((&mut entity_map, &mut allocator)),
+        };
+        let decode = |column: &EncodedColumn| mapper.scope(|| decode_column(column));
+        decode_all(&column_table.columns, &decode)?
+    };
+
+    let columns = DecodedColumns {
+        entity_map,
+        allocator,
+        columns: columns.into_iter().map(Some).collect(),
+    };
+    Ok(CookedPrefab::deserialize_with_layout(
+        &mut deserializer,
+        Some(columns),
+    )?)
+}
+
+fn decode_column(column: &EncodedColumn) -> bincode::Result<DecodedColumn> {
+    let registry = crate::registration::global_component_registry();
+    let registration = registry
+        .by_uuid()
+        .get(&column.component_type)
+        .ok_or_else(|| {
+            bincode::Error::custom(format!(
+                "column of unregistered component type {}",
+                uuid::Uuid::from_bytes(column.component_type)
+            ))
+        })?;
+
+    let mut deserializer = bincode::Deserializer::from_slice(&column.data.0, DefaultOptions::new());
+    let mut deserializer = erased_serde::Deserializer::erase(&mut deserializer);
+    registration
+        .decode_column(&mut deserializer)
+        .map_err(bincode::Error::custom)
+}
@@ -0,0 +1,245 @@
+//! Binary containers for cooked prefabs, chosen with `CookedFormat`.
+//!
+//! `CookedFormat::Fast` trades generality for load time. It is laid out as:
+//!
+//! - a header: the magic bytes `PFFAST\0\0`, then the version and the number of sections as
+//!   little endian `u32`s
+//! - an offset table with the kind (`u32`), offset (`u64`) and length (`u64`) of each section,
+//!   little endian, with offsets from the start of the data
+//! - the sections. The world is stored in the columnar layout of `write_cooked_columns`, so
+//!   components are loaded a column at a time. Parameters, resources and blobs are bincode
+//!
+//! Each section is decoded from its own slice, so nothing is scanned to find where a section
+//! starts. Sections of an unknown kind are skipped. The entity map isn't stored, as loading the
+//! world rebuilds it.
+//!
+//! Like the columnar layout, the data depends on the registered component types and on the
+//! machine for `pod` components, so it is only meant for cooked output that can be rebuilt.
+use crate::format::blobs::{BlobData, BlobId};
+use crate::world_serde::{
+    assign_missing_uuids, CustomDeserializer, CustomSerializer, UnknownComponentPolicy,
+};
+use crate::{
+    read_cooked_columns, write_cooked_columns, CookedColumnsError, CookedPrefab, PrefabResources,
+    UuidEntityBimap,
+};
+use bincode::config::DefaultOptions;
+use bincode::Options;
+use legion::World;
+use serde::de::DeserializeSeed;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+
+/// How a cooked prefab is stored by `write_cooked_prefab` and `read_cooked_prefab`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CookedFormat {
+    /// The serde representation of `CookedPrefab`, written with bincode
+    Bincode,
+    /// The columnar layout of `write_cooked_columns`
+    Columnar,
+    /// A header and offset table followed by sections, with the world in the columnar layout. See
+    /// the module docs
+    Fast,
+}
+
+#[derive(Debug)]
+pub enum CookedFormatError {
+    /// The data doesn't start with the header of the format
+    UnknownHeader,
+    /// The data was written by a different version of the format and must be cooked again
+    UnsupportedVersion(u32),
+    /// The offset table or a section extends past the end of the data
+    Truncated,
+    /// The data doesn't have a world section
+    MissingWorld,
+    Bincode(bincode::Error),
+    Columns(CookedColumnsError),
+}
+
+impl From<bincode::Error> for CookedFormatError {
+    fn from(error: bincode::Error) -> Self {
+        CookedFormatError::Bincode(error)
+    }
+}
+
+impl From<CookedColumnsError> for CookedFormatError {
+    fn from(error: CookedColumnsError) -> Self {
+        CookedFormatError::Columns(error)
+    }
+}
+
+const MAGIC: &[u8; 8] = b"PFFAST\0\0";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 16;
+const TABLE_ENTRY_LEN: usize = 20;
+
+const SECTION_WORLD: u32 = 1;
+const SECTION_PARAMETERS: u32 = 2;
+const SECTION_RESOURCES: u32 = 3;
+const SECTION_BLOBS: u32 = 4;
+
+/// Saves a cooked prefab in the given format
+pub fn write_cooked_prefab(
+    cooked_prefab: &CookedPrefab,
+    format: CookedFormat,
+) -> Result<Vec<u8>, CookedFormatError> {
+    match format {
+        CookedFormat::Bincode => Ok(DefaultOptions::new().serialize(cooked_prefab)?),
+        CookedFormat::Columnar => Ok(write_cooked_columns(cooked_prefab)?),
+        CookedFormat::Fast => write_fast(cooked_prefab),
+    }
+}
+
+/// Loads a cooked prefab saved by `write_cooked_prefab` with the same format
+pub fn read_cooked_prefab(
+    bytes: &[u8],
+    format: CookedFormat,
+) -> Result<CookedPrefab, CookedFormatError> {
+    match format {
+        CookedFormat::Bincode => Ok(DefaultOptions::new().deserialize(bytes)?),
+        CookedFormat::Columnar => Ok(read_cooked_columns(bytes)?),
+        CookedFormat::Fast => read_fast(bytes),
+    }
+}
+
+fn write_fast(cooked_prefab: &CookedPrefab) -> Result<Vec<u8>, CookedFormatError> {
+    let options = DefaultOptions::new();
+    let blobs: BTreeMap<&BlobId, &BlobData> = cooked_prefab.blobs.iter().collect();
+    let sections = [
+        (SECTION_WORLD, world_section(cooked_prefab)?),
+        (
+            SECTION_PARAMETERS,
+            options.serialize(&cooked_prefab.parameters)?,
+        ),
+        (
+            SECTION_RESOURCES,
+            options.serialize(&cooked_prefab.resources)?,
+        ),
+        (SECTION_BLOBS, options.serialize(&blobs)?),
+    ];
+
+    let mut offset = HEADER_LEN + sections.len() * TABLE_ENTRY_LEN;
+    let total_len = offset + sections.iter().map(|(_, data)| data.len()).sum::<usize>();
+    let mut bytes = Vec::with_capacity(total_len);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(sections.len() as u32).to_le_bytes());
+    for (kind, data) in &sections {
+        bytes.extend_from_slice(&kind.to_le_bytes());
+        bytes.extend_from_slice(&(offset as u64).to_le_bytes());
+        bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        offset += data.len();
+    }
+    for (_, data) in &sections {
+        bytes.extend_from_slice(data);
+    }
+    Ok(bytes)
+}
+
+fn read_fast(bytes: &[u8]) -> Result<CookedPrefab, CookedFormatError> {
+    if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(CookedFormatError::UnknownHeader);
+    }
+    let version = read_u32(bytes, 8);
+    if version != VERSION {
+        return Err(CookedFormatError::UnsupportedVersion(version));
+    }
+
+    let section_count = read_u32(bytes, 12) as usize;
+    let table_len = section_count
+        .checked_mul(TABLE_ENTRY_LEN)
+        .ok_or(CookedFormatError::Truncated)?;
+    if bytes.len() - HEADER_LEN < table_len {
+        return Err(CookedFormatError::Truncated);
+    }
+
+    let options = DefaultOptions::new();
+    let mut world = None;
+    let mut parameters = Vec::new();
+    let mut resources = PrefabResources::new();
+    let mut blobs = HashMap::new();
+    for index in 0..section_count {
+        let entry = HEADER_LEN + index * TABLE_ENTRY_LEN;
+        let offset = read_u64(bytes, entry + 4) as usize;
+        let len = read_u64(bytes, entry + 12) as usize;
+        let section = offset
+            .checked_add(len)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or(CookedFormatError::Truncated)?;
+
+        match read_u32(bytes, entry) {
+            SECTION_WORLD => world = Some(read_world_section(section)?),
+            SECTION_PARAMETERS => parameters = options.deserialize(section)?,
+            SECTION_RESOURCES => resources = options.deserialize(section)?,
+            SECTION_BLOBS => blobs = options.deserialize(section)?,
+            _ => {}
+        }
+    }
+
+    let (world, entities) = world.ok_or(CookedFormatError::MissingWorld)?;
+    Ok(CookedPrefab {
+        world,
+        entities,
+        parameters,
+        resources,
+        blobs,
+    })
+}
+
+fn world_section(cooked_prefab: &CookedPrefab) -> bincode::Result<Vec<u8>> {
+    let registry = crate::registration::global_component_registry();
+
+    // Entities of the world without a UUID are added to the map, so serialize with a copy
+    let mut entity_map = cooked_prefab.entities.clone();
+    assign_missing_uuids(&cooked_prefab.world, &mut entity_map);
+    let custom_serializer = CustomSerializer {
+        comp_types: registry.by_type_id(),
+        entity_map: RefCell::new(&mut entity_map),
+        component_filter: None,
+        columnar: true,
+        column_table: None,
+    };
+
+    DefaultOptions::new().serialize(
+        &cooked_prefab
+            .world
+            .as_serializable(legion::query::any(), &custom_serializer),
+    )
+}
+
+fn read_world_section(section: &[u8]) -> bincode::Result<(World, UuidEntityBimap)> {
+    let registry = crate::registration::global_component_registry();
+
+    let mut entity_map = UuidEntityBimap::new();
+    let custom_deserializer = CustomDeserializer {
+        comp_types: registry.by_type_id(),
+        comp_types_uuid: registry.by_uuid(),
+        entity_map: RefCell::new(&mut entity_map),
+        allocator: RefCell::new(legion::world::Allocate::new()),
+        columnar: true,
+        unknown_components: UnknownComponentPolicy::Error,
+        skipped_components: RefCell::default(),
+        decoded_columns: None,
+    };
+
+    let mut deserializer = bincode::Deserializer::from_slice(section, DefaultOptions::new());
+    let world = legion::serialize::DeserializeNewWorld(&custom_deserializer)
+        .deserialize(&mut deserializer)?;
+
+    Ok((world, entity_map))
+}
+
+fn read_u32(
+    bytes: &[u8],
+    at: usize,
+) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn read_u64(
+    bytes: &[u8],
+    at: usize,
+) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
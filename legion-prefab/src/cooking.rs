@@ -1,72 +1,637 @@
 use legion::*;
-use legion::storage::ComponentTypeId;
-use std::collections::HashMap;
-use crate::{CookedPrefab, Prefab, ComponentRegistration, CopyCloneImpl};
-use prefab_format::{PrefabUuid, ComponentTypeUuid};
+use legion::storage::{Archetype, ArchetypeWriter, ComponentTypeId, Components, EntityLayout};
+use legion::world::{Allocate, EntityHasher, EntityRewrite, Merger};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use crate::world_serde::EntityUuidMapper;
+use crate::{
+    apply_parameter_value, field_paths_from_ron, find_locked_field_overrides, CookedPrefab,
+    DiffSingleKind, Prefab, ComponentRegistration, CopyCloneImpl, ParameterError, PrefabResources,
+    PrefabRef, UuidEntityBimap, validate_cooked_prefab, ValidationError,
+};
+use prefab_format::{PrefabUuid, ComponentTypeUuid, PrefabParameter};
 use std::hash::BuildHasher;
+use crate::override_locks::{overlaps, touched_locked_fields};
 
+/// Merges the prefabs in `prefab_cook_order` into a single world and applies their overrides and
+/// parameter values. Fails if a prefab ref sets a parameter the referenced prefab doesn't declare,
+/// or a parameter value can't be applied to the fields bound to it.
+///
+/// Entities are added to the cooked world in ascending UUID order, so queries visit archetypes in
+/// order of their lowest entity UUID and the entities of each archetype in UUID order.
 pub fn cook_prefab<S: BuildHasher, T: BuildHasher, U: BuildHasher>(
     registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
     registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, T>,
     prefab_cook_order: &[PrefabUuid],
     prefab_lookup: &HashMap<PrefabUuid, &Prefab, U>,
-) -> CookedPrefab {
+) -> Result<CookedPrefab, ParameterError> {
     // Create a new world to hold the cooked data
     let mut world = World::default();
 
     // This will allow us to look up the cooked entity ID by the entity's original UUID
-    let mut entity_lookup = HashMap::new();
-
-    // merge all entity data from all prefabs. This data doesn't include any overrides, so order
-    // doesn't matter
-    for prefab in prefab_lookup.values() {
-        // Create the clone_merge impl. For prefab cooking, we will clone everything so we don't need to
-        // set up any transformations
-        let mut clone_merge_impl = CopyCloneImpl::new(registered_components);
-
-        // Clone all the entities from the prefab into the cooked world.
-        let result_mappings =
-            world.clone_from(&prefab.world, &legion::query::any(), &mut clone_merge_impl);
-
-        // Iterate the entities in this prefab. Determine where they are stored in the cooked
-        // world and store this in entity_lookup
-        for (entity_uuid, prefab_entity) in &prefab.prefab_meta.entities {
-            let cooked_entity = result_mappings[prefab_entity];
-            entity_lookup.insert(*entity_uuid, cooked_entity);
+    let mut entity_lookup = UuidEntityBimap::new();
+
+    // The entities of all prefabs in UUID order, which is the order they are cooked in
+    let mut prefab_entities: Vec<_> = prefab_lookup
+        .iter()
+        .flat_map(|(prefab_id, prefab)| {
+            prefab
+                .prefab_meta
+                .entities
+                .iter()
+                .map(move |(entity_uuid, entity)| (*entity_uuid, *prefab_id, *entity))
+        })
+        .collect();
+    prefab_entities.sort_by_key(|(entity_uuid, _, _)| *entity_uuid);
+
+    // Choose the cooked entity of every prefab entity up front, so that components can be pointed
+    // at entities of prefabs that haven't been merged yet
+    let mut allocator = Allocate::new();
+    for (entity_uuid, _, _) in &prefab_entities {
+        entity_lookup.insert(*entity_uuid, allocator.next().unwrap());
+    }
+
+    // merge all entity data from all prefabs. This data doesn't include any overrides, so the
+    // order doesn't change the result. legion creates archetypes as their first entity is added
+    // and keeps entities in the order they were added, so merging in UUID order makes the cooked
+    // world (and what it serializes to) the same every time, rather than depend on hash map order
+    let mut clone_impls: HashMap<_, _> = prefab_lookup
+        .iter()
+        .map(|(prefab_id, prefab)| {
+            let clone_impl = CookCloneImpl::new(registered_components, prefab, &entity_lookup);
+            (*prefab_id, clone_impl)
+        })
+        .collect();
+    for (_, prefab_id, entity) in &prefab_entities {
+        let clone_impl = clone_impls.get_mut(prefab_id).unwrap();
+        world.clone_from_single(&prefab_lookup[prefab_id].world, *entity, clone_impl);
+    }
+
+    // replace bundle markers with the components of the bundle, so that overrides and parameters
+    // can change them
+    crate::expand_component_bundles(&mut world, registered_components_by_uuid);
+
+    // set fields bound to a parameter to the parameter's default, unless a prefab ref sets the
+    // parameter or the entity authored a value for the field. Overrides are applied on top of
+    // these
+    let parameters_set_by_refs: HashSet<_> = prefab_cook_order
+        .iter()
+        .flat_map(|prefab_id| &prefab_lookup[prefab_id].prefab_meta.prefab_refs)
+        .flat_map(|(target_prefab, prefab_ref)| {
+            prefab_ref
+                .parameter_values
+                .keys()
+                .map(move |name| (*target_prefab, name.as_str()))
+        })
+        .collect();
+    for prefab_id in prefab_cook_order {
+        for parameter in &prefab_lookup[prefab_id].prefab_meta.parameters {
+            if parameters_set_by_refs.contains(&(*prefab_id, parameter.name.as_str())) {
+                continue;
+            }
+
+            let unauthored_bindings = PrefabParameter {
+                bindings: parameter
+                    .bindings
+                    .iter()
+                    .filter(|binding| {
+                        !has_authored_value(
+                            registered_components_by_uuid,
+                            &world,
+                            &entity_lookup,
+                            &binding.entity,
+                            &binding.component_type,
+                            &binding.field,
+                        )
+                    })
+                    .cloned()
+                    .collect(),
+                ..parameter.clone()
+            };
+            apply_parameter_value(
+                registered_components_by_uuid,
+                &mut world,
+                &entity_lookup,
+                &unauthored_bindings,
+                &parameter.default,
+            )?;
         }
     }
 
+    // Buffers reused by every prefab in the cook order
+    let mut scratch = CookScratch::default();
+
+    // Entity fields in override data are written as UUIDs. A UUID that wasn't cooked is given an
+    // entity that isn't in the world, which only this copy of the lookup knows about
+    let mut override_entities = entity_lookup.clone();
+    let override_mapper = EntityUuidMapper {
+        entity_map: RefCell::new(&mut override_entities),
+        allocator: RefCell::new(&mut allocator),
+    };
+
     // apply component override data. iteration of prefabs is in order such that "base" prefabs
     // are processed first
     for prefab_id in prefab_cook_order {
         // fetch the data for the prefab
         let prefab = prefab_lookup[prefab_id];
 
-        // Iterate all the other prefabs that this prefab references
-        for dependency_prefab_ref in prefab.prefab_meta.prefab_refs.values() {
+        // When several refs of this prefab override the same component, they are applied in order
+        // of the referenced prefab's UUID, so the last one wins. See `find_override_conflicts`.
+        let prefab_refs = sorted_prefab_refs(prefab);
+
+        // Group the overrides of all the prefabs this prefab references by component type and
+        // entity, so only the overridden entities are visited
+        scratch.clear();
+        for &(_, dependency_prefab_ref) in &prefab_refs {
             // Iterate all the entities for which we have override data
             for (entity_id, component_overrides) in &dependency_prefab_ref.overrides {
                 // Find where this entity is stored within the cooked data
                 let cooked_entity = entity_lookup[entity_id];
 
-                // Iterate all the component types for which we have override data
                 for component_override in component_overrides {
-                    let component_registration =
+                    // Overrides of locked fields are reported by cook_prefab_validated
+                    let registration =
                         &registered_components_by_uuid[&component_override.component_type];
+                    if !touched_locked_fields(registration, &component_override.data).is_empty() {
+                        continue;
+                    }
+                    // So are overrides of components the entity doesn't have. Clone-only
+                    // components can't be diffed, so their overrides are ignored
+                    if registration.is_clone_only()
+                        || !has_component(&world, cooked_entity, registration)
+                    {
+                        continue;
+                    }
 
-                    let mut deserializer =
-                        ron::de::Deserializer::from_str(&component_override.data).unwrap();
+                    scratch.push_override(
+                        component_override.component_type,
+                        cooked_entity,
+                        &component_override.data,
+                    );
+                }
+            }
+        }
 
-                    let mut de = erased_serde::Deserializer::erase(&mut deserializer);
-                    component_registration.apply_diff(&mut de, &mut world, cooked_entity);
+        for (component_type, overrides) in &scratch.overrides_by_type {
+            let registration = &registered_components_by_uuid[component_type];
+            override_mapper.scope(|| {
+                for (entity, override_list) in overrides {
+                    for data in override_list {
+                        let mut deserializer = ron::de::Deserializer::from_str(data).unwrap();
+                        registration.apply_diff(
+                            &mut erased_serde::Deserializer::erase(&mut deserializer),
+                            &mut world,
+                            *entity,
+                        );
+                    }
                 }
+            });
+        }
+
+        for &(dependency_prefab_id, dependency_prefab_ref) in &prefab_refs {
+            // Parameter values set by this prefab ref. These are applied after the overrides, so
+            // a value wins over an override of the same field
+            let dependency_parameters = &prefab_lookup[dependency_prefab_id].prefab_meta.parameters;
+            for (name, value) in &dependency_prefab_ref.parameter_values {
+                let parameter = dependency_parameters
+                    .iter()
+                    .find(|parameter| &parameter.name == name)
+                    .ok_or_else(|| ParameterError::UnknownParameter(name.clone()))?;
+                apply_parameter_value(
+                    registered_components_by_uuid,
+                    &mut world,
+                    &entity_lookup,
+                    parameter,
+                    value,
+                )?;
             }
         }
     }
 
+    // resources of a prefab replace those of the same type from the prefabs it references
+    let mut resources = PrefabResources::new();
+    let mut blobs = HashMap::new();
+    for prefab_id in prefab_cook_order {
+        let prefab = prefab_lookup[prefab_id];
+        resources.extend_from(&prefab.resources);
+        blobs.extend(
+            prefab
+                .prefab_meta
+                .blobs
+                .iter()
+                .map(|(blob, data)| (*blob, data.clone())),
+        );
+    }
+
     // the resulting world can now be saved
-    crate::CookedPrefab {
+    Ok(crate::CookedPrefab {
         world,
         entities: entity_lookup,
+        // the root prefab's parameters can still be set when spawning
+        parameters: prefab_cook_order
+            .last()
+            .map(|root| prefab_lookup[root].prefab_meta.parameters.clone())
+            .unwrap_or_default(),
+        resources,
+        blobs,
+    })
+}
+
+// The prefab refs of a prefab in the order their overrides are applied
+pub(crate) fn sorted_prefab_refs(prefab: &Prefab) -> Vec<(&PrefabUuid, &PrefabRef)> {
+    let mut prefab_refs: Vec<_> = prefab.prefab_meta.prefab_refs.iter().collect();
+    prefab_refs.sort_by_key(|(prefab_id, _)| **prefab_id);
+    prefab_refs
+}
+
+/// Like `cook_prefab`, but also runs the validate fns attached to the registrations on the cooked
+/// data, and reports the overrides `cook_prefab` skips: those of locked fields and of components
+/// the entity doesn't have. The cooked prefab is only returned if no errors were found. A
+/// parameter error is reported as the only error, since cooking stops there.
+pub fn cook_prefab_validated<S: BuildHasher, T: BuildHasher, U: BuildHasher>(
+    registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, T>,
+    prefab_cook_order: &[PrefabUuid],
+    prefab_lookup: &HashMap<PrefabUuid, &Prefab, U>,
+) -> Result<CookedPrefab, Vec<ValidationError>> {
+    let cooked_prefab = cook_prefab(
+        registered_components,
+        registered_components_by_uuid,
+        prefab_cook_order,
+        prefab_lookup,
+    )
+    .map_err(|error| {
+        vec![parameter_validation_error(
+            error,
+            registered_components_by_uuid,
+        )]
+    })?;
+
+    let mut errors = vec![];
+    for prefab_id in prefab_cook_order {
+        let locked_field_overrides =
+            find_locked_field_overrides(prefab_lookup[prefab_id], registered_components_by_uuid);
+        for locked_field_override in locked_field_overrides {
+            let registration =
+                &registered_components_by_uuid[&locked_field_override.component_type];
+            errors.push(ValidationError {
+                entity: locked_field_override.entity,
+                component_type: locked_field_override.component_type,
+                component_type_name: registration.type_name(),
+                message: format!(
+                    "override from prefab {} (ref of {}) changes locked fields {}",
+                    uuid::Uuid::from_bytes(*prefab_id),
+                    uuid::Uuid::from_bytes(locked_field_override.prefab_ref),
+                    locked_field_override.locked_fields.join(", ")
+                ),
+            });
+        }
+    }
+
+    // Overrides of components the entity doesn't have, which cook_prefab also skips
+    for prefab_id in prefab_cook_order {
+        for (target_prefab, prefab_ref) in sorted_prefab_refs(prefab_lookup[prefab_id]) {
+            let mut overrides: Vec<_> = prefab_ref.overrides.iter().collect();
+            overrides.sort_by_key(|(entity, _)| **entity);
+            for (entity, component_overrides) in overrides {
+                for component_override in component_overrides {
+                    let registration =
+                        &registered_components_by_uuid[&component_override.component_type];
+                    let has_component = cooked_prefab
+                        .entities
+                        .entity(entity)
+                        .map(|cooked_entity| {
+                            has_component(&cooked_prefab.world, cooked_entity, registration)
+                        })
+                        .unwrap_or(false);
+                    if has_component {
+                        continue;
+                    }
+                    errors.push(ValidationError {
+                        entity: *entity,
+                        component_type: component_override.component_type,
+                        component_type_name: registration.type_name(),
+                        message: format!(
+                            "override from prefab {} (ref of {}) changes a component the entity \
+                             doesn't have",
+                            uuid::Uuid::from_bytes(*prefab_id),
+                            uuid::Uuid::from_bytes(*target_prefab)
+                        ),
+                    });
+                }
+            }
+        }
+    }
+
+    // Components referring to these hold entities that aren't in the cooked world
+    for prefab_id in prefab_cook_order {
+        let mut missing_entities: Vec<_> = prefab_lookup[prefab_id]
+            .prefab_meta
+            .entity_refs
+            .keys()
+            .filter(|entity| !cooked_prefab.entities.contains_uuid(entity))
+            .collect();
+        missing_entities.sort();
+        for entity in missing_entities {
+            errors.push(ValidationError {
+                entity: *entity,
+                component_type: Default::default(),
+                component_type_name: "",
+                message: format!(
+                    "components of prefab {} refer to entity {}, which isn't in any cooked prefab",
+                    uuid::Uuid::from_bytes(*prefab_id),
+                    uuid::Uuid::from_bytes(*entity)
+                ),
+            });
+        }
+    }
+
+    errors.extend(validate_cooked_prefab(
+        &cooked_prefab,
+        registered_components_by_uuid,
+    ));
+    if errors.is_empty() {
+        Ok(cooked_prefab)
+    } else {
+        Err(errors)
+    }
+}
+
+// Copies a prefab's entities into the cooked world as the entities chosen for their UUIDs, and
+// points `Entity` fields of the copied components at the cooked entities. This includes references
+// to entities of other prefabs, which the prefab lists in `entity_refs`.
+struct CookCloneImpl<'a, S: BuildHasher> {
+    copy: CopyCloneImpl<'a, S>,
+    // The cooked entity for each entity of the prefab and each entity it refers to
+    cooked_entities: HashMap<Entity, Entity, EntityHasher>,
+}
+
+impl<'a, S: BuildHasher> CookCloneImpl<'a, S> {
+    fn new(
+        registered_components: &'a HashMap<ComponentTypeId, ComponentRegistration, S>,
+        prefab: &Prefab,
+        entity_lookup: &UuidEntityBimap,
+    ) -> Self {
+        // References to entities that aren't cooked are left as they are, and reported by
+        // cook_prefab_validated
+        let cooked_entities = prefab
+            .prefab_meta
+            .entities
+            .iter()
+            .chain(&prefab.prefab_meta.entity_refs)
+            .filter_map(|(entity_uuid, entity)| Some((*entity, entity_lookup.entity(entity_uuid)?)))
+            .collect();
+        CookCloneImpl {
+            copy: CopyCloneImpl::new(registered_components),
+            cooked_entities,
+        }
+    }
+}
+
+impl<'a, S: BuildHasher> Merger for CookCloneImpl<'a, S> {
+    fn prefers_new_archetype() -> bool {
+        false
+    }
+
+    fn entity_map(&mut self) -> EntityRewrite {
+        EntityRewrite::Replace(self.cooked_entities.clone())
+    }
+
+    fn assign_id(
+        &mut self,
+        existing: Entity,
+        allocator: &mut Allocate,
+    ) -> Entity {
+        self.cooked_entities
+            .get(&existing)
+            .cloned()
+            .unwrap_or_else(|| allocator.next().unwrap())
+    }
+
+    fn convert_layout(
+        &mut self,
+        source_layout: EntityLayout,
+    ) -> EntityLayout {
+        self.copy.convert_layout(source_layout)
+    }
+
+    fn merge_archetype(
+        &mut self,
+        src_entity_range: Range<usize>,
+        src_arch: &Archetype,
+        src_components: &Components,
+        dst: &mut ArchetypeWriter,
+    ) {
+        self.copy
+            .merge_archetype(src_entity_range, src_arch, src_components, dst)
+    }
+}
+
+// Override data grouped by component type and entity. Cooking a large prefab tree visits many
+// prefabs, so the maps and lists are cleared and reused rather than allocated for each prefab.
+#[derive(Default)]
+struct CookScratch<'a> {
+    overrides_by_type: HashMap<ComponentTypeUuid, HashMap<Entity, Vec<&'a str>>>,
+    // Emptied override lists, kept for their capacity
+    unused_override_lists: Vec<Vec<&'a str>>,
+}
+
+impl<'a> CookScratch<'a> {
+    fn clear(&mut self) {
+        for overrides in self.overrides_by_type.values_mut() {
+            for (_, mut override_list) in overrides.drain() {
+                override_list.clear();
+                self.unused_override_lists.push(override_list);
+            }
+        }
+    }
+
+    fn push_override(
+        &mut self,
+        component_type: ComponentTypeUuid,
+        entity: Entity,
+        data: &'a str,
+    ) {
+        let unused_override_lists = &mut self.unused_override_lists;
+        self.overrides_by_type
+            .entry(component_type)
+            .or_default()
+            .entry(entity)
+            .or_insert_with(|| unused_override_lists.pop().unwrap_or_default())
+            .push(data);
+    }
+}
+
+fn has_component(
+    world: &World,
+    entity: Entity,
+    registration: &ComponentRegistration,
+) -> bool {
+    world
+        .entry_ref(entity)
+        .map(|entry| {
+            entry
+                .archetype()
+                .layout()
+                .has_component_by_id(registration.component_type_id())
+        })
+        .unwrap_or(false)
+}
+
+// True if an entity's component has a value for a field other than the one the component type
+// defaults to, which is taken to mean the prefab authored the value. If the entity or component
+// can't be found, this returns false so that applying the parameter reports the problem.
+fn has_authored_value<S: BuildHasher>(
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+    world: &World,
+    entity_lookup: &UuidEntityBimap,
+    entity: &prefab_format::EntityUuid,
+    component_type: &ComponentTypeUuid,
+    field: &str,
+) -> bool {
+    let (registration, entity) = match (
+        registered_components_by_uuid.get(component_type),
+        entity_lookup.entity(entity),
+    ) {
+        (Some(registration), Some(entity)) => (registration, entity),
+        _ => return false,
+    };
+
+    let mut defaults = World::default();
+    let default_entity = defaults.push(());
+    registration.add_default_to_entity(&mut defaults, default_entity);
+
+    let mut ron_ser = ron::ser::Serializer::new(None, true);
+    let mut erased = erased_serde::Serializer::erase(&mut ron_ser);
+    let result = registration.diff_single(
+        &mut erased,
+        &defaults,
+        Some(default_entity),
+        world,
+        Some(entity),
+    );
+    match result.kind {
+        DiffSingleKind::Change => field_paths_from_ron(&ron_ser.into_output_string())
+            .map(|paths| paths.iter().any(|path| overlaps(path, field)))
+            .unwrap_or(true),
+        DiffSingleKind::NoChange | DiffSingleKind::Add | DiffSingleKind::Remove => false,
+    }
+}
+
+// cook_prefab_validated reports everything as a ValidationError
+fn parameter_validation_error<S: BuildHasher>(
+    error: ParameterError,
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+) -> ValidationError {
+    let (entity, component_type) = match &error {
+        ParameterError::MissingEntity { entity, .. } => (*entity, Default::default()),
+        ParameterError::UnregisteredComponent { component_type, .. } => {
+            (Default::default(), *component_type)
+        }
+        ParameterError::MissingComponent {
+            entity,
+            component_type,
+            ..
+        } => (*entity, *component_type),
+        ParameterError::UnknownParameter(_) | ParameterError::InvalidFieldPath { .. } => {
+            Default::default()
+        }
+    };
+    ValidationError {
+        entity,
+        component_type,
+        component_type_name: registered_components_by_uuid
+            .get(&component_type)
+            .map(|registration| registration.type_name())
+            .unwrap_or(""),
+        message: format!("failed to apply parameter: {:?}", error),
+    }
+}
+
+#[derive(Debug)]
+pub enum PrefabCookOrderError {
+    /// The lookup function didn't know about this prefab
+    MissingPrefab(PrefabUuid),
+    /// The prefab references itself, directly or through other prefabs
+    CyclicReference(PrefabUuid),
+}
+
+/// Returns every prefab that `root` depends on, each listed once and after all of its own
+/// dependencies, followed by `root` itself. This is the order prefabs should be loaded in and the
+/// `prefab_cook_order` expected by `cook_prefab`.
+///
+/// `prefab_refs` returns the prefabs directly referenced by a prefab, including the prefab it
+/// extends, or None if the prefab can't be found. `scan_prefab` can be used to find them without
+/// loading the prefab. A prefab that extends itself, directly or through other prefabs, is a
+/// cyclic reference.
+pub fn prefab_cook_order<F: FnMut(&PrefabUuid) -> Option<Vec<PrefabUuid>>>(
+    root: &PrefabUuid,
+    mut prefab_refs: F,
+) -> Result<Vec<PrefabUuid>, PrefabCookOrderError> {
+    let mut cook_order = vec![];
+    let mut visited = HashSet::new();
+    let mut in_progress = HashSet::new();
+    add_to_cook_order(
+        root,
+        &mut prefab_refs,
+        &mut visited,
+        &mut in_progress,
+        &mut cook_order,
+    )?;
+    Ok(cook_order)
+}
+
+/// The prefabs that `prefab_id` inherits from through `extends`, starting with `prefab_id` itself
+/// and ending with the prefab that doesn't extend another
+pub fn base_prefab_chain<U: BuildHasher>(
+    prefab_id: &PrefabUuid,
+    prefab_lookup: &HashMap<PrefabUuid, &Prefab, U>,
+) -> Result<Vec<PrefabUuid>, PrefabCookOrderError> {
+    let mut chain = vec![*prefab_id];
+    let mut current = *prefab_id;
+    loop {
+        let prefab = prefab_lookup
+            .get(&current)
+            .ok_or(PrefabCookOrderError::MissingPrefab(current))?;
+        match prefab.prefab_meta.extends {
+            Some(base_prefab) if chain.contains(&base_prefab) => {
+                return Err(PrefabCookOrderError::CyclicReference(base_prefab));
+            }
+            Some(base_prefab) => {
+                chain.push(base_prefab);
+                current = base_prefab;
+            }
+            None => return Ok(chain),
+        }
     }
 }
+
+// Depth-first, adding each prefab after everything it references
+fn add_to_cook_order<F: FnMut(&PrefabUuid) -> Option<Vec<PrefabUuid>>>(
+    prefab_id: &PrefabUuid,
+    prefab_refs: &mut F,
+    visited: &mut HashSet<PrefabUuid>,
+    in_progress: &mut HashSet<PrefabUuid>,
+    cook_order: &mut Vec<PrefabUuid>,
+) -> Result<(), PrefabCookOrderError> {
+    if visited.contains(prefab_id) {
+        return Ok(());
+    }
+    if !in_progress.insert(*prefab_id) {
+        return Err(PrefabCookOrderError::CyclicReference(*prefab_id));
+    }
+
+    let mut refs = prefab_refs(prefab_id).ok_or(PrefabCookOrderError::MissingPrefab(*prefab_id))?;
+    // Sorted so the order doesn't depend on hash map iteration
+    refs.sort();
+    refs.dedup();
+    for referenced_id in &refs {
+        add_to_cook_order(referenced_id, prefab_refs, visited, in_progress, cook_order)?;
+    }
+
+    in_progress.remove(prefab_id);
+    visited.insert(*prefab_id);
+    cook_order.push(*prefab_id);
+    Ok(())
+}
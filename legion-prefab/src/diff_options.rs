@@ -0,0 +1,107 @@
+use crate::prefab_diff::DiffCommand;
+use serde::de::DeserializeSeed;
+use serde_diff::SerdeDiff;
+
+/// Options for `ComponentRegistration::diff_single_with_options`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DiffOptions {
+    /// If set, floats that differ by at most this much count as unchanged. This avoids spurious
+    /// overrides and transactions from tiny floating point drift, i.e. from gizmo math. The
+    /// tolerance applies to each field, so a component with a real change only has that change in
+    /// its diff. If the shape of the component changed, i.e. a `Vec` got longer, it is diffed
+    /// exactly.
+    pub float_epsilon: Option<f64>,
+    /// Fills in `bytes_written` and `changed_fields` of the `DiffSingleResult`. Measuring walks
+    /// the diff again, so it's off unless a caller needs the sizes.
+    pub measure: bool,
+}
+
+impl DiffOptions {
+    pub fn with_float_epsilon(float_epsilon: f64) -> Self {
+        DiffOptions {
+            float_epsilon: Some(float_epsilon),
+            ..Default::default()
+        }
+    }
+}
+
+/// Resets the floats of `dst` that differ from `src` by at most `epsilon` to their value in `src`,
+/// so that diffing `src` against `dst` leaves them out
+pub(crate) fn reset_float_drift<T: SerdeDiff>(
+    src: &T,
+    dst: &mut T,
+    epsilon: f64,
+) {
+    let (forward, backward) = match (diff_commands(src, dst), diff_commands(dst, src)) {
+        (Some(forward), Some(backward)) => (forward, backward),
+        _ => return,
+    };
+
+    // Both diffs visit the changed values in the same order, so the commands pair up unless a
+    // collection changed size or a map changed keys
+    if forward.len() != backward.len() {
+        return;
+    }
+
+    // The backward diff restricted to the drifted values. Applying it to dst sets them back.
+    let mut reset = vec![];
+    let mut entered = vec![];
+    for (new, old) in forward.into_iter().zip(backward) {
+        match (new, old) {
+            (DiffCommand::Value(new), DiffCommand::Value(old)) => {
+                if is_drift(&new, &old, epsilon) {
+                    reset.push(DiffCommand::Value(old));
+                }
+            }
+            (DiffCommand::Enter(_), old @ DiffCommand::Enter(_))
+            | (DiffCommand::EnterKey(_), old @ DiffCommand::EnterKey(_)) => {
+                entered.push(reset.len());
+                reset.push(old);
+            }
+            (DiffCommand::Exit, DiffCommand::Exit) => {
+                // Leave out paths that don't lead to a drifted value
+                let start = entered.pop().unwrap_or(0);
+                if reset.len() == start + 1 {
+                    reset.truncate(start);
+                } else {
+                    reset.push(DiffCommand::Exit);
+                }
+            }
+            _ => return,
+        }
+    }
+
+    if reset.is_empty() {
+        return;
+    }
+
+    // If the reset can't be applied, dst is diffed exactly
+    if let Ok(reset) = ron::ser::to_string(&reset) {
+        if let Ok(mut deserializer) = ron::de::Deserializer::from_str(&reset) {
+            let _ = serde_diff::Apply::deserializable(dst).deserialize(&mut deserializer);
+        }
+    }
+}
+
+fn diff_commands<T: SerdeDiff>(
+    old: &T,
+    new: &T,
+) -> Option<Vec<DiffCommand>> {
+    let diff = ron::ser::to_string(&serde_diff::Diff::serializable(old, new)).ok()?;
+    ron::de::from_str(&diff).ok()
+}
+
+// RON numbers don't say whether they're floats, but integers never drift by a fraction
+fn is_drift(
+    new: &ron::Value,
+    old: &ron::Value,
+    epsilon: f64,
+) -> bool {
+    match (new, old) {
+        (ron::Value::Number(new), ron::Value::Number(old)) => {
+            let (new, old) = (new.get(), old.get());
+            (new.fract() != 0.0 || old.fract() != 0.0) && (new - old).abs() <= epsilon
+        }
+        _ => false,
+    }
+}
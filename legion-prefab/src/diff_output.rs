@@ -0,0 +1,151 @@
+use serde::ser::{self, Impossible, Serialize, SerializeSeq, Serializer};
+
+/// Passes a `serde_diff::Diff` on to another serializer, but only starts the diff's sequence when
+/// the first command is written. serde_diff writes commands only for changes, so a diff without
+/// changes writes nothing and the diff is walked once either way.
+pub(crate) struct ChangedDiffSerializer<S: Serializer> {
+    inner: Option<S>,
+    len: Option<usize>,
+    seq: Option<S::SerializeSeq>,
+}
+
+impl<S: Serializer> ChangedDiffSerializer<S> {
+    pub fn new(inner: S) -> Self {
+        ChangedDiffSerializer {
+            inner: Some(inner),
+            len: None,
+            seq: None,
+        }
+    }
+}
+
+fn not_a_diff<E: ser::Error>() -> E {
+    E::custom("expected a serde_diff::Diff, which serializes as a sequence")
+}
+
+macro_rules! not_a_diff {
+    ($($method:ident($($arg:ident: $ty:ty),*) -> $ok:ty),*) => {
+        $(
+            fn $method(
+                self,
+                $($arg: $ty),*
+            ) -> Result<$ok, S::Error> {
+                Err(not_a_diff())
+            }
+        )*
+    };
+}
+
+impl<'a, S: Serializer> Serializer for &'a mut ChangedDiffSerializer<S> {
+    type Ok = ();
+    type Error = S::Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Impossible<(), S::Error>;
+    type SerializeTupleStruct = Impossible<(), S::Error>;
+    type SerializeTupleVariant = Impossible<(), S::Error>;
+    type SerializeMap = Impossible<(), S::Error>;
+    type SerializeStruct = Impossible<(), S::Error>;
+    type SerializeStructVariant = Impossible<(), S::Error>;
+
+    fn serialize_seq(
+        self,
+        len: Option<usize>,
+    ) -> Result<Self, S::Error> {
+        // Passed on when the sequence starts, formats like bincode need it up front
+        self.len = len;
+        Ok(self)
+    }
+
+    not_a_diff!(
+        serialize_bool(_v: bool) -> (),
+        serialize_i8(_v: i8) -> (),
+        serialize_i16(_v: i16) -> (),
+        serialize_i32(_v: i32) -> (),
+        serialize_i64(_v: i64) -> (),
+        serialize_u8(_v: u8) -> (),
+        serialize_u16(_v: u16) -> (),
+        serialize_u32(_v: u32) -> (),
+        serialize_u64(_v: u64) -> (),
+        serialize_f32(_v: f32) -> (),
+        serialize_f64(_v: f64) -> (),
+        serialize_char(_v: char) -> (),
+        serialize_str(_v: &str) -> (),
+        serialize_bytes(_v: &[u8]) -> (),
+        serialize_none() -> (),
+        serialize_unit() -> (),
+        serialize_unit_struct(_name: &'static str) -> (),
+        serialize_unit_variant(_name: &'static str, _index: u32, _variant: &'static str) -> (),
+        serialize_tuple(_len: usize) -> Self::SerializeTuple,
+        serialize_tuple_struct(_name: &'static str, _len: usize) -> Self::SerializeTupleStruct,
+        serialize_tuple_variant(
+            _name: &'static str,
+            _index: u32,
+            _variant: &'static str,
+            _len: usize
+        ) -> Self::SerializeTupleVariant,
+        serialize_map(_len: Option<usize>) -> Self::SerializeMap,
+        serialize_struct(_name: &'static str, _len: usize) -> Self::SerializeStruct,
+        serialize_struct_variant(
+            _name: &'static str,
+            _index: u32,
+            _variant: &'static str,
+            _len: usize
+        ) -> Self::SerializeStructVariant
+    );
+
+    fn serialize_some<T: ?Sized + Serialize>(
+        self,
+        _value: &T,
+    ) -> Result<(), S::Error> {
+        Err(not_a_diff())
+    }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _value: &T,
+    ) -> Result<(), S::Error> {
+        Err(not_a_diff())
+    }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<(), S::Error> {
+        Err(not_a_diff())
+    }
+}
+
+// Each element of the diff is a command
+impl<'a, S: Serializer> SerializeSeq for &'a mut ChangedDiffSerializer<S> {
+    type Ok = ();
+    type Error = S::Error;
+    fn serialize_element<T: ?Sized + Serialize>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        if self.seq.is_none() {
+            let inner = self
+                .inner
+                .take()
+                .expect("diff sequence was already finished");
+            self.seq = Some(inner.serialize_seq(self.len)?);
+        }
+        self.seq.as_mut().unwrap().serialize_element(value)
+    }
+    fn end(self) -> Result<(), S::Error> {
+        match self.seq.take() {
+            Some(seq) => seq.end().map(|_| ()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Walks `value` with bincode writing to nowhere, for serialization that only matters for its side
+/// effects, like the entities an `EntityUuidMapper` sees
+pub(crate) fn serialize_discarded<T: ?Sized + Serialize>(value: &T) -> bincode::Result<()> {
+    let mut serializer =
+        bincode::Serializer::new(std::io::sink(), bincode::config::DefaultOptions::new());
+    value.serialize(&mut serializer)
+}
@@ -0,0 +1,129 @@
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid};
+use crate::{
+    cook_prefab, ComponentRegistration, CookedPrefab, CopyCloneImpl, ParameterError, Prefab,
+    UuidEntityBimap,
+};
+use legion::storage::ComponentTypeId;
+use legion::World;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::BuildHasher;
+
+// Entity layers split the entities of one prefab file into named groups (i.e. gameplay, lighting,
+// navigation) that can be cooked into separate worlds, or left out when cooking. They are
+// unrelated to `OverrideLayer`, which applies override files on top of prefabs.
+
+/// The entity layer of every entity declared by the given prefabs. Entities in the default layer
+/// aren't included.
+pub fn entity_layers<'a, U: BuildHasher>(
+    prefab_lookup: &HashMap<PrefabUuid, &'a Prefab, U>
+) -> HashMap<EntityUuid, &'a str> {
+    let mut layers = HashMap::new();
+    for prefab in prefab_lookup.values() {
+        for (entity_uuid, layer) in &prefab.prefab_meta.entity_layers {
+            layers.insert(*entity_uuid, layer.as_str());
+        }
+    }
+    layers
+}
+
+/// Cooks a prefab like `cook_prefab`, keeping only the entities of the layers `include_layer`
+/// accepts. `include_layer` is given `None` for the default layer.
+pub fn cook_prefab_selected_layers<S, T, U, F>(
+    registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, T>,
+    prefab_cook_order: &[PrefabUuid],
+    prefab_lookup: &HashMap<PrefabUuid, &Prefab, U>,
+    include_layer: F,
+) -> Result<CookedPrefab, ParameterError>
+where
+    S: BuildHasher,
+    T: BuildHasher,
+    U: BuildHasher,
+    F: Fn(Option<&str>) -> bool,
+{
+    let mut cooked = cook_prefab(
+        registered_components,
+        registered_components_by_uuid,
+        prefab_cook_order,
+        prefab_lookup,
+    )?;
+    retain_layers(&mut cooked, &entity_layers(prefab_lookup), include_layer);
+    Ok(cooked)
+}
+
+/// Cooks a prefab into a separate world for each of its entity layers, keyed by layer name (`None`
+/// for the default layer). Every layer keeps the prefab's resources.
+pub fn cook_prefab_per_layer<S: BuildHasher, T: BuildHasher, U: BuildHasher>(
+    registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, T>,
+    prefab_cook_order: &[PrefabUuid],
+    prefab_lookup: &HashMap<PrefabUuid, &Prefab, U>,
+) -> Result<BTreeMap<Option<String>, CookedPrefab>, ParameterError> {
+    // Overrides may apply to entities in any layer, so the whole prefab is cooked once and then
+    // split
+    let cooked = cook_prefab(
+        registered_components,
+        registered_components_by_uuid,
+        prefab_cook_order,
+        prefab_lookup,
+    )?;
+    let layers = entity_layers(prefab_lookup);
+    let layer_names: BTreeSet<Option<&str>> = cooked
+        .entities
+        .uuids()
+        .map(|entity_uuid| layers.get(entity_uuid).cloned())
+        .collect();
+
+    let mut cooked_layers = BTreeMap::new();
+    for layer_name in layer_names {
+        let mut clone_merge_impl = CopyCloneImpl::new(registered_components);
+        let mut world = World::default();
+        let result_mappings =
+            world.clone_from(&cooked.world, &legion::query::any(), &mut clone_merge_impl);
+        let entities: UuidEntityBimap = cooked
+            .entities
+            .iter()
+            .map(|(entity_uuid, entity)| (*entity_uuid, result_mappings[entity]))
+            .collect();
+
+        let mut cooked_layer = CookedPrefab {
+            world,
+            entities,
+            parameters: cooked.parameters.clone(),
+            resources: cooked.resources.clone(),
+            blobs: cooked.blobs.clone(),
+        };
+        retain_layers(&mut cooked_layer, &layers, |layer| layer == layer_name);
+        cooked_layers.insert(layer_name.map(|name| name.to_string()), cooked_layer);
+    }
+    Ok(cooked_layers)
+}
+
+// Removes the entities of excluded layers, along with parameter bindings to them
+fn retain_layers<F: Fn(Option<&str>) -> bool>(
+    cooked: &mut CookedPrefab,
+    layers: &HashMap<EntityUuid, &str>,
+    include_layer: F,
+) {
+    let excluded: Vec<EntityUuid> = cooked
+        .entities
+        .uuids()
+        .filter(|entity_uuid| !include_layer(layers.get(entity_uuid).cloned()))
+        .cloned()
+        .collect();
+
+    for entity_uuid in &excluded {
+        if let Some(entity) = cooked.entities.remove_uuid(entity_uuid) {
+            cooked.world.remove(entity);
+        }
+    }
+
+    for parameter in &mut cooked.parameters {
+        parameter
+            .bindings
+            .retain(|binding| !excluded.contains(&binding.entity));
+    }
+    cooked
+        .parameters
+        .retain(|parameter| !parameter.bindings.is_empty());
+}
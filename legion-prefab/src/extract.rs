@@ -0,0 +1,182 @@
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabRefTransform};
+use crate::world_serde::EntityUuidMapper;
+use crate::{
+    clone_entities, CloneWorldError, ComponentRegistration, Prefab, PrefabMeta, PrefabRef,
+    UnregisteredComponentPolicy, UuidEntityBimap,
+};
+use legion::storage::ComponentTypeId;
+use legion::world::{Allocate, World};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
+
+#[derive(Debug)]
+pub enum ExtractPrefabError {
+    /// The entity isn't stored in the prefab. Entities of referenced prefabs can't be extracted.
+    EntityNotInPrefab(EntityUuid),
+    /// A component of a selected entity refers to an entity that isn't selected, which the new
+    /// prefab would have no way to refer to
+    ReferencesOutsideSelection {
+        entity: EntityUuid,
+        component_type: ComponentTypeUuid,
+    },
+    Clone(CloneWorldError),
+}
+
+/// Moves the `selection` entities of `prefab` into a new prefab and references the new prefab
+/// from `prefab` in their place, i.e. to turn part of a level into a reusable prefab. Nothing is
+/// changed if an error is returned.
+///
+/// The entities keep their UUIDs, so parameter bindings, layers, hierarchy entries and components
+/// of `prefab` that refer to them still apply through the prefab ref. Layers and hierarchy
+/// entries that only involve selected entities are moved to the new prefab. The new prefab has
+/// the entities' current values, so the prefab ref starts out without overrides. Blobs and
+/// resources stay in `prefab`.
+pub fn extract_prefab<S: BuildHasher>(
+    prefab: &mut Prefab,
+    selection: &HashSet<EntityUuid>,
+    registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+) -> Result<Prefab, ExtractPrefabError> {
+    // Sorted so the new prefab's world is in the same order every time
+    let mut selected_uuids: Vec<_> = selection.iter().cloned().collect();
+    selected_uuids.sort();
+
+    let mut selected = UuidEntityBimap::with_capacity(selected_uuids.len());
+    for entity_uuid in &selected_uuids {
+        let entity = *prefab
+            .prefab_meta
+            .entities
+            .get(entity_uuid)
+            .ok_or(ExtractPrefabError::EntityNotInPrefab(*entity_uuid))?;
+        selected.insert(*entity_uuid, entity);
+    }
+
+    check_references(&prefab.world, &selected, registered_components)?;
+
+    let src_entities: Vec<_> = selected_uuids
+        .iter()
+        .map(|entity_uuid| selected.entity(entity_uuid).unwrap())
+        .collect();
+    let mut world = World::default();
+    let result_mappings = clone_entities(
+        &prefab.world,
+        &src_entities,
+        &mut world,
+        registered_components,
+        UnregisteredComponentPolicy::Error,
+    )
+    .map_err(ExtractPrefabError::Clone)?;
+
+    //
+    // Nothing can fail from here on
+    //
+    let id = *uuid::Uuid::new_v4().as_bytes();
+    let mut prefab_meta = PrefabMeta {
+        id,
+        prefab_refs: HashMap::new(),
+        parameters: vec![],
+        entity_layers: HashMap::new(),
+        hierarchy: HashMap::new(),
+        blobs: HashMap::new(),
+        extends: None,
+        entities: HashMap::new(),
+        entity_refs: HashMap::new(),
+    };
+
+    for (entity_uuid, src_entity) in selected.iter() {
+        prefab_meta
+            .entities
+            .insert(*entity_uuid, result_mappings[src_entity]);
+        prefab.prefab_meta.entities.remove(entity_uuid);
+        prefab.world.remove(*src_entity);
+        // Components left in `prefab` may still refer to the entity, now through the prefab ref
+        prefab
+            .prefab_meta
+            .entity_refs
+            .insert(*entity_uuid, *src_entity);
+
+        if let Some(layer) = prefab.prefab_meta.entity_layers.remove(entity_uuid) {
+            prefab_meta.entity_layers.insert(*entity_uuid, layer);
+        }
+    }
+
+    let moved_parents: Vec<_> = prefab
+        .prefab_meta
+        .hierarchy
+        .iter()
+        .filter(|(parent, children)| {
+            selection.contains(*parent) && children.iter().all(|child| selection.contains(child))
+        })
+        .map(|(parent, _)| *parent)
+        .collect();
+    for parent in moved_parents {
+        let children = prefab.prefab_meta.hierarchy.remove(&parent).unwrap();
+        prefab_meta.hierarchy.insert(parent, children);
+    }
+
+    prefab.prefab_meta.prefab_refs.insert(
+        id,
+        PrefabRef {
+            overrides: HashMap::new(),
+            parameter_values: Default::default(),
+            transform: PrefabRefTransform::IDENTITY,
+        },
+    );
+
+    Ok(Prefab {
+        world,
+        prefab_meta,
+        resources: Default::default(),
+    })
+}
+
+// Serializes every component of the selected entities with only the selected entities known to
+// the entity serializer. Referring to any other entity makes the serialization fail.
+fn check_references<S: BuildHasher>(
+    world: &World,
+    selected: &UuidEntityBimap,
+    registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+) -> Result<(), ExtractPrefabError> {
+    let mut entity_map = selected.clone();
+    let mut allocator = Allocate::new();
+    let mapper = EntityUuidMapper {
+        entity_map: RefCell::new(&mut entity_map),
+        allocator: RefCell::new(&mut allocator),
+    };
+
+    let mut selected_entities: Vec<_> = selected.iter().collect();
+    selected_entities.sort_by_key(|(entity_uuid, _)| **entity_uuid);
+
+    for (entity_uuid, entity) in selected_entities {
+        let component_types = world
+            .entry_ref(*entity)
+            .expect("prefab entity not in world")
+            .archetype()
+            .layout()
+            .component_types()
+            .to_vec();
+
+        for component_type in &component_types {
+            // Unregistered components are reported when cloning
+            let registration = match registered_components.get(component_type) {
+                Some(registration) if !registration.is_clone_only() => registration,
+                _ => continue,
+            };
+            let mut result = Ok(());
+            mapper.scope(|| {
+                registration.serialize_single(world, *entity, &mut |component| {
+                    result = crate::diff_output::serialize_discarded(component);
+                })
+            });
+
+            if result.is_err() {
+                return Err(ExtractPrefabError::ReferencesOutsideSelection {
+                    entity: *entity_uuid,
+                    component_type: *registration.uuid(),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid};
+use crate::{
+    cook_prefab, cooked_hierarchy, prefab_cook_order, ComponentRegistration, HierarchyError,
+    ParameterError, Prefab, PrefabCookOrderError, PrefabMeta,
+};
+use legion::storage::ComponentTypeId;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+#[derive(Debug)]
+pub enum FlattenPrefabError {
+    CookOrder(PrefabCookOrderError),
+    Hierarchy(HierarchyError),
+    Parameter(ParameterError),
+}
+
+impl From<PrefabCookOrderError> for FlattenPrefabError {
+    fn from(error: PrefabCookOrderError) -> Self {
+        FlattenPrefabError::CookOrder(error)
+    }
+}
+
+impl From<HierarchyError> for FlattenPrefabError {
+    fn from(error: HierarchyError) -> Self {
+        FlattenPrefabError::Hierarchy(error)
+    }
+}
+
+impl From<ParameterError> for FlattenPrefabError {
+    fn from(error: ParameterError) -> Self {
+        FlattenPrefabError::Parameter(error)
+    }
+}
+
+/// Resolves every prefab ref of `root` (and of the prefabs it references) into a single prefab
+/// that doesn't reference anything, i.e. for handing an asset to a team that can't load the
+/// prefabs it depends on.
+///
+/// The result gets a new prefab ID, and entity UUIDs are derived from it with
+/// `flattened_entity_uuid`, so it can be used alongside the prefabs it was made from. It keeps the
+/// parameters of `root`, with the values set by prefab refs already applied. Layers, hierarchy,
+/// resources and blobs of all the prefabs are combined like when cooking.
+pub fn flatten_prefab<S, T, U>(
+    registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, T>,
+    root: &PrefabUuid,
+    prefab_lookup: &HashMap<PrefabUuid, &Prefab, U>,
+) -> Result<Prefab, FlattenPrefabError>
+where
+    S: BuildHasher,
+    T: BuildHasher,
+    U: BuildHasher,
+{
+    let prefab_cook_order = prefab_cook_order(root, |prefab_id| {
+        prefab_lookup
+            .get(prefab_id)
+            .map(|prefab| prefab.prefab_meta.prefab_refs.keys().cloned().collect())
+    })?;
+
+    let cooked_prefab = cook_prefab(
+        registered_components,
+        registered_components_by_uuid,
+        &prefab_cook_order,
+        prefab_lookup,
+    )?;
+    let hierarchy = cooked_hierarchy(&cooked_prefab, &prefab_cook_order, prefab_lookup)?;
+
+    let id = *uuid::Uuid::new_v4().as_bytes();
+    let flattened = |entity: &EntityUuid| flattened_entity_uuid(&id, entity);
+
+    let entities = cooked_prefab
+        .entities
+        .iter()
+        .map(|(entity_uuid, entity)| (flattened(entity_uuid), *entity))
+        .collect();
+
+    let mut parameters = cooked_prefab.parameters;
+    for parameter in &mut parameters {
+        for binding in &mut parameter.bindings {
+            binding.entity = flattened(&binding.entity);
+        }
+    }
+
+    // Later prefabs in the cook order move entities of the prefabs they reference to other layers
+    let mut entity_layers = HashMap::new();
+    for prefab_id in &prefab_cook_order {
+        for (entity, layer) in &prefab_lookup[prefab_id].prefab_meta.entity_layers {
+            entity_layers.insert(flattened(entity), layer.clone());
+        }
+    }
+
+    let hierarchy = hierarchy
+        .iter()
+        .map(|(parent, children)| (flattened(parent), children.iter().map(flattened).collect()))
+        .collect();
+
+    Ok(Prefab {
+        world: cooked_prefab.world,
+        prefab_meta: PrefabMeta {
+            id,
+            prefab_refs: HashMap::new(),
+            parameters,
+            entity_layers,
+            hierarchy,
+            blobs: cooked_prefab.blobs,
+            extends: None,
+            entities,
+            entity_refs: HashMap::new(),
+        },
+        resources: cooked_prefab.resources,
+    })
+}
+
+/// The UUID an entity is given in the prefab flattened into `flattened_prefab`
+pub fn flattened_entity_uuid(
+    flattened_prefab: &PrefabUuid,
+    entity: &EntityUuid,
+) -> EntityUuid {
+    let mut flattened_entity = *entity;
+    for (byte, prefab_byte) in flattened_entity.iter_mut().zip(flattened_prefab.iter()) {
+        *byte ^= prefab_byte;
+    }
+    flattened_entity
+}
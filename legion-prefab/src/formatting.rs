@@ -0,0 +1,193 @@
+use crate::{Prefab, PrefabFormatDeserializer, PrefabFormatSerializer, PrefabSerdeContext};
+use prefab_format::ron_patch::{RonPatchError, RonPrefabDocument};
+use std::collections::HashSet;
+use std::hash::BuildHasher;
+
+#[derive(Debug)]
+pub enum FormatPrefabError {
+    Deserialize(ron::de::Error),
+    Serialize(ron::ser::Error),
+}
+
+/// The layout used when writing canonical prefab files. Line endings are always "\n" so that
+/// files formatted on different platforms are identical.
+pub fn canonical_pretty_config() -> ron::ser::PrettyConfig {
+    ron::ser::PrettyConfig {
+        depth_limit: !0,
+        new_line: "\n".to_string(),
+        indentor: "    ".to_string(),
+        separate_tuple_members: false,
+        enumerate_arrays: false,
+    }
+}
+
+/// Rewrites a RON prefab source file into a canonical layout: fixed indentation and line endings,
+/// objects/components/overrides sorted by UUID, and UUIDs written in lowercase hyphenated form.
+///
+/// The prefab is round-tripped through the registered component types, so every component in
+/// the file must be registered in `context`. Comments are not preserved.
+pub fn format_prefab<T: BuildHasher>(
+    source: &str,
+    context: PrefabSerdeContext<T>,
+) -> Result<String, FormatPrefabError> {
+    let prefab = load_ron_prefab(source, context)?;
+    to_canonical_string(&prefab, context)
+}
+
+/// Saves a prefab over its original RON source text, editing only the parts of the text that
+/// changed. Comments and layout of unchanged entities, components and overrides are kept.
+///
+/// Changed component data and override diffs are replaced in place, and added/removed entities
+/// and components are inserted/removed. If a change can't be expressed as an edit of the
+/// original text (or the original text can't be scanned), this falls back to writing the whole
+/// prefab in the canonical layout, like `format_prefab`. That is always the case for prefabs with
+/// entity templates, which are written with the templates expanded into their entities.
+pub fn save_prefab_preserving_format<T: BuildHasher>(
+    original: &str,
+    prefab: &Prefab,
+    context: PrefabSerdeContext<T>,
+) -> Result<String, FormatPrefabError> {
+    // Changes are found by comparing canonical text of the original and new prefab so that
+    // differences in layout alone don't count as a change
+    let original_prefab = load_ron_prefab(original, context)?;
+    let old_text = to_canonical_string(&original_prefab, context)?;
+    let new_text = to_canonical_string(prefab, context)?;
+    let old_doc = RonPrefabDocument::parse(&old_text).expect("canonical prefab text should parse");
+    let new_doc = RonPrefabDocument::parse(&new_text).expect("canonical prefab text should parse");
+
+    if let Ok(mut doc) = RonPrefabDocument::parse(original) {
+        if patch_document(&mut doc, &old_doc, &new_doc).is_ok() {
+            return Ok(doc.finish());
+        }
+    }
+
+    Ok(new_text)
+}
+
+pub(crate) fn load_ron_prefab<T: BuildHasher>(
+    source: &str,
+    context: PrefabSerdeContext<T>,
+) -> Result<Prefab, FormatPrefabError> {
+    let mut de = ron::de::Deserializer::from_str(source).map_err(FormatPrefabError::Deserialize)?;
+    let prefab_deser = PrefabFormatDeserializer::new(context);
+    prefab_format::deserialize(&mut de, &prefab_deser).map_err(FormatPrefabError::Deserialize)?;
+    de.end().map_err(FormatPrefabError::Deserialize)?;
+    Ok(prefab_deser.prefab())
+}
+
+fn to_canonical_string<T: BuildHasher>(
+    prefab: &Prefab,
+    context: PrefabSerdeContext<T>,
+) -> Result<String, FormatPrefabError> {
+    let mut ron_ser = ron::ser::Serializer::new(Some(canonical_pretty_config()), true);
+    let prefab_ser = PrefabFormatSerializer::new(context, prefab);
+    prefab_format::serialize(&mut ron_ser, &prefab_ser, prefab.prefab_id())
+        .map_err(FormatPrefabError::Serialize)?;
+
+    let mut output = ron_ser.into_output_string();
+    output.push('\n');
+    Ok(output)
+}
+
+// Applies the differences between old and new (both canonical text) to doc (the original text)
+fn patch_document(
+    doc: &mut RonPrefabDocument,
+    old: &RonPrefabDocument,
+    new: &RonPrefabDocument,
+) -> Result<(), RonPatchError> {
+    if old.prefab_id() != new.prefab_id()
+        || old.base_prefab() != new.base_prefab()
+        || old.type_map_text() != new.type_map_text()
+        || old.parameters_text() != new.parameters_text()
+        || old.layers_text() != new.layers_text()
+        || old.hierarchy_text() != new.hierarchy_text()
+        || old.blobs_text() != new.blobs_text()
+        // Templates are expanded when the prefab is loaded, so canonical text never has any. The
+        // original text's templates can't be kept
+        || doc.templates_text() != new.templates_text()
+    {
+        return Err(RonPatchError::Unsupported);
+    }
+
+    // Entities
+    let old_entities: HashSet<_> = old.entities().into_iter().collect();
+    let new_entities: HashSet<_> = new.entities().into_iter().collect();
+    for entity in old.entities() {
+        if !new_entities.contains(&entity) {
+            doc.remove_entity(&entity)?;
+        }
+    }
+
+    for entity in new.entities() {
+        if !old_entities.contains(&entity) {
+            doc.insert_object(&new.entity_text(&entity).unwrap())?;
+            continue;
+        }
+
+        let new_types: HashSet<_> = new.component_types(&entity).into_iter().collect();
+        for component_type in old.component_types(&entity) {
+            if !new_types.contains(&component_type) {
+                doc.remove_component(&entity, &component_type)?;
+            }
+        }
+
+        for component_type in new.component_types(&entity) {
+            let new_data = new.component_data_text(&entity, &component_type).unwrap();
+            match old.component_data_text(&entity, &component_type) {
+                Some(old_data) if old_data == new_data => {}
+                Some(_) => doc.replace_component_data(&entity, &component_type, &new_data)?,
+                None => doc.insert_component(&entity, &component_type, &new_data)?,
+            }
+        }
+    }
+
+    // Prefab refs, including the base prefab. Only changes within existing entity overrides are
+    // patched
+    let old_refs: HashSet<_> = old.prefab_refs().into_iter().collect();
+    let new_refs: HashSet<_> = new.prefab_refs().into_iter().collect();
+    if old_refs != new_refs {
+        return Err(RonPatchError::Unsupported);
+    }
+
+    for prefab_ref in new.base_prefab().into_iter().chain(new.prefab_refs()) {
+        if old.parameter_values_text(&prefab_ref) != new.parameter_values_text(&prefab_ref)
+            || old.prefab_ref_transform_text(&prefab_ref)
+                != new.prefab_ref_transform_text(&prefab_ref)
+        {
+            return Err(RonPatchError::Unsupported);
+        }
+
+        let old_overrides = old.prefab_ref_overrides(&prefab_ref);
+        let new_overrides = new.prefab_ref_overrides(&prefab_ref);
+        let old_override_entities: HashSet<_> = old_overrides.iter().map(|(e, _)| *e).collect();
+        let new_override_entities: HashSet<_> = new_overrides.iter().map(|(e, _)| *e).collect();
+        if old_override_entities != new_override_entities {
+            return Err(RonPatchError::Unsupported);
+        }
+
+        for (entity, new_types) in &new_overrides {
+            let new_type_set: HashSet<_> = new_types.iter().cloned().collect();
+            let old_types = &old_overrides.iter().find(|(e, _)| e == entity).unwrap().1;
+            for component_type in old_types {
+                if !new_type_set.contains(component_type) {
+                    doc.remove_override(&prefab_ref, entity, component_type)?;
+                }
+            }
+
+            for component_type in new_types {
+                let new_diff = new
+                    .override_diff_text(&prefab_ref, entity, component_type)
+                    .unwrap();
+                match old.override_diff_text(&prefab_ref, entity, component_type) {
+                    Some(old_diff) if old_diff == new_diff => {}
+                    Some(_) => {
+                        doc.replace_override_diff(&prefab_ref, entity, component_type, &new_diff)?
+                    }
+                    None => doc.insert_override(&prefab_ref, entity, component_type, &new_diff)?,
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid};
+use crate::cooking::cook_prefab;
+use crate::{ComponentRegistration, CookedPrefab, ParameterError, Prefab};
+use legion::storage::ComponentTypeId;
+use legion::world::{Entity, World};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::BuildHasher;
+
+#[derive(Debug)]
+pub enum HierarchyError {
+    /// The prefab lists children for an entity that isn't in the cooked prefab
+    MissingParent {
+        prefab: PrefabUuid,
+        parent: EntityUuid,
+    },
+    /// The prefab lists a child that isn't in the cooked prefab
+    MissingChild {
+        prefab: PrefabUuid,
+        child: EntityUuid,
+    },
+    /// The prefab lists the entity as a child of more than one entity, or more than once
+    MultipleParents {
+        prefab: PrefabUuid,
+        child: EntityUuid,
+    },
+    /// The entity is its own ancestor
+    Cycle { entity: EntityUuid },
+    /// Cooking failed to apply a parameter, see `cook_prefab`
+    Parameter(ParameterError),
+}
+
+/// Combines the hierarchies of the cooked prefabs into the children of each entity, in order.
+///
+/// Hierarchies are applied in `prefab_cook_order`, so a prefab can move an entity of a prefab it
+/// references under a different parent. Within a prefab, an entity may only be listed as a child
+/// once.
+pub fn cooked_hierarchy<S: BuildHasher>(
+    cooked_prefab: &CookedPrefab,
+    prefab_cook_order: &[PrefabUuid],
+    prefab_lookup: &HashMap<PrefabUuid, &Prefab, S>,
+) -> Result<BTreeMap<EntityUuid, Vec<EntityUuid>>, HierarchyError> {
+    let mut children_of: BTreeMap<EntityUuid, Vec<EntityUuid>> = BTreeMap::new();
+    let mut parent_of: HashMap<EntityUuid, EntityUuid> = HashMap::new();
+
+    for prefab_id in prefab_cook_order {
+        let prefab = prefab_lookup[prefab_id];
+
+        let mut hierarchy: Vec<_> = prefab.prefab_meta.hierarchy.iter().collect();
+        hierarchy.sort_by_key(|(parent, _)| **parent);
+
+        let mut seen_children = HashSet::new();
+        for (parent, children) in hierarchy {
+            if !cooked_prefab.entities.contains_uuid(parent) {
+                return Err(HierarchyError::MissingParent {
+                    prefab: *prefab_id,
+                    parent: *parent,
+                });
+            }
+
+            for child in children {
+                if !cooked_prefab.entities.contains_uuid(child) {
+                    return Err(HierarchyError::MissingChild {
+                        prefab: *prefab_id,
+                        child: *child,
+                    });
+                }
+
+                if !seen_children.insert(*child) {
+                    return Err(HierarchyError::MultipleParents {
+                        prefab: *prefab_id,
+                        child: *child,
+                    });
+                }
+
+                // Detach the child from the parent a referenced prefab gave it
+                if let Some(old_parent) = parent_of.insert(*child, *parent) {
+                    if let Some(siblings) = children_of.get_mut(&old_parent) {
+                        siblings.retain(|sibling| sibling != child);
+                    }
+                }
+                children_of.entry(*parent).or_default().push(*child);
+            }
+        }
+    }
+
+    // Walk up from every entity. Reaching the starting entity again means there is a cycle. A
+    // walk that is longer than the number of entities has entered a cycle that doesn't include
+    // the starting entity, which is found when starting from an entity in the cycle.
+    let mut entities: Vec<_> = parent_of.keys().collect();
+    entities.sort();
+    for entity in entities {
+        let mut ancestor = parent_of.get(entity);
+        let mut depth = 0;
+        while let Some(parent) = ancestor {
+            if parent == entity {
+                return Err(HierarchyError::Cycle { entity: *entity });
+            }
+            if depth > parent_of.len() {
+                break;
+            }
+            ancestor = parent_of.get(parent);
+            depth += 1;
+        }
+    }
+
+    children_of.retain(|_, children| !children.is_empty());
+    Ok(children_of)
+}
+
+/// Cooks a prefab like `cook_prefab` and then resolves the hierarchy with `cooked_hierarchy`.
+/// `attach` is called with each parent and its children, in order, and is expected to add the
+/// engine's hierarchy components to them. Parents are visited in order of their UUID, so the
+/// cooked world is the same every time.
+pub fn cook_prefab_with_hierarchy<S, T, U, F>(
+    registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, T>,
+    prefab_cook_order: &[PrefabUuid],
+    prefab_lookup: &HashMap<PrefabUuid, &Prefab, U>,
+    mut attach: F,
+) -> Result<CookedPrefab, HierarchyError>
+where
+    S: BuildHasher,
+    T: BuildHasher,
+    U: BuildHasher,
+    F: FnMut(&mut World, Entity, &[Entity]),
+{
+    let mut cooked_prefab = cook_prefab(
+        registered_components,
+        registered_components_by_uuid,
+        prefab_cook_order,
+        prefab_lookup,
+    )
+    .map_err(HierarchyError::Parameter)?;
+
+    let hierarchy = cooked_hierarchy(&cooked_prefab, prefab_cook_order, prefab_lookup)?;
+    for (parent, children) in &hierarchy {
+        let parent = cooked_prefab.entities[parent];
+        let children: Vec<Entity> = children
+            .iter()
+            .map(|child| cooked_prefab.entities[child])
+            .collect();
+        attach(&mut cooked_prefab.world, parent, &children);
+    }
+
+    Ok(cooked_prefab)
+}
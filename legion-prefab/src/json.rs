@@ -0,0 +1,43 @@
+//! Helpers for loading and saving legion prefabs as JSON
+use crate::{
+    CookedPrefab, Prefab, PrefabFormatDeserializer, PrefabFormatSerializer, PrefabSerdeContext,
+};
+use std::hash::BuildHasher;
+
+/// Loads a prefab from source data in JSON prefab format
+pub fn prefab_from_str<T: BuildHasher>(
+    json: &str,
+    context: PrefabSerdeContext<T>,
+) -> Result<Prefab, serde_json::Error> {
+    let prefab_deser = PrefabFormatDeserializer::new(context);
+    prefab_format::json::from_str(json, &prefab_deser)?;
+    Ok(prefab_deser.prefab())
+}
+
+/// Saves a prefab as source data in JSON prefab format
+pub fn prefab_to_string<T: BuildHasher>(
+    prefab: &Prefab,
+    context: PrefabSerdeContext<T>,
+) -> Result<String, serde_json::Error> {
+    let prefab_ser = PrefabFormatSerializer::new(context, prefab);
+    prefab_format::json::to_string(&prefab_ser, prefab.prefab_id())
+}
+
+/// Saves a prefab as source data in indented JSON prefab format
+pub fn prefab_to_string_pretty<T: BuildHasher>(
+    prefab: &Prefab,
+    context: PrefabSerdeContext<T>,
+) -> Result<String, serde_json::Error> {
+    let prefab_ser = PrefabFormatSerializer::new(context, prefab);
+    prefab_format::json::to_string_pretty(&prefab_ser, prefab.prefab_id())
+}
+
+/// Loads a cooked prefab from JSON
+pub fn cooked_prefab_from_str(json: &str) -> Result<CookedPrefab, serde_json::Error> {
+    serde_json::from_str(json)
+}
+
+/// Saves a cooked prefab as JSON
+pub fn cooked_prefab_to_string(cooked_prefab: &CookedPrefab) -> Result<String, serde_json::Error> {
+    serde_json::to_string(cooked_prefab)
+}
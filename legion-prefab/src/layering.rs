@@ -0,0 +1,149 @@
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid};
+use crate::{cook_prefab, ComponentRegistration, CookedPrefab, ParameterError, Prefab};
+use legion::storage::ComponentTypeId;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+/// Overrides loaded from a separate file (i.e. the base game, a DLC or a mod) that are applied to
+/// prefabs when they are cooked, without changing the prefabs themselves
+pub struct OverrideLayer<'a> {
+    /// Used to identify the layer in the report
+    pub name: String,
+    /// Layers are applied from lowest to highest priority, so when several layers override the
+    /// same component, the highest priority layer is applied last and wins. Layers with the same
+    /// priority are applied in the order they were given.
+    pub priority: i32,
+    /// The overrides are the prefab refs of this prefab. Entities defined in the layer itself are
+    /// ignored.
+    pub prefab: &'a Prefab,
+}
+
+/// An override from a layer that could not be applied
+#[derive(Debug, Clone)]
+pub struct SkippedOverride {
+    pub layer: String,
+    pub prefab_ref: PrefabUuid,
+    pub entity: EntityUuid,
+    pub component_type: ComponentTypeUuid,
+}
+
+#[derive(Debug, Default)]
+pub struct LayerReport {
+    /// The layer that was applied last for each overridden (entity, component type)
+    pub winning_layers: HashMap<(EntityUuid, ComponentTypeUuid), String>,
+    /// Overrides of entities or components that don't exist in the cooked prefab, of component
+    /// types that aren't registered, or that change locked fields
+    pub skipped: Vec<SkippedOverride>,
+}
+
+impl LayerReport {
+    pub fn winning_layer(
+        &self,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+    ) -> Option<&str> {
+        self.winning_layers
+            .get(&(*entity, *component_type))
+            .map(|layer| layer.as_str())
+    }
+}
+
+/// Cooks a prefab like `cook_prefab` and then applies the overrides in `layers`, in priority
+/// order. Layer overrides only apply to prefabs in `prefab_cook_order`.
+pub fn cook_prefab_with_layers<S: BuildHasher, T: BuildHasher, U: BuildHasher>(
+    registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, T>,
+    prefab_cook_order: &[PrefabUuid],
+    prefab_lookup: &HashMap<PrefabUuid, &Prefab, U>,
+    layers: &[OverrideLayer],
+) -> Result<(CookedPrefab, LayerReport), ParameterError> {
+    let mut cooked = cook_prefab(
+        registered_components,
+        registered_components_by_uuid,
+        prefab_cook_order,
+        prefab_lookup,
+    )?;
+    let mut report = LayerReport::default();
+
+    let mut sorted_layers: Vec<_> = layers.iter().collect();
+    sorted_layers.sort_by_key(|layer| layer.priority);
+
+    for layer in sorted_layers {
+        // Sorted so that a layer overriding several prefabs applies them in a consistent order
+        let mut prefab_refs: Vec<_> = layer.prefab.prefab_meta.prefab_refs.iter().collect();
+        prefab_refs.sort_by_key(|(prefab_id, _)| **prefab_id);
+
+        for (prefab_id, prefab_ref) in prefab_refs {
+            if !prefab_cook_order.contains(prefab_id) {
+                continue;
+            }
+
+            for (entity_id, component_overrides) in &prefab_ref.overrides {
+                for component_override in component_overrides {
+                    let component_type = component_override.component_type;
+                    let target = cooked.entities.entity(entity_id).filter(|entity| {
+                        registered_components_by_uuid
+                            .get(&component_type)
+                            .map(|registration| has_component(registration, &cooked.world, *entity))
+                            .unwrap_or(false)
+                    });
+
+                    let cooked_entity = match target {
+                        Some(cooked_entity) => cooked_entity,
+                        None => {
+                            report.skipped.push(SkippedOverride {
+                                layer: layer.name.clone(),
+                                prefab_ref: *prefab_id,
+                                entity: *entity_id,
+                                component_type,
+                            });
+                            continue;
+                        }
+                    };
+
+                    let component_registration = &registered_components_by_uuid[&component_type];
+                    let locked_fields = crate::override_locks::touched_locked_fields(
+                        component_registration,
+                        &component_override.data,
+                    );
+                    if !locked_fields.is_empty() {
+                        report.skipped.push(SkippedOverride {
+                            layer: layer.name.clone(),
+                            prefab_ref: *prefab_id,
+                            entity: *entity_id,
+                            component_type,
+                        });
+                        continue;
+                    }
+
+                    let mut deserializer =
+                        ron::de::Deserializer::from_str(&component_override.data).unwrap();
+                    let mut de = erased_serde::Deserializer::erase(&mut deserializer);
+                    component_registration.apply_diff(&mut de, &mut cooked.world, cooked_entity);
+
+                    report
+                        .winning_layers
+                        .insert((*entity_id, component_type), layer.name.clone());
+                }
+            }
+        }
+    }
+
+    Ok((cooked, report))
+}
+
+fn has_component(
+    registration: &ComponentRegistration,
+    world: &legion::world::World,
+    entity: legion::Entity,
+) -> bool {
+    world
+        .entry_ref(entity)
+        .map(|entry| {
+            entry
+                .archetype()
+                .layout()
+                .has_component_by_id(registration.component_type_id())
+        })
+        .unwrap_or(false)
+}
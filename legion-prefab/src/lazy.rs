@@ -0,0 +1,119 @@
+use crate::format::blobs::BlobData;
+use crate::format::raw::{ComponentOverrideRaw, EntityComponentRaw, PrefabObjectRaw, PrefabRaw};
+use crate::format::{EntityUuid, PrefabParameter, PrefabRefTransform, StorageDeserializer};
+use crate::{Prefab, PrefabFormatDeserializer, PrefabSerdeContext};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde_diff::SerdeDiff;
+use std::collections::BTreeMap;
+use std::hash::BuildHasher;
+
+// A `PrefabRaw` keeps component data and override diffs as RON text. Tools that only reorganize
+// prefabs (moving, renaming or re-parenting objects) can work on it directly and write it back
+// with `PrefabRaw::to_ron_string`. The functions here decode that text into concrete types when
+// it's actually needed.
+
+/// Deserializes the data of a single component
+pub fn decode_component<T: DeserializeOwned>(
+    component: &EntityComponentRaw
+) -> Result<T, ron::de::Error> {
+    ron::de::from_str(&component.data)
+}
+
+/// Applies a component override's diff to a value
+pub fn apply_component_override<T: SerdeDiff + DeserializeOwned>(
+    component_override: &ComponentOverrideRaw,
+    value: &mut T,
+) -> Result<(), ron::de::Error> {
+    let mut de = ron::de::Deserializer::from_str(&component_override.diff)?;
+    <serde_diff::Apply<T> as serde::de::DeserializeSeed>::deserialize(
+        serde_diff::Apply::deserializable(value),
+        &mut de,
+    )?;
+    de.end()
+}
+
+/// Decodes every component in a raw prefab using the registered component types, producing the
+/// same result as loading the prefab's source file
+pub fn load_raw_prefab<T: BuildHasher>(
+    raw: &PrefabRaw,
+    context: PrefabSerdeContext<T>,
+) -> Result<Prefab, ron::de::Error> {
+    let storage = PrefabFormatDeserializer::new(context);
+    storage.begin_prefab(&raw.id);
+    if let Some(parameters) = &raw.parameters {
+        for parameter in ron::de::from_str::<Vec<PrefabParameter>>(parameters)? {
+            storage.declare_parameter(&raw.id, parameter);
+        }
+    }
+    if let Some(layers) = &raw.layers {
+        for (name, entities) in ron::de::from_str::<BTreeMap<String, Vec<uuid::Uuid>>>(layers)? {
+            let entities: Vec<EntityUuid> =
+                entities.iter().map(|entity| *entity.as_bytes()).collect();
+            storage.declare_layer(&raw.id, &name, &entities);
+        }
+    }
+    if let Some(hierarchy) = &raw.hierarchy {
+        for (parent, children) in
+            ron::de::from_str::<BTreeMap<uuid::Uuid, Vec<uuid::Uuid>>>(hierarchy)?
+        {
+            let children: Vec<EntityUuid> =
+                children.iter().map(|child| *child.as_bytes()).collect();
+            storage.declare_children(&raw.id, parent.as_bytes(), &children);
+        }
+    }
+    if let Some(blobs) = &raw.blobs {
+        for (blob, data) in ron::de::from_str::<BTreeMap<uuid::Uuid, BlobData>>(blobs)? {
+            storage.declare_blob(&raw.id, blob.as_bytes(), data);
+        }
+    }
+
+    for object in &raw.objects {
+        match object {
+            PrefabObjectRaw::Entity(entity) => {
+                storage.begin_entity_object(&raw.id, &entity.id);
+                for component in &entity.components {
+                    let mut de = ron::de::Deserializer::from_str(&component.data)?;
+                    storage.deserialize_component(
+                        &raw.id,
+                        &entity.id,
+                        &component.component_type,
+                        &mut de,
+                    )?;
+                    de.end()?;
+                }
+                storage.end_entity_object(&raw.id, &entity.id);
+            }
+            PrefabObjectRaw::PrefabRef(prefab_ref) => {
+                storage.begin_prefab_ref(&raw.id, &prefab_ref.prefab_id);
+                if let Some(parameter_values) = &prefab_ref.parameter_values {
+                    let parameter_values: BTreeMap<String, String> =
+                        ron::de::from_str(parameter_values)?;
+                    for (name, value) in &parameter_values {
+                        storage.set_parameter_value(&raw.id, &prefab_ref.prefab_id, name, value);
+                    }
+                }
+                if let Some(transform) = &prefab_ref.transform {
+                    let transform: PrefabRefTransform = ron::de::from_str(transform)?;
+                    storage.set_prefab_ref_transform(&raw.id, &prefab_ref.prefab_id, &transform);
+                }
+                for entity_override in &prefab_ref.entity_overrides {
+                    for component_override in &entity_override.component_overrides {
+                        // Diffs are stored unparsed, so they're handed over as a plain string
+                        let de: serde::de::value::StrDeserializer<ron::de::Error> =
+                            component_override.diff.as_str().into_deserializer();
+                        storage.apply_component_diff(
+                            &raw.id,
+                            &prefab_ref.prefab_id,
+                            &entity_override.entity_id,
+                            &component_override.component_type,
+                            de,
+                        )?;
+                    }
+                }
+                storage.end_prefab_ref(&raw.id, &prefab_ref.prefab_id);
+            }
+        }
+    }
+
+    Ok(storage.prefab())
+}
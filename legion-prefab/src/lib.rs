@@ -1,10 +1,24 @@
 #[doc(hidden)]
 pub use inventory;
 
+// Derives everything a component type needs and registers it
+pub use legion_prefab_derive::prefab_component;
+
 use prefab_format as format;
 
 mod registration;
-pub use registration::{ComponentRegistration, iter_component_registrations, DiffSingleResult};
+pub use registration::{
+    ComponentRegistration, ComponentRegistry, iter_component_registrations, DiffSingleResult,
+    DiffSingleKind, ApplyDiffBatchCallback, global_component_registry, PodComponent, DecodedColumn,
+};
+#[doc(hidden)]
+pub use registration::parse_component_uuid;
+
+// Singleton data stored in prefabs alongside entities
+mod resources;
+pub use resources::{
+    iter_resource_registrations, PrefabResources, ResourceRegistration, ResourceTypeUuid,
+};
 
 mod prefab_uncooked;
 pub use prefab_uncooked::{
@@ -12,6 +26,14 @@ pub use prefab_uncooked::{
     PrefabFormatSerializer,
 };
 
+// Editing uncooked prefabs without getting the world and prefab_meta out of sync
+mod prefab_editing;
+pub use prefab_editing::PrefabEditError;
+
+// One-to-one mapping between entity UUIDs and legion entities
+mod bimap;
+pub use bimap::UuidEntityBimap;
+
 mod prefab_cooked;
 pub use prefab_cooked::CookedPrefab;
 
@@ -20,14 +42,183 @@ pub use prefab_builder::PrefabBuilder;
 pub use prefab_builder::PrefabBuilderError;
 
 mod world_serde;
+pub use world_serde::{
+    deserialize_world, deserialize_world_with_policy, serialize_world, serialize_world_filtered,
+    serialize_world_masked, UnknownComponentPolicy,
+};
+
+// Restricts which component types are written for a particular consumer
+mod component_mask;
+pub use component_mask::ComponentMask;
+
+// Choosing between the human readable and packed world layouts
+mod world_serialize_mode;
+pub use world_serialize_mode::WorldSerializeMode;
+
+// Writes serde_diff diffs only if they have changes, and serializes without output
+mod diff_output;
+
+// Options for diffing components, i.e. ignoring float drift
+mod diff_options;
+pub use diff_options::DiffOptions;
 
 mod cooking;
-pub use cooking::cook_prefab;
+pub use cooking::{
+    base_prefab_chain, cook_prefab, cook_prefab_validated, prefab_cook_order, PrefabCookOrderError,
+};
+
+// Resolves the parent/child relationships declared by prefabs while cooking
+mod hierarchy;
+pub use hierarchy::{cook_prefab_with_hierarchy, cooked_hierarchy, HierarchyError};
+
+// Hands the offsets stored on prefab refs to the engine so it can place nested instances
+mod ref_transforms;
+pub use ref_transforms::apply_prefab_ref_transforms;
+
+// Reports components that several prefab refs override, and which override wins
+mod override_conflicts;
+pub use override_conflicts::{find_override_conflicts, OverrideConflict, OverrideSource};
+
+// Groups of components that are added as one marker and expanded when cooking
+mod bundles;
+pub use bundles::{expand_component_bundles, iter_component_bundles, ComponentBundle};
+
+// Whether diffs of a component type store changed fields or the whole value
+mod override_policy;
+pub use override_policy::OverridePolicy;
+
+// Keeps prefab refs from overriding fields that registrations lock
+mod override_locks;
+pub use override_locks::{find_locked_field_overrides, LockedFieldOverride};
+
+// Checks cooked component data with the validate fns attached to registrations
+mod validation;
+pub use validation::{validate_cooked_prefab, ValidationCtx, ValidationError};
+
+// Spawns cooked prefabs and tracks which prefab spawned entities came from
+mod spawn;
+pub use spawn::{
+    spawn_cooked_prefab, despawn_instance, find_prefab_instances, InstanceHandle,
+    PrefabInstanceComponent,
+};
+
+// Estimates the memory spawning a cooked prefab takes
+mod spawn_cost;
+pub use spawn_cost::{ComponentSpawnCost, SpawnCost};
+
+// Per-instance component overrides supplied when spawning a cooked prefab
+mod spawn_overrides;
+pub use spawn_overrides::{
+    spawn_cooked_prefab_with_overrides, SpawnOverride, SpawnOverrideError, SpawnOverrides,
+};
+
+// Keeps track of which live entities were spawned from which prefab
+mod tracker;
+pub use tracker::{PrefabInstanceTracker, PrefabInstanceId};
+
+// Saves worlds with prefab instances stored as diffs against their cooked prefabs, for small save
+// files, and binds them to the current prefabs when loading
+mod savegame;
+pub use savegame::{
+    load_world, restore_snapshot, save_snapshot, save_world, Savegame, SavedComponent, SavedEntity,
+    SavedInstance, SavegameSnapshot, SnapshotError,
+};
+
+// Sets the component fields bound to prefab parameters
+mod parameters;
+pub use parameters::{apply_parameter_value, apply_prefab_parameters, ParameterError};
+
+// Applies overrides from separate files (DLC, mods) on top of prefabs while cooking
+mod layering;
+pub use layering::{cook_prefab_with_layers, OverrideLayer, LayerReport, SkippedOverride};
+
+// Cooks the named groups of entities (gameplay, lighting, ...) in a prefab separately
+mod entity_layers;
+pub use entity_layers::{cook_prefab_per_layer, cook_prefab_selected_layers, entity_layers};
+
+// Loads a prefab and the prefabs it references from a PrefabSource or AsyncPrefabSource
+mod async_loading;
+pub use async_loading::{load_prefab, load_prefab_async, LoadedPrefabs, LoadPrefabError};
+
+// Rewrites prefab source files into a canonical layout
+mod formatting;
+pub use formatting::{
+    format_prefab, save_prefab_preserving_format, canonical_pretty_config, FormatPrefabError,
+};
+
+// Checks that prefabs survive being saved and loaded again, for use in tests
+mod roundtrip;
+pub use roundtrip::{
+    assert_prefab_roundtrip, check_prefab_roundtrip, PrefabFormat, PrefabRoundtripError,
+};
+
+// Upgrades prefab source files after component schema changes
+mod migrations;
+pub use migrations::{
+    ComponentMigration, iter_component_migrations, migrate_ron_prefab, MigratedComponent,
+    MigratePrefabError,
+};
+
+// Runs format and component migrations over a directory of prefab files
+mod migrate_directory;
+pub use migrate_directory::{
+    find_prefab_files, migrate_prefab_directory, MigrateFileError, MigratedFile, MigrationReport,
+};
+
+// Finds structural differences between two versions of a prefab
+mod prefab_diff;
+pub use prefab_diff::{
+    diff_prefabs, diff_prefab_files, diff_prefab_sources, PrefabDiffReport, PrefabDiffError,
+    field_diffs_from_ron, field_paths_from_ron, PrefabDiff, EntityChanges, ComponentChange, OverrideChange, FieldDiff,
+    FieldChange, HierarchyChange, ParameterChange, LayerChange, RefTransformChange,
+    ParameterValueChange, ExtendsChange,
+};
+
+// Decodes the component data of untyped prefabs on demand
+mod lazy;
+pub use lazy::{decode_component, apply_component_override, load_raw_prefab};
+
+// Checks prefab source files for mistakes without loading them
+mod lint;
+pub use lint::{lint_prefab, lint_prefab_with_refs, LintFinding, LintLocation, LintRule, LintSeverity};
+
+// Finds the entities and overrides in prefab files that use a component type
+mod component_search;
+pub use component_search::{find_component_usages, ComponentUsage, ComponentUsageError};
+
+// Lists the UUIDs used by a set of prefabs and flags unknown or retired component types
+mod uuid_report;
+pub use uuid_report::{uuid_report, ComponentTypeStatus, ComponentTypeUsage, UuidReport};
+
+// Three-way merge of prefabs that were changed independently
+mod merge;
+pub use merge::{merge_prefabs, MergeConflict};
+
+// Levels made of placed prefab instances
+mod scene;
+pub use scene::{cook_scene, scene_entity_uuid, Scene, SceneError, SceneInstance};
+
+// Bakes a prefab and everything it references into one standalone prefab
+mod flatten;
+pub use flatten::{flatten_prefab, flattened_entity_uuid, FlattenPrefabError};
+
+// Turns a selection of entities into a new prefab that the original references
+mod extract;
+pub use extract::{extract_prefab, ExtractPrefabError};
+
+// Moves an entity from one prefab to another, fixing up what refers to it where possible
+mod move_entity;
+pub use move_entity::{move_entity, MoveEntityError, MoveEntityIssue, MoveEntityReport};
 
 // Implements a safer, easier to use layer on top of legion's clone_from and clone_from_single by
 // using the type registry in legion-prefab
 mod clone_merge;
 pub use clone_merge::CopyCloneImpl;
+pub use clone_merge::clone_world;
+pub use clone_merge::clone_entities;
+pub use clone_merge::clone_entities_with_children;
+pub use clone_merge::CloneWorldError;
+pub use clone_merge::UnregisteredComponentPolicy;
 pub use clone_merge::SpawnCloneImpl;
 pub use clone_merge::SpawnCloneImplHandlerSet;
 pub use clone_merge::SpawnFrom;
@@ -38,3 +229,23 @@ mod option_iter;
 pub use option_iter::OptionIter;
 pub use option_iter::get_component_slice_from_archetype;
 pub use option_iter::iter_component_slice_from_archetype;
+
+// A binary layout for cooked prefabs that loads a component column at a time
+mod cooked_columns;
+pub use cooked_columns::{read_cooked_columns, write_cooked_columns, CookedColumnsError};
+#[cfg(feature = "parallel")]
+pub use cooked_columns::read_cooked_columns_parallel;
+
+// Binary containers for cooked prefabs, including one laid out for load time
+mod cooked_format;
+pub use cooked_format::{read_cooked_prefab, write_cooked_prefab, CookedFormat, CookedFormatError};
+
+// Helpers for loading/saving prefabs in formats other than RON
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+#[cfg(feature = "yaml")]
+pub mod yaml;
@@ -0,0 +1,313 @@
+use crate::format::raw::{PrefabObjectRaw, PrefabRaw, PrefabRefRaw};
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid};
+use crate::ComponentRegistry;
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintSeverity {
+    /// The prefab loads but probably doesn't do what was intended
+    Warning,
+    /// The prefab will fail to load or data in it will be silently dropped
+    Error,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LintRule {
+    /// Two entities in the prefab have the same ID
+    DuplicateEntityId,
+    /// An entity has more than one component of the same type
+    DuplicateComponent,
+    /// A component or override uses a component type that isn't registered
+    UnregisteredComponent,
+    /// The same prefab is referenced more than once
+    DuplicatePrefabRef,
+    /// A component of an entity in a referenced prefab is overridden more than once
+    DuplicateOverride,
+    /// A referenced prefab could not be found
+    UnresolvedPrefabRef,
+    /// An override targets an entity that doesn't exist in the referenced prefab
+    OverrideOfMissingEntity,
+    /// An override diffs a component that the target entity doesn't have
+    OverrideOfMissingComponent,
+}
+
+impl LintRule {
+    pub fn severity(self) -> LintSeverity {
+        match self {
+            LintRule::UnregisteredComponent => LintSeverity::Warning,
+            _ => LintSeverity::Error,
+        }
+    }
+}
+
+/// Where in the prefab a finding was made. Fields that don't apply to the finding are None.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintLocation {
+    /// Index into the prefab's objects list
+    pub object_index: usize,
+    pub entity: Option<EntityUuid>,
+    pub prefab_ref: Option<PrefabUuid>,
+    pub component_type: Option<ComponentTypeUuid>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LintFinding {
+    pub rule: LintRule,
+    pub severity: LintSeverity,
+    pub location: LintLocation,
+    pub message: String,
+}
+
+impl std::fmt::Display for LintFinding {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        let severity = match self.severity {
+            LintSeverity::Warning => "warning",
+            LintSeverity::Error => "error",
+        };
+        write!(
+            f,
+            "{}: {} (object {})",
+            severity, self.message, self.location.object_index
+        )
+    }
+}
+
+/// Checks a prefab for mistakes that can be found without loading it: duplicate IDs and
+/// unregistered component types. Overrides can only be checked against the prefabs they
+/// reference, see `lint_prefab_with_refs`.
+pub fn lint_prefab(
+    prefab: &PrefabRaw,
+    registry: &ComponentRegistry,
+) -> Vec<LintFinding> {
+    let mut linter = Linter {
+        registry,
+        findings: vec![],
+    };
+    linter.lint(prefab, None::<&HashMap<PrefabUuid, PrefabRaw>>);
+    linter.findings
+}
+
+/// Runs the checks of `lint_prefab`, and also checks every prefab ref and override against the
+/// referenced prefabs. Referenced prefabs missing from `referenced_prefabs` are reported.
+pub fn lint_prefab_with_refs<S: BuildHasher>(
+    prefab: &PrefabRaw,
+    registry: &ComponentRegistry,
+    referenced_prefabs: &HashMap<PrefabUuid, PrefabRaw, S>,
+) -> Vec<LintFinding> {
+    let mut linter = Linter {
+        registry,
+        findings: vec![],
+    };
+    linter.lint(prefab, Some(referenced_prefabs));
+    linter.findings
+}
+
+struct Linter<'a> {
+    registry: &'a ComponentRegistry,
+    findings: Vec<LintFinding>,
+}
+
+impl<'a> Linter<'a> {
+    fn lint<S: BuildHasher>(
+        &mut self,
+        prefab: &PrefabRaw,
+        referenced_prefabs: Option<&HashMap<PrefabUuid, PrefabRaw, S>>,
+    ) {
+        let mut entity_ids = HashSet::new();
+        let mut prefab_ref_ids = HashSet::new();
+
+        for (object_index, object) in prefab.objects.iter().enumerate() {
+            match object {
+                PrefabObjectRaw::Entity(entity) => {
+                    let location = LintLocation {
+                        object_index,
+                        entity: Some(entity.id),
+                        prefab_ref: None,
+                        component_type: None,
+                    };
+                    if !entity_ids.insert(entity.id) {
+                        self.push(
+                            LintRule::DuplicateEntityId,
+                            location.clone(),
+                            format!("entity {} is defined more than once", uuid_str(&entity.id)),
+                        );
+                    }
+
+                    let mut component_types = HashSet::new();
+                    for component in &entity.components {
+                        let location = LintLocation {
+                            component_type: Some(component.component_type),
+                            ..location.clone()
+                        };
+                        if !component_types.insert(component.component_type) {
+                            self.push(
+                                LintRule::DuplicateComponent,
+                                location.clone(),
+                                format!(
+                                    "entity {} has more than one {} component",
+                                    uuid_str(&entity.id),
+                                    self.component_name(&component.component_type)
+                                ),
+                            );
+                        }
+                        self.check_registered(location);
+                    }
+                }
+                PrefabObjectRaw::PrefabRef(prefab_ref) => {
+                    if !prefab_ref_ids.insert(prefab_ref.prefab_id) {
+                        self.push(
+                            LintRule::DuplicatePrefabRef,
+                            prefab_ref_location(object_index, prefab_ref),
+                            format!(
+                                "prefab {} is referenced more than once",
+                                uuid_str(&prefab_ref.prefab_id)
+                            ),
+                        );
+                    }
+
+                    // None if referenced prefabs weren't provided, Some(None) if this one is
+                    // missing
+                    let referenced_prefab =
+                        referenced_prefabs.map(|prefabs| prefabs.get(&prefab_ref.prefab_id));
+                    if let Some(None) = referenced_prefab {
+                        self.push(
+                            LintRule::UnresolvedPrefabRef,
+                            prefab_ref_location(object_index, prefab_ref),
+                            format!(
+                                "referenced prefab {} could not be found",
+                                uuid_str(&prefab_ref.prefab_id)
+                            ),
+                        );
+                    }
+
+                    self.lint_overrides(object_index, prefab_ref, referenced_prefab.flatten());
+                }
+            }
+        }
+    }
+
+    // Checks the overrides of a prefab ref, and checks them against the referenced prefab if it's
+    // available
+    fn lint_overrides(
+        &mut self,
+        object_index: usize,
+        prefab_ref: &PrefabRefRaw,
+        referenced_prefab: Option<&PrefabRaw>,
+    ) {
+        let mut overridden = HashSet::new();
+        for entity_override in &prefab_ref.entity_overrides {
+            let entity_id = entity_override.entity_id;
+            let location = LintLocation {
+                entity: Some(entity_id),
+                ..prefab_ref_location(object_index, prefab_ref)
+            };
+
+            let target_entity = referenced_prefab.and_then(|prefab| prefab.entity(&entity_id));
+            if referenced_prefab.is_some() && target_entity.is_none() {
+                self.push(
+                    LintRule::OverrideOfMissingEntity,
+                    location.clone(),
+                    format!(
+                        "override targets entity {} which is not in prefab {}",
+                        uuid_str(&entity_id),
+                        uuid_str(&prefab_ref.prefab_id)
+                    ),
+                );
+            }
+
+            for component_override in &entity_override.component_overrides {
+                let component_type = component_override.component_type;
+                let location = LintLocation {
+                    component_type: Some(component_type),
+                    ..location.clone()
+                };
+                if !overridden.insert((entity_id, component_type)) {
+                    self.push(
+                        LintRule::DuplicateOverride,
+                        location.clone(),
+                        format!(
+                            "{} component of entity {} is overridden more than once",
+                            self.component_name(&component_type),
+                            uuid_str(&entity_id)
+                        ),
+                    );
+                }
+                if let Some(target_entity) = target_entity {
+                    if target_entity.component(&component_type).is_none() {
+                        self.push(
+                            LintRule::OverrideOfMissingComponent,
+                            location.clone(),
+                            format!(
+                                "override diffs a {} component but entity {} doesn't have one",
+                                self.component_name(&component_type),
+                                uuid_str(&entity_id)
+                            ),
+                        );
+                    }
+                }
+                self.check_registered(location);
+            }
+        }
+    }
+
+    fn check_registered(
+        &mut self,
+        location: LintLocation,
+    ) {
+        let component_type = location.component_type.unwrap();
+        if !self.registry.contains(&component_type) {
+            self.push(
+                LintRule::UnregisteredComponent,
+                location,
+                format!(
+                    "component type {} is not registered",
+                    uuid_str(&component_type)
+                ),
+            );
+        }
+    }
+
+    fn component_name(
+        &self,
+        component_type: &ComponentTypeUuid,
+    ) -> String {
+        match self.registry.get(component_type) {
+            Some(registration) => registration.type_name().to_string(),
+            None => uuid_str(component_type),
+        }
+    }
+
+    fn push(
+        &mut self,
+        rule: LintRule,
+        location: LintLocation,
+        message: String,
+    ) {
+        self.findings.push(LintFinding {
+            rule,
+            severity: rule.severity(),
+            location,
+            message,
+        });
+    }
+}
+
+fn prefab_ref_location(
+    object_index: usize,
+    prefab_ref: &PrefabRefRaw,
+) -> LintLocation {
+    LintLocation {
+        object_index,
+        entity: None,
+        prefab_ref: Some(prefab_ref.prefab_id),
+        component_type: None,
+    }
+}
+
+fn uuid_str(bytes: &uuid::Bytes) -> String {
+    uuid::Uuid::from_bytes(*bytes).to_string()
+}
@@ -0,0 +1,614 @@
+use crate::format::blobs::BlobId;
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabRefTransform, PrefabUuid};
+use crate::prefab_diff::{override_data_by_key, sorted};
+use crate::{
+    field_diffs_from_ron, ComponentOverride, ComponentRegistration, DiffSingleKind, FieldChange,
+    FieldDiff, Prefab, PrefabMeta, PrefabRef, PrefabSerdeContext,
+};
+use legion::{Entity, EntityStore, World};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::BuildHasher;
+
+/// A change that could not be merged automatically
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeConflict {
+    /// One side removed the entity and the other side changed it
+    EntityRemovedAndChanged { entity: EntityUuid },
+    /// Both sides added the component with different data
+    ComponentAddedDifferently {
+        entity: EntityUuid,
+        component_type: ComponentTypeUuid,
+    },
+    /// One side removed the component and the other side changed it
+    ComponentRemovedAndChanged {
+        entity: EntityUuid,
+        component_type: ComponentTypeUuid,
+    },
+    /// Both sides changed the same field (or a field and something inside it) differently
+    FieldChangedDifferently {
+        entity: EntityUuid,
+        component_type: ComponentTypeUuid,
+        path: String,
+    },
+    /// One side removed the prefab ref and the other side changed its overrides
+    PrefabRefRemovedAndChanged { prefab_ref: PrefabUuid },
+    /// Both sides changed the same component override differently
+    OverrideChangedDifferently {
+        prefab_ref: PrefabUuid,
+        entity: EntityUuid,
+        component_type: ComponentTypeUuid,
+    },
+    /// Both sides changed the prefab's parameter declarations differently
+    ParametersChangedDifferently,
+    /// Both sides changed the prefab to extend different base prefabs
+    ExtendsChangedDifferently,
+    /// Both sides moved the entity to different entity layers
+    LayerChangedDifferently { entity: EntityUuid },
+    /// Both sides changed the children of the entity differently
+    ChildrenChangedDifferently { parent: EntityUuid },
+    /// Both sides changed the data of the same blob differently
+    BlobChangedDifferently { blob: BlobId },
+    /// Both sides set the same parameter of a prefab ref to different values
+    ParameterValueChangedDifferently {
+        prefab_ref: PrefabUuid,
+        name: String,
+    },
+    /// Both sides changed the offset of the same prefab ref differently
+    PrefabRefTransformChangedDifferently { prefab_ref: PrefabUuid },
+}
+
+// How a component changed between base and one side of the merge
+#[derive(PartialEq)]
+enum ComponentState {
+    Unchanged,
+    Added,
+    Removed,
+    // Contains the RON-encoded serde_diff
+    Changed(String),
+}
+
+fn component_state(
+    registration: &ComponentRegistration,
+    base_world: &World,
+    base_entity: Option<Entity>,
+    side_world: &World,
+    side_entity: Option<Entity>,
+) -> ComponentState {
+    let mut ron_ser = ron::ser::Serializer::new(None, true);
+    let mut erased = erased_serde::Serializer::erase(&mut ron_ser);
+    let result = registration.diff_single(
+        &mut erased,
+        base_world,
+        base_entity,
+        side_world,
+        side_entity,
+    );
+    match result.kind {
+        DiffSingleKind::NoChange => ComponentState::Unchanged,
+        DiffSingleKind::Add => ComponentState::Added,
+        DiffSingleKind::Remove => ComponentState::Removed,
+        DiffSingleKind::Change => ComponentState::Changed(ron_ser.into_output_string()),
+    }
+}
+
+fn has_component(
+    registration: &ComponentRegistration,
+    world: &World,
+    entity: Option<Entity>,
+) -> bool {
+    entity
+        .and_then(|entity| world.entry_ref(entity).ok())
+        .map(|entry| {
+            entry
+                .archetype()
+                .layout()
+                .has_component_by_id(registration.component_type_id())
+        })
+        .unwrap_or(false)
+}
+
+fn serialize_component(
+    registration: &ComponentRegistration,
+    world: &World,
+    entity: Entity,
+) -> String {
+    let mut ron_ser = ron::ser::Serializer::new(None, true);
+    registration.serialize_single(world, entity, &mut |comp| {
+        erased_serde::serialize(comp, &mut ron_ser).expect("failed to serialize component");
+    });
+    ron_ser.into_output_string()
+}
+
+// Components are copied between worlds by round-tripping them through RON, which only requires
+// the registrations keyed by UUID
+fn copy_component(
+    registration: &ComponentRegistration,
+    src_world: &World,
+    src_entity: Entity,
+    dst_world: &mut World,
+    dst_entity: Entity,
+) {
+    let data = serialize_component(registration, src_world, src_entity);
+    let mut de = ron::de::Deserializer::from_str(&data).expect("failed to read component");
+    registration.add_to_entity(
+        &mut erased_serde::Deserializer::erase(&mut de),
+        dst_world,
+        dst_entity,
+    );
+}
+
+fn apply_diff(
+    registration: &ComponentRegistration,
+    diff: &str,
+    world: &mut World,
+    entity: Entity,
+) {
+    let mut de = ron::de::Deserializer::from_str(diff).expect("failed to read diff");
+    registration.apply_diff(
+        &mut erased_serde::Deserializer::erase(&mut de),
+        world,
+        entity,
+    );
+}
+
+// Two field changes overlap if they're at the same path or one path is inside the other
+fn paths_overlap(
+    a: &str,
+    b: &str,
+) -> bool {
+    fn contains(
+        outer: &str,
+        inner: &str,
+    ) -> bool {
+        inner == outer
+            || outer.is_empty()
+            || (inner.starts_with(outer)
+                && (inner[outer.len()..].starts_with('.') || inner[outer.len()..].starts_with('[')))
+    }
+    contains(a, b) || contains(b, a)
+}
+
+// Returns the paths changed differently by both diffs. Identical changes to the same field
+// don't conflict.
+fn conflicting_paths(
+    ours: &[FieldDiff],
+    theirs: &[FieldDiff],
+) -> Vec<String> {
+    let mut paths = vec![];
+    for our_diff in ours {
+        for their_diff in theirs {
+            if !paths_overlap(&our_diff.path, &their_diff.path) {
+                continue;
+            }
+            let identical_set = our_diff.path == their_diff.path
+                && match (&our_diff.change, &their_diff.change) {
+                    (FieldChange::Set(a), FieldChange::Set(b)) => a == b,
+                    _ => false,
+                };
+            if !identical_set && !paths.contains(&our_diff.path) {
+                paths.push(our_diff.path.clone());
+            }
+        }
+    }
+    paths
+}
+
+/// Merges the changes made in `ours` and `theirs` (relative to their common ancestor `base`) at the
+/// entity, component and field level. Entities, components and overrides changed on only one side
+/// take that side's version. Components changed on both sides are merged field by field using
+/// their serde-diffs, as long as the two sides changed different fields.
+///
+/// Returns the merged prefab (with the ID of `ours`), or every conflict if the changes can't be
+/// merged.
+pub fn merge_prefabs<T: BuildHasher>(
+    base: &Prefab,
+    ours: &Prefab,
+    theirs: &Prefab,
+    context: PrefabSerdeContext<T>,
+) -> Result<Prefab, Vec<MergeConflict>> {
+    let mut merged = Prefab {
+        world: World::default(),
+        prefab_meta: PrefabMeta {
+            id: ours.prefab_id(),
+            prefab_refs: HashMap::new(),
+            entities: HashMap::new(),
+            parameters: vec![],
+            entity_layers: HashMap::new(),
+            hierarchy: HashMap::new(),
+            blobs: HashMap::new(),
+            extends: None,
+            entity_refs: HashMap::new(),
+        },
+        // Resources are opaque, so they can't be merged. Keep ours
+        resources: ours.resources.clone(),
+    };
+    let mut conflicts = vec![];
+
+    // Parameter declarations are merged as a whole
+    let base_parameters = &base.prefab_meta.parameters;
+    let our_parameters = &ours.prefab_meta.parameters;
+    let their_parameters = &theirs.prefab_meta.parameters;
+    if our_parameters == base_parameters {
+        merged.prefab_meta.parameters = their_parameters.clone();
+    } else if their_parameters == base_parameters || their_parameters == our_parameters {
+        merged.prefab_meta.parameters = our_parameters.clone();
+    } else {
+        merged.prefab_meta.parameters = our_parameters.clone();
+        conflicts.push(MergeConflict::ParametersChangedDifferently);
+    }
+
+    // The base prefab is merged like the parameters. Its overrides are merged with the other
+    // prefab refs
+    let base_extends = base.prefab_meta.extends;
+    let our_extends = ours.prefab_meta.extends;
+    let their_extends = theirs.prefab_meta.extends;
+    if our_extends == base_extends {
+        merged.prefab_meta.extends = their_extends;
+    } else if their_extends == base_extends || their_extends == our_extends {
+        merged.prefab_meta.extends = our_extends;
+    } else {
+        merged.prefab_meta.extends = our_extends;
+        conflicts.push(MergeConflict::ExtendsChangedDifferently);
+    }
+
+    // The hierarchy is merged per parent, with each list of children merged as a whole so that
+    // their order is kept
+    let mut parents = HashSet::new();
+    for prefab in &[base, ours, theirs] {
+        parents.extend(prefab.prefab_meta.hierarchy.keys().cloned());
+    }
+    for parent in sorted(parents.into_iter()) {
+        let base_children = base.prefab_meta.hierarchy.get(&parent);
+        let our_children = ours.prefab_meta.hierarchy.get(&parent);
+        let their_children = theirs.prefab_meta.hierarchy.get(&parent);
+        let merged_children = if our_children == base_children {
+            their_children
+        } else if their_children == base_children || their_children == our_children {
+            our_children
+        } else {
+            conflicts.push(MergeConflict::ChildrenChangedDifferently { parent });
+            our_children
+        };
+        if let Some(children) = merged_children {
+            merged
+                .prefab_meta
+                .hierarchy
+                .insert(parent, children.clone());
+        }
+    }
+
+    // Blobs are merged per blob
+    let mut blob_ids = HashSet::new();
+    for prefab in &[base, ours, theirs] {
+        blob_ids.extend(prefab.prefab_meta.blobs.keys().cloned());
+    }
+    for blob in sorted(blob_ids.into_iter()) {
+        let base_blob = base.prefab_meta.blobs.get(&blob);
+        let our_blob = ours.prefab_meta.blobs.get(&blob);
+        let their_blob = theirs.prefab_meta.blobs.get(&blob);
+        let merged_blob = if our_blob == base_blob {
+            their_blob
+        } else if their_blob == base_blob || their_blob == our_blob {
+            our_blob
+        } else {
+            conflicts.push(MergeConflict::BlobChangedDifferently { blob });
+            our_blob
+        };
+        if let Some(data) = merged_blob {
+            merged.prefab_meta.blobs.insert(blob, data.clone());
+        }
+    }
+
+    let mut registrations: Vec<_> = context.registered_components.iter().collect();
+    registrations.sort_by_key(|(uuid, _)| **uuid);
+
+    //
+    // Entities
+    //
+    let mut entity_uuids = HashSet::new();
+    for prefab in &[base, ours, theirs] {
+        entity_uuids.extend(prefab.prefab_meta.entities.keys().cloned());
+    }
+
+    for entity_uuid in sorted(entity_uuids.into_iter()) {
+        let base_entity = base.prefab_meta.entities.get(&entity_uuid).cloned();
+        let our_entity = ours.prefab_meta.entities.get(&entity_uuid).cloned();
+        let their_entity = theirs.prefab_meta.entities.get(&entity_uuid).cloned();
+
+        // An entity removed on either side is removed, unless the other side changed it
+        if base_entity.is_some() && (our_entity.is_none() || their_entity.is_none()) {
+            let (side, side_entity) = match (our_entity, their_entity) {
+                (Some(entity), None) => (ours, Some(entity)),
+                (None, Some(entity)) => (theirs, Some(entity)),
+                _ => continue,
+            };
+            let changed = registrations.iter().any(|(_, registration)| {
+                component_state(
+                    registration,
+                    &base.world,
+                    base_entity,
+                    &side.world,
+                    side_entity,
+                ) != ComponentState::Unchanged
+            });
+            if changed {
+                conflicts.push(MergeConflict::EntityRemovedAndChanged {
+                    entity: entity_uuid,
+                });
+            }
+            continue;
+        }
+
+        let merged_entity = merged.world.push(());
+        merged
+            .prefab_meta
+            .entities
+            .insert(entity_uuid, merged_entity);
+
+        // Layer membership is merged per entity
+        let base_layer = base.prefab_meta.entity_layers.get(&entity_uuid);
+        let our_layer = ours.prefab_meta.entity_layers.get(&entity_uuid);
+        let their_layer = theirs.prefab_meta.entity_layers.get(&entity_uuid);
+        let merged_layer = if our_layer == base_layer {
+            their_layer
+        } else if their_layer == base_layer || their_layer == our_layer {
+            our_layer
+        } else {
+            conflicts.push(MergeConflict::LayerChangedDifferently {
+                entity: entity_uuid,
+            });
+            our_layer
+        };
+        if let Some(layer) = merged_layer {
+            merged
+                .prefab_meta
+                .entity_layers
+                .insert(entity_uuid, layer.clone());
+        }
+
+        for (component_type, registration) in &registrations {
+            let our_state = component_state(
+                registration,
+                &base.world,
+                base_entity,
+                &ours.world,
+                our_entity,
+            );
+            let their_state = component_state(
+                registration,
+                &base.world,
+                base_entity,
+                &theirs.world,
+                their_entity,
+            );
+
+            // Takes one side's version of the component, if it has one
+            let mut copy_from = |side: &Prefab, side_entity: Option<Entity>| {
+                if has_component(registration, &side.world, side_entity) {
+                    copy_component(
+                        registration,
+                        &side.world,
+                        side_entity.unwrap(),
+                        &mut merged.world,
+                        merged_entity,
+                    );
+                }
+            };
+
+            match (our_state, their_state) {
+                (_, ComponentState::Unchanged) => copy_from(ours, our_entity),
+                (ComponentState::Unchanged, _) => copy_from(theirs, their_entity),
+                (ComponentState::Removed, ComponentState::Removed) => {}
+                (ComponentState::Added, ComponentState::Added) => {
+                    let our_data =
+                        serialize_component(registration, &ours.world, our_entity.unwrap());
+                    let their_data =
+                        serialize_component(registration, &theirs.world, their_entity.unwrap());
+                    if our_data == their_data {
+                        copy_from(ours, our_entity);
+                    } else {
+                        conflicts.push(MergeConflict::ComponentAddedDifferently {
+                            entity: entity_uuid,
+                            component_type: **component_type,
+                        });
+                    }
+                }
+                (ComponentState::Changed(our_diff), ComponentState::Changed(their_diff)) => {
+                    if our_diff == their_diff {
+                        copy_from(ours, our_entity);
+                        continue;
+                    }
+
+                    // If either diff can't be read, treat it as a change to the whole component
+                    let read_diff = |diff: &str| {
+                        field_diffs_from_ron(diff).unwrap_or_else(|_| {
+                            vec![FieldDiff {
+                                path: String::new(),
+                                change: FieldChange::Set(diff.to_string()),
+                            }]
+                        })
+                    };
+                    let paths = conflicting_paths(&read_diff(&our_diff), &read_diff(&their_diff));
+                    if !paths.is_empty() {
+                        for path in paths {
+                            conflicts.push(MergeConflict::FieldChangedDifferently {
+                                entity: entity_uuid,
+                                component_type: **component_type,
+                                path,
+                            });
+                        }
+                        continue;
+                    }
+
+                    copy_from(base, base_entity);
+                    apply_diff(registration, &our_diff, &mut merged.world, merged_entity);
+                    apply_diff(registration, &their_diff, &mut merged.world, merged_entity);
+                }
+                // Added can't be combined with Removed/Changed since they disagree about whether
+                // base has the component, so this is Removed on one side and Changed on the other
+                _ => conflicts.push(MergeConflict::ComponentRemovedAndChanged {
+                    entity: entity_uuid,
+                    component_type: **component_type,
+                }),
+            }
+        }
+    }
+
+    //
+    // Prefab refs and their overrides
+    //
+    let mut prefab_ref_uuids = HashSet::new();
+    for prefab in &[base, ours, theirs] {
+        prefab_ref_uuids.extend(prefab.prefab_meta.prefab_refs.keys().cloned());
+    }
+
+    for prefab_ref in sorted(prefab_ref_uuids.into_iter()) {
+        let in_base = base.prefab_meta.prefab_refs.contains_key(&prefab_ref);
+        let in_ours = ours.prefab_meta.prefab_refs.contains_key(&prefab_ref);
+        let in_theirs = theirs.prefab_meta.prefab_refs.contains_key(&prefab_ref);
+
+        let base_overrides = override_data_by_key(base, &prefab_ref);
+        let our_overrides = override_data_by_key(ours, &prefab_ref);
+        let their_overrides = override_data_by_key(theirs, &prefab_ref);
+
+        if in_base && (!in_ours || !in_theirs) {
+            let base_values = parameter_values(base, &prefab_ref);
+            let base_transform = prefab_ref_transform(base, &prefab_ref);
+            let kept_overrides_changed = (in_ours
+                && (our_overrides != base_overrides
+                    || parameter_values(ours, &prefab_ref) != base_values
+                    || prefab_ref_transform(ours, &prefab_ref) != base_transform))
+                || (in_theirs
+                    && (their_overrides != base_overrides
+                        || parameter_values(theirs, &prefab_ref) != base_values
+                        || prefab_ref_transform(theirs, &prefab_ref) != base_transform));
+            if kept_overrides_changed {
+                conflicts.push(MergeConflict::PrefabRefRemovedAndChanged { prefab_ref });
+            }
+            continue;
+        }
+
+        let mut keys = HashSet::new();
+        keys.extend(base_overrides.keys().cloned());
+        keys.extend(our_overrides.keys().cloned());
+        keys.extend(their_overrides.keys().cloned());
+
+        let mut overrides: HashMap<EntityUuid, Vec<ComponentOverride>> = HashMap::new();
+        for key in sorted(keys.into_iter()) {
+            let base_data = base_overrides.get(&key);
+            let our_data = our_overrides.get(&key);
+            let their_data = their_overrides.get(&key);
+
+            let merged_data = if our_data == base_data {
+                their_data
+            } else if their_data == base_data || their_data == our_data {
+                our_data
+            } else {
+                conflicts.push(MergeConflict::OverrideChangedDifferently {
+                    prefab_ref,
+                    entity: key.0,
+                    component_type: key.1,
+                });
+                continue;
+            };
+
+            if let Some(data) = merged_data {
+                overrides.entry(key.0).or_default().push(ComponentOverride {
+                    component_type: key.1,
+                    data: data.clone(),
+                });
+            }
+        }
+
+        let parameter_values = merge_parameter_values(
+            prefab_ref,
+            parameter_values(base, &prefab_ref),
+            parameter_values(ours, &prefab_ref),
+            parameter_values(theirs, &prefab_ref),
+            &mut conflicts,
+        );
+
+        let base_transform = prefab_ref_transform(base, &prefab_ref);
+        let our_transform = prefab_ref_transform(ours, &prefab_ref);
+        let their_transform = prefab_ref_transform(theirs, &prefab_ref);
+        let transform = if our_transform == base_transform {
+            their_transform
+        } else if their_transform == base_transform || their_transform == our_transform {
+            our_transform
+        } else {
+            conflicts.push(MergeConflict::PrefabRefTransformChangedDifferently { prefab_ref });
+            our_transform
+        };
+
+        merged.prefab_meta.prefab_refs.insert(
+            prefab_ref,
+            PrefabRef {
+                overrides,
+                parameter_values,
+                transform: transform.unwrap_or_default(),
+            },
+        );
+    }
+
+    if conflicts.is_empty() {
+        Ok(merged)
+    } else {
+        Err(conflicts)
+    }
+}
+
+fn parameter_values<'a>(
+    prefab: &'a Prefab,
+    prefab_ref: &PrefabUuid,
+) -> Option<&'a BTreeMap<String, String>> {
+    prefab
+        .prefab_meta
+        .prefab_refs
+        .get(prefab_ref)
+        .map(|prefab_ref| &prefab_ref.parameter_values)
+}
+
+fn prefab_ref_transform(
+    prefab: &Prefab,
+    prefab_ref: &PrefabUuid,
+) -> Option<PrefabRefTransform> {
+    prefab
+        .prefab_meta
+        .prefab_refs
+        .get(prefab_ref)
+        .map(|prefab_ref| prefab_ref.transform)
+}
+
+fn merge_parameter_values(
+    prefab_ref: PrefabUuid,
+    base: Option<&BTreeMap<String, String>>,
+    ours: Option<&BTreeMap<String, String>>,
+    theirs: Option<&BTreeMap<String, String>>,
+    conflicts: &mut Vec<MergeConflict>,
+) -> BTreeMap<String, String> {
+    let mut names = HashSet::new();
+    for values in [base, ours, theirs].iter().flatten() {
+        names.extend(values.keys().cloned());
+    }
+
+    let mut merged = BTreeMap::new();
+    for name in sorted(names.into_iter()) {
+        let base_value = base.and_then(|values| values.get(&name));
+        let our_value = ours.and_then(|values| values.get(&name));
+        let their_value = theirs.and_then(|values| values.get(&name));
+
+        let merged_value = if our_value == base_value {
+            their_value
+        } else if their_value == base_value || their_value == our_value {
+            our_value
+        } else {
+            conflicts.push(MergeConflict::ParameterValueChangedDifferently { prefab_ref, name });
+            continue;
+        };
+
+        if let Some(value) = merged_value {
+            merged.insert(name, value.clone());
+        }
+    }
+    merged
+}
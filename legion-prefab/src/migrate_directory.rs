@@ -0,0 +1,218 @@
+use crate::format::migrations::FormatMigrationError;
+use crate::format::ComponentTypeUuid;
+use crate::{ComponentMigration, MigratePrefabError, MigratedComponent};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::path::{Path, PathBuf};
+
+/// The changes made to one prefab file by `migrate_prefab_directory`
+#[derive(Debug, Clone)]
+pub struct MigratedFile {
+    pub path: PathBuf,
+    /// The format version the file was upgraded from, if it wasn't current
+    pub from_format_version: Option<u32>,
+    pub migrated_components: Vec<MigratedComponent>,
+}
+
+#[derive(Debug)]
+pub enum MigrateFileError {
+    Io(std::io::Error),
+    Format(FormatMigrationError),
+    Component(MigratePrefabError),
+}
+
+/// What `migrate_prefab_directory` did. The `Display` impl writes a human-readable report.
+#[derive(Debug)]
+pub struct MigrationReport {
+    pub dry_run: bool,
+    /// The number of prefab files found
+    pub file_count: usize,
+    /// Files that were upgraded (or would be, for a dry run), ordered by path
+    pub migrated: Vec<MigratedFile>,
+    /// Files that couldn't be upgraded, ordered by path. They are left unchanged.
+    pub failed: Vec<(PathBuf, MigrateFileError)>,
+    type_names: HashMap<ComponentTypeUuid, &'static str>,
+}
+
+/// Upgrades every `.prefab` file under a directory in place: first to the current format version,
+/// then through the component migrations (keyed by the type they migrate from, like
+/// `migrate_ron_prefab`). Use `iter_component_migrations` to run every registered migration.
+///
+/// Files are replaced by renaming a fully written copy over them, so an interrupted run never
+/// leaves a file half written. Current files aren't touched, and a file that fails to upgrade
+/// doesn't stop the others, so the runner can be run again after fixing the failures.
+pub fn migrate_prefab_directory<T: BuildHasher>(
+    dir: &Path,
+    migrations: &HashMap<ComponentTypeUuid, ComponentMigration, T>,
+    dry_run: bool,
+) -> std::io::Result<MigrationReport> {
+    let files = find_prefab_files(dir)?;
+
+    let mut type_names = HashMap::new();
+    for migration in migrations.values() {
+        type_names.insert(*migration.from_type(), migration.from_type_name());
+        type_names.insert(*migration.to_type(), migration.to_type_name());
+    }
+    let mut report = MigrationReport {
+        dry_run,
+        file_count: files.len(),
+        migrated: vec![],
+        failed: vec![],
+        type_names,
+    };
+
+    for path in files {
+        match migrate_file(&path, migrations, dry_run) {
+            Ok(Some(migrated)) => report.migrated.push(migrated),
+            Ok(None) => {}
+            Err(e) => report.failed.push((path, e)),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Finds every `.prefab` file under a directory and its subdirectories, ordered by path
+pub fn find_prefab_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    collect_prefab_files(dir, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+fn collect_prefab_files(
+    dir: &Path,
+    files: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_prefab_files(&path, files)?;
+        } else if path.extension().map(|ext| ext == "prefab").unwrap_or(false) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn migrate_file<T: BuildHasher>(
+    path: &Path,
+    migrations: &HashMap<ComponentTypeUuid, ComponentMigration, T>,
+    dry_run: bool,
+) -> Result<Option<MigratedFile>, MigrateFileError> {
+    let mut text = std::fs::read_to_string(path).map_err(MigrateFileError::Io)?;
+
+    // The format has to be upgraded first, component migrations only understand the current one
+    let format_upgrade =
+        crate::format::migrations::upgrade_ron_format(&text).map_err(MigrateFileError::Format)?;
+    let from_format_version = format_upgrade.map(|(from_version, upgraded)| {
+        text = upgraded;
+        from_version
+    });
+
+    let mut migrated_components = vec![];
+    if let Some((migrated_text, migrated)) =
+        crate::migrate_ron_prefab(&text, migrations).map_err(MigrateFileError::Component)?
+    {
+        text = migrated_text;
+        migrated_components = migrated;
+    }
+
+    if from_format_version.is_none() && migrated_components.is_empty() {
+        return Ok(None);
+    }
+
+    if !dry_run {
+        let temp_path = path.with_extension("prefab.migrating");
+        std::fs::write(&temp_path, text).map_err(MigrateFileError::Io)?;
+        std::fs::rename(&temp_path, path).map_err(MigrateFileError::Io)?;
+    }
+
+    Ok(Some(MigratedFile {
+        path: path.to_path_buf(),
+        from_format_version,
+        migrated_components,
+    }))
+}
+
+impl MigrationReport {
+    fn type_name(
+        &self,
+        component_type: &ComponentTypeUuid,
+    ) -> String {
+        match self.type_names.get(component_type) {
+            Some(name) => name.to_string(),
+            None => uuid::Uuid::from_bytes(*component_type).to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for MigrationReport {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        for migrated in &self.migrated {
+            writeln!(f, "{}", migrated.path.display())?;
+            if let Some(from_version) = migrated.from_format_version {
+                writeln!(
+                    f,
+                    "    format version {} -> {}",
+                    from_version,
+                    crate::format::FORMAT_VERSION
+                )?;
+            }
+            for component in &migrated.migrated_components {
+                let entity = component.entity.map(uuid::Uuid::from_bytes);
+                let from_type = self.type_name(&component.from_type);
+                let to_type = self.type_name(&component.to_type);
+                match (component.prefab_ref, entity, component.template) {
+                    (Some(prefab_ref), Some(entity), _) => writeln!(
+                        f,
+                        "    override on entity {} in prefab ref {}: {} -> {}",
+                        entity,
+                        uuid::Uuid::from_bytes(prefab_ref),
+                        from_type,
+                        to_type
+                    )?,
+                    (None, Some(entity), Some(_)) => writeln!(
+                        f,
+                        "    template diff on entity {}: {} -> {}",
+                        entity, from_type, to_type
+                    )?,
+                    (_, None, Some(template)) => writeln!(
+                        f,
+                        "    template {}: {} -> {}",
+                        uuid::Uuid::from_bytes(template),
+                        from_type,
+                        to_type
+                    )?,
+                    (_, Some(entity), None) => {
+                        writeln!(f, "    entity {}: {} -> {}", entity, from_type, to_type)?
+                    }
+                    (_, None, None) => unreachable!(),
+                }
+            }
+        }
+        for (path, e) in &self.failed {
+            writeln!(f, "{}: failed to upgrade prefab: {:?}", path.display(), e)?;
+        }
+
+        let verb = if self.dry_run {
+            "would be upgraded"
+        } else {
+            "upgraded"
+        };
+        write!(
+            f,
+            "{} of {} prefab files {}",
+            self.migrated.len(),
+            self.file_count,
+            verb
+        )?;
+        if !self.failed.is_empty() {
+            write!(f, ", {} failed", self.failed.len())?;
+        }
+        writeln!(f)
+    }
+}
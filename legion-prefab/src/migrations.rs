@@ -0,0 +1,542 @@
+use crate::format::ron_patch::{RonPatchError, RonPrefabDocument};
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid, TemplateUuid};
+use crate::prefab_diff::{DiffCommand, DiffPathElement};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_diff::SerdeDiff;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use type_uuid::TypeUuid;
+
+type MigrateFn = fn(&str) -> Result<String, String>;
+
+// The top-level fields of the new type, found by serializing the migrated default. None if the new
+// type doesn't serialize as a struct.
+type FieldNamesFn = fn() -> Option<Vec<String>>;
+
+/// Converts data saved for one component type into another component type, so that prefab files
+/// can be upgraded after a component's schema changes. The old type keeps its UUID and the new
+/// type must have a different one, so it's always clear which data still needs migrating. The old
+/// type only needs to be deserializable, it does not need to be registered as a component.
+///
+/// Component data is migrated with the `From` conversion. Diffs (overrides and template diffs)
+/// can't be converted that way, since they only hold the fields they change. By default they are
+/// migrated by renaming the top-level fields they change (see `with_field_renames`), keeping the
+/// values as they are. That is only correct if the conversion moves fields without changing their
+/// values. Diffs that change a field the new type doesn't have fail to migrate. A conversion that
+/// does more needs its own diff migration, see `with_diff_migration`.
+#[derive(Clone)]
+pub struct ComponentMigration {
+    from_type: ComponentTypeUuid,
+    to_type: ComponentTypeUuid,
+    from_type_name: &'static str,
+    to_type_name: &'static str,
+    migrate_data_fn: MigrateFn,
+    migrate_diff_fn: Option<MigrateFn>,
+    to_field_names_fn: FieldNamesFn,
+    field_renames: &'static [(&'static str, &'static str)],
+}
+
+impl ComponentMigration {
+    pub fn from_type(&self) -> &ComponentTypeUuid {
+        &self.from_type
+    }
+
+    pub fn to_type(&self) -> &ComponentTypeUuid {
+        &self.to_type
+    }
+
+    pub fn from_type_name(&self) -> &'static str {
+        self.from_type_name
+    }
+
+    pub fn to_type_name(&self) -> &'static str {
+        self.to_type_name
+    }
+
+    // Converts RON component data of the old type to RON component data of the new type
+    pub fn migrate_data(
+        &self,
+        data: &str,
+    ) -> Result<String, String> {
+        (self.migrate_data_fn)(data)
+    }
+
+    // Converts a RON serde_diff of the old type to a RON serde_diff of the new type, see the type
+    // docs
+    pub fn migrate_diff(
+        &self,
+        diff: &str,
+    ) -> Result<String, String> {
+        match self.migrate_diff_fn {
+            Some(migrate_diff_fn) => migrate_diff_fn(diff),
+            None => self.rename_diff_fields(diff),
+        }
+    }
+
+    /// Fields of the old type that are called differently in the new type, as `(old, new)` pairs.
+    /// Diffs that change them are rewritten to change the new field instead.
+    pub fn with_field_renames(
+        mut self,
+        field_renames: &'static [(&'static str, &'static str)],
+    ) -> Self {
+        self.field_renames = field_renames;
+        self
+    }
+
+    /// Migrates diffs with a function instead of renaming their fields. It gets a RON serde_diff of
+    /// the old type and returns one of the new type, or an error if the diff can't be migrated
+    /// exactly.
+    pub fn with_diff_migration(
+        mut self,
+        migrate_diff_fn: fn(&str) -> Result<String, String>,
+    ) -> Self {
+        self.migrate_diff_fn = Some(migrate_diff_fn);
+        self
+    }
+
+    // Only the top-level fields are rewritten. Everything inside them, including changes to
+    // collections, is kept as it is. A diff that replaces the whole value (i.e. of an enum) holds
+    // complete data, which is migrated like component data.
+    fn rename_diff_fields(
+        &self,
+        diff: &str,
+    ) -> Result<String, String> {
+        let mut commands: Vec<DiffCommand> = ron::de::from_str(diff).map_err(|e| e.to_string())?;
+        let mut to_field_names = None;
+        let mut depth = 0;
+        for command in &mut commands {
+            match command {
+                DiffCommand::Enter(element) => {
+                    if depth == 0 {
+                        self.rename_field(element, &mut to_field_names)?;
+                    }
+                    depth += 1;
+                }
+                DiffCommand::EnterKey(_) => depth += 1,
+                DiffCommand::Exit => depth = depth.saturating_sub(1),
+                DiffCommand::Value(value) if depth == 0 => {
+                    let data = ron::ser::to_string(&*value).map_err(|e| e.to_string())?;
+                    let new_data = self.migrate_data(&data)?;
+                    *value = ron::de::from_str(&new_data).map_err(|e| e.to_string())?;
+                }
+                _ => {}
+            }
+        }
+        ron::ser::to_string(&commands).map_err(|e| e.to_string())
+    }
+
+    fn rename_field(
+        &self,
+        element: &mut DiffPathElement,
+        to_field_names: &mut Option<Option<Vec<String>>>,
+    ) -> Result<(), String> {
+        let name = match element {
+            DiffPathElement::Field(name) => name,
+            _ => {
+                return Err(format!(
+                    "a diff of {} that doesn't change fields by name can't be migrated to {} \
+                     without a diff migration",
+                    self.from_type_name, self.to_type_name
+                ))
+            }
+        };
+        if let Some((_, new_name)) = self
+            .field_renames
+            .iter()
+            .find(|(old, _)| *old == name.as_str())
+        {
+            *name = new_name.to_string();
+        }
+
+        let to_field_names = to_field_names.get_or_insert_with(self.to_field_names_fn);
+        let exists = to_field_names
+            .as_ref()
+            .map(|names| names.contains(&*name))
+            .unwrap_or(false);
+        if exists {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} has no field {} to migrate a diff of {} to, add a field rename or a diff \
+                 migration",
+                self.to_type_name, name, self.from_type_name
+            ))
+        }
+    }
+
+    pub fn of<
+        Old: TypeUuid + DeserializeOwned + SerdeDiff + Default + 'static,
+        New: TypeUuid + From<Old> + Serialize + SerdeDiff + 'static,
+    >() -> Self {
+        Self {
+            from_type: Old::UUID,
+            to_type: New::UUID,
+            from_type_name: std::any::type_name::<Old>(),
+            to_type_name: std::any::type_name::<New>(),
+            migrate_data_fn: |data| {
+                let old: Old = ron::de::from_str(data).map_err(|e| e.to_string())?;
+                let new = New::from(old);
+                let mut ron_ser =
+                    ron::ser::Serializer::new(Some(crate::canonical_pretty_config()), true);
+                new.serialize(&mut ron_ser).map_err(|e| e.to_string())?;
+                Ok(ron_ser.into_output_string())
+            },
+            migrate_diff_fn: None,
+            to_field_names_fn: || {
+                let data = ron::ser::to_string(&New::from(Old::default())).ok()?;
+                match ron::de::from_str(&data).ok()? {
+                    ron::Value::Map(fields) => Some(
+                        fields
+                            .keys()
+                            .filter_map(|key| match key {
+                                ron::Value::String(name) => Some(name.clone()),
+                                _ => None,
+                            })
+                            .collect(),
+                    ),
+                    _ => None,
+                }
+            },
+            field_renames: &[],
+        }
+    }
+}
+
+inventory::collect!(ComponentMigration);
+
+pub fn iter_component_migrations() -> impl Iterator<Item = &'static ComponentMigration> {
+    inventory::iter::<ComponentMigration>.into_iter()
+}
+
+/// Registers a `ComponentMigration` from one component type to another, so that
+/// `migrate_ron_prefab` and `prefab upgrade` pick it up.
+///
+/// `register_component_migration!(HealthV1, Health, renames = [("hp", "health")])` also renames
+/// fields in overrides, see `ComponentMigration::with_field_renames`.
+///
+/// `register_component_migration!(HealthV1, Health, diff = migrate_health_diff)` migrates overrides
+/// with a function, see `ComponentMigration::with_diff_migration`.
+#[macro_export]
+macro_rules! register_component_migration {
+    ($old_type:ty, $new_type:ty) => {
+        $crate::register_component_migration!(legion_prefab; $old_type, $new_type);
+    };
+    ($krate:ident; $old_type:ty, $new_type:ty) => {
+        $crate::inventory::submit!{
+            #![crate = $krate]
+            $crate::ComponentMigration::of::<$old_type, $new_type>()
+        }
+    };
+    ($old_type:ty, $new_type:ty, renames = [$(($old:literal, $new:literal)),* $(,)?]) => {
+        $crate::register_component_migration!(
+            legion_prefab; $old_type, $new_type, renames = [$(($old, $new)),*]
+        );
+    };
+    (
+        $krate:ident; $old_type:ty, $new_type:ty,
+        renames = [$(($old:literal, $new:literal)),* $(,)?]
+    ) => {
+        $crate::inventory::submit!{
+            #![crate = $krate]
+            $crate::ComponentMigration::of::<$old_type, $new_type>()
+                .with_field_renames(&[$(($old, $new)),*])
+        }
+    };
+    ($old_type:ty, $new_type:ty, diff = $migrate_diff_fn:expr) => {
+        $crate::register_component_migration!(
+            legion_prefab; $old_type, $new_type, diff = $migrate_diff_fn
+        );
+    };
+    ($krate:ident; $old_type:ty, $new_type:ty, diff = $migrate_diff_fn:expr) => {
+        $crate::inventory::submit!{
+            #![crate = $krate]
+            $crate::ComponentMigration::of::<$old_type, $new_type>()
+                .with_diff_migration($migrate_diff_fn)
+        }
+    };
+}
+
+/// A component or component override that was migrated to a new type
+#[derive(Debug, Clone)]
+pub struct MigratedComponent {
+    /// The prefab ref or base prefab, if this was an override
+    pub prefab_ref: Option<PrefabUuid>,
+    /// None for a component of an entity template
+    pub entity: Option<EntityUuid>,
+    /// The entity template, if this was a template component or the diff an entity applies to
+    /// one
+    pub template: Option<TemplateUuid>,
+    pub from_type: ComponentTypeUuid,
+    pub to_type: ComponentTypeUuid,
+}
+
+#[derive(Debug)]
+pub enum MigratePrefabError {
+    /// The source text could not be scanned as a prefab
+    Parse(RonPatchError),
+    /// A migration failed. Contains the type being migrated from and the error
+    MigrationFailed(ComponentTypeUuid, String),
+    /// Migrations kept applying, probably because they form a cycle
+    TooManyPasses,
+}
+
+/// Applies component migrations (keyed by the type they migrate from) to RON prefab source text.
+/// Only the migrated components and overrides are rewritten, the rest of the file is unchanged.
+/// Migrations are chained, so data migrated to a type that has a migration of its own is migrated
+/// again. Returns None if nothing needed migrating.
+pub fn migrate_ron_prefab<T: BuildHasher>(
+    source: &str,
+    migrations: &HashMap<ComponentTypeUuid, ComponentMigration, T>,
+) -> Result<Option<(String, Vec<MigratedComponent>)>, MigratePrefabError> {
+    let mut text = source.to_string();
+    let mut migrated = vec![];
+
+    // Each pass migrates everything by one step. Chains can't be longer than the number of
+    // migrations unless there's a cycle.
+    for _ in 0..=migrations.len() {
+        match migrate_pass(&text, migrations)? {
+            Some((new_text, mut pass_migrated)) => {
+                text = new_text;
+                migrated.append(&mut pass_migrated);
+            }
+            None if migrated.is_empty() => return Ok(None),
+            None => return Ok(Some((text, migrated))),
+        }
+    }
+
+    Err(MigratePrefabError::TooManyPasses)
+}
+
+fn migrate_pass<T: BuildHasher>(
+    source: &str,
+    migrations: &HashMap<ComponentTypeUuid, ComponentMigration, T>,
+) -> Result<Option<(String, Vec<MigratedComponent>)>, MigratePrefabError> {
+    let mut doc = RonPrefabDocument::parse(source).map_err(MigratePrefabError::Parse)?;
+    let mut migrated = vec![];
+
+    for entity in doc.entities() {
+        for component_type in doc.component_types(&entity) {
+            let migration = match migrations.get(&component_type) {
+                Some(migration) => migration,
+                None => continue,
+            };
+            let failed = |e| MigratePrefabError::MigrationFailed(component_type, e);
+
+            let data = doc.component_data_text(&entity, &component_type).unwrap();
+            let new_data = migration.migrate_data(&data).map_err(failed)?;
+            doc.replace_component_data(&entity, &component_type, &new_data)
+                .map_err(MigratePrefabError::Parse)?;
+            doc.replace_component_type(&entity, &component_type, migration.to_type())
+                .map_err(MigratePrefabError::Parse)?;
+
+            migrated.push(MigratedComponent {
+                prefab_ref: None,
+                entity: Some(entity),
+                template: None,
+                from_type: component_type,
+                to_type: *migration.to_type(),
+            });
+        }
+
+        let template = doc.entity_template(&entity);
+        for component_type in doc.template_diff_types(&entity) {
+            let migration = match migrations.get(&component_type) {
+                Some(migration) => migration,
+                None => continue,
+            };
+
+            let diff_text = doc.template_diff_text(&entity, &component_type).unwrap();
+            let new_diff_text = migrate_diff_text(migration, &diff_text)?;
+            doc.replace_template_diff(&entity, &component_type, &new_diff_text)
+                .map_err(MigratePrefabError::Parse)?;
+            doc.replace_template_diff_component_type(&entity, &component_type, migration.to_type())
+                .map_err(MigratePrefabError::Parse)?;
+
+            migrated.push(MigratedComponent {
+                prefab_ref: None,
+                entity: Some(entity),
+                template,
+                from_type: component_type,
+                to_type: *migration.to_type(),
+            });
+        }
+    }
+
+    for template in doc.templates() {
+        for component_type in doc.template_component_types(&template) {
+            let migration = match migrations.get(&component_type) {
+                Some(migration) => migration,
+                None => continue,
+            };
+            let failed = |e| MigratePrefabError::MigrationFailed(component_type, e);
+
+            let data = doc
+                .template_component_data_text(&template, &component_type)
+                .unwrap();
+            let new_data = migration.migrate_data(&data).map_err(failed)?;
+            doc.replace_template_component_data(&template, &component_type, &new_data)
+                .map_err(MigratePrefabError::Parse)?;
+            doc.replace_template_component_type(&template, &component_type, migration.to_type())
+                .map_err(MigratePrefabError::Parse)?;
+
+            migrated.push(MigratedComponent {
+                prefab_ref: None,
+                entity: None,
+                template: Some(template),
+                from_type: component_type,
+                to_type: *migration.to_type(),
+            });
+        }
+    }
+
+    for prefab_ref in doc.base_prefab().into_iter().chain(doc.prefab_refs()) {
+        for (entity, component_types) in doc.prefab_ref_overrides(&prefab_ref) {
+            for component_type in component_types {
+                let migration = match migrations.get(&component_type) {
+                    Some(migration) => migration,
+                    None => continue,
+                };
+
+                let diff_text = doc
+                    .override_diff_text(&prefab_ref, &entity, &component_type)
+                    .unwrap();
+                let new_diff_text = migrate_diff_text(migration, &diff_text)?;
+                doc.replace_override_diff(&prefab_ref, &entity, &component_type, &new_diff_text)
+                    .map_err(MigratePrefabError::Parse)?;
+                doc.replace_override_component_type(
+                    &prefab_ref,
+                    &entity,
+                    &component_type,
+                    migration.to_type(),
+                )
+                .map_err(MigratePrefabError::Parse)?;
+
+                migrated.push(MigratedComponent {
+                    prefab_ref: Some(prefab_ref),
+                    entity: Some(entity),
+                    template: None,
+                    from_type: component_type,
+                    to_type: *migration.to_type(),
+                });
+            }
+        }
+    }
+
+    if migrated.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some((doc.finish(), migrated)))
+    }
+}
+
+// Override and template diffs are stored as a RON string containing the RON-encoded diff
+fn migrate_diff_text(
+    migration: &ComponentMigration,
+    diff_text: &str,
+) -> Result<String, MigratePrefabError> {
+    let failed = |e| MigratePrefabError::MigrationFailed(*migration.from_type(), e);
+    let diff: String = ron::de::from_str(diff_text).map_err(|e| failed(e.to_string()))?;
+    let new_diff = migration.migrate_diff(&diff).map_err(failed)?;
+    ron::ser::to_string(&new_diff).map_err(|e| failed(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::DeserializeSeed;
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, SerdeDiff, TypeUuid)]
+    #[uuid = "7d0f2c4e-8a3b-4f51-9e6d-2b1c0a9f8e01"]
+    struct InventoryV1 {
+        hp: u32,
+        items: Vec<u32>,
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, SerdeDiff, TypeUuid)]
+    #[uuid = "7d0f2c4e-8a3b-4f51-9e6d-2b1c0a9f8e02"]
+    struct Inventory {
+        health: u32,
+        items: Vec<u32>,
+    }
+
+    impl From<InventoryV1> for Inventory {
+        fn from(old: InventoryV1) -> Self {
+            Inventory {
+                health: old.hp,
+                items: old.items,
+            }
+        }
+    }
+
+    fn migration() -> ComponentMigration {
+        ComponentMigration::of::<InventoryV1, Inventory>().with_field_renames(&[("hp", "health")])
+    }
+
+    // Migrates the override that turns `base` into `overridden`, and applies it to the migrated
+    // base
+    fn migrate_override(
+        migration: &ComponentMigration,
+        base: &InventoryV1,
+        overridden: &InventoryV1,
+    ) -> Result<Inventory, String> {
+        let diff = ron::ser::to_string(&serde_diff::Diff::serializable(base, overridden)).unwrap();
+        let new_diff = migration.migrate_diff(&diff)?;
+
+        let mut new = Inventory::from(base.clone());
+        let mut de = ron::de::Deserializer::from_str(&new_diff).unwrap();
+        serde_diff::Apply::deserializable(&mut new)
+            .deserialize(&mut de)
+            .unwrap();
+        Ok(new)
+    }
+
+    #[test]
+    fn override_to_a_default_value_is_kept() {
+        let base = InventoryV1 {
+            hp: 100,
+            items: vec![],
+        };
+        let overridden = InventoryV1 {
+            hp: 0,
+            items: vec![],
+        };
+        assert_eq!(
+            migrate_override(&migration(), &base, &overridden),
+            Ok(Inventory::from(overridden))
+        );
+    }
+
+    #[test]
+    fn vec_overrides_are_kept() {
+        let base = InventoryV1 {
+            hp: 100,
+            items: vec![1, 2, 3],
+        };
+        for items in vec![vec![1, 2], vec![1, 5, 3], vec![1, 2, 3, 4], vec![]] {
+            let overridden = InventoryV1 { hp: 100, items };
+            assert_eq!(
+                migrate_override(&migration(), &base, &overridden),
+                Ok(Inventory::from(overridden))
+            );
+        }
+    }
+
+    #[test]
+    fn missing_field_is_an_error() {
+        let base = InventoryV1::default();
+        let overridden = InventoryV1 {
+            hp: 5,
+            items: vec![],
+        };
+        let migration = ComponentMigration::of::<InventoryV1, Inventory>();
+        assert!(migrate_override(&migration, &base, &overridden).is_err());
+    }
+
+    #[test]
+    fn diff_migration_replaces_renames() {
+        let migration = ComponentMigration::of::<InventoryV1, Inventory>()
+            .with_diff_migration(|_| Err("not exact".to_string()));
+        assert_eq!(migration.migrate_diff("[]"), Err("not exact".to_string()));
+    }
+}
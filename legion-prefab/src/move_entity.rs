@@ -0,0 +1,308 @@
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid};
+use crate::world_serde::EntityUuidMapper;
+use crate::{ComponentOverride, ComponentRegistration, Prefab};
+use legion::storage::ComponentTypeId;
+use legion::world::Allocate;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+#[derive(Debug)]
+pub enum MoveEntityError {
+    /// The entity isn't stored in the source prefab
+    EntityNotInSource(EntityUuid),
+    /// The destination prefab already has an entity with the UUID
+    EntityInDestination(EntityUuid),
+    /// The entity has a component of a type that isn't registered
+    UnregisteredComponent(ComponentTypeId),
+    /// A component of the entity refers to an entity that has no UUID in the source prefab, so
+    /// the reference can't be moved
+    UnknownEntityReference(ComponentTypeUuid),
+}
+
+/// Something `move_entity` couldn't fix by itself. The move still happened.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MoveEntityIssue {
+    /// A component of the moved entity refers to an entity that isn't in the destination prefab,
+    /// and that the destination prefab didn't refer to before
+    ReferenceFromMovedEntity { component_type: ComponentTypeUuid },
+    /// A component of an entity of the source prefab refers to the moved entity
+    ReferenceToMovedEntity {
+        entity: EntityUuid,
+        component_type: ComponentTypeUuid,
+    },
+    /// A parameter of the source prefab was bound to the moved entity. The binding was removed.
+    ParameterBindingRemoved {
+        parameter: String,
+        component_type: ComponentTypeUuid,
+    },
+    /// The moved entity had children, or a parent, in the source prefab. The entity was removed
+    /// from the source prefab's hierarchy.
+    HierarchyEntryRemoved { parent: EntityUuid },
+    /// The destination prefab overrode a component that the moved entity doesn't have, so the
+    /// override was dropped
+    OverrideDropped { component_type: ComponentTypeUuid },
+}
+
+/// What `move_entity` did and what it couldn't fix
+#[derive(Debug, Default)]
+pub struct MoveEntityReport {
+    /// Overrides of the entity by the destination prefab that were applied to the moved entity
+    pub applied_overrides: Vec<ComponentTypeUuid>,
+    pub issues: Vec<MoveEntityIssue>,
+}
+
+/// Moves an entity of `src_prefab` into `dst_prefab`, keeping its UUID.
+///
+/// Entity references are fixed up by UUID: references between the moved entity's components and
+/// to entities of `dst_prefab` keep working. If `dst_prefab` references `src_prefab` and
+/// overrides the entity, the overrides are applied to the moved components and removed. Anything
+/// else that referred to the entity is listed in the report. Prefabs other than the two given may
+/// still override the entity through `src_prefab`, which this can't see.
+pub fn move_entity<S: BuildHasher>(
+    src_prefab: &mut Prefab,
+    entity_uuid: &EntityUuid,
+    dst_prefab: &mut Prefab,
+    registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+) -> Result<MoveEntityReport, MoveEntityError> {
+    let src_entity = *src_prefab
+        .prefab_meta
+        .entities
+        .get(entity_uuid)
+        .ok_or(MoveEntityError::EntityNotInSource(*entity_uuid))?;
+    if dst_prefab.prefab_meta.entities.contains_key(entity_uuid) {
+        return Err(MoveEntityError::EntityInDestination(*entity_uuid));
+    }
+
+    let mut registrations = vec![];
+    let component_types = src_prefab
+        .world
+        .entry_ref(src_entity)
+        .expect("prefab entity not in world")
+        .archetype()
+        .layout()
+        .component_types()
+        .to_vec();
+    for component_type in component_types {
+        let registration = registered_components
+            .get(&component_type)
+            .ok_or(MoveEntityError::UnregisteredComponent(component_type))?;
+        // Runtime-only data isn't part of the prefab, so it isn't moved
+        if !registration.is_clone_only() {
+            registrations.push(registration);
+        }
+    }
+
+    let mut report = MoveEntityReport::default();
+
+    //
+    // Copy the components through RON text so that entity references are written as UUIDs by the
+    // source prefab and read back as entities of the destination prefab
+    //
+    let mut src_entities = src_prefab.entity_uuids();
+    let mut allocator = Allocate::new();
+    let mut component_data = vec![];
+    {
+        let mapper = EntityUuidMapper {
+            entity_map: RefCell::new(&mut src_entities),
+            allocator: RefCell::new(&mut allocator),
+        };
+        for registration in &registrations {
+            let mut data = None;
+            mapper.scope(|| {
+                registration.serialize_single(&src_prefab.world, src_entity, &mut |component| {
+                    data = Some(ron::ser::to_string(component));
+                })
+            });
+            if let Some(data) = data {
+                let data = data
+                    .map_err(|_| MoveEntityError::UnknownEntityReference(*registration.uuid()))?;
+                component_data.push(data);
+            }
+        }
+    }
+
+    let dst_entity = dst_prefab.world.push(());
+    dst_prefab
+        .prefab_meta
+        .entities
+        .insert(*entity_uuid, dst_entity);
+    let mut dst_entities = dst_prefab.entity_uuids();
+    {
+        let mapper = EntityUuidMapper {
+            entity_map: RefCell::new(&mut dst_entities),
+            allocator: RefCell::new(&mut allocator),
+        };
+        for (registration, data) in registrations.iter().zip(&component_data) {
+            let known_entities = mapper.entity_map.borrow().len();
+            mapper.scope(|| {
+                let mut deserializer = ron::de::Deserializer::from_str(data).unwrap();
+                let mut de = erased_serde::Deserializer::erase(&mut deserializer);
+                registration.add_to_entity(&mut de, &mut dst_prefab.world, dst_entity);
+            });
+            if mapper.entity_map.borrow().len() != known_entities {
+                report
+                    .issues
+                    .push(MoveEntityIssue::ReferenceFromMovedEntity {
+                        component_type: *registration.uuid(),
+                    });
+            }
+        }
+
+        // The destination prefab's overrides of the entity become part of its data
+        let src_prefab_id = src_prefab.prefab_id();
+        if let Some(overrides) = take_overrides(dst_prefab, &src_prefab_id, entity_uuid) {
+            for component_override in overrides {
+                let registration = registrations
+                    .iter()
+                    .find(|registration| *registration.uuid() == component_override.component_type);
+                match registration {
+                    Some(registration) => {
+                        mapper.scope(|| {
+                            let mut deserializer =
+                                ron::de::Deserializer::from_str(&component_override.data).unwrap();
+                            let mut de = erased_serde::Deserializer::erase(&mut deserializer);
+                            registration.apply_diff(&mut de, &mut dst_prefab.world, dst_entity);
+                        });
+                        report
+                            .applied_overrides
+                            .push(component_override.component_type);
+                    }
+                    None => report.issues.push(MoveEntityIssue::OverrideDropped {
+                        component_type: component_override.component_type,
+                    }),
+                }
+            }
+        }
+    }
+
+    // Entities the moved components refer to that aren't in the destination prefab
+    for (uuid, entity) in dst_entities.iter() {
+        if !dst_prefab.prefab_meta.entities.contains_key(uuid) {
+            dst_prefab.prefab_meta.entity_refs.insert(*uuid, *entity);
+        }
+    }
+
+    //
+    // Remove the entity from the source prefab
+    //
+    src_prefab.prefab_meta.entities.remove(entity_uuid);
+    src_prefab.world.remove(src_entity);
+
+    if let Some(layer) = src_prefab.prefab_meta.entity_layers.remove(entity_uuid) {
+        dst_prefab
+            .prefab_meta
+            .entity_layers
+            .insert(*entity_uuid, layer);
+    }
+
+    for parameter in &mut src_prefab.prefab_meta.parameters {
+        let name = &parameter.name;
+        parameter.bindings.retain(|binding| {
+            if binding.entity != *entity_uuid {
+                return true;
+            }
+            report
+                .issues
+                .push(MoveEntityIssue::ParameterBindingRemoved {
+                    parameter: name.clone(),
+                    component_type: binding.component_type,
+                });
+            false
+        });
+    }
+
+    let hierarchy = &mut src_prefab.prefab_meta.hierarchy;
+    if hierarchy.remove(entity_uuid).is_some() {
+        report.issues.push(MoveEntityIssue::HierarchyEntryRemoved {
+            parent: *entity_uuid,
+        });
+    }
+    let mut parents: Vec<_> = hierarchy.keys().cloned().collect();
+    parents.sort();
+    for parent in parents {
+        let children = hierarchy.get_mut(&parent).unwrap();
+        let child_count = children.len();
+        children.retain(|child| child != entity_uuid);
+        if children.len() != child_count {
+            report
+                .issues
+                .push(MoveEntityIssue::HierarchyEntryRemoved { parent });
+        }
+        if children.is_empty() {
+            hierarchy.remove(&parent);
+        }
+    }
+
+    report
+        .issues
+        .extend(references_to_entity(src_prefab, registered_components));
+
+    // Components left in the source prefab may still refer to the entity, now in another prefab
+    src_prefab
+        .prefab_meta
+        .entity_refs
+        .insert(*entity_uuid, src_entity);
+
+    Ok(report)
+}
+
+fn take_overrides(
+    prefab: &mut Prefab,
+    prefab_ref: &PrefabUuid,
+    entity_uuid: &EntityUuid,
+) -> Option<Vec<ComponentOverride>> {
+    prefab
+        .prefab_meta
+        .prefab_refs
+        .get_mut(prefab_ref)
+        .and_then(|prefab_ref| prefab_ref.overrides.remove(entity_uuid))
+}
+
+// Serializes every component of the prefab, which no longer knows the removed entity's UUID, so a
+// reference to the removed entity makes the serialization fail
+fn references_to_entity<S: BuildHasher>(
+    prefab: &Prefab,
+    registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+) -> Vec<MoveEntityIssue> {
+    let world = &prefab.world;
+    let entities = &prefab.prefab_meta.entities;
+    let mut entity_map = prefab.entity_uuids();
+    let mut allocator = Allocate::new();
+    let mapper = EntityUuidMapper {
+        entity_map: RefCell::new(&mut entity_map),
+        allocator: RefCell::new(&mut allocator),
+    };
+
+    let mut sorted_entities: Vec<_> = entities.iter().collect();
+    sorted_entities.sort_by_key(|(entity_uuid, _)| **entity_uuid);
+
+    let mut issues = vec![];
+    for (entity_uuid, entity) in sorted_entities {
+        let component_types = match world.entry_ref(*entity) {
+            Ok(entry) => entry.archetype().layout().component_types().to_vec(),
+            Err(_) => continue,
+        };
+
+        for component_type in &component_types {
+            let registration = match registered_components.get(component_type) {
+                Some(registration) if !registration.is_clone_only() => registration,
+                _ => continue,
+            };
+
+            let mut result = Ok(());
+            mapper.scope(|| {
+                registration.serialize_single(world, *entity, &mut |component| {
+                    result = crate::diff_output::serialize_discarded(component);
+                })
+            });
+            if result.is_err() {
+                issues.push(MoveEntityIssue::ReferenceToMovedEntity {
+                    entity: *entity_uuid,
+                    component_type: *registration.uuid(),
+                });
+            }
+        }
+    }
+    issues
+}
@@ -0,0 +1,37 @@
+//! Helpers for loading and saving legion prefabs as MessagePack
+use crate::{
+    CookedPrefab, Prefab, PrefabFormatDeserializer, PrefabFormatSerializer, PrefabSerdeContext,
+};
+use std::hash::BuildHasher;
+
+/// Loads a prefab from source data in MessagePack prefab format
+pub fn prefab_from_slice<T: BuildHasher>(
+    bytes: &[u8],
+    context: PrefabSerdeContext<T>,
+) -> Result<Prefab, rmp_serde::decode::Error> {
+    let prefab_deser = PrefabFormatDeserializer::new(context);
+    prefab_format::msgpack::from_slice(bytes, &prefab_deser)?;
+    Ok(prefab_deser.prefab())
+}
+
+/// Saves a prefab as source data in MessagePack prefab format
+pub fn prefab_to_vec<T: BuildHasher>(
+    prefab: &Prefab,
+    context: PrefabSerdeContext<T>,
+) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    let prefab_ser = PrefabFormatSerializer::new(context, prefab);
+    prefab_format::msgpack::to_vec(&prefab_ser, prefab.prefab_id())
+}
+
+/// Loads a cooked prefab from MessagePack
+pub fn cooked_prefab_from_slice(bytes: &[u8]) -> Result<CookedPrefab, rmp_serde::decode::Error> {
+    rmp_serde::from_read_ref(bytes)
+}
+
+/// Saves a cooked prefab as MessagePack. Structs are written as maps to match
+/// `prefab_format::msgpack`.
+pub fn cooked_prefab_to_vec(
+    cooked_prefab: &CookedPrefab
+) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    rmp_serde::to_vec_named(cooked_prefab)
+}
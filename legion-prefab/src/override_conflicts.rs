@@ -0,0 +1,85 @@
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid};
+use crate::cooking::sorted_prefab_refs;
+use crate::Prefab;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+/// Where an override came from: a prefab ref of `prefab` that references `prefab_ref`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OverrideSource {
+    pub prefab: PrefabUuid,
+    pub prefab_ref: PrefabUuid,
+}
+
+/// A component of an entity that is overridden more than once while cooking
+#[derive(Debug, Clone)]
+pub struct OverrideConflict {
+    pub entity: EntityUuid,
+    pub component_type: ComponentTypeUuid,
+    /// Every override of the component, in the order they are applied. Each override only changes
+    /// the fields it contains, so where two overrides change the same field, the later one wins.
+    pub overrides: Vec<OverrideSource>,
+}
+
+impl OverrideConflict {
+    /// The override that is applied last
+    pub fn winner(&self) -> &OverrideSource {
+        self.overrides.last().unwrap()
+    }
+
+    /// True if different refs of the same prefab override the component. Unlike a prefab
+    /// overriding what a prefab it references already overrode, this is rarely intended, since
+    /// the result only depends on the UUIDs of the referenced prefabs.
+    pub fn is_between_sibling_refs(&self) -> bool {
+        self.overrides.iter().enumerate().any(|(i, a)| {
+            self.overrides[i + 1..]
+                .iter()
+                .any(|b| a.prefab == b.prefab && a.prefab_ref != b.prefab_ref)
+        })
+    }
+}
+
+/// Finds components that are overridden more than once when cooking the given prefabs, so that
+/// tools can point them out.
+///
+/// Cooking applies overrides in this order, so the last override of a field wins:
+/// - Prefabs are applied in `prefab_cook_order`, so a prefab's overrides are applied after those
+///   of the prefabs it references
+/// - The refs of a prefab are applied in order of the referenced prefab's UUID
+/// - The overrides in a ref are applied in the order they are listed
+pub fn find_override_conflicts<S: BuildHasher>(
+    prefab_cook_order: &[PrefabUuid],
+    prefab_lookup: &HashMap<PrefabUuid, &Prefab, S>,
+) -> Vec<OverrideConflict> {
+    // Sorted so that conflicts are reported in the same order every time
+    let mut overrides: BTreeMap<(EntityUuid, ComponentTypeUuid), Vec<OverrideSource>> =
+        BTreeMap::new();
+
+    for prefab_id in prefab_cook_order {
+        let prefab = prefab_lookup[prefab_id];
+        for (prefab_ref_id, prefab_ref) in sorted_prefab_refs(prefab) {
+            for (entity, component_overrides) in &prefab_ref.overrides {
+                for component_override in component_overrides {
+                    overrides
+                        .entry((*entity, component_override.component_type))
+                        .or_default()
+                        .push(OverrideSource {
+                            prefab: *prefab_id,
+                            prefab_ref: *prefab_ref_id,
+                        });
+                }
+            }
+        }
+    }
+
+    overrides
+        .into_iter()
+        .filter(|(_, sources)| sources.len() > 1)
+        .map(|((entity, component_type), overrides)| OverrideConflict {
+            entity,
+            component_type,
+            overrides,
+        })
+        .collect()
+}
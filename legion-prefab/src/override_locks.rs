@@ -0,0 +1,87 @@
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid};
+use crate::cooking::sorted_prefab_refs;
+use crate::{ComponentRegistration, Prefab};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+// Fields are locked with `ComponentRegistration::with_locked_fields`. Locking works on whole
+// overrides: an override that changes a locked field is rejected when it's written by
+// `PrefabBuilder` or `Prefab::override_ref_component`, and skipped (including the fields it
+// changes that aren't locked) when cooking, so locked values can't be silently changed per
+// instance.
+
+/// An override that changes fields locked by the component's registration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockedFieldOverride {
+    pub prefab_ref: PrefabUuid,
+    pub entity: EntityUuid,
+    pub component_type: ComponentTypeUuid,
+    /// The locked fields the override changes
+    pub locked_fields: Vec<&'static str>,
+}
+
+/// Finds the overrides of a prefab that change locked fields, in the order they are cooked
+pub fn find_locked_field_overrides<S: BuildHasher>(
+    prefab: &Prefab,
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+) -> Vec<LockedFieldOverride> {
+    let mut locked_field_overrides = vec![];
+    for (prefab_ref_id, prefab_ref) in sorted_prefab_refs(prefab) {
+        let mut entities: Vec<_> = prefab_ref.overrides.iter().collect();
+        entities.sort_by_key(|(entity, _)| **entity);
+
+        for (entity, component_overrides) in entities {
+            for component_override in component_overrides {
+                let registration =
+                    match registered_components_by_uuid.get(&component_override.component_type) {
+                        Some(registration) => registration,
+                        None => continue,
+                    };
+
+                let locked_fields = touched_locked_fields(registration, &component_override.data);
+                if !locked_fields.is_empty() {
+                    locked_field_overrides.push(LockedFieldOverride {
+                        prefab_ref: *prefab_ref_id,
+                        entity: *entity,
+                        component_type: component_override.component_type,
+                        locked_fields,
+                    });
+                }
+            }
+        }
+    }
+
+    locked_field_overrides
+}
+
+// The locked fields of the registration that a RON-encoded override changes. A diff that can't be
+// read is treated as replacing the whole component.
+pub(crate) fn touched_locked_fields(
+    registration: &ComponentRegistration,
+    data: &str,
+) -> Vec<&'static str> {
+    let locked_fields = registration.locked_fields();
+    if locked_fields.is_empty() {
+        return vec![];
+    }
+
+    let paths = crate::field_paths_from_ron(data).unwrap_or_else(|_| vec![String::new()]);
+    locked_fields
+        .iter()
+        .copied()
+        .filter(|locked_field| paths.iter().any(|path| overlaps(path, locked_field)))
+        .collect()
+}
+
+// True if one path is within the other. `stats` overlaps `stats.health` and `stats[2]` but not
+// `stats_max`, and the empty path (the whole component) overlaps everything.
+pub(crate) fn overlaps(
+    a: &str,
+    b: &str,
+) -> bool {
+    let (shorter, longer) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    longer.starts_with(shorter)
+        && (shorter.is_empty()
+            || longer.len() == shorter.len()
+            || matches!(longer.as_bytes()[shorter.len()], b'.' | b'['))
+}
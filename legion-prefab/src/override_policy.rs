@@ -0,0 +1,102 @@
+use serde::de::{DeserializeOwned, SeqAccess};
+use serde::ser::SerializeSeq;
+use serde::Serialize;
+use serde_diff::{ApplyContext, DiffContext, SerdeDiff};
+use type_uuid::TypeUuid;
+
+/// How diffs of a component type are stored, i.e. in transactions and in the overrides of prefab
+/// refs. Set with `ComponentRegistration::with_override_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverridePolicy {
+    /// Only the fields that changed are stored, so an instance keeps getting changes to the other
+    /// fields from the prefab it references
+    FieldDiffs,
+    /// The whole value is stored whenever anything changed. For data that is only meaningful as a
+    /// whole, like curves or asset handles.
+    Replace,
+}
+
+impl Default for OverridePolicy {
+    fn default() -> Self {
+        OverridePolicy::FieldDiffs
+    }
+}
+
+// Diffs and applies a component as a single value, like a field marked `#[serde_diff(opaque)]`.
+// Serializes the same as the component itself.
+#[derive(Serialize)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub(crate) struct WholeValue<T>(T);
+
+impl<T> WholeValue<T> {
+    pub(crate) fn from_ref(value: &T) -> &Self {
+        // Sound because WholeValue is repr(transparent)
+        unsafe { &*(value as *const T as *const Self) }
+    }
+
+    pub(crate) fn from_mut(value: &mut T) -> &mut Self {
+        unsafe { &mut *(value as *mut T as *mut Self) }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + SerdeDiff> SerdeDiff for WholeValue<T> {
+    fn diff<'a, S: SerializeSeq>(
+        &self,
+        ctx: &mut DiffContext<'a, S>,
+        other: &Self,
+    ) -> Result<bool, S::Error> {
+        // Whether anything changed is only known after walking the field diff
+        let diff = serde_diff::Diff::serializable(&self.0, &other.0);
+        crate::diff_output::serialize_discarded(&diff).expect("failed to serialize diff");
+
+        if diff.has_changes() {
+            ctx.save_value(&other.0)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn apply<'de, A>(
+        &mut self,
+        seq: &mut A,
+        ctx: &mut ApplyContext,
+    ) -> Result<bool, <A as SeqAccess<'de>>::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        ctx.read_value(seq, &mut self.0)
+    }
+}
+
+// A RON diff from `old` to `new`, following the policy the component type is registered with.
+// None if nothing differs.
+pub(crate) fn ron_diff<T: TypeUuid + Serialize + DeserializeOwned + SerdeDiff>(
+    old: &T,
+    new: &T,
+) -> Result<Option<String>, ron::ser::Error> {
+    let policy = crate::registration::global_component_registry()
+        .by_uuid()
+        .get(&T::UUID)
+        .map(|registration| registration.override_policy())
+        .unwrap_or_default();
+
+    match policy {
+        OverridePolicy::FieldDiffs => changed_ron(&serde_diff::Diff::serializable(old, new)),
+        OverridePolicy::Replace => changed_ron(&serde_diff::Diff::serializable(
+            WholeValue::from_ref(old),
+            WholeValue::from_ref(new),
+        )),
+    }
+}
+
+// Writes the diff once, nothing is written if it has no changes
+fn changed_ron<D: Serialize>(diff: &D) -> Result<Option<String>, ron::ser::Error> {
+    let mut ron_ser = ron::ser::Serializer::new(None, false);
+    diff.serialize(&mut crate::diff_output::ChangedDiffSerializer::new(
+        &mut ron_ser,
+    ))?;
+    let data = ron_ser.into_output_string();
+    Ok(if data.is_empty() { None } else { Some(data) })
+}
@@ -0,0 +1,118 @@
+use crate::format::{
+    field_assignment_diff, ComponentTypeUuid, EntityUuid, FieldPathError, PrefabParameter,
+};
+use crate::{ComponentRegistration, UuidEntityBimap};
+use legion::{EntityStore, World};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+
+#[derive(Debug)]
+pub enum ParameterError {
+    /// A value was given for a parameter the prefab doesn't declare
+    UnknownParameter(String),
+    /// A binding refers to an entity that isn't in the world
+    MissingEntity {
+        parameter: String,
+        entity: EntityUuid,
+    },
+    /// A binding refers to a component type that isn't registered
+    UnregisteredComponent {
+        parameter: String,
+        component_type: ComponentTypeUuid,
+    },
+    /// A binding refers to a component the entity doesn't have
+    MissingComponent {
+        parameter: String,
+        entity: EntityUuid,
+        component_type: ComponentTypeUuid,
+    },
+    InvalidFieldPath {
+        parameter: String,
+        error: FieldPathError,
+    },
+}
+
+/// Sets every field bound to `parameter` to `value` (RON text). `entities` maps the entity UUIDs
+/// of the prefab to entities in `world`.
+pub fn apply_parameter_value<S: BuildHasher>(
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+    world: &mut World,
+    entities: &UuidEntityBimap,
+    parameter: &PrefabParameter,
+    value: &str,
+) -> Result<(), ParameterError> {
+    for binding in &parameter.bindings {
+        let missing_entity = || ParameterError::MissingEntity {
+            parameter: parameter.name.clone(),
+            entity: binding.entity,
+        };
+        let entity = entities
+            .entity(&binding.entity)
+            .ok_or_else(missing_entity)?;
+
+        let registration = registered_components_by_uuid
+            .get(&binding.component_type)
+            .ok_or_else(|| ParameterError::UnregisteredComponent {
+                parameter: parameter.name.clone(),
+                component_type: binding.component_type,
+            })?;
+
+        let has_component = world
+            .entry_ref(entity)
+            .map(|entry| {
+                entry
+                    .archetype()
+                    .layout()
+                    .has_component_by_id(registration.component_type_id())
+            })
+            .unwrap_or(false);
+        if !has_component {
+            return Err(ParameterError::MissingComponent {
+                parameter: parameter.name.clone(),
+                entity: binding.entity,
+                component_type: binding.component_type,
+            });
+        }
+
+        let diff = field_assignment_diff(&binding.field, value).map_err(|error| {
+            ParameterError::InvalidFieldPath {
+                parameter: parameter.name.clone(),
+                error,
+            }
+        })?;
+
+        let mut deserializer = ron::de::Deserializer::from_str(&diff).unwrap();
+        let mut de = erased_serde::Deserializer::erase(&mut deserializer);
+        registration.apply_diff(&mut de, world, entity);
+    }
+
+    Ok(())
+}
+
+/// Sets parameter values on a spawned instance of a cooked prefab. `parameters` are the
+/// parameters of the cooked prefab (`CookedPrefab::parameters`) and `entities` maps the prefab's
+/// entity UUIDs to the spawned entities, i.e. `InstanceHandle::entities`. Parameters without
+/// a value keep the value they were cooked with.
+pub fn apply_prefab_parameters<S: BuildHasher>(
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+    world: &mut World,
+    entities: &UuidEntityBimap,
+    parameters: &[PrefabParameter],
+    values: &BTreeMap<String, String>,
+) -> Result<(), ParameterError> {
+    for (name, value) in values {
+        let parameter = parameters
+            .iter()
+            .find(|parameter| &parameter.name == name)
+            .ok_or_else(|| ParameterError::UnknownParameter(name.clone()))?;
+        apply_parameter_value(
+            registered_components_by_uuid,
+            world,
+            entities,
+            parameter,
+            value,
+        )?;
+    }
+
+    Ok(())
+}
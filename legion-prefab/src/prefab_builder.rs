@@ -2,8 +2,8 @@ use legion::*;
 use prefab_format::{EntityUuid, ComponentTypeUuid, PrefabUuid};
 
 use std::collections::HashMap;
-use crate::{ComponentRegistration, DiffSingleResult, ComponentOverride, PrefabMeta, PrefabRef};
-use crate::{CookedPrefab, CopyCloneImpl, Prefab};
+use crate::{ComponentRegistration, DiffSingleKind, ComponentOverride, PrefabMeta, PrefabRef};
+use crate::{CookedPrefab, CopyCloneImpl, DiffOptions, Prefab};
 use fnv::FnvHashMap;
 use std::hash::BuildHasher;
 
@@ -44,6 +44,8 @@ pub struct PrefabBuilder {
     uuid_to_entities: FnvHashMap<EntityUuid, EntityInfo>,
 
     parent_prefab: PrefabUuid,
+
+    diff_options: DiffOptions,
 }
 
 #[derive(Debug)]
@@ -51,6 +53,13 @@ pub enum PrefabBuilderError {
     EntityDeleted,
     ComponentRemoved,
     ComponentAdded,
+    /// A component was changed in fields that its registration locks, see
+    /// `ComponentRegistration::with_locked_fields`
+    LockedFieldsChanged {
+        entity: EntityUuid,
+        component_type: ComponentTypeUuid,
+        locked_fields: Vec<&'static str>,
+    },
 }
 
 impl PrefabBuilder {
@@ -79,9 +88,18 @@ impl PrefabBuilder {
             after_world,
             uuid_to_entities,
             parent_prefab: prefab_uuid,
+            diff_options: DiffOptions::default(),
         }
     }
 
+    /// Sets the options used to find overrides in `create_prefab`
+    pub fn set_diff_options(
+        &mut self,
+        diff_options: DiffOptions,
+    ) {
+        self.diff_options = diff_options;
+    }
+
     pub fn world(&self) -> &World {
         &self.after_world
     }
@@ -129,38 +147,55 @@ impl PrefabBuilder {
             }
         }
 
+        // Component types are visited in UUID order so the overrides of an entity are in the same
+        // order every time the prefab is created. Otherwise saving it again would reorder them.
+        let mut component_types: Vec<_> = registered_components.iter().collect();
+        component_types.sort_by_key(|(component_type, _)| **component_type);
+
         let mut entity_overrides = HashMap::new();
         for (entity_uuid, entity_info) in &self.uuid_to_entities {
             let mut component_overrides = vec![];
 
-            for (component_type, registration) in registered_components {
+            for (component_type, registration) in &component_types {
                 let mut ron_ser = ron::ser::Serializer::new(None, true);
                 let mut erased = erased_serde::Serializer::erase(&mut ron_ser);
 
-                let result = registration.diff_single(
+                let result = registration.diff_single_with_options(
                     &mut erased,
                     &self.before_world,
                     Some(entity_info.before_entity()),
                     &self.after_world,
                     Some(entity_info.after_entity()),
+                    &self.diff_options,
                 );
 
-                match result {
-                    DiffSingleResult::NoChange => {
+                match result.kind {
+                    DiffSingleKind::NoChange => {
                         // Do nothing
                     }
-                    DiffSingleResult::Change => {
-                        // Store the change
+                    DiffSingleKind::Change => {
+                        // Store the change, unless it changes locked fields
+                        let data = ron_ser.into_output_string();
+                        let locked_fields =
+                            crate::override_locks::touched_locked_fields(registration, &data);
+                        if !locked_fields.is_empty() {
+                            return Err(PrefabBuilderError::LockedFieldsChanged {
+                                entity: *entity_uuid,
+                                component_type: **component_type,
+                                locked_fields,
+                            });
+                        }
+
                         component_overrides.push(ComponentOverride {
-                            component_type: *component_type,
-                            data: ron_ser.into_output_string(),
+                            component_type: **component_type,
+                            data,
                         })
                     }
-                    DiffSingleResult::Add => {
+                    DiffSingleKind::Add => {
                         // Fail, a component was added. This is not supported
                         return Err(PrefabBuilderError::ComponentAdded);
                     }
-                    DiffSingleResult::Remove => {
+                    DiffSingleKind::Remove => {
                         // Fail, a component was deleted. This is not supported
                         return Err(PrefabBuilderError::ComponentRemoved);
                     }
@@ -174,6 +209,8 @@ impl PrefabBuilder {
 
         let prefab_ref = PrefabRef {
             overrides: entity_overrides,
+            parameter_values: Default::default(),
+            transform: Default::default(),
         };
 
         let mut prefab_refs = HashMap::new();
@@ -183,11 +220,18 @@ impl PrefabBuilder {
             id: *uuid::Uuid::new_v4().as_bytes(),
             prefab_refs,
             entities: new_prefab_entities,
+            parameters: Default::default(),
+            entity_layers: Default::default(),
+            hierarchy: Default::default(),
+            blobs: Default::default(),
+            extends: None,
+            entity_refs: Default::default(),
         };
 
         Ok(Prefab {
             world: new_prefab_world,
             prefab_meta,
+            resources: Default::default(),
         })
     }
 }
@@ -1,16 +1,53 @@
-use crate::format::EntityUuid;
-use crate::world_serde::{CustomDeserializer, CustomSerializer};
+use crate::format::blobs::{BlobData, BlobId, BlobRef};
+use crate::format::{ComponentTypeUuid, PrefabParameter};
+use crate::cooked_columns::{DecodedColumns, EncodedColumn};
+use crate::world_serde::{
+    assign_missing_uuids, CustomDeserializer, CustomSerializer, UnknownComponentPolicy,
+};
+use crate::{PrefabResources, UuidEntityBimap};
 use legion::World;
+use serde::de::DeserializeSeed;
 use serde::{Deserialize, Serialize};
 use serde::{Deserializer, Serializer};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use type_uuid::TypeUuid;
 
+/// The result of cooking a prefab and the prefabs it references into a single world. Like
+/// `Prefab`, it has a stable `TypeUuid` so it can be registered as an asset type directly.
+///
+/// The entities of the world are in ascending UUID order (see `cook_prefab`), and keep that order
+/// when the cooked prefab is serialized and loaded again.
+#[derive(TypeUuid)]
+#[uuid = "cbb72bb6-96d3-45f0-8838-bea6ddbbb757"]
 pub struct CookedPrefab {
     pub world: legion::world::World,
-    pub entities: HashMap<EntityUuid, legion::Entity>,
+    pub entities: UuidEntityBimap,
+    /// Parameters declared by the root prefab, which can be set when spawning with
+    /// `apply_prefab_parameters`
+    pub parameters: Vec<PrefabParameter>,
+    /// Resources of all the cooked prefabs. Resources of a prefab replace those of the same type
+    /// from the prefabs it references
+    pub resources: PrefabResources,
+    /// Blobs of all the cooked prefabs, for `BlobRef`s in component data
+    pub blobs: HashMap<BlobId, BlobData>,
 }
 
+impl CookedPrefab {
+    pub fn blob(
+        &self,
+        blob: &BlobRef,
+    ) -> Option<&BlobData> {
+        self.blobs.get(blob.id())
+    }
+}
+
+// Must stay Send + Sync to be usable as an asset, like Prefab
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<CookedPrefab>();
+};
+
 impl Serialize for CookedPrefab {
     fn serialize<S>(
         &self,
@@ -19,29 +56,48 @@ impl Serialize for CookedPrefab {
     where
         S: Serializer,
     {
+        self.serialize_with_layout(serializer, None)
+    }
+}
+
+impl CookedPrefab {
+    // A column table moves the world's component slices out of the prefab, which is only used by
+    // the packed world layout, see `write_cooked_columns`
+    pub(crate) fn serialize_with_layout<S: Serializer>(
+        &self,
+        serializer: S,
+        column_table: Option<&RefCell<Vec<EncodedColumn>>>,
+    ) -> Result<S::Ok, S::Error> {
         use serde::ser::SerializeStruct;
-        use std::iter::FromIterator;
 
-        //TODO: Not good to allocate and throw away
-        let comp_types = HashMap::from_iter(
-            crate::registration::iter_component_registrations()
-                .map(|reg| (reg.component_type_id(), reg.clone())),
-        );
+        let registry = crate::registration::global_component_registry();
 
-        let mut entity_map =
-            HashMap::from_iter(self.entities.iter().map(|(uuid, entity)| (*entity, *uuid)));
+        // Entities of the world without a UUID are added to the map, so serialize with a copy
+        let mut entity_map = self.entities.clone();
+        assign_missing_uuids(&self.world, &mut entity_map);
 
         let custom_serializer = CustomSerializer {
-            comp_types: &comp_types,
+            comp_types: registry.by_type_id(),
             entity_map: RefCell::new(&mut entity_map),
+            component_filter: None,
+            columnar: column_table.is_some(),
+            column_table,
         };
 
         let serializable_world = self
             .world
             .as_serializable(legion::query::any(), &custom_serializer);
-        let mut struct_ser = serializer.serialize_struct("CookedPrefab", 2)?;
+        let blobs: BTreeMap<uuid::Uuid, &BlobData> = self
+            .blobs
+            .iter()
+            .map(|(blob, data)| (uuid::Uuid::from_bytes(*blob), data))
+            .collect();
+        let mut struct_ser = serializer.serialize_struct("CookedPrefab", 5)?;
         struct_ser.serialize_field("entities", &self.entities)?;
         struct_ser.serialize_field("world", &serializable_world)?;
+        struct_ser.serialize_field("parameters", &self.parameters)?;
+        struct_ser.serialize_field("resources", &self.resources)?;
+        struct_ser.serialize_field("blobs", &blobs)?;
         struct_ser.end()
     }
 }
@@ -51,15 +107,54 @@ impl Serialize for CookedPrefab {
 enum CookedPrefabField {
     Entities,
     World,
+    Parameters,
+    Resources,
+    Blobs,
 }
 impl<'de> Deserialize<'de> for CookedPrefab {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        struct PrefabDeserVisitor;
+        CookedPrefab::deserialize_with_layout(deserializer, None)
+    }
+}
+
+impl CookedPrefab {
+    /// Deserializes a cooked prefab, handling components of a type that isn't registered according
+    /// to `unknown_components`. Also returns the unknown component types that were skipped.
+    pub fn deserialize_with_policy<'de, D: Deserializer<'de>>(
+        deserializer: D,
+        unknown_components: UnknownComponentPolicy,
+    ) -> Result<(Self, Vec<ComponentTypeUuid>), D::Error> {
+        CookedPrefab::deserialize_with_options(deserializer, None, unknown_components)
+    }
+
+    // The columns of a prefab written with a column table are decoded before the prefab is read,
+    // see `read_cooked_columns`
+    pub(crate) fn deserialize_with_layout<'de, D: Deserializer<'de>>(
+        deserializer: D,
+        columns: Option<DecodedColumns>,
+    ) -> Result<Self, D::Error> {
+        let (cooked_prefab, _) = CookedPrefab::deserialize_with_options(
+            deserializer,
+            columns,
+            UnknownComponentPolicy::Error,
+        )?;
+        Ok(cooked_prefab)
+    }
+
+    fn deserialize_with_options<'de, D: Deserializer<'de>>(
+        deserializer: D,
+        columns: Option<DecodedColumns>,
+        unknown_components: UnknownComponentPolicy,
+    ) -> Result<(Self, Vec<ComponentTypeUuid>), D::Error> {
+        struct PrefabDeserVisitor {
+            columns: Option<DecodedColumns>,
+            unknown_components: UnknownComponentPolicy,
+        }
         impl<'de> serde::de::Visitor<'de> for PrefabDeserVisitor {
-            type Value = CookedPrefab;
+            type Value = (CookedPrefab, Vec<ComponentTypeUuid>);
 
             fn expecting(
                 &self,
@@ -74,13 +169,29 @@ impl<'de> Deserialize<'de> for CookedPrefab {
             where
                 V: serde::de::SeqAccess<'de>,
             {
-                let entities: HashMap<EntityUuid, legion::Entity> =
-                    seq.next_element()?.expect("expected entities");
-                let world = seq.next_element::<WorldDeser>()?.expect("expected world");
-                Ok(CookedPrefab {
+                let entities = seq
+                    .next_element::<UuidEntityBimap>()?
+                    .expect("expected entities");
+                let world = seq
+                    .next_element_seed(WorldSeed {
+                        columns: self.columns,
+                        unknown_components: self.unknown_components,
+                    })?
+                    .expect("expected world");
+                // Older cooked prefabs written in self-describing formats end before these
+                // fields. Bincode has no length for a struct, so an older bincode file fails
+                // with an end of input error instead
+                let parameters = seq.next_element()?.unwrap_or_default();
+                let resources = seq.next_element()?.unwrap_or_default();
+                let blobs = seq.next_element::<CookedBlobs>()?.unwrap_or_default();
+                let cooked_prefab = CookedPrefab {
                     world: world.0,
                     entities,
-                })
+                    parameters,
+                    resources,
+                    blobs: blobs_by_id(blobs),
+                };
+                Ok((cooked_prefab, world.1))
             }
 
             fn visit_map<V>(
@@ -90,60 +201,113 @@ impl<'de> Deserialize<'de> for CookedPrefab {
             where
                 V: serde::de::MapAccess<'de>,
             {
-                let mut entities: Option<HashMap<EntityUuid, legion::Entity>> = None;
+                let mut entities: Option<UuidEntityBimap> = None;
+                let mut world = None;
+                let mut parameters = vec![];
+                let mut resources = PrefabResources::new();
+                let mut blobs = CookedBlobs::new();
+                let mut columns = self.columns;
                 while let Some(key) = map.next_key()? {
                     match key {
                         CookedPrefabField::Entities => {
                             entities = Some(map.next_value()?);
                         }
                         CookedPrefabField::World => {
-                            let world_deser = map.next_value::<WorldDeser>()?;
-                            let entities = entities.expect("expected prefab_meta before world");
-                            return Ok(CookedPrefab {
-                                world: world_deser.0,
-                                entities,
-                            });
+                            world = Some(map.next_value_seed(WorldSeed {
+                                columns: columns.take(),
+                                unknown_components: self.unknown_components,
+                            })?);
+                        }
+                        CookedPrefabField::Parameters => {
+                            parameters = map.next_value()?;
+                        }
+                        CookedPrefabField::Resources => {
+                            resources = map.next_value()?;
+                        }
+                        CookedPrefabField::Blobs => {
+                            blobs = map.next_value()?;
                         }
                     }
                 }
-                Err(serde::de::Error::missing_field("data"))
+                let entities =
+                    entities.ok_or_else(|| serde::de::Error::missing_field("entities"))?;
+                let world = world.ok_or_else(|| serde::de::Error::missing_field("world"))?;
+                let cooked_prefab = CookedPrefab {
+                    world: world.0,
+                    entities,
+                    parameters,
+                    resources,
+                    blobs: blobs_by_id(blobs),
+                };
+                Ok((cooked_prefab, world.1))
             }
         }
-        const FIELDS: &[&str] = &["entities", "world"];
-        deserializer.deserialize_struct("Prefab", FIELDS, PrefabDeserVisitor)
+        const FIELDS: &[&str] = &["entities", "world", "parameters", "resources", "blobs"];
+        deserializer.deserialize_struct(
+            "Prefab",
+            FIELDS,
+            PrefabDeserVisitor {
+                columns,
+                unknown_components,
+            },
+        )
     }
 }
-struct WorldDeser(legion::world::World, HashMap<EntityUuid, legion::Entity>);
-impl<'de> Deserialize<'de> for WorldDeser {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+
+// Blobs are keyed by UUID in the serialized data, which is written as a string by text formats
+type CookedBlobs = BTreeMap<uuid::Uuid, BlobData>;
+
+fn blobs_by_id(blobs: CookedBlobs) -> HashMap<BlobId, BlobData> {
+    blobs
+        .into_iter()
+        .map(|(blob, data)| (*blob.as_bytes(), data))
+        .collect()
+}
+// The world's entities, and the unknown component types that were skipped
+struct WorldDeser(legion::world::World, Vec<ComponentTypeUuid>);
+struct WorldSeed {
+    columns: Option<DecodedColumns>,
+    unknown_components: UnknownComponentPolicy,
+}
+impl<'de> DeserializeSeed<'de> for WorldSeed {
+    type Value = WorldDeser;
+
+    fn deserialize<D>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error>
     where
         D: Deserializer<'de>,
     {
-        use std::iter::FromIterator;
-
-        //TODO: Not good to allocate and throw away
-        let comp_types = HashMap::from_iter(
-            crate::registration::iter_component_registrations()
-                .map(|reg| (reg.component_type_id(), reg.clone())),
-        );
-        let comp_types_uuid = HashMap::from_iter(
-            crate::registration::iter_component_registrations()
-                .map(|reg| (*reg.uuid(), reg.clone())),
-        );
-
-        let mut entity_map = HashMap::new();
+        let registry = crate::registration::global_component_registry();
+
+        // Decoded columns refer to entities that were allocated while decoding them
+        let (mut entity_map, allocator, decoded_columns) = match self.columns {
+            Some(columns) => (
+                columns.entity_map,
+                columns.allocator,
+                Some(RefCell::new(columns.columns)),
+            ),
+            None => (UuidEntityBimap::new(), legion::world::Allocate::new(), None),
+        };
         let custom_deserializer = CustomDeserializer {
-            comp_types: &comp_types,
-            comp_types_uuid: &comp_types_uuid,
+            comp_types: registry.by_type_id(),
+            comp_types_uuid: registry.by_uuid(),
             entity_map: RefCell::new(&mut entity_map),
-            allocator: RefCell::new(legion::world::Allocate::new()),
+            allocator: RefCell::new(allocator),
+            columnar: decoded_columns.is_some(),
+            unknown_components: self.unknown_components,
+            skipped_components: RefCell::default(),
+            decoded_columns,
         };
 
         let seed = legion::serialize::DeserializeNewWorld(&custom_deserializer);
 
-        use serde::de::DeserializeSeed;
-        let world: World = seed.deserialize(deserializer).unwrap();
+        let world: World = seed.deserialize(deserializer)?;
 
-        Ok(WorldDeser(world, entity_map))
+        Ok(WorldDeser(
+            world,
+            custom_deserializer.skipped_components.into_inner(),
+        ))
     }
 }
@@ -0,0 +1,772 @@
+use crate::format::blobs::BlobId;
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabParameter, PrefabRefTransform, PrefabUuid};
+use crate::{DiffSingleKind, Prefab, PrefabSerdeContext};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::hash::BuildHasher;
+use std::path::Path;
+
+// Mirrors the commands serde-diff writes when a diff is serialized. serde-diff doesn't expose its
+// command type, but diffs are stored as RON in prefabs so they can be read back by variant name.
+// Written back as RON, the commands can be applied like any other diff.
+#[derive(Serialize, Deserialize)]
+pub(crate) enum DiffCommand {
+    Enter(DiffPathElement),
+    Value(ron::Value),
+    Remove(usize),
+    AddKey(ron::Value),
+    EnterKey(ron::Value),
+    RemoveKey(ron::Value),
+    Exit,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) enum DiffPathElement {
+    Field(String),
+    FieldIndex(u16),
+    CollectionIndex(usize),
+    AddToCollection,
+}
+
+/// A change to a single value within a component
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldChange {
+    /// The value was set. Contains the new value as RON text
+    Set(String),
+    /// This many elements were removed from the end of the collection
+    RemoveElements(usize),
+    /// A key was removed from the map. Contains the key as RON text
+    RemoveKey(String),
+}
+
+/// A change within a component along with the path of the changed field, i.e. `position[1]` or
+/// `stats.health`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDiff {
+    pub path: String,
+    pub change: FieldChange,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComponentChange {
+    Added,
+    Removed,
+    Changed(Vec<FieldDiff>),
+}
+
+/// Component changes on an entity that exists in both prefabs
+#[derive(Debug, Clone)]
+pub struct EntityChanges {
+    pub entity: EntityUuid,
+    pub components: Vec<(ComponentTypeUuid, ComponentChange)>,
+}
+
+/// A component override that was added, removed or changed. Override field diffs describe what
+/// the override does to the referenced prefab's entity, so a changed override has field diffs for
+/// both the old and new version of the override.
+#[derive(Debug, Clone)]
+pub struct OverrideChange {
+    pub prefab_ref: PrefabUuid,
+    pub entity: EntityUuid,
+    pub component_type: ComponentTypeUuid,
+    pub before: Option<Vec<FieldDiff>>,
+    pub after: Option<Vec<FieldDiff>>,
+}
+
+/// The children of an entity that changed. An entity without children has an empty list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HierarchyChange {
+    pub parent: EntityUuid,
+    pub before: Vec<EntityUuid>,
+    pub after: Vec<EntityUuid>,
+}
+
+/// A parameter that was added, removed or changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterChange {
+    pub name: String,
+    pub before: Option<PrefabParameter>,
+    pub after: Option<PrefabParameter>,
+}
+
+/// An entity that moved to another layer. `None` is the default layer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerChange {
+    pub entity: EntityUuid,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// The transform of a prefab ref that is in both prefabs
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefTransformChange {
+    pub prefab_ref: PrefabUuid,
+    pub before: PrefabRefTransform,
+    pub after: PrefabRefTransform,
+}
+
+/// A parameter value set by a prefab ref that was added, removed or changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterValueChange {
+    pub prefab_ref: PrefabUuid,
+    pub name: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// The prefab that the prefab extends changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtendsChange {
+    pub before: Option<PrefabUuid>,
+    pub after: Option<PrefabUuid>,
+}
+
+/// Structural differences between two versions of a prefab. Everything is sorted by UUID (or by
+/// name, for parameters) so the result is stable.
+#[derive(Debug, Clone, Default)]
+pub struct PrefabDiff {
+    pub entities_added: Vec<EntityUuid>,
+    pub entities_removed: Vec<EntityUuid>,
+    pub entities_changed: Vec<EntityChanges>,
+    pub prefab_refs_added: Vec<PrefabUuid>,
+    pub prefab_refs_removed: Vec<PrefabUuid>,
+    pub overrides_changed: Vec<OverrideChange>,
+    pub hierarchy_changed: Vec<HierarchyChange>,
+    pub parameters_changed: Vec<ParameterChange>,
+    pub layers_changed: Vec<LayerChange>,
+    pub ref_transforms_changed: Vec<RefTransformChange>,
+    pub parameter_values_changed: Vec<ParameterValueChange>,
+    pub blobs_added: Vec<BlobId>,
+    pub blobs_removed: Vec<BlobId>,
+    pub blobs_changed: Vec<BlobId>,
+    pub extends_changed: Option<ExtendsChange>,
+}
+
+impl PrefabDiff {
+    pub fn is_empty(&self) -> bool {
+        self.entities_added.is_empty()
+            && self.entities_removed.is_empty()
+            && self.entities_changed.is_empty()
+            && self.prefab_refs_added.is_empty()
+            && self.prefab_refs_removed.is_empty()
+            && self.overrides_changed.is_empty()
+            && self.hierarchy_changed.is_empty()
+            && self.parameters_changed.is_empty()
+            && self.layers_changed.is_empty()
+            && self.ref_transforms_changed.is_empty()
+            && self.parameter_values_changed.is_empty()
+            && self.blobs_added.is_empty()
+            && self.blobs_removed.is_empty()
+            && self.blobs_changed.is_empty()
+            && self.extends_changed.is_none()
+    }
+}
+
+/// Reads a RON-encoded serde-diff (as stored in `ComponentOverride::data`) into a list of changed
+/// field paths
+pub fn field_diffs_from_ron(diff: &str) -> Result<Vec<FieldDiff>, ron::de::Error> {
+    let commands: Vec<DiffCommand> = ron::de::from_str(diff)?;
+
+    fn to_ron(value: &ron::Value) -> String {
+        ron::ser::to_string(value).unwrap_or_else(|_| "?".to_string())
+    }
+
+    fn join(
+        path: &[String],
+        last: Option<&str>,
+    ) -> String {
+        let mut joined = String::new();
+        for element in path.iter().map(|x| x.as_str()).chain(last) {
+            if !joined.is_empty() && !element.starts_with('[') {
+                joined.push('.');
+            }
+            joined.push_str(element);
+        }
+        joined
+    }
+
+    let mut field_diffs = vec![];
+    let mut path = vec![];
+    // A key added to a map applies to the value that follows it
+    let mut added_key = None;
+    for command in commands {
+        match command {
+            DiffCommand::Enter(element) => path.push(match element {
+                DiffPathElement::Field(name) => name,
+                DiffPathElement::FieldIndex(index) => format!("{}", index),
+                DiffPathElement::CollectionIndex(index) => format!("[{}]", index),
+                DiffPathElement::AddToCollection => "[+]".to_string(),
+            }),
+            DiffCommand::EnterKey(key) => path.push(format!("[{}]", to_ron(&key))),
+            DiffCommand::Exit => {
+                path.pop();
+            }
+            DiffCommand::AddKey(key) => added_key = Some(format!("[{}]", to_ron(&key))),
+            DiffCommand::Value(value) => field_diffs.push(FieldDiff {
+                path: join(&path, added_key.take().as_deref()),
+                change: FieldChange::Set(to_ron(&value)),
+            }),
+            DiffCommand::Remove(count) => field_diffs.push(FieldDiff {
+                path: join(&path, None),
+                change: FieldChange::RemoveElements(count),
+            }),
+            DiffCommand::RemoveKey(key) => field_diffs.push(FieldDiff {
+                path: join(&path, None),
+                change: FieldChange::RemoveKey(to_ron(&key)),
+            }),
+        }
+    }
+
+    Ok(field_diffs)
+}
+
+/// Lists the field paths a RON-encoded serde-diff touches (i.e. `translation.x`) without applying
+/// it. Paths are sorted and each is listed once.
+pub fn field_paths_from_ron(diff: &str) -> Result<Vec<String>, ron::de::Error> {
+    let mut paths: Vec<_> = field_diffs_from_ron(diff)?
+        .into_iter()
+        .map(|field_diff| field_diff.path)
+        .collect();
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+// If a diff can't be read as a list of fields, the raw diff is reported as a change to the whole
+// component
+fn field_diffs_or_raw(diff: &str) -> Vec<FieldDiff> {
+    field_diffs_from_ron(diff).unwrap_or_else(|_| {
+        vec![FieldDiff {
+            path: String::new(),
+            change: FieldChange::Set(diff.to_string()),
+        }]
+    })
+}
+
+// The override data of a prefab ref, keyed by entity and component type. Empty if the prefab does
+// not reference the prefab.
+pub(crate) fn override_data_by_key(
+    prefab: &Prefab,
+    prefab_ref: &PrefabUuid,
+) -> HashMap<(EntityUuid, ComponentTypeUuid), String> {
+    let mut overrides = HashMap::new();
+    if let Some(r) = prefab.prefab_meta.prefab_refs.get(prefab_ref) {
+        for (entity, component_overrides) in &r.overrides {
+            for component_override in component_overrides {
+                overrides.insert(
+                    (*entity, component_override.component_type),
+                    component_override.data.clone(),
+                );
+            }
+        }
+    }
+    overrides
+}
+
+pub(crate) fn sorted<T: Ord, I: Iterator<Item = T>>(iter: I) -> Vec<T> {
+    let mut values: Vec<_> = iter.collect();
+    values.sort();
+    values
+}
+
+/// Finds the entities, components, fields, overrides and prefab metadata (hierarchy, parameters,
+/// layers, prefab ref transforms and parameter values, blobs and the extended prefab) that differ
+/// between two prefabs. Entities and prefab refs are matched by UUID, parameters by name.
+pub fn diff_prefabs<T: BuildHasher>(
+    before: &Prefab,
+    after: &Prefab,
+    context: PrefabSerdeContext<T>,
+) -> PrefabDiff {
+    let mut diff = PrefabDiff::default();
+
+    //
+    // Entities
+    //
+    let before_entities: HashSet<_> = before.prefab_meta.entities.keys().cloned().collect();
+    let after_entities: HashSet<_> = after.prefab_meta.entities.keys().cloned().collect();
+    diff.entities_added = sorted(after_entities.difference(&before_entities).cloned());
+    diff.entities_removed = sorted(before_entities.difference(&after_entities).cloned());
+
+    let mut component_types: Vec<_> = context.registered_components.keys().cloned().collect();
+    component_types.sort();
+
+    for entity_uuid in sorted(before_entities.intersection(&after_entities).cloned()) {
+        let before_entity = before.prefab_meta.entities[&entity_uuid];
+        let after_entity = after.prefab_meta.entities[&entity_uuid];
+
+        let mut components = vec![];
+        for component_type in &component_types {
+            let registration = &context.registered_components[component_type];
+            let mut ron_ser = ron::ser::Serializer::new(None, true);
+            let mut erased = erased_serde::Serializer::erase(&mut ron_ser);
+            let result = registration.diff_single(
+                &mut erased,
+                &before.world,
+                Some(before_entity),
+                &after.world,
+                Some(after_entity),
+            );
+
+            let change = match result.kind {
+                DiffSingleKind::NoChange => continue,
+                DiffSingleKind::Add => ComponentChange::Added,
+                DiffSingleKind::Remove => ComponentChange::Removed,
+                DiffSingleKind::Change => {
+                    ComponentChange::Changed(field_diffs_or_raw(&ron_ser.into_output_string()))
+                }
+            };
+            components.push((*component_type, change));
+        }
+
+        if !components.is_empty() {
+            diff.entities_changed.push(EntityChanges {
+                entity: entity_uuid,
+                components,
+            });
+        }
+    }
+
+    //
+    // Prefab refs and their overrides
+    //
+    let before_refs: HashSet<_> = before.prefab_meta.prefab_refs.keys().cloned().collect();
+    let after_refs: HashSet<_> = after.prefab_meta.prefab_refs.keys().cloned().collect();
+    diff.prefab_refs_added = sorted(after_refs.difference(&before_refs).cloned());
+    diff.prefab_refs_removed = sorted(before_refs.difference(&after_refs).cloned());
+
+    for prefab_ref in sorted(before_refs.union(&after_refs).cloned()) {
+        // Every override of an added/removed prefab ref shows up as added/removed
+        let before_overrides = override_data_by_key(before, &prefab_ref);
+        let after_overrides = override_data_by_key(after, &prefab_ref);
+        let mut keys: HashSet<_> = before_overrides.keys().cloned().collect();
+        keys.extend(after_overrides.keys().cloned());
+
+        for key in sorted(keys.into_iter()) {
+            let before_data = before_overrides.get(&key);
+            let after_data = after_overrides.get(&key);
+            if before_data == after_data {
+                continue;
+            }
+
+            diff.overrides_changed.push(OverrideChange {
+                prefab_ref,
+                entity: key.0,
+                component_type: key.1,
+                before: before_data.map(|data| field_diffs_or_raw(data)),
+                after: after_data.map(|data| field_diffs_or_raw(data)),
+            });
+        }
+
+        // Like overrides, the parameter values of an added/removed prefab ref show up as
+        // added/removed
+        let empty = BTreeMap::new();
+        let before_ref = before.prefab_meta.prefab_refs.get(&prefab_ref);
+        let after_ref = after.prefab_meta.prefab_refs.get(&prefab_ref);
+        let before_values = before_ref.map_or(&empty, |r| &r.parameter_values);
+        let after_values = after_ref.map_or(&empty, |r| &r.parameter_values);
+        let names: BTreeSet<_> = before_values.keys().chain(after_values.keys()).collect();
+        for name in names {
+            let before_value = before_values.get(name);
+            let after_value = after_values.get(name);
+            if before_value != after_value {
+                diff.parameter_values_changed.push(ParameterValueChange {
+                    prefab_ref,
+                    name: name.clone(),
+                    before: before_value.cloned(),
+                    after: after_value.cloned(),
+                });
+            }
+        }
+
+        if let (Some(before_ref), Some(after_ref)) = (before_ref, after_ref) {
+            if before_ref.transform != after_ref.transform {
+                diff.ref_transforms_changed.push(RefTransformChange {
+                    prefab_ref,
+                    before: before_ref.transform,
+                    after: after_ref.transform,
+                });
+            }
+        }
+    }
+
+    //
+    // Hierarchy, parameters and layers
+    //
+    let children = |prefab: &Prefab, parent: &EntityUuid| {
+        prefab
+            .prefab_meta
+            .hierarchy
+            .get(parent)
+            .cloned()
+            .unwrap_or_default()
+    };
+    let parents: BTreeSet<_> = before
+        .prefab_meta
+        .hierarchy
+        .keys()
+        .chain(after.prefab_meta.hierarchy.keys())
+        .collect();
+    for parent in parents {
+        let before_children = children(before, parent);
+        let after_children = children(after, parent);
+        if before_children != after_children {
+            diff.hierarchy_changed.push(HierarchyChange {
+                parent: *parent,
+                before: before_children,
+                after: after_children,
+            });
+        }
+    }
+
+    let parameter = |prefab: &Prefab, name: &str| {
+        prefab
+            .prefab_meta
+            .parameters
+            .iter()
+            .find(|parameter| parameter.name == name)
+            .cloned()
+    };
+    let parameter_names: BTreeSet<_> = before
+        .prefab_meta
+        .parameters
+        .iter()
+        .chain(&after.prefab_meta.parameters)
+        .map(|parameter| parameter.name.as_str())
+        .collect();
+    for name in parameter_names {
+        let before_parameter = parameter(before, name);
+        let after_parameter = parameter(after, name);
+        if before_parameter != after_parameter {
+            diff.parameters_changed.push(ParameterChange {
+                name: name.to_string(),
+                before: before_parameter,
+                after: after_parameter,
+            });
+        }
+    }
+
+    let before_layers = &before.prefab_meta.entity_layers;
+    let after_layers = &after.prefab_meta.entity_layers;
+    let layered_entities: BTreeSet<_> = before_layers.keys().chain(after_layers.keys()).collect();
+    for entity in layered_entities {
+        let before_layer = before_layers.get(entity);
+        let after_layer = after_layers.get(entity);
+        if before_layer != after_layer {
+            diff.layers_changed.push(LayerChange {
+                entity: *entity,
+                before: before_layer.cloned(),
+                after: after_layer.cloned(),
+            });
+        }
+    }
+
+    //
+    // Blobs and the extended prefab
+    //
+    let before_blobs = &before.prefab_meta.blobs;
+    let after_blobs = &after.prefab_meta.blobs;
+    let blobs: BTreeSet<_> = before_blobs.keys().chain(after_blobs.keys()).collect();
+    for blob in blobs {
+        match (before_blobs.get(blob), after_blobs.get(blob)) {
+            (None, Some(_)) => diff.blobs_added.push(*blob),
+            (Some(_), None) => diff.blobs_removed.push(*blob),
+            (Some(before_data), Some(after_data)) if before_data != after_data => {
+                diff.blobs_changed.push(*blob)
+            }
+            _ => {}
+        }
+    }
+
+    if before.prefab_meta.extends != after.prefab_meta.extends {
+        diff.extends_changed = Some(ExtendsChange {
+            before: before.prefab_meta.extends,
+            after: after.prefab_meta.extends,
+        });
+    }
+
+    diff
+}
+
+#[derive(Debug)]
+pub enum PrefabDiffError {
+    Io(std::io::Error),
+    Load(ron::de::Error),
+}
+
+/// A `PrefabDiff` along with the names of the component types it mentions. The `Display` impl
+/// writes a human-readable report where `+` marks additions, `-` removals and `~` changes.
+#[derive(Debug, Clone)]
+pub struct PrefabDiffReport {
+    pub diff: PrefabDiff,
+    pub component_names: HashMap<ComponentTypeUuid, &'static str>,
+}
+
+impl PrefabDiffReport {
+    pub fn new<T: BuildHasher>(
+        diff: PrefabDiff,
+        context: PrefabSerdeContext<T>,
+    ) -> Self {
+        let component_names = context
+            .registered_components
+            .iter()
+            .map(|(uuid, registration)| (*uuid, registration.type_name()))
+            .collect();
+        PrefabDiffReport {
+            diff,
+            component_names,
+        }
+    }
+
+    /// The registered type name of the component type, or its UUID if it isn't registered
+    pub fn component_name(
+        &self,
+        component_type: &ComponentTypeUuid,
+    ) -> String {
+        match self.component_names.get(component_type) {
+            Some(name) => name.to_string(),
+            None => uuid::Uuid::from_bytes(*component_type).to_string(),
+        }
+    }
+}
+
+fn write_field_diffs(
+    f: &mut fmt::Formatter,
+    field_diffs: &[FieldDiff],
+    indent: &str,
+) -> fmt::Result {
+    for field_diff in field_diffs {
+        let path = if field_diff.path.is_empty() {
+            "(all)"
+        } else {
+            field_diff.path.as_str()
+        };
+        match &field_diff.change {
+            FieldChange::Set(value) => writeln!(f, "{}{} = {}", indent, path, value)?,
+            FieldChange::RemoveElements(count) => {
+                writeln!(f, "{}{}: removed {} element(s)", indent, path, count)?
+            }
+            FieldChange::RemoveKey(key) => writeln!(f, "{}{}: removed key {}", indent, path, key)?,
+        }
+    }
+    Ok(())
+}
+
+// `+` if the value was added, `-` if it was removed and `~` if it changed
+fn change_marker<T>(
+    before: &Option<T>,
+    after: &Option<T>,
+) -> &'static str {
+    match (before, after) {
+        (None, Some(_)) => "+",
+        (Some(_), None) => "-",
+        _ => "~",
+    }
+}
+
+fn write_parameter(
+    f: &mut fmt::Formatter,
+    label: &str,
+    parameter: &PrefabParameter,
+) -> fmt::Result {
+    writeln!(
+        f,
+        "    {}: {} = {}, {} binding(s)",
+        label,
+        parameter.type_name,
+        parameter.default,
+        parameter.bindings.len()
+    )
+}
+
+fn write_transform(
+    f: &mut fmt::Formatter,
+    label: &str,
+    transform: &PrefabRefTransform,
+) -> fmt::Result {
+    writeln!(
+        f,
+        "    {}: position {:?}, rotation {:?}, scale {:?}",
+        label, transform.position, transform.rotation, transform.scale
+    )
+}
+
+impl fmt::Display for PrefabDiffReport {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        let diff = &self.diff;
+        let uuid_str = |bytes: &uuid::Bytes| uuid::Uuid::from_bytes(*bytes).to_string();
+
+        if diff.is_empty() {
+            return writeln!(f, "No differences");
+        }
+
+        for entity in &diff.entities_added {
+            writeln!(f, "+ entity {}", uuid_str(entity))?;
+        }
+        for entity in &diff.entities_removed {
+            writeln!(f, "- entity {}", uuid_str(entity))?;
+        }
+        for entity_changes in &diff.entities_changed {
+            writeln!(f, "~ entity {}", uuid_str(&entity_changes.entity))?;
+            for (component_type, change) in &entity_changes.components {
+                let name = self.component_name(component_type);
+                match change {
+                    ComponentChange::Added => writeln!(f, "    + {}", name)?,
+                    ComponentChange::Removed => writeln!(f, "    - {}", name)?,
+                    ComponentChange::Changed(field_diffs) => {
+                        writeln!(f, "    ~ {}", name)?;
+                        write_field_diffs(f, field_diffs, "        ")?;
+                    }
+                }
+            }
+        }
+
+        for prefab_ref in &diff.prefab_refs_added {
+            writeln!(f, "+ prefab ref {}", uuid_str(prefab_ref))?;
+        }
+        for prefab_ref in &diff.prefab_refs_removed {
+            writeln!(f, "- prefab ref {}", uuid_str(prefab_ref))?;
+        }
+        for override_change in &diff.overrides_changed {
+            writeln!(
+                f,
+                "{} override of {} on entity {} in prefab ref {}",
+                change_marker(&override_change.before, &override_change.after),
+                self.component_name(&override_change.component_type),
+                uuid_str(&override_change.entity),
+                uuid_str(&override_change.prefab_ref)
+            )?;
+            if let Some(before) = &override_change.before {
+                writeln!(f, "    before:")?;
+                write_field_diffs(f, before, "        ")?;
+            }
+            if let Some(after) = &override_change.after {
+                writeln!(f, "    after:")?;
+                write_field_diffs(f, after, "        ")?;
+            }
+        }
+        for transform_change in &diff.ref_transforms_changed {
+            writeln!(
+                f,
+                "~ transform of prefab ref {}",
+                uuid_str(&transform_change.prefab_ref)
+            )?;
+            write_transform(f, "before", &transform_change.before)?;
+            write_transform(f, "after", &transform_change.after)?;
+        }
+        for value_change in &diff.parameter_values_changed {
+            writeln!(
+                f,
+                "{} parameter value {} in prefab ref {}: {} -> {}",
+                change_marker(&value_change.before, &value_change.after),
+                value_change.name,
+                uuid_str(&value_change.prefab_ref),
+                value_change.before.as_deref().unwrap_or("(unset)"),
+                value_change.after.as_deref().unwrap_or("(unset)")
+            )?;
+        }
+
+        let entity_list = |entities: &[EntityUuid]| {
+            if entities.is_empty() {
+                "(none)".to_string()
+            } else {
+                let entities: Vec<_> = entities.iter().map(uuid_str).collect();
+                entities.join(", ")
+            }
+        };
+        for hierarchy_change in &diff.hierarchy_changed {
+            writeln!(
+                f,
+                "~ children of entity {}",
+                uuid_str(&hierarchy_change.parent)
+            )?;
+            writeln!(f, "    before: {}", entity_list(&hierarchy_change.before))?;
+            writeln!(f, "    after: {}", entity_list(&hierarchy_change.after))?;
+        }
+        for parameter_change in &diff.parameters_changed {
+            writeln!(
+                f,
+                "{} parameter {}",
+                change_marker(&parameter_change.before, &parameter_change.after),
+                parameter_change.name
+            )?;
+            if let Some(before) = &parameter_change.before {
+                write_parameter(f, "before", before)?;
+            }
+            if let Some(after) = &parameter_change.after {
+                write_parameter(f, "after", after)?;
+            }
+        }
+        for layer_change in &diff.layers_changed {
+            writeln!(
+                f,
+                "~ layer of entity {}: {} -> {}",
+                uuid_str(&layer_change.entity),
+                layer_change.before.as_deref().unwrap_or("(default)"),
+                layer_change.after.as_deref().unwrap_or("(default)")
+            )?;
+        }
+
+        for blob in &diff.blobs_added {
+            writeln!(f, "+ blob {}", uuid_str(blob))?;
+        }
+        for blob in &diff.blobs_removed {
+            writeln!(f, "- blob {}", uuid_str(blob))?;
+        }
+        for blob in &diff.blobs_changed {
+            writeln!(f, "~ blob {}", uuid_str(blob))?;
+        }
+        if let Some(extends_change) = &diff.extends_changed {
+            let prefab_str = |prefab: &Option<PrefabUuid>| {
+                prefab
+                    .as_ref()
+                    .map(uuid_str)
+                    .unwrap_or_else(|| "(none)".to_string())
+            };
+            writeln!(
+                f,
+                "{} extends: {} -> {}",
+                change_marker(&extends_change.before, &extends_change.after),
+                prefab_str(&extends_change.before),
+                prefab_str(&extends_change.after)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Loads two RON prefab sources and reports what changed from `before` to `after`
+pub fn diff_prefab_sources<T: BuildHasher>(
+    before: &str,
+    after: &str,
+    context: PrefabSerdeContext<T>,
+) -> Result<PrefabDiffReport, PrefabDiffError> {
+    let load = |source| {
+        crate::formatting::load_ron_prefab(source, context).map_err(|e| match e {
+            crate::FormatPrefabError::Deserialize(e) => PrefabDiffError::Load(e),
+            // Loading never serializes
+            crate::FormatPrefabError::Serialize(_) => unreachable!(),
+        })
+    };
+    let before = load(before)?;
+    let after = load(after)?;
+    Ok(PrefabDiffReport::new(
+        diff_prefabs(&before, &after, context),
+        context,
+    ))
+}
+
+/// Loads two RON prefab files and reports what changed from `before` to `after`
+pub fn diff_prefab_files<T: BuildHasher, P: AsRef<Path>>(
+    before: P,
+    after: P,
+    context: PrefabSerdeContext<T>,
+) -> Result<PrefabDiffReport, PrefabDiffError> {
+    let before = std::fs::read_to_string(before).map_err(PrefabDiffError::Io)?;
+    let after = std::fs::read_to_string(after).map_err(PrefabDiffError::Io)?;
+    diff_prefab_sources(&before, &after, context)
+}
@@ -0,0 +1,164 @@
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid};
+use crate::{ComponentOverride, Prefab};
+use legion::storage::Component;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_diff::SerdeDiff;
+use type_uuid::TypeUuid;
+
+#[derive(Debug)]
+pub enum PrefabEditError {
+    /// The entity isn't stored in this prefab. Entities of referenced prefabs are changed with
+    /// `override_ref_component`
+    EntityNotInPrefab(EntityUuid),
+    /// The entity is stored in this prefab, so it is changed directly instead of overridden
+    EntityInPrefab(EntityUuid),
+    /// This prefab doesn't reference the given prefab
+    MissingPrefabRef(PrefabUuid),
+    /// The component can't be removed because a parameter of the prefab is bound to it
+    ComponentBoundToParameter {
+        entity: EntityUuid,
+        component_type: ComponentTypeUuid,
+        parameter: String,
+    },
+    /// The override changes fields that the component's registration locks, see
+    /// `ComponentRegistration::with_locked_fields`
+    LockedFieldsChanged {
+        entity: EntityUuid,
+        component_type: ComponentTypeUuid,
+        locked_fields: Vec<&'static str>,
+    },
+}
+
+// Editing the world and prefab_meta by hand makes it easy to i.e. add an entity without a UUID or
+// override an entity of this prefab. These keep the two consistent.
+impl Prefab {
+    /// Adds an entity without components to this prefab and returns its UUID
+    pub fn add_entity(&mut self) -> EntityUuid {
+        let entity_uuid = *uuid::Uuid::new_v4().as_bytes();
+        let entity = self.world.push(());
+        self.prefab_meta.entities.insert(entity_uuid, entity);
+        entity_uuid
+    }
+
+    /// Sets a component of an entity of this prefab, adding it if the entity doesn't have one
+    pub fn set_component<T: Component>(
+        &mut self,
+        entity_uuid: &EntityUuid,
+        component: T,
+    ) -> Result<(), PrefabEditError> {
+        let entity = *self
+            .prefab_meta
+            .entities
+            .get(entity_uuid)
+            .ok_or(PrefabEditError::EntityNotInPrefab(*entity_uuid))?;
+        self.world
+            .entry(entity)
+            .expect("prefab entity not in world")
+            .add_component(component);
+        Ok(())
+    }
+
+    /// Removes a component from an entity of this prefab. Returns false if the entity doesn't
+    /// have the component.
+    pub fn remove_component<T: Component + TypeUuid>(
+        &mut self,
+        entity_uuid: &EntityUuid,
+    ) -> Result<bool, PrefabEditError> {
+        let entity = *self
+            .prefab_meta
+            .entities
+            .get(entity_uuid)
+            .ok_or(PrefabEditError::EntityNotInPrefab(*entity_uuid))?;
+
+        for parameter in &self.prefab_meta.parameters {
+            let is_bound = parameter
+                .bindings
+                .iter()
+                .any(|binding| binding.entity == *entity_uuid && binding.component_type == T::UUID);
+            if is_bound {
+                return Err(PrefabEditError::ComponentBoundToParameter {
+                    entity: *entity_uuid,
+                    component_type: T::UUID,
+                    parameter: parameter.name.clone(),
+                });
+            }
+        }
+
+        let mut entry = self
+            .world
+            .entry(entity)
+            .expect("prefab entity not in world");
+        if entry.get_component::<T>().is_err() {
+            return Ok(false);
+        }
+        entry.remove_component::<T>();
+        Ok(true)
+    }
+
+    /// Overrides a component of an entity of a referenced prefab so that it has `value`. `base` is
+    /// the value the entity has without this prefab's override, i.e. from the cooked referenced
+    /// prefab. Only the fields that differ are stored (or the whole value, if the component is
+    /// registered with `OverridePolicy::Replace`), replacing any earlier override of the
+    /// component, and the override is removed if nothing differs. Fails if the override would
+    /// change fields locked by the component's registration.
+    pub fn override_ref_component<T: TypeUuid + Serialize + DeserializeOwned + SerdeDiff>(
+        &mut self,
+        prefab_ref: &PrefabUuid,
+        entity_uuid: &EntityUuid,
+        base: &T,
+        value: &T,
+    ) -> Result<(), PrefabEditError> {
+        if self.prefab_meta.entities.contains_key(entity_uuid) {
+            return Err(PrefabEditError::EntityInPrefab(*entity_uuid));
+        }
+
+        let overrides = &mut self
+            .prefab_meta
+            .prefab_refs
+            .get_mut(prefab_ref)
+            .ok_or(PrefabEditError::MissingPrefabRef(*prefab_ref))?
+            .overrides;
+
+        let data = crate::override_policy::ron_diff(base, value).expect("failed to serialize diff");
+
+        let entity_overrides = overrides.entry(*entity_uuid).or_default();
+        let existing = entity_overrides
+            .iter()
+            .position(|component_override| component_override.component_type == T::UUID);
+
+        if let Some(data) = data {
+            let registration = crate::registration::global_component_registry()
+                .by_uuid()
+                .get(&T::UUID);
+            if let Some(registration) = registration {
+                let locked_fields =
+                    crate::override_locks::touched_locked_fields(registration, &data);
+                if !locked_fields.is_empty() {
+                    return Err(PrefabEditError::LockedFieldsChanged {
+                        entity: *entity_uuid,
+                        component_type: T::UUID,
+                        locked_fields,
+                    });
+                }
+            }
+
+            let component_override = ComponentOverride {
+                component_type: T::UUID,
+                data,
+            };
+            match existing {
+                Some(index) => entity_overrides[index] = component_override,
+                None => entity_overrides.push(component_override),
+            }
+        } else if let Some(index) = existing {
+            entity_overrides.remove(index);
+        }
+
+        if entity_overrides.is_empty() {
+            overrides.remove(entity_uuid);
+        }
+
+        Ok(())
+    }
+}
@@ -1,51 +1,117 @@
-use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid, StorageDeserializer, StorageSerializer};
-use crate::world_serde::{CustomDeserializer, CustomSerializer};
-use crate::ComponentRegistration;
-use legion::storage::ComponentTypeId;
+use crate::format::blobs::{BlobData, BlobId, BlobRef};
+use crate::format::{
+    ComponentTypeUuid, EntityUuid, PrefabParameter, PrefabRefTransform, PrefabUuid,
+    StorageDeserializer, StorageSerializer, TemplateUuid,
+};
+use crate::world_serde::{
+    assign_missing_uuids, CustomDeserializer, CustomSerializer, EntityUuidMapper,
+    UnknownComponentPolicy,
+};
+use crate::{ComponentRegistration, CopyCloneImpl, PrefabResources, UuidEntityBimap};
+use legion::storage::{Archetype, ArchetypeWriter, ComponentTypeId, Components, EntityLayout};
+use legion::world::{Allocate, Merger};
 use legion::*;
 use serde::de::DeserializeSeed;
 use serde::{Deserialize, Serialize};
 use serde::{Deserializer, Serializer};
 use std::hash::BuildHasher;
+use std::ops::Range;
+use type_uuid::TypeUuid;
 use std::{
     cell::{RefCell, RefMut},
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
 };
 
 /// The data we override on a component of an entity in another prefab that we reference
 #[derive(Serialize, Deserialize)]
 pub struct ComponentOverride {
     /// The component type to which we will apply this override data
+    #[serde(with = "prefab_format::uuid_bytes")]
     pub component_type: ComponentTypeUuid,
 
     /// The data used to override (in Ron-encoded serde_diff format)
     pub data: String,
 }
 
+impl ComponentOverride {
+    /// The paths of the fields this override changes, i.e. `translation.x`
+    pub fn field_paths(&self) -> Result<Vec<String>, ron::de::Error> {
+        crate::prefab_diff::field_paths_from_ron(&self.data)
+    }
+}
+
 /// Represents a reference from one prefab to another, along with the data with which it should be
 /// overridden
 #[derive(Serialize, Deserialize)]
 pub struct PrefabRef {
     /// The entities in the other prefab we will override and the data with which to override them
+    #[serde(with = "prefab_format::uuid_bytes::map")]
     pub overrides: HashMap<EntityUuid, Vec<ComponentOverride>>,
+
+    /// Values (RON text) for parameters declared by the other prefab, by parameter name
+    #[serde(default)]
+    pub parameter_values: BTreeMap<String, String>,
+
+    /// Offset applied to the root entities of the other prefab. See `apply_prefab_ref_transforms`.
+    #[serde(default)]
+    pub transform: PrefabRefTransform,
 }
 
 #[derive(Serialize, Deserialize)]
 /// Represents a list of entities in this prefab and references to other prefabs
 pub struct PrefabMeta {
     /// Unique ID of this prefab
+    #[serde(with = "prefab_format::uuid_bytes")]
     pub id: PrefabUuid,
 
     /// The other prefabs that this prefab will include, plus the data we will override them with
+    #[serde(with = "prefab_format::uuid_bytes::map")]
     pub prefab_refs: HashMap<PrefabUuid, PrefabRef>,
 
+    /// Parameters that prefabs referencing this prefab can set
+    #[serde(default)]
+    pub parameters: Vec<PrefabParameter>,
+
+    /// The entity layer (i.e. gameplay, lighting) each entity of this prefab is in. Entities that
+    /// aren't listed are in the default layer. See `entity_layers` for cooking layers separately.
+    #[serde(default, with = "prefab_format::uuid_bytes::map")]
+    pub entity_layers: HashMap<EntityUuid, String>,
+
+    /// The children of each entity that has any, in order. A child may also be an entity of a
+    /// referenced prefab, which is how a nested prefab instance is attached under an entity of
+    /// this prefab. See `cook_prefab_with_hierarchy`.
+    #[serde(default, with = "prefab_format::uuid_bytes::map")]
+    pub hierarchy: HashMap<EntityUuid, Vec<EntityUuid>>,
+
+    /// Binary data referenced by `BlobRef`s in component data, stored in the prefab's blob section
+    #[serde(default, with = "prefab_format::uuid_bytes::map")]
+    pub blobs: HashMap<BlobId, BlobData>,
+
+    /// The prefab this prefab extends, inheriting all of its entities. The base is also in
+    /// `prefab_refs`, where the changes this prefab makes to the base's entities are stored, so it
+    /// is cooked like any referenced prefab. Unlike a reference, it isn't an instance placed in
+    /// this prefab but what this prefab is built on, and is written in the file's `extends` field.
+    #[serde(default)]
+    pub extends: Option<PrefabUuid>,
+
     #[serde(skip, default)]
     // The entities that are stored in this prefab
     pub entities: HashMap<EntityUuid, Entity>,
+
+    #[serde(skip, default)]
+    // Entities that components of this prefab refer to but that aren't stored in it, i.e. entities
+    // of referenced prefabs, with the entity the components hold in their place. Cooking points
+    // these references at the cooked entities.
+    pub entity_refs: HashMap<EntityUuid, Entity>,
 }
 
 /// The uncooked prefab format. Raw entity data is stored in the legion::World. Metadata includes
 /// component overrides and mappings from EntityUuid to legion::Entity
+///
+/// Has a stable `TypeUuid` and serializes with the global component registry, so it can be
+/// registered as an asset type directly.
+#[derive(TypeUuid)]
+#[uuid = "db9a8d9e-babe-4991-a770-fd8e728b707b"]
 pub struct Prefab {
     /// The legion world contains entity data for all entities in this prefab. (EntityRef data is
     /// not included)
@@ -54,8 +120,17 @@ pub struct Prefab {
     /// Metadata for the prefab (references to other prefabs and mappings of EntityUUID to
     /// Entity
     pub prefab_meta: PrefabMeta,
+
+    /// Singleton data stored with the prefab, i.e. level settings
+    pub resources: PrefabResources,
 }
 
+// Asset systems load assets on one thread and use them on others
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Prefab>();
+};
+
 impl Prefab {
     pub fn new(world: World) -> Self {
         let mut entities = HashMap::new();
@@ -69,14 +144,87 @@ impl Prefab {
             id: *uuid::Uuid::new_v4().as_bytes(),
             entities,
             prefab_refs: Default::default(),
+            parameters: Default::default(),
+            entity_layers: Default::default(),
+            hierarchy: Default::default(),
+            blobs: Default::default(),
+            extends: None,
+            entity_refs: Default::default(),
         };
 
-        Prefab { world, prefab_meta }
+        Prefab {
+            world,
+            prefab_meta,
+            resources: Default::default(),
+        }
     }
 
     pub fn prefab_id(&self) -> PrefabUuid {
         self.prefab_meta.id
     }
+
+    // The UUID of every entity the prefab's components can refer to, i.e. to write `Entity` fields
+    // as UUIDs
+    pub(crate) fn entity_uuids(&self) -> UuidEntityBimap {
+        self.prefab_meta
+            .entity_refs
+            .iter()
+            .chain(&self.prefab_meta.entities)
+            .map(|(entity_uuid, entity)| (*entity_uuid, *entity))
+            .collect()
+    }
+
+    /// The data of a blob in the prefab's blob section. Blobs stored next to the prefab file are
+    /// read with `prefab_format::blobs::read_external_blob`.
+    pub fn blob(
+        &self,
+        blob: &BlobRef,
+    ) -> Option<&BlobData> {
+        self.prefab_meta.blobs.get(blob.id())
+    }
+
+    /// A copy of a component of an entity, as authored in this prefab. Entities of referenced
+    /// prefabs aren't in this prefab's world, and overrides aren't applied, so this returns None
+    /// for them.
+    pub fn get_component<T: legion::storage::Component + Clone>(
+        &self,
+        entity_uuid: &EntityUuid,
+    ) -> Option<T> {
+        let entity = *self.prefab_meta.entities.get(entity_uuid)?;
+        let entry = self.world.entry_ref(entity).ok()?;
+        entry.get_component::<T>().ok().cloned()
+    }
+
+    /// Like `get_component`, for tools that don't know the component's type. The component is
+    /// returned as RON text, like component data in a prefab file. Returns None if the entity
+    /// doesn't have the component or its type isn't registered (or is clone-only).
+    pub fn get_component_by_uuid<S: BuildHasher>(
+        &self,
+        entity_uuid: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+    ) -> Option<String> {
+        let registration = registered_components_by_uuid
+            .get(component_type)
+            .filter(|registration| !registration.is_clone_only())?;
+        let entity = *self.prefab_meta.entities.get(entity_uuid)?;
+        let has_component = self
+            .world
+            .entry_ref(entity)
+            .ok()?
+            .archetype()
+            .layout()
+            .has_component_by_id(registration.component_type_id());
+        if !has_component {
+            return None;
+        }
+
+        let mut data = None;
+        registration.serialize_single(&self.world, entity, &mut |component| {
+            data = Some(ron::ser::to_string(component).expect("failed to serialize component"));
+        });
+        data
+    }
 }
 
 pub struct PrefabSerdeContext<'a, T: BuildHasher> {
@@ -98,22 +246,164 @@ impl<'a, T: BuildHasher> Copy for PrefabSerdeContext<'a, T> {}
 pub struct PrefabFormatDeserializer<'a, T: BuildHasher> {
     prefab: RefCell<Option<Prefab>>,
     context: PrefabSerdeContext<'a, T>,
+    // Every entity UUID seen so far, including UUIDs in `Entity` fields of components. An entity
+    // referenced before it is declared is given a placeholder entity
+    entity_refs: RefCell<UuidEntityBimap>,
+    allocator: RefCell<Allocate>,
+    // Maps the entities that were referenced before being declared to their placeholder
+    placeholders: RefCell<HashMap<Entity, Entity>>,
+    // The names the file's type map gives component types
+    type_names: RefCell<HashMap<ComponentTypeUuid, String>>,
+    type_name_fallback: bool,
+    // The file's entity templates, one entity per template. Entities that instantiate a template
+    // get copies of its components, so templates don't exist in the loaded prefab
+    templates: RefCell<World>,
+    template_entities: RefCell<HashMap<TemplateUuid, Entity>>,
+    components_by_type_id:
+        once_cell::unsync::OnceCell<HashMap<ComponentTypeId, ComponentRegistration>>,
 }
 impl<'a, T: BuildHasher> PrefabFormatDeserializer<'a, T> {
     pub fn new(context: PrefabSerdeContext<'a, T>) -> Self {
         Self {
             prefab: RefCell::new(None),
             context,
+            entity_refs: RefCell::new(UuidEntityBimap::new()),
+            allocator: RefCell::new(Allocate::new()),
+            placeholders: RefCell::new(HashMap::new()),
+            type_names: RefCell::new(HashMap::new()),
+            type_name_fallback: false,
+            templates: RefCell::new(World::default()),
+            template_entities: RefCell::new(HashMap::new()),
+            components_by_type_id: once_cell::unsync::OnceCell::new(),
         }
     }
+    /// If a component type UUID in the file isn't registered, but the file's type map names it,
+    /// the component is loaded as the registered type with that type name. This helps when UUIDs
+    /// were regenerated by accident. Saving the prefab afterwards writes the registered UUIDs.
+    pub fn with_type_name_fallback(mut self) -> Self {
+        self.type_name_fallback = true;
+        self
+    }
     pub fn prefab(self) -> Prefab {
-        self.prefab
+        let mut prefab = self
+            .prefab
             .into_inner()
-            .expect("no valid prefab - make sure to deserialize before calling prefab()")
+            .expect("no valid prefab - make sure to deserialize before calling prefab()");
+
+        // UUIDs that components referred to without the prefab declaring an entity for them
+        prefab.prefab_meta.entity_refs = self
+            .entity_refs
+            .into_inner()
+            .iter()
+            .filter(|(entity_uuid, _)| !prefab.prefab_meta.entities.contains_key(*entity_uuid))
+            .map(|(entity_uuid, entity)| (*entity_uuid, *entity))
+            .collect();
+
+        let placeholders = self.placeholders.into_inner();
+        if !placeholders.is_empty() {
+            resolve_entity_placeholders(
+                &mut prefab,
+                &placeholders,
+                self.context.registered_components,
+            );
+        }
+
+        prefab
+    }
+}
+
+// Components that referenced an entity before it was declared hold a placeholder entity. Clone the
+// world so that those entities take the placeholder's ID
+fn resolve_entity_placeholders<T: BuildHasher>(
+    prefab: &mut Prefab,
+    placeholders: &HashMap<Entity, Entity>,
+    registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, T>,
+) {
+    let components_by_type_id = components_by_type_id(registered_components);
+    let mut merger = PlaceholderCloneImpl {
+        copy: CopyCloneImpl::new(&components_by_type_id),
+        placeholders,
+    };
+    let mut world = World::default();
+    world.clone_from(&prefab.world, &legion::query::any(), &mut merger);
+    prefab.world = world;
+
+    for entity in prefab.prefab_meta.entities.values_mut() {
+        if let Some(placeholder) = placeholders.get(entity) {
+            *entity = *placeholder;
+        }
+    }
+}
+
+fn components_by_type_id<T: BuildHasher>(
+    registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, T>
+) -> HashMap<ComponentTypeId, ComponentRegistration> {
+    registered_components
+        .values()
+        .map(|registration| (registration.component_type_id(), registration.clone()))
+        .collect()
+}
+
+// Copies a world, keeping the IDs of all entities except for those with a placeholder
+struct PlaceholderCloneImpl<'a> {
+    copy: CopyCloneImpl<'a, std::collections::hash_map::RandomState>,
+    placeholders: &'a HashMap<Entity, Entity>,
+}
+
+impl<'a> Merger for PlaceholderCloneImpl<'a> {
+    fn prefers_new_archetype() -> bool {
+        false
+    }
+
+    fn assign_id(
+        &mut self,
+        existing: Entity,
+        _allocator: &mut Allocate,
+    ) -> Entity {
+        self.placeholders
+            .get(&existing)
+            .cloned()
+            .unwrap_or(existing)
+    }
+
+    fn convert_layout(
+        &mut self,
+        source_layout: EntityLayout,
+    ) -> EntityLayout {
+        self.copy.convert_layout(source_layout)
+    }
+
+    fn merge_archetype(
+        &mut self,
+        src_entity_range: Range<usize>,
+        src_arch: &Archetype,
+        src_components: &Components,
+        dst: &mut ArchetypeWriter,
+    ) {
+        self.copy
+            .merge_archetype(src_entity_range, src_arch, src_components, dst)
     }
 }
 
 impl<'a, T: BuildHasher> PrefabFormatDeserializer<'a, T> {
+    fn registration(
+        &self,
+        component_type: &ComponentTypeUuid,
+    ) -> Option<&'a ComponentRegistration> {
+        let registered_components = self.context.registered_components;
+        if let Some(registration) = registered_components.get(component_type) {
+            return Some(registration);
+        }
+        if !self.type_name_fallback {
+            return None;
+        }
+        let type_names = self.type_names.borrow();
+        let type_name = type_names.get(component_type)?;
+        registered_components
+            .values()
+            .find(|registration| registration.type_name() == type_name)
+    }
+
     fn get_or_insert_prefab_mut(
         &self,
         prefab_uuid: &PrefabUuid,
@@ -129,7 +419,14 @@ impl<'a, T: BuildHasher> PrefabFormatDeserializer<'a, T> {
                     id: *prefab_uuid,
                     entities: HashMap::new(),
                     prefab_refs: HashMap::new(),
+                    parameters: Vec::new(),
+                    entity_layers: HashMap::new(),
+                    hierarchy: HashMap::new(),
+                    blobs: HashMap::new(),
+                    extends: None,
+                    entity_refs: HashMap::new(),
                 },
+                resources: Default::default(),
             });
         }
 
@@ -154,6 +451,15 @@ impl<T: BuildHasher> StorageDeserializer for PrefabFormatDeserializer<'_, T> {
         let mut prefab = self.get_or_insert_prefab_mut(prefab);
         let new_entity = prefab.world.push(());
         prefab.prefab_meta.entities.insert(*entity, new_entity);
+
+        let mut entity_refs = self.entity_refs.borrow_mut();
+        if let Some(placeholder) = entity_refs.entity(entity) {
+            self.placeholders
+                .borrow_mut()
+                .insert(new_entity, placeholder);
+        } else {
+            entity_refs.insert(*entity, new_entity);
+        }
     }
     fn end_entity_object(
         &self,
@@ -176,22 +482,26 @@ impl<T: BuildHasher> StorageDeserializer for PrefabFormatDeserializer<'_, T> {
             // deserializer implementation error, begin_entity_object shall always be called before deserialize_component
             .expect("could not find prefab entity");
 
-        let registered = self
-            .context
-            .registered_components
-            .get(component_type)
-            .ok_or_else(|| {
-                <D::Error as serde::de::Error>::custom(format!(
-                    "Component type {:?} was not registered when deserializing",
-                    component_type
-                ))
-            })?;
-
-        registered.add_to_entity(
-            &mut erased_serde::Deserializer::erase(deserializer),
-            &mut prefab.world,
-            entity,
-        );
+        let registered = self.registration(component_type).ok_or_else(|| {
+            <D::Error as serde::de::Error>::custom(format!(
+                "Component type {:?} was not registered when deserializing",
+                component_type
+            ))
+        })?;
+
+        let mut entity_refs = self.entity_refs.borrow_mut();
+        let mut allocator = self.allocator.borrow_mut();
+        let mapper = EntityUuidMapper {
+            entity_map: RefCell::new(&mut *entity_refs),
+            allocator: RefCell::new(&mut *allocator),
+        };
+        mapper.scope(|| {
+            registered.add_to_entity(
+                &mut erased_serde::Deserializer::erase(deserializer),
+                &mut prefab.world,
+                entity,
+            )
+        });
         Ok(())
     }
     fn begin_prefab_ref(
@@ -206,6 +516,8 @@ impl<T: BuildHasher> StorageDeserializer for PrefabFormatDeserializer<'_, T> {
             .entry(*target_prefab)
             .or_insert_with(|| PrefabRef {
                 overrides: HashMap::new(),
+                parameter_values: BTreeMap::new(),
+                transform: PrefabRefTransform::IDENTITY,
             });
     }
     fn end_prefab_ref(
@@ -214,6 +526,97 @@ impl<T: BuildHasher> StorageDeserializer for PrefabFormatDeserializer<'_, T> {
         _target_prefab: &PrefabUuid,
     ) {
     }
+    fn declare_parameter(
+        &self,
+        prefab: &PrefabUuid,
+        parameter: PrefabParameter,
+    ) {
+        let mut prefab = self.get_or_insert_prefab_mut(prefab);
+        prefab.prefab_meta.parameters.push(parameter);
+    }
+    fn declare_layer(
+        &self,
+        prefab: &PrefabUuid,
+        name: &str,
+        entities: &[EntityUuid],
+    ) {
+        let mut prefab = self.get_or_insert_prefab_mut(prefab);
+        for entity in entities {
+            prefab
+                .prefab_meta
+                .entity_layers
+                .insert(*entity, name.to_string());
+        }
+    }
+    fn declare_children(
+        &self,
+        prefab: &PrefabUuid,
+        parent: &EntityUuid,
+        children: &[EntityUuid],
+    ) {
+        let mut prefab = self.get_or_insert_prefab_mut(prefab);
+        prefab
+            .prefab_meta
+            .hierarchy
+            .insert(*parent, children.to_vec());
+    }
+    fn declare_blob(
+        &self,
+        prefab: &PrefabUuid,
+        blob: &BlobId,
+        data: BlobData,
+    ) {
+        let mut prefab = self.get_or_insert_prefab_mut(prefab);
+        prefab.prefab_meta.blobs.insert(*blob, data);
+    }
+    fn declare_component_type_name(
+        &self,
+        _prefab: &PrefabUuid,
+        name: &str,
+        component_type: &ComponentTypeUuid,
+    ) {
+        self.type_names
+            .borrow_mut()
+            .insert(*component_type, name.to_string());
+    }
+    fn set_parameter_value(
+        &self,
+        parent_prefab: &PrefabUuid,
+        prefab_ref: &PrefabUuid,
+        name: &str,
+        value: &str,
+    ) {
+        let mut prefab = self.get_or_insert_prefab_mut(parent_prefab);
+        prefab
+            .prefab_meta
+            .prefab_refs
+            .get_mut(prefab_ref)
+            .expect("set_parameter_value called without begin_prefab_ref")
+            .parameter_values
+            .insert(name.to_string(), value.to_string());
+    }
+    fn set_prefab_ref_transform(
+        &self,
+        parent_prefab: &PrefabUuid,
+        prefab_ref: &PrefabUuid,
+        transform: &PrefabRefTransform,
+    ) {
+        let mut prefab = self.get_or_insert_prefab_mut(parent_prefab);
+        prefab
+            .prefab_meta
+            .prefab_refs
+            .get_mut(prefab_ref)
+            .expect("set_prefab_ref_transform called without begin_prefab_ref")
+            .transform = *transform;
+    }
+    fn set_base_prefab(
+        &self,
+        prefab: &PrefabUuid,
+        base_prefab: &PrefabUuid,
+    ) {
+        let mut prefab = self.get_or_insert_prefab_mut(prefab);
+        prefab.prefab_meta.extends = Some(*base_prefab);
+    }
     fn apply_component_diff<'de, D: Deserializer<'de>>(
         &self,
         parent_prefab: &PrefabUuid,
@@ -232,12 +635,130 @@ impl<T: BuildHasher> StorageDeserializer for PrefabFormatDeserializer<'_, T> {
             .overrides
             .entry(*entity)
             .or_insert_with(Vec::<ComponentOverride>::new);
+        // Overrides of unregistered types are kept as they are
+        let component_type = self
+            .registration(component_type)
+            .map_or(*component_type, |registration| *registration.uuid());
         overrides.push(ComponentOverride {
-            component_type: *component_type,
+            component_type,
             data: String::deserialize(deserializer)?,
         });
         Ok(())
     }
+    fn deserialize_template_component<'de, D: Deserializer<'de>>(
+        &self,
+        _prefab: &PrefabUuid,
+        template: &TemplateUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        let registered = self.registration(component_type).ok_or_else(|| {
+            <D::Error as serde::de::Error>::custom(format!(
+                "Component type {:?} was not registered when deserializing",
+                component_type
+            ))
+        })?;
+
+        let mut templates = self.templates.borrow_mut();
+        let template_entity = *self
+            .template_entities
+            .borrow_mut()
+            .entry(*template)
+            .or_insert_with(|| templates.push(()));
+
+        let mut entity_refs = self.entity_refs.borrow_mut();
+        let mut allocator = self.allocator.borrow_mut();
+        let mapper = EntityUuidMapper {
+            entity_map: RefCell::new(&mut *entity_refs),
+            allocator: RefCell::new(&mut *allocator),
+        };
+        mapper.scope(|| {
+            registered.add_to_entity(
+                &mut erased_serde::Deserializer::erase(deserializer),
+                &mut templates,
+                template_entity,
+            )
+        });
+        Ok(())
+    }
+    fn instantiate_template(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+        template: &TemplateUuid,
+    ) {
+        let template_entity = match self.template_entities.borrow().get(template) {
+            Some(template_entity) => *template_entity,
+            None => {
+                log::warn!(
+                    "Entity {:?} instantiates template {:?}, which the prefab doesn't declare",
+                    uuid::Uuid::from_bytes(*entity),
+                    uuid::Uuid::from_bytes(*template)
+                );
+                return;
+            }
+        };
+
+        let mut prefab = self.get_or_insert_prefab_mut(prefab);
+        let new_entity = prefab.prefab_meta.entities[entity];
+
+        // Replace the empty entity from begin_entity_object with a copy of the template that keeps
+        // its ID
+        let components_by_type_id = self
+            .components_by_type_id
+            .get_or_init(|| components_by_type_id(self.context.registered_components));
+        let mut placeholders = HashMap::new();
+        placeholders.insert(template_entity, new_entity);
+        let mut merger = PlaceholderCloneImpl {
+            copy: CopyCloneImpl::new(components_by_type_id),
+            placeholders: &placeholders,
+        };
+        prefab.world.remove(new_entity);
+        prefab
+            .world
+            .clone_from_single(&self.templates.borrow(), template_entity, &mut merger);
+    }
+    fn apply_template_diff<'de, D: Deserializer<'de>>(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        let mut prefab = self.get_or_insert_prefab_mut(prefab);
+        let entity = *prefab
+            .prefab_meta
+            .entities
+            .get(entity)
+            .expect("could not find prefab entity");
+
+        let registered = self.registration(component_type).ok_or_else(|| {
+            <D::Error as serde::de::Error>::custom(format!(
+                "Component type {:?} was not registered when deserializing",
+                component_type
+            ))
+        })?;
+
+        // Like overrides, the diff is stored as RON text
+        let diff = String::deserialize(deserializer)?;
+        let mut diff_deserializer = ron::de::Deserializer::from_str(&diff)
+            .map_err(<D::Error as serde::de::Error>::custom)?;
+
+        let mut entity_refs = self.entity_refs.borrow_mut();
+        let mut allocator = self.allocator.borrow_mut();
+        let mapper = EntityUuidMapper {
+            entity_map: RefCell::new(&mut *entity_refs),
+            allocator: RefCell::new(&mut *allocator),
+        };
+        mapper.scope(|| {
+            registered.apply_diff(
+                &mut erased_serde::Deserializer::erase(&mut diff_deserializer),
+                &mut prefab.world,
+                entity,
+            )
+        });
+        Ok(())
+    }
 }
 
 impl Serialize for Prefab {
@@ -249,31 +770,26 @@ impl Serialize for Prefab {
         S: serde::Serializer,
     {
         use serde::ser::SerializeStruct;
-        use std::iter::FromIterator;
 
-        //TODO: Not good to allocate and throw away
-        let comp_types = HashMap::from_iter(
-            crate::registration::iter_component_registrations()
-                .map(|reg| (reg.component_type_id(), reg.clone())),
-        );
-        let mut entity_map = HashMap::from_iter(
-            self.prefab_meta
-                .entities
-                .iter()
-                .map(|(uuid, entity)| (*entity, *uuid)),
-        );
+        let registry = crate::registration::global_component_registry();
+        let mut entity_map = self.entity_uuids();
+        assign_missing_uuids(&self.world, &mut entity_map);
 
         let custom_serializer = CustomSerializer {
-            comp_types: &comp_types,
+            comp_types: registry.by_type_id(),
             entity_map: RefCell::new(&mut entity_map),
+            component_filter: None,
+            columnar: false,
+            column_table: None,
         };
 
         let serializable_world = self
             .world
             .as_serializable(legion::query::any(), &custom_serializer);
-        let mut struct_ser = serializer.serialize_struct("Prefab", 2)?;
+        let mut struct_ser = serializer.serialize_struct("Prefab", 3)?;
         struct_ser.serialize_field("prefab_meta", &self.prefab_meta)?;
         struct_ser.serialize_field("world", &serializable_world)?;
+        struct_ser.serialize_field("resources", &self.resources)?;
         struct_ser.end()
     }
 }
@@ -283,6 +799,7 @@ impl Serialize for Prefab {
 enum PrefabField {
     PrefabMeta,
     World,
+    Resources,
 }
 impl<'de> Deserialize<'de> for Prefab {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
@@ -309,10 +826,13 @@ impl<'de> Deserialize<'de> for Prefab {
                 let mut prefab_meta: PrefabMeta =
                     seq.next_element()?.expect("expected prefab_meta");
                 let world = seq.next_element::<WorldDeser>()?.expect("expected world");
-                prefab_meta.entities = world.1;
+                // Not present in prefabs saved before resources were supported
+                let resources = seq.next_element()?.unwrap_or_default();
+                world.set_entities(&mut prefab_meta);
                 Ok(Prefab {
                     prefab_meta,
                     world: world.0,
+                    resources,
                 })
             }
 
@@ -324,60 +844,75 @@ impl<'de> Deserialize<'de> for Prefab {
                 V: serde::de::MapAccess<'de>,
             {
                 let mut prefab_meta: Option<PrefabMeta> = None;
+                let mut world = None;
+                let mut resources = PrefabResources::new();
                 while let Some(key) = map.next_key()? {
                     match key {
                         PrefabField::PrefabMeta => {
                             prefab_meta = Some(map.next_value()?);
                         }
                         PrefabField::World => {
-                            let world_deser = map.next_value::<WorldDeser>()?;
-                            let mut prefab_meta =
-                                prefab_meta.expect("expected prefab_meta before world");
-                            prefab_meta.entities = world_deser.1;
-                            return Ok(Prefab {
-                                prefab_meta,
-                                world: world_deser.0,
-                            });
+                            world = Some(map.next_value::<WorldDeser>()?);
+                        }
+                        PrefabField::Resources => {
+                            resources = map.next_value()?;
                         }
                     }
                 }
-                Err(serde::de::Error::missing_field("data"))
+                let mut prefab_meta =
+                    prefab_meta.ok_or_else(|| serde::de::Error::missing_field("prefab_meta"))?;
+                let world = world.ok_or_else(|| serde::de::Error::missing_field("world"))?;
+                world.set_entities(&mut prefab_meta);
+                Ok(Prefab {
+                    prefab_meta,
+                    world: world.0,
+                    resources,
+                })
             }
         }
-        const FIELDS: &[&str] = &["prefab_meta", "world"];
+        const FIELDS: &[&str] = &["prefab_meta", "world", "resources"];
         deserializer.deserialize_struct("Prefab", FIELDS, PrefabDeserVisitor)
     }
 }
 struct WorldDeser(World, HashMap<EntityUuid, legion::Entity>);
+impl WorldDeser {
+    // UUIDs of entities that aren't in the world were only referred to by components
+    fn set_entities(
+        &self,
+        prefab_meta: &mut PrefabMeta,
+    ) {
+        let (entities, entity_refs) = self
+            .1
+            .iter()
+            .map(|(entity_uuid, entity)| (*entity_uuid, *entity))
+            .partition(|(_, entity)| self.0.contains(*entity));
+        prefab_meta.entities = entities;
+        prefab_meta.entity_refs = entity_refs;
+    }
+}
 impl<'de> Deserialize<'de> for WorldDeser {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        use std::iter::FromIterator;
+        let registry = crate::registration::global_component_registry();
 
-        //TODO: Not good to allocate and throw away
-        let comp_types = HashMap::from_iter(
-            crate::registration::iter_component_registrations()
-                .map(|reg| (reg.component_type_id(), reg.clone())),
-        );
-        let comp_types_uuid = HashMap::from_iter(
-            crate::registration::iter_component_registrations()
-                .map(|reg| (*reg.uuid(), reg.clone())),
-        );
-
-        let mut entity_map = HashMap::new();
+        let mut entity_map = UuidEntityBimap::new();
         let custom_deserializer = CustomDeserializer {
-            comp_types: &comp_types,
-            comp_types_uuid: &comp_types_uuid,
+            comp_types: registry.by_type_id(),
+            comp_types_uuid: registry.by_uuid(),
             entity_map: RefCell::new(&mut entity_map),
             allocator: RefCell::new(legion::world::Allocate::new()),
+            columnar: false,
+            unknown_components: UnknownComponentPolicy::Error,
+            skipped_components: RefCell::default(),
+            decoded_columns: None,
         };
 
         let seed = legion::serialize::DeserializeNewWorld(&custom_deserializer);
         let world: World = seed.deserialize(deserializer).unwrap();
 
-        Ok(WorldDeser(world, entity_map))
+        Ok(WorldDeser(world, entity_map.uuid_to_entity().clone()))
     }
 }
 
@@ -385,6 +920,10 @@ pub struct PrefabFormatSerializer<'a, 'b, T: BuildHasher> {
     prefab: &'b Prefab,
     context: PrefabSerdeContext<'a, T>,
     type_id_to_uuid: HashMap<ComponentTypeId, ComponentTypeUuid>,
+    // Used to write `Entity` fields of components as UUIDs
+    entity_refs: RefCell<UuidEntityBimap>,
+    allocator: RefCell<Allocate>,
+    write_type_names: bool,
 }
 impl<'a, 'b, T: BuildHasher> PrefabFormatSerializer<'a, 'b, T> {
     pub fn new(
@@ -399,14 +938,30 @@ impl<'a, 'b, T: BuildHasher> PrefabFormatSerializer<'a, 'b, T> {
                 context
                     .registered_components
                     .iter()
+                    .filter(|(_, reg)| !reg.is_clone_only())
                     .map(|(type_id, reg)| (reg.component_type_id(), *type_id)),
             ),
+            entity_refs: RefCell::new(prefab.entity_uuids()),
+            allocator: RefCell::new(Allocate::new()),
+            write_type_names: false,
         }
     }
+
+    /// Writes component types by their registered type name instead of their UUID in text
+    /// formats, which makes the file easier to review. The names are listed with their UUIDs in
+    /// the file's `type_map`.
+    pub fn with_type_names(mut self) -> Self {
+        self.write_type_names = true;
+        self
+    }
 }
+// Everything is returned sorted by UUID so that saving the same prefab twice produces identical
+// output
 impl<T: BuildHasher> StorageSerializer for PrefabFormatSerializer<'_, '_, T> {
     fn entities(&self) -> Vec<EntityUuid> {
-        self.prefab.prefab_meta.entities.keys().cloned().collect()
+        let mut entities: Vec<_> = self.prefab.prefab_meta.entities.keys().cloned().collect();
+        entities.sort();
+        entities
     }
 
     fn component_types(
@@ -420,13 +975,16 @@ impl<T: BuildHasher> StorageSerializer for PrefabFormatSerializer<'_, '_, T> {
             .entry_ref(entity)
             .expect("entity not in World when serializing prefab");
 
-        e.archetype()
+        let mut component_types: Vec<_> = e
+            .archetype()
             .layout()
             .component_types()
             .iter()
             .filter_map(|type_id| self.type_id_to_uuid.get(type_id).cloned())
             .filter(|type_id| self.context.registered_components.contains_key(type_id))
-            .collect()
+            .collect();
+        component_types.sort();
+        component_types
     }
     fn serialize_entity_component<S: Serializer>(
         &self,
@@ -437,38 +995,51 @@ impl<T: BuildHasher> StorageSerializer for PrefabFormatSerializer<'_, '_, T> {
         let mut result = None;
         let mut serializer = Some(serializer);
         let entity = self.prefab.prefab_meta.entities[entity_uuid];
-        self.context.registered_components[component].serialize_single(
-            &self.prefab.world,
-            entity,
-            &mut |comp| {
-                result = Some(erased_serde::serialize(comp, serializer.take().unwrap()));
-            },
-        );
+        let mut entity_refs = self.entity_refs.borrow_mut();
+        let mut allocator = self.allocator.borrow_mut();
+        let mapper = EntityUuidMapper {
+            entity_map: RefCell::new(&mut *entity_refs),
+            allocator: RefCell::new(&mut *allocator),
+        };
+        mapper.scope(|| {
+            self.context.registered_components[component].serialize_single(
+                &self.prefab.world,
+                entity,
+                &mut |comp| {
+                    result = Some(erased_serde::serialize(comp, serializer.take().unwrap()));
+                },
+            )
+        });
         result.unwrap()
     }
     fn prefab_refs(&self) -> Vec<PrefabUuid> {
-        self.prefab
+        let mut prefab_refs: Vec<_> = self
+            .prefab
             .prefab_meta
             .prefab_refs
             .keys()
             .cloned()
-            .collect()
+            .collect();
+        prefab_refs.sort();
+        prefab_refs
     }
     fn prefab_ref_overrides(
         &self,
         uuid: &PrefabUuid,
     ) -> Vec<(EntityUuid, Vec<ComponentTypeUuid>)> {
         let prefab_ref = &self.prefab.prefab_meta.prefab_refs[uuid];
-        prefab_ref
+        let mut overrides: Vec<(EntityUuid, Vec<ComponentTypeUuid>)> = prefab_ref
             .overrides
             .iter()
             .map(|(entity_uuid, comps)| {
-                (
-                    *entity_uuid,
-                    comps.iter().map(|comp| comp.component_type).collect(),
-                )
+                let mut component_types: Vec<_> =
+                    comps.iter().map(|comp| comp.component_type).collect();
+                component_types.sort();
+                (*entity_uuid, component_types)
             })
-            .collect()
+            .collect();
+        overrides.sort_by(|a, b| a.0.cmp(&b.0));
+        overrides
     }
     fn serialize_component_override_diff<S: Serializer>(
         &self,
@@ -484,4 +1055,72 @@ impl<T: BuildHasher> StorageSerializer for PrefabFormatSerializer<'_, '_, T> {
             .expect("invalid component type when serializing component override diff");
         comp_override.data.serialize(serializer)
     }
+    fn parameters(&self) -> Vec<PrefabParameter> {
+        self.prefab.prefab_meta.parameters.clone()
+    }
+    fn layers(&self) -> BTreeMap<String, Vec<EntityUuid>> {
+        let mut layers: BTreeMap<String, Vec<EntityUuid>> = BTreeMap::new();
+        for (entity, layer) in &self.prefab.prefab_meta.entity_layers {
+            layers.entry(layer.clone()).or_default().push(*entity);
+        }
+        for entities in layers.values_mut() {
+            entities.sort();
+        }
+        layers
+    }
+    fn hierarchy(&self) -> BTreeMap<EntityUuid, Vec<EntityUuid>> {
+        self.prefab
+            .prefab_meta
+            .hierarchy
+            .iter()
+            .filter(|(_, children)| !children.is_empty())
+            .map(|(parent, children)| (*parent, children.clone()))
+            .collect()
+    }
+    fn base_prefab(&self) -> Option<PrefabUuid> {
+        self.prefab.prefab_meta.extends
+    }
+    fn blobs(&self) -> Vec<(BlobId, &BlobData)> {
+        self.prefab
+            .prefab_meta
+            .blobs
+            .iter()
+            .map(|(blob, data)| (*blob, data))
+            .collect()
+    }
+    fn prefab_ref_parameter_values(
+        &self,
+        uuid: &PrefabUuid,
+    ) -> BTreeMap<String, String> {
+        self.prefab.prefab_meta.prefab_refs[uuid]
+            .parameter_values
+            .clone()
+    }
+    fn prefab_ref_transform(
+        &self,
+        uuid: &PrefabUuid,
+    ) -> PrefabRefTransform {
+        self.prefab.prefab_meta.prefab_refs[uuid].transform
+    }
+    fn component_type_name(
+        &self,
+        component_type: &ComponentTypeUuid,
+    ) -> Option<String> {
+        if !self.write_type_names {
+            return None;
+        }
+        self.context
+            .registered_components
+            .get(component_type)
+            .map(|registration| registration.type_name().to_string())
+    }
+    fn is_marker_component(
+        &self,
+        component_type: &ComponentTypeUuid,
+    ) -> bool {
+        self.context
+            .registered_components
+            .get(component_type)
+            .map_or(false, |registration| registration.is_marker())
+    }
 }
@@ -0,0 +1,72 @@
+use crate::format::{EntityUuid, PrefabRefTransform, PrefabUuid};
+use crate::cooking::sorted_prefab_refs;
+use crate::{cooked_hierarchy, CookedPrefab, HierarchyError, Prefab};
+use legion::world::{Entity, World};
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
+
+/// Calls `compose` for the root entities of every prefab ref with an offset, so the engine can
+/// combine the offset with the entity's transform component. Run this on the result of
+/// `cook_prefab` (or `cook_prefab_with_hierarchy`), so the offset is applied on top of the
+/// overrides of the instance.
+///
+/// The roots of an instance are the entities of the referenced prefab (including those of the
+/// prefabs it references in turn) that don't have a parent within it in the cooked hierarchy.
+/// Offsets are applied in `prefab_cook_order`, so for nested instances the inner offset is passed
+/// to `compose` before the outer one.
+pub fn apply_prefab_ref_transforms<S, F>(
+    cooked_prefab: &mut CookedPrefab,
+    prefab_cook_order: &[PrefabUuid],
+    prefab_lookup: &HashMap<PrefabUuid, &Prefab, S>,
+    mut compose: F,
+) -> Result<(), HierarchyError>
+where
+    S: BuildHasher,
+    F: FnMut(&mut World, Entity, &PrefabRefTransform),
+{
+    let hierarchy = cooked_hierarchy(cooked_prefab, prefab_cook_order, prefab_lookup)?;
+    let mut parent_of = HashMap::new();
+    for (parent, children) in &hierarchy {
+        for child in children {
+            parent_of.insert(*child, *parent);
+        }
+    }
+
+    // The entities each prefab contributes to the cooked prefab. Referenced prefabs come earlier
+    // in the cook order, so their entities are already known
+    let mut prefab_entities: HashMap<PrefabUuid, HashSet<EntityUuid>> = HashMap::new();
+    for prefab_id in prefab_cook_order {
+        let prefab = prefab_lookup[prefab_id];
+        let mut entities: HashSet<EntityUuid> =
+            prefab.prefab_meta.entities.keys().cloned().collect();
+
+        for (prefab_ref_id, prefab_ref) in sorted_prefab_refs(prefab) {
+            let instance_entities = &prefab_entities[prefab_ref_id];
+            entities.extend(instance_entities.iter().cloned());
+
+            if prefab_ref.transform.is_identity() {
+                continue;
+            }
+
+            let mut roots: Vec<_> = instance_entities
+                .iter()
+                .filter(|entity| {
+                    parent_of
+                        .get(*entity)
+                        .map_or(true, |parent| !instance_entities.contains(parent))
+                })
+                .collect();
+            roots.sort();
+
+            for root in roots {
+                if let Some(entity) = cooked_prefab.entities.entity(root) {
+                    compose(&mut cooked_prefab.world, entity, &prefab_ref.transform);
+                }
+            }
+        }
+
+        prefab_entities.insert(*prefab_id, entities);
+    }
+
+    Ok(())
+}
@@ -4,118 +4,323 @@ use legion::storage::{
     ArchetypeWriter, UnknownComponentWriter,
 };
 use serde::{
-    de::{self, DeserializeSeed, IgnoredAny, Visitor},
-    Deserialize, Deserializer, Serialize,
+    de::{self, IgnoredAny},
+    Deserialize, Serialize,
 };
 use serde_diff::SerdeDiff;
-use std::{any::TypeId, marker::PhantomData, ptr::NonNull};
+use std::{
+    any::TypeId,
+    borrow::Cow,
+    collections::{hash_map::RandomState, HashMap},
+};
 use type_uuid::TypeUuid;
+use once_cell::sync::OnceCell;
 use legion::storage::ComponentTypeId;
 use legion::EntityStore;
 use legion::world::{Entity, World};
 use std::ops::Range;
+use crate::PrefabSerdeContext;
+use crate::CopyCloneImpl;
+use crate::DiffOptions;
+use crate::OverridePolicy;
+use crate::override_policy::WholeValue;
+use crate::format::ComponentTypeUuid;
+use crate::format::EntityUuid;
+use crate::validation::{ValidateWorldFn, ValidationCtx, ValidationError};
+use std::sync::Arc;
 
-struct ComponentDeserializer<'de, T: Deserialize<'de>> {
-    ptr: *mut T,
-    _marker: PhantomData<&'de T>,
+/// What `ComponentRegistration::diff_single` found for a component
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffSingleKind {
+    NoChange,
+    Change,
+    Add,
+    Remove,
 }
 
-impl<'de, T: Deserialize<'de> + 'static> DeserializeSeed<'de> for ComponentDeserializer<'de, T> {
-    type Value = ();
-    fn deserialize<D>(
-        self,
-        deserializer: D,
-    ) -> Result<Self::Value, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let value = <T as Deserialize<'de>>::deserialize(deserializer)?;
-        unsafe {
-            std::ptr::write(self.ptr, value);
+/// The result of `ComponentRegistration::diff_single`. Describes what was written, so callers can
+/// decide what to do with it (skip it, report stats) without reading it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffSingleResult {
+    pub kind: DiffSingleKind,
+    /// The size of what was written to the serializer when encoded with bincode, which is how
+    /// transactions store diffs. Text formats write more. 0 if nothing was written, or if
+    /// `DiffOptions::measure` wasn't set.
+    pub bytes_written: u64,
+    /// The number of values a `Change` sets or removes, counting nested fields separately. 0 for
+    /// the other kinds, or if `DiffOptions::measure` wasn't set.
+    pub changed_fields: usize,
+}
+
+impl DiffSingleResult {
+    fn new(kind: DiffSingleKind) -> Self {
+        DiffSingleResult {
+            kind,
+            bytes_written: 0,
+            changed_fields: 0,
         }
-        Ok(())
+    }
+
+    pub fn is_no_change(&self) -> bool {
+        self.kind == DiffSingleKind::NoChange
     }
 }
 
-struct ComponentSeqDeserializer<'a, T> {
-    get_next_storage_fn: &'a mut dyn FnMut() -> Option<(NonNull<u8>, usize)>,
-    _marker: PhantomData<T>,
+// Size of the data written for a diff, see DiffSingleResult::bytes_written
+fn bincode_size<T: ?Sized + Serialize>(value: &T) -> u64 {
+    use bincode::Options;
+    bincode::config::DefaultOptions::new()
+        .serialized_size(value)
+        .expect("failed to serialize diff")
 }
 
-impl<'de, 'a, T: for<'b> Deserialize<'b> + 'static> DeserializeSeed<'de>
-    for ComponentSeqDeserializer<'a, T>
-{
-    type Value = ();
-    fn deserialize<D>(
-        self,
-        deserializer: D,
-    ) -> Result<Self::Value, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        deserializer.deserialize_seq(self)
+// See DiffSingleResult::changed_fields
+fn changed_field_count<T: SerdeDiff>(diff: &serde_diff::Diff<T>) -> usize {
+    ron::ser::to_string(diff)
+        .ok()
+        .and_then(|diff| crate::field_diffs_from_ron(&diff).ok())
+        .map(|field_diffs| field_diffs.len())
+        .unwrap_or(0)
+}
+
+fn diff_entry_ref(
+    world: &World,
+    entity: Option<Entity>,
+) -> Option<legion::world::EntryRef> {
+    entity.and_then(|e| {
+        let entry_ref = world.entry_ref(e);
+        match entry_ref {
+            Ok(e) => Some(e),
+            Err(legion::world::EntityAccessError::EntityNotFound) => None,
+            Err(legion::world::EntityAccessError::AccessDenied) => {
+                panic!("Could not access world during diff")
+            }
+        }
+    })
+}
+
+fn diff_component<'a, T: legion::storage::Component>(
+    entry: &'a Option<legion::world::EntryRef>
+) -> Option<&'a T> {
+    entry
+        .as_ref()
+        .and_then(|entry| match entry.get_component::<T>() {
+            Ok(comp) => Some(comp),
+            Err(legion::world::ComponentError::NotFound { .. }) => None,
+            Err(legion::world::ComponentError::Denied { .. }) => {
+                panic!("Could not access component during diff")
+            }
+        })
+}
+
+// Diffs the serialized form of a component. T is the component type, or the proxy it's
+// (de)serialized as.
+fn diff_components<T: Serialize + SerdeDiff>(
+    ser: &mut dyn erased_serde::Serializer,
+    src_comp: Option<&T>,
+    dst_comp: Option<&T>,
+    options: &DiffOptions,
+) -> DiffSingleResult {
+    if let (Some(src_comp), Some(dst_comp)) = (src_comp, dst_comp) {
+        //
+        // Component exists before and after the change. If differences exist, serialize
+        // a diff and return a Change result. Otherwise, serialize nothing and return
+        // NoChange
+        //
+        // Whether there are differences is only known after walking the diff, so nothing
+        // is written to ser until the diff's first command
+        let diff = serde_diff::Diff::serializable(src_comp, dst_comp);
+        <serde_diff::Diff<T> as serde::ser::Serialize>::serialize(
+            &diff,
+            &mut crate::diff_output::ChangedDiffSerializer::new(ser),
+        )
+        .expect("failed to serialize diff");
+
+        if diff.has_changes() {
+            let mut result = DiffSingleResult::new(DiffSingleKind::Change);
+            if options.measure {
+                result.bytes_written = bincode_size(&diff);
+                result.changed_fields = changed_field_count(&diff);
+            }
+            result
+        } else {
+            DiffSingleResult::new(DiffSingleKind::NoChange)
+        }
+    } else if let Some(dst_comp) = &dst_comp {
+        //
+        // Component was created, serialize the object and return an Add result
+        //
+        erased_serde::serialize(dst_comp, ser).unwrap();
+        let mut result = DiffSingleResult::new(DiffSingleKind::Add);
+        if options.measure {
+            result.bytes_written = bincode_size(dst_comp);
+        }
+        result
+    } else if src_comp.is_some() {
+        //
+        // Component was removed, do not serialize anything and return a Remove result
+        //
+        DiffSingleResult::new(DiffSingleKind::Remove)
+    } else {
+        //
+        // Component didn't exist before or after, so do nothing
+        //
+        DiffSingleResult::new(DiffSingleKind::NoChange)
+    }
+}
+
+// dst with the floats that only drifted from src reset, see DiffOptions::float_epsilon
+fn without_float_drift<'a, T: Clone + SerdeDiff>(
+    src: Option<&T>,
+    dst: Option<&'a T>,
+    options: &DiffOptions,
+) -> Option<Cow<'a, T>> {
+    match (src, dst, options.float_epsilon) {
+        (Some(src), Some(dst), Some(float_epsilon)) => {
+            let mut dst = dst.clone();
+            crate::diff_options::reset_float_drift(src, &mut dst, float_epsilon);
+            Some(Cow::Owned(dst))
+        }
+        _ => dst.map(Cow::Borrowed),
     }
 }
-impl<'de, 'a, T: for<'b> Deserialize<'b> + 'static> Visitor<'de>
-    for ComponentSeqDeserializer<'a, T>
-{
-    type Value = ();
 
-    fn expecting(
+// Applies a diff written with `OverridePolicy::Replace`
+fn apply_whole_value_diff<T: Serialize + for<'de> Deserialize<'de> + SerdeDiff>(
+    d: &mut dyn erased_serde::Deserializer,
+    comp: &mut T,
+) {
+    <serde_diff::Apply<WholeValue<T>> as serde::de::DeserializeSeed>::deserialize(
+        serde_diff::Apply::deserializable(WholeValue::from_mut(comp)),
+        d,
+    )
+    .expect("failed to deserialize diff");
+}
+
+// The paths of the fields that differ between two values, like FieldDiff::path
+fn changed_field_paths<T: Serialize + SerdeDiff>(
+    before: &T,
+    after: &T,
+) -> Vec<String> {
+    let diff = serde_diff::Diff::serializable(before, after);
+    let diff = ron::ser::to_string(&diff).expect("failed to serialize diff");
+    crate::prefab_diff::field_paths_from_ron(&diff).expect("failed to read diff")
+}
+
+/// A component type that can be written and read as its raw bytes, see
+/// `ComponentRegistration::with_pod_packing`.
+///
+/// # Safety
+///
+/// The type must not have padding, must not contain pointers, references or `Entity` values, and
+/// every bit pattern of its size must be a valid value.
+pub unsafe trait PodComponent: Copy + 'static {}
+
+// Serializes the components of an archetype as one byte buffer
+struct PodBytes<'a>(&'a [u8]);
+
+impl<'a> Serialize for PodBytes<'a> {
+    fn serialize<S: serde::Serializer>(
         &self,
-        formatter: &mut std::fmt::Formatter,
-    ) -> std::fmt::Result {
-        formatter.write_str("sequence of objects")
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        crate::format::bytes::serialize(self.0, serializer)
     }
-    fn visit_seq<A>(
-        self,
-        mut seq: A,
-    ) -> Result<Self::Value, A::Error>
-    where
-        A: de::SeqAccess<'de>,
-    {
-        let size = seq.size_hint();
-        for _ in 0..size.unwrap_or(std::usize::MAX) {
-            match (self.get_next_storage_fn)() {
-                Some((storage_ptr, storage_len)) => {
-                    let storage_ptr = storage_ptr.as_ptr() as *mut T;
-                    for idx in 0..storage_len {
-                        let element_ptr = unsafe { storage_ptr.add(idx) };
-
-                        if seq
-                            .next_element_seed(ComponentDeserializer {
-                                ptr: element_ptr,
-                                _marker: PhantomData,
-                            })?
-                            .is_none()
-                        {
-                            panic!(
-                                "expected {} elements in chunk but only {} found",
-                                storage_len, idx
-                            );
-                        }
-                    }
-                }
-                None => {
-                    if seq.next_element::<IgnoredAny>()?.is_some() {
-                        panic!("unexpected element when there was no storage space available");
-                    } else {
-                        // No more elements and no more storage - that's what we want!
-                        break;
-                    }
-                }
-            }
+}
+
+/// Components of one type, deserialized but not added to a world yet. Either a component slice of a
+/// packed world, or a single component from `ComponentRegistration::comp_decode`.
+pub struct DecodedColumn {
+    component_type_id: ComponentTypeId,
+    // A Vec of the components, moved into the storage by write_fn. Dropping the column without
+    // writing it drops the components
+    components: Box<dyn std::any::Any + Send>,
+    write_fn: fn(Box<dyn std::any::Any + Send>, UnknownComponentWriter),
+    add_to_entity_fn: fn(Box<dyn std::any::Any + Send>, &mut World, Entity),
+}
+
+impl DecodedColumn {
+    fn new<T: legion::storage::Component>(components: Vec<T>) -> Self {
+        DecodedColumn {
+            component_type_id: ComponentTypeId::of::<T>(),
+            components: Box::new(components),
+            write_fn: write_column::<T>,
+            add_to_entity_fn: add_column_to_entity::<T>,
         }
-        Ok(())
+    }
+
+    pub fn component_type_id(&self) -> ComponentTypeId {
+        self.component_type_id
+    }
+
+    /// Appends the components to a storage of the column's component type
+    pub fn write(
+        self,
+        storage: UnknownComponentWriter,
+    ) {
+        (self.write_fn)(self.components, storage)
+    }
+
+    /// Adds the components to an entity one at a time. Each moves the entity to a new archetype,
+    /// unless it already has a component of the type, which is then replaced.
+    pub fn add_to_entity(
+        self,
+        world: &mut World,
+        entity: Entity,
+    ) {
+        (self.add_to_entity_fn)(self.components, world, entity)
     }
 }
 
-#[derive(PartialEq)]
-pub enum DiffSingleResult {
-    NoChange,
-    Change,
-    Add,
-    Remove,
+fn add_column_to_entity<T: legion::storage::Component>(
+    components: Box<dyn std::any::Any + Send>,
+    world: &mut World,
+    entity: Entity,
+) {
+    let components = components
+        .downcast::<Vec<T>>()
+        .expect("column was decoded as a different component type");
+    for component in *components {
+        world.entry(entity).unwrap().add_component(component);
+    }
+}
+
+fn write_column<T: legion::storage::Component>(
+    components: Box<dyn std::any::Any + Send>,
+    mut storage: UnknownComponentWriter,
+) {
+    let components = components
+        .downcast::<Vec<T>>()
+        .expect("column was decoded as a different component type");
+
+    // Ownership of the components moves to the storage. If the copy panics they are leaked
+    // rather than dropped by both the Vec and the storage.
+    let mut components = std::mem::ManuallyDrop::new(*components);
+    unsafe {
+        storage.extend_memcopy_raw(components.as_ptr() as *const u8, components.len());
+        components.set_len(0);
+        std::mem::ManuallyDrop::drop(&mut components);
+    }
+}
+
+// legion's WorldDeserializer takes each component of a human-readable world as bytes, which it
+// copies into the archetype's storage and then treats as initialized. The component is moved
+// straight into the buffer instead of being read back as bytes, which would read its padding. The
+// buffer only has byte alignment, so the write must be unaligned. Ownership moves with the write,
+// so nothing is dropped or forgotten here.
+fn into_component_bytes<T>(component: T) -> Box<[u8]> {
+    let mut bytes = vec![0u8; std::mem::size_of::<T>()].into_boxed_slice();
+    unsafe {
+        std::ptr::write_unaligned(bytes.as_mut_ptr() as *mut T, component);
+    }
+    bytes
+}
+
+fn clone_only_panic<T>() -> ! {
+    panic!(
+        "{} is registered as clone-only and can't be serialized or diffed",
+        std::any::type_name::<T>()
+    )
 }
 
 type CompRegisterFn = fn(&mut EntityLayout);
@@ -127,10 +332,8 @@ type CompSerializeSliceFn = fn(
 );
 type CompDeserializeFn =
     fn(&mut dyn erased_serde::Deserializer) -> Result<Box<[u8]>, erased_serde::Error>;
-type CompDeserializeSliceFn = fn(
-    storage: UnknownComponentWriter,
-    deserializer: &mut dyn erased_serde::Deserializer,
-) -> Result<(), erased_serde::Error>;
+type CompDecodeColumnFn =
+    fn(&mut dyn erased_serde::Deserializer) -> Result<DecodedColumn, erased_serde::Error>;
 type SerializeSingleFn = fn(&World, Entity, &mut dyn FnMut(&dyn erased_serde::Serialize));
 type DiffSingleFn = fn(
     &mut dyn erased_serde::Serializer,
@@ -138,8 +341,16 @@ type DiffSingleFn = fn(
     Option<Entity>,
     &World,
     Option<Entity>,
+    &DiffOptions,
 ) -> DiffSingleResult;
 type ApplyDiffFn = fn(&mut dyn erased_serde::Deserializer, &mut World, Entity);
+type ApplyDiffWithChangesFn =
+    fn(&mut dyn erased_serde::Deserializer, &mut World, Entity) -> Vec<String>;
+/// Passed to `ComponentRegistration::apply_diff_batch`. Called with an entity and a function that
+/// applies a diff (read from the given deserializer) to the entity's component
+pub type ApplyDiffBatchCallback<'a> =
+    dyn FnMut(Entity, &mut dyn FnMut(&mut dyn erased_serde::Deserializer)) + 'a;
+type ApplyDiffBatchFn = fn(&mut World, &mut ApplyDiffBatchCallback);
 type CompCloneFn = fn(
     src_entity_range: Range<usize>,
     src_arch: &Archetype,
@@ -156,18 +367,27 @@ pub struct ComponentRegistration {
     uuid: type_uuid::Bytes,
     ty: TypeId,
     type_name: &'static str,
+    component_size: usize,
     register_comp_fn: CompRegisterFn,
     comp_serialize_fn: CompSerializeFn,
     comp_serialize_slice_fn: CompSerializeSliceFn,
     comp_deserialize_fn: CompDeserializeFn,
-    comp_deserialize_slice_fn: CompDeserializeSliceFn,
+    comp_decode_fn: CompDecodeColumnFn,
+    comp_decode_column_fn: CompDecodeColumnFn,
     serialize_single_fn: SerializeSingleFn,
     diff_single_fn: DiffSingleFn,
     apply_diff_fn: ApplyDiffFn,
+    apply_diff_with_changes_fn: ApplyDiffWithChangesFn,
+    apply_diff_batch_fn: ApplyDiffBatchFn,
     comp_clone_fn: CompCloneFn,
     add_default_to_entity_fn: AddDefaultToEntityFn,
     add_to_entity_fn: AddToEntityFn,
     remove_from_entity_fn: RemoveFromEntityFn,
+    validate_fn: Option<Arc<ValidateWorldFn>>,
+    locked_fields: &'static [&'static str],
+    override_policy: OverridePolicy,
+    clone_only: bool,
+    marker: bool,
 }
 
 impl ComponentRegistration {
@@ -187,6 +407,12 @@ impl ComponentRegistration {
         self.type_name
     }
 
+    /// The size of one component in a world's storage. Memory the component owns elsewhere, like
+    /// the contents of a `Vec`, isn't included.
+    pub fn component_size(&self) -> usize {
+        self.component_size
+    }
+
     pub fn register_component(
         &self,
         layout: &mut EntityLayout,
@@ -213,7 +439,10 @@ impl ComponentRegistration {
         (self.comp_serialize_slice_fn)(storage, archetype, serialize_fn)
     }
 
-    // Used when deserializing a legion world in a human-readable format
+    // Used when deserializing a legion world in a human-readable format. legion's
+    // WorldDeserializer requires each component as bytes, which it copies into the archetype's
+    // storage and then treats as initialized, so the component is moved into the returned buffer
+    // and must not be dropped by the caller. Use `comp_decode` everywhere else.
     pub fn comp_deserialize(
         &self,
         deserializer: &mut dyn erased_serde::Deserializer,
@@ -221,13 +450,34 @@ impl ComponentRegistration {
         (self.comp_deserialize_fn)(deserializer)
     }
 
+    /// Deserializes a single component without adding it to a world yet. It is kept as the
+    /// component type, and `DecodedColumn::write` moves it into the destination storage.
+    pub fn comp_decode(
+        &self,
+        deserializer: &mut dyn erased_serde::Deserializer,
+    ) -> Result<DecodedColumn, erased_serde::Error> {
+        (self.comp_decode_fn)(deserializer)
+    }
+
     // Used when deserializing a legion world in a non-human-readable format
     pub fn comp_deserialize_slice(
         &self,
         storage: UnknownComponentWriter,
         deserializer: &mut dyn erased_serde::Deserializer,
     ) -> Result<(), erased_serde::Error> {
-        (self.comp_deserialize_slice_fn)(storage, deserializer)
+        // Nothing is written to the storage until every component has been deserialized, so a
+        // failure leaves the storage unchanged and drops the components decoded so far
+        self.decode_column(deserializer)?.write(storage);
+        Ok(())
+    }
+
+    // Deserializes a component slice written in a non-human-readable format without adding it to
+    // a world yet, so columns can be decoded on other threads. See `read_cooked_columns_parallel`
+    pub(crate) fn decode_column(
+        &self,
+        deserializer: &mut dyn erased_serde::Deserializer,
+    ) -> Result<DecodedColumn, erased_serde::Error> {
+        (self.comp_decode_column_fn)(deserializer)
     }
 
     // Used when serializing a single component into prefab format
@@ -279,7 +529,27 @@ impl ComponentRegistration {
         dst_world: &legion::world::World,
         dst_entity: Option<Entity>,
     ) -> DiffSingleResult {
-        (self.diff_single_fn)(ser, src_world, src_entity, dst_world, dst_entity)
+        self.diff_single_with_options(
+            ser,
+            src_world,
+            src_entity,
+            dst_world,
+            dst_entity,
+            &DiffOptions::default(),
+        )
+    }
+
+    // Like diff_single, but i.e. allows small float differences to count as no change
+    pub fn diff_single_with_options(
+        &self,
+        ser: &mut dyn erased_serde::Serializer,
+        src_world: &legion::world::World,
+        src_entity: Option<Entity>,
+        dst_world: &legion::world::World,
+        dst_entity: Option<Entity>,
+        options: &DiffOptions,
+    ) -> DiffSingleResult {
+        (self.diff_single_fn)(ser, src_world, src_entity, dst_world, dst_entity, options)
     }
 
     // Used for applying a diff stored in a prefab to an entity specified by an overridden prefab
@@ -293,6 +563,45 @@ impl ComponentRegistration {
         (self.apply_diff_fn)(de, world, entity);
     }
 
+    // Like apply_diff, but also returns the paths of the fields that changed, i.e. `stats.health`
+    // or `waypoints[2]`, so editors and hot reloading can react to only those fields. An empty
+    // path means the whole component was replaced. Slower than apply_diff, as the component is
+    // copied and diffed again.
+    pub fn apply_diff_with_changes(
+        &self,
+        de: &mut dyn erased_serde::Deserializer,
+        world: &mut legion::world::World,
+        entity: Entity,
+    ) -> Vec<String> {
+        (self.apply_diff_with_changes_fn)(de, world, entity)
+    }
+
+    // Used for applying diffs to many entities at once, i.e. large transactions and hot reloads.
+    // Components are visited archetype by archetype instead of looking up each entity separately.
+    // `diff_fn` is called for every entity in the world that has this component, and calls the
+    // function it's given to apply a diff to it. Entities it doesn't call the function for are
+    // left unchanged.
+    pub fn apply_diff_batch(
+        &self,
+        world: &mut legion::world::World,
+        diff_fn: &mut ApplyDiffBatchCallback,
+    ) {
+        (self.apply_diff_batch_fn)(world, diff_fn);
+    }
+
+    // Used when cooking to check every component of this type that belongs to an entity in
+    // `entity_to_uuid`. Does nothing if no validate fn is attached.
+    pub fn validate(
+        &self,
+        world: &legion::world::World,
+        entity_to_uuid: &HashMap<Entity, EntityUuid>,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        if let Some(validate_fn) = &self.validate_fn {
+            validate_fn(world, entity_to_uuid, errors);
+        }
+    }
+
     // Used to clone components from one world into another
     #[allow(clippy::missing_safety_doc)]
     pub unsafe fn clone_components(
@@ -317,11 +626,30 @@ impl ComponentRegistration {
             + legion::storage::Component
             + 'static,
     >() -> Self {
-        Self {
+        Self::of_with_uuid::<T>(T::UUID)
+    }
+
+    /// Like `of`, but the UUID that identifies the component type in prefabs is given instead of
+    /// coming from `TypeUuid`. Useful for types from other crates that don't implement it.
+    pub fn of_with_uuid<
+        T: Clone
+            + Serialize
+            + SerdeDiff
+            + for<'de> Deserialize<'de>
+            + Send
+            + Sync
+            + Default
+            + legion::storage::Component
+            + 'static,
+    >(
+        uuid: ComponentTypeUuid
+    ) -> Self {
+        let registration = Self {
             component_type_id: ComponentTypeId::of::<T>(),
-            uuid: T::UUID,
+            uuid,
             ty: TypeId::of::<T>(),
             type_name: std::any::type_name::<T>(),
+            component_size: std::mem::size_of::<T>(),
             register_comp_fn: |layout| {
                 layout.register_component::<T>();
             },
@@ -334,26 +662,11 @@ impl ComponentRegistration {
                 let slice = std::slice::from_raw_parts(ptr as *const T, len);
                 (serialize_fn)(&slice);
             },
-            comp_deserialize_fn: |d| {
-                let component = erased_serde::deserialize::<T>(d)?;
-                unsafe {
-                    let vec = std::slice::from_raw_parts(
-                        &component as *const T as *const u8,
-                        std::mem::size_of::<T>(),
-                    )
-                    .to_vec();
-                    std::mem::forget(component);
-                    Ok(vec.into_boxed_slice())
-                }
-            },
-            comp_deserialize_slice_fn: |mut storage, deserializer| {
-                let mut components = erased_serde::deserialize::<Vec<T>>(deserializer)?;
-                unsafe {
-                    let ptr = components.as_ptr();
-                    storage.extend_memcopy_raw(ptr as *const u8, components.len());
-                    components.set_len(0);
-                }
-                Ok(())
+            comp_deserialize_fn: |d| Ok(into_component_bytes(erased_serde::deserialize::<T>(d)?)),
+            comp_decode_fn: |d| Ok(DecodedColumn::new(vec![erased_serde::deserialize::<T>(d)?])),
+            comp_decode_column_fn: |deserializer| {
+                let components = erased_serde::deserialize::<Vec<T>>(deserializer)?;
+                Ok(DecodedColumn::new(components))
             },
             serialize_single_fn: |world, entity, s_fn| {
                 let comp = world.entry_ref(entity).unwrap();
@@ -363,77 +676,13 @@ impl ComponentRegistration {
                         .expect("entity not present when serializing component"),
                 );
             },
-            diff_single_fn: |ser, src_world, src_entity, dst_world, dst_entity| {
+            diff_single_fn: |ser, src_world, src_entity, dst_world, dst_entity, options| {
                 // TODO propagate error
-
-                fn get_entry_ref(
-                    world: &World,
-                    entity: Option<Entity>,
-                ) -> Option<legion::world::EntryRef> {
-                    entity.and_then(|e| {
-                        let entry_ref = world.entry_ref(e);
-                        match entry_ref {
-                            Ok(e) => Some(e),
-                            Err(legion::world::EntityAccessError::EntityNotFound) => None,
-                            Err(legion::world::EntityAccessError::AccessDenied) => {
-                                panic!("Could not access world during diff")
-                            }
-                        }
-                    })
-                }
-
-                fn get_component<'a, T: legion::storage::Component>(
-                    entry: &'a Option<legion::world::EntryRef>
-                ) -> Option<&'a T> {
-                    entry
-                        .as_ref()
-                        .and_then(|entry| match entry.get_component::<T>() {
-                            Ok(comp) => Some(comp),
-                            Err(legion::world::ComponentError::NotFound { .. }) => None,
-                            Err(legion::world::ComponentError::Denied { .. }) => {
-                                panic!("Could not access component during diff")
-                            }
-                        })
-                }
-
-                let src_entity = get_entry_ref(src_world, src_entity);
-                let dst_entity = get_entry_ref(dst_world, dst_entity);
-
-                let src_comp = get_component(&src_entity);
-                let dst_comp = get_component(&dst_entity);
-
-                if let (Some(src_comp), Some(dst_comp)) = (src_comp, dst_comp) {
-                    //
-                    // Component exists before and after the change. If differences exist, serialize
-                    // a diff and return a Change result. Otherwise, serialize nothing and return
-                    // NoChange
-                    //
-                    let diff = serde_diff::Diff::serializable(src_comp, dst_comp);
-                    <serde_diff::Diff<T> as serde::ser::Serialize>::serialize(&diff, ser)
-                        .expect("failed to serialize diff");
-
-                    if diff.has_changes() {
-                        DiffSingleResult::Change
-                    } else {
-                        DiffSingleResult::NoChange
-                    }
-                } else if let Some(dst_comp) = &dst_comp {
-                    //
-                    // Component was created, serialize the object and return an Add result
-                    //
-                    erased_serde::serialize(dst_comp, ser).unwrap();
-                    DiffSingleResult::Add
-                } else if src_comp.is_some() {
-                    //
-                    // Component was removed, do not serialize anything and return a Remove result
-                    //
-                    DiffSingleResult::Remove
-                } else {
-                    //
-                    // Component didn't exist before or after, so do nothing
-                    //
-                    DiffSingleResult::NoChange
-                }
+                let src_entity = diff_entry_ref(src_world, src_entity);
+                let dst_entity = diff_entry_ref(dst_world, dst_entity);
+                let src_comp = diff_component::<T>(&src_entity);
+                let dst_comp = without_float_drift(src_comp, diff_component(&dst_entity), options);
+                diff_components::<T>(ser, src_comp, dst_comp.as_deref(), options)
             },
             apply_diff_fn: |d, world, entity| {
                 //TODO: propagate error
@@ -449,6 +698,35 @@ impl ComponentRegistration {
                 )
                 .expect("failed to deserialize diff");
             },
+            apply_diff_with_changes_fn: |d, world, entity| {
+                let mut e = world.entry(entity).unwrap();
+
+                let comp = e
+                    .get_component_mut::<T>()
+                    .expect("expected component data when diffing");
+                let comp: &mut T = &mut *comp;
+                let before = comp.clone();
+                <serde_diff::Apply<T> as serde::de::DeserializeSeed>::deserialize(
+                    serde_diff::Apply::deserializable(&mut *comp),
+                    d,
+                )
+                .expect("failed to deserialize diff");
+                changed_field_paths(&before, comp)
+            },
+            apply_diff_batch_fn: |world, diff_fn| {
+                use legion::IntoQuery;
+                let mut query = <(Entity, legion::Write<T>)>::query();
+                for (entity, comp) in query.iter_mut(world) {
+                    diff_fn(*entity, &mut |d| {
+                        //TODO: propagate error
+                        <serde_diff::Apply<T> as serde::de::DeserializeSeed>::deserialize(
+                            serde_diff::Apply::deserializable(&mut *comp),
+                            d,
+                        )
+                        .expect("failed to deserialize diff");
+                    });
+                }
+            },
             comp_clone_fn: |src_entity_range, src_arch, src_components, dst| unsafe {
                 let src_components = src_components.get(ComponentTypeId::of::<T>()).unwrap();
                 let src = src_components.downcast_ref::<T::Storage>().unwrap();
@@ -474,7 +752,474 @@ impl ComponentRegistration {
             remove_from_entity_fn: |world, entity| {
                 world.entry(entity).unwrap().remove_component::<T>()
             },
+            validate_fn: None,
+            locked_fields: &[],
+            override_policy: OverridePolicy::FieldDiffs,
+            clone_only: false,
+            marker: false,
+        };
+
+        if std::mem::size_of::<T>() == 0 {
+            registration.with_marker_paths::<T>()
+        } else {
+            registration
+        }
+    }
+
+    /// Registers a component type that doesn't implement serde or `SerdeDiff` itself, i.e. a math
+    /// type from another crate. `P` is a proxy that the component is converted to whenever it's
+    /// serialized or diffed, and converted back from when it's deserialized or a diff is applied,
+    /// so gameplay code keeps using `T` directly:
+    ///
+    /// ```ignore
+    /// #[derive(Serialize, Deserialize, SerdeDiff)]
+    /// struct Vec3Def { x: f32, y: f32, z: f32 }
+    ///
+    /// impl From<&Vec3> for Vec3Def { ... }
+    /// impl From<Vec3Def> for Vec3 { ... }
+    ///
+    /// register_component_type!(Vec3, proxy = Vec3Def, uuid = "...");
+    /// ```
+    ///
+    /// A proxy with the same fields as `T` serializes the same way as a `#[serde(remote = ...)]`
+    /// definition would.
+    pub fn of_proxied<
+        T: Clone + Send + Sync + Default + legion::storage::Component + 'static,
+        P: for<'a> From<&'a T> + Into<T> + Serialize + SerdeDiff + for<'de> Deserialize<'de> + 'static,
+    >(
+        uuid: ComponentTypeUuid
+    ) -> Self {
+        Self {
+            component_type_id: ComponentTypeId::of::<T>(),
+            uuid,
+            ty: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+            component_size: std::mem::size_of::<T>(),
+            register_comp_fn: |layout| {
+                layout.register_component::<T>();
+            },
+            comp_serialize_fn: |ptr, serialize_fn| unsafe {
+                let component = &*(ptr as *const T);
+                serialize_fn(&P::from(component));
+            },
+            comp_serialize_slice_fn: |storage, archetype, serialize_fn| unsafe {
+                let (ptr, len) = storage.get_raw(archetype).unwrap();
+                let slice = std::slice::from_raw_parts(ptr as *const T, len);
+                let proxies: Vec<P> = slice.iter().map(P::from).collect();
+                (serialize_fn)(&proxies);
+            },
+            comp_deserialize_fn: |d| {
+                let component: T = erased_serde::deserialize::<P>(d)?.into();
+                Ok(into_component_bytes(component))
+            },
+            comp_decode_fn: |d| {
+                let component: T = erased_serde::deserialize::<P>(d)?.into();
+                Ok(DecodedColumn::new(vec![component]))
+            },
+            comp_decode_column_fn: |deserializer| {
+                let proxies = erased_serde::deserialize::<Vec<P>>(deserializer)?;
+                let components: Vec<T> = proxies.into_iter().map(Into::into).collect();
+                Ok(DecodedColumn::new(components))
+            },
+            serialize_single_fn: |world, entity, s_fn| {
+                let comp = world.entry_ref(entity).unwrap();
+                let component = comp
+                    .get_component::<T>()
+                    .expect("entity not present when serializing component");
+                s_fn(&P::from(component));
+            },
+            diff_single_fn: |ser, src_world, src_entity, dst_world, dst_entity, options| {
+                let src_entity = diff_entry_ref(src_world, src_entity);
+                let dst_entity = diff_entry_ref(dst_world, dst_entity);
+                let src_proxy = diff_component::<T>(&src_entity).map(P::from);
+                let mut dst_proxy = diff_component::<T>(&dst_entity).map(P::from);
+                if let (Some(src_proxy), Some(dst_proxy), Some(float_epsilon)) =
+                    (&src_proxy, &mut dst_proxy, options.float_epsilon)
+                {
+                    crate::diff_options::reset_float_drift(src_proxy, dst_proxy, float_epsilon);
+                }
+                diff_components::<P>(ser, src_proxy.as_ref(), dst_proxy.as_ref(), options)
+            },
+            apply_diff_fn: |d, world, entity| {
+                let mut e = world.entry(entity).unwrap();
+                let comp = e
+                    .get_component_mut::<T>()
+                    .expect("expected component data when diffing");
+                let mut proxy = P::from(&*comp);
+                <serde_diff::Apply<P> as serde::de::DeserializeSeed>::deserialize(
+                    serde_diff::Apply::deserializable(&mut proxy),
+                    d,
+                )
+                .expect("failed to deserialize diff");
+                *comp = proxy.into();
+            },
+            apply_diff_with_changes_fn: |d, world, entity| {
+                let mut e = world.entry(entity).unwrap();
+                let comp = e
+                    .get_component_mut::<T>()
+                    .expect("expected component data when diffing");
+                let before = P::from(&*comp);
+                let mut proxy = P::from(&*comp);
+                <serde_diff::Apply<P> as serde::de::DeserializeSeed>::deserialize(
+                    serde_diff::Apply::deserializable(&mut proxy),
+                    d,
+                )
+                .expect("failed to deserialize diff");
+                let paths = changed_field_paths(&before, &proxy);
+                *comp = proxy.into();
+                paths
+            },
+            apply_diff_batch_fn: |world, diff_fn| {
+                use legion::IntoQuery;
+                let mut query = <(Entity, legion::Write<T>)>::query();
+                for (entity, comp) in query.iter_mut(world) {
+                    diff_fn(*entity, &mut |d| {
+                        let mut proxy = P::from(&*comp);
+                        <serde_diff::Apply<P> as serde::de::DeserializeSeed>::deserialize(
+                            serde_diff::Apply::deserializable(&mut proxy),
+                            d,
+                        )
+                        .expect("failed to deserialize diff");
+                        *comp = proxy.into();
+                    });
+                }
+            },
+            comp_clone_fn: |src_entity_range, src_arch, src_components, dst| unsafe {
+                let src_components = src_components.get(ComponentTypeId::of::<T>()).unwrap();
+                let src = src_components.downcast_ref::<T::Storage>().unwrap();
+                let mut dst = dst.claim_components::<T>();
+
+                let src_slice = &src.get(src_arch.index()).unwrap().into_slice()[src_entity_range];
+                dst.ensure_capacity(src_slice.len());
+                for component in src_slice {
+                    let cloned = <T as Clone>::clone(&component);
+                    dst.extend_memcopy(&cloned as *const T, 1);
+                    std::mem::forget(cloned);
+                }
+            },
+            add_default_to_entity_fn: |world, entity| {
+                world.entry(entity).unwrap().add_component(T::default())
+            },
+            add_to_entity_fn: |d, world, entity| {
+                let comp: T = erased_serde::deserialize::<P>(d)
+                    .expect("failed to deserialize component")
+                    .into();
+                world.entry(entity).unwrap().add_component(comp);
+            },
+            remove_from_entity_fn: |world, entity| {
+                world.entry(entity).unwrap().remove_component::<T>()
+            },
+            validate_fn: None,
+            locked_fields: &[],
+            override_policy: OverridePolicy::FieldDiffs,
+            clone_only: false,
+            marker: false,
+        }
+    }
+
+    /// Registers a runtime-only component type, i.e. a cache or a handle, that is kept when worlds
+    /// are cloned or merged (for example on hot reload) but is never written to prefabs.
+    /// Serializers skip it and diffs always report no change. It's kept out of the UUID index of
+    /// `ComponentRegistry`, and `uuid()` returns the nil UUID.
+    pub fn clone_only<T: Clone + Send + Sync + legion::storage::Component + 'static>() -> Self {
+        Self {
+            component_type_id: ComponentTypeId::of::<T>(),
+            uuid: [0; 16],
+            ty: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+            component_size: std::mem::size_of::<T>(),
+            register_comp_fn: |layout| {
+                layout.register_component::<T>();
+            },
+            comp_serialize_fn: |_, _| clone_only_panic::<T>(),
+            comp_serialize_slice_fn: |_, _, _| clone_only_panic::<T>(),
+            comp_deserialize_fn: |_| clone_only_panic::<T>(),
+            comp_decode_fn: |_| clone_only_panic::<T>(),
+            comp_decode_column_fn: |_| clone_only_panic::<T>(),
+            serialize_single_fn: |_, _, _| clone_only_panic::<T>(),
+            diff_single_fn: |_, _, _, _, _, _| DiffSingleResult::new(DiffSingleKind::NoChange),
+            apply_diff_fn: |_, _, _| clone_only_panic::<T>(),
+            apply_diff_with_changes_fn: |_, _, _| clone_only_panic::<T>(),
+            apply_diff_batch_fn: |_, _| {},
+            comp_clone_fn: |src_entity_range, src_arch, src_components, dst| unsafe {
+                let src_components = src_components.get(ComponentTypeId::of::<T>()).unwrap();
+                let src = src_components.downcast_ref::<T::Storage>().unwrap();
+                let mut dst = dst.claim_components::<T>();
+
+                let src_slice = &src.get(src_arch.index()).unwrap().into_slice()[src_entity_range];
+                dst.ensure_capacity(src_slice.len());
+                for component in src_slice {
+                    let cloned = <T as Clone>::clone(&component);
+                    dst.extend_memcopy(&cloned as *const T, 1);
+                    std::mem::forget(cloned);
+                }
+            },
+            add_default_to_entity_fn: |_, _| clone_only_panic::<T>(),
+            add_to_entity_fn: |_, _, _| clone_only_panic::<T>(),
+            remove_from_entity_fn: |world, entity| {
+                world.entry(entity).unwrap().remove_component::<T>()
+            },
+            validate_fn: None,
+            locked_fields: &[],
+            override_policy: OverridePolicy::FieldDiffs,
+            clone_only: true,
+            marker: false,
+        }
+    }
+
+    /// True if the type was registered with `clone_only`
+    pub fn is_clone_only(&self) -> bool {
+        self.clone_only
+    }
+
+    // Zero-sized marker components have nothing to serialize, clone or diff. Only whether an
+    // entity has one matters, so they're written without data and created with Default when read.
+    fn with_marker_paths<T: Default + legion::storage::Component>(mut self) -> Self {
+        self.marker = true;
+        self.comp_deserialize_fn = |d| {
+            IgnoredAny::deserialize(d)?;
+            Ok(into_component_bytes(T::default()))
+        };
+        self.comp_decode_fn = |d| {
+            IgnoredAny::deserialize(d)?;
+            Ok(DecodedColumn::new(vec![T::default()]))
+        };
+        self.diff_single_fn = |ser, src_world, src_entity, dst_world, dst_entity, _| {
+            let src_entity = diff_entry_ref(src_world, src_entity);
+            let dst_entity = diff_entry_ref(dst_world, dst_entity);
+            match (
+                diff_component::<T>(&src_entity),
+                diff_component::<T>(&dst_entity),
+            ) {
+                (None, Some(_)) => {
+                    erased_serde::serialize(&(), ser).unwrap();
+                    DiffSingleResult::new(DiffSingleKind::Add)
+                }
+                (Some(_), None) => DiffSingleResult::new(DiffSingleKind::Remove),
+                _ => DiffSingleResult::new(DiffSingleKind::NoChange),
+            }
+        };
+        self.apply_diff_fn = |d, _, _| {
+            IgnoredAny::deserialize(d).expect("failed to deserialize diff");
+        };
+        self.apply_diff_with_changes_fn = |d, _, _| {
+            IgnoredAny::deserialize(d).expect("failed to deserialize diff");
+            vec![]
+        };
+        self.apply_diff_batch_fn = |world, diff_fn| {
+            use legion::IntoQuery;
+            let mut query = <(Entity, legion::Read<T>)>::query();
+            for (entity, _) in query.iter(world) {
+                diff_fn(*entity, &mut |d| {
+                    IgnoredAny::deserialize(d).expect("failed to deserialize diff");
+                });
+            }
+        };
+        self.comp_clone_fn = |src_entity_range, _, _, dst| unsafe {
+            // Copying a zero-sized value doesn't read memory, so any aligned pointer will do
+            let mut dst = dst.claim_components::<T>();
+            dst.extend_memcopy(
+                std::ptr::NonNull::<T>::dangling().as_ptr(),
+                src_entity_range.len(),
+            );
+        };
+        self.add_to_entity_fn = |d, world, entity| {
+            IgnoredAny::deserialize(d).expect("failed to deserialize component");
+            world.entry(entity).unwrap().add_component(T::default());
+        };
+        self
+    }
+
+    /// True for zero-sized component types. Prefabs store only whether an entity has a marker
+    /// component, and diffs of markers only add or remove them.
+    pub fn is_marker(&self) -> bool {
+        self.marker
+    }
+
+    /// Clones components of a `Copy` type by copying the whole slice at once instead of cloning
+    /// them one at a time. Speeds up cloning worlds with many small components, like transforms.
+    pub fn with_copy_clone<T: Copy + legion::storage::Component>(mut self) -> Self {
+        self.assert_type::<T>("copy clone");
+        self.comp_clone_fn = |src_entity_range, src_arch, src_components, dst| unsafe {
+            let src_components = src_components.get(ComponentTypeId::of::<T>()).unwrap();
+            let src = src_components.downcast_ref::<T::Storage>().unwrap();
+            let mut dst = dst.claim_components::<T>();
+
+            let src_slice = &src.get(src_arch.index()).unwrap().into_slice()[src_entity_range];
+            dst.extend_memcopy(src_slice.as_ptr(), src_slice.len());
+        };
+        self
+    }
+
+    /// Like `with_copy_clone`, and also writes the components of each archetype as a single byte
+    /// buffer when a world is serialized in packed mode, instead of serializing each component.
+    /// The bytes are in the layout of the machine that wrote them, so only use this for data that
+    /// is read back on the same kind of machine, like cooked prefabs for one platform. Human
+    /// readable serialization is unchanged.
+    pub fn with_pod_packing<T: PodComponent + legion::storage::Component>(self) -> Self {
+        let mut registration = self.with_copy_clone::<T>();
+        registration.comp_serialize_slice_fn = |storage, archetype, serialize_fn| unsafe {
+            let (ptr, len) = storage.get_raw(archetype).unwrap();
+            let bytes = std::slice::from_raw_parts(ptr, len * std::mem::size_of::<T>());
+            (serialize_fn)(&PodBytes(bytes));
+        };
+        registration.comp_decode_column_fn = |deserializer| {
+            let bytes = erased_serde::deserialize::<crate::format::bytes::ByteBuf>(deserializer)?.0;
+            let size = std::mem::size_of::<T>();
+            if size == 0 || bytes.len() % size != 0 {
+                return Err(de::Error::invalid_length(
+                    bytes.len(),
+                    &"a whole number of components",
+                ));
+            }
+
+            // The buffer isn't aligned for T, so copy it into one that is
+            let len = bytes.len() / size;
+            let mut components = Vec::<T>::with_capacity(len);
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    bytes.as_ptr(),
+                    components.as_mut_ptr() as *mut u8,
+                    bytes.len(),
+                );
+                components.set_len(len);
+            }
+            Ok(DecodedColumn::new(components))
+        };
+        registration
+    }
+
+    fn assert_type<T: 'static>(
+        &self,
+        what: &str,
+    ) {
+        assert_eq!(
+            self.ty,
+            TypeId::of::<T>(),
+            "{} for {} takes a different component type",
+            what,
+            self.type_name
+        );
+    }
+
+    /// Attaches a function that checks component data when prefabs are cooked, i.e. for negative
+    /// health or an empty asset path. See `validate_cooked_prefab`.
+    pub fn with_validate_fn<T: legion::storage::Component>(
+        mut self,
+        validate_fn: fn(&T, &ValidationCtx) -> Vec<ValidationError>,
+    ) -> Self {
+        self.assert_type::<T>("validate fn");
+        self.validate_fn = Some(crate::validation::validate_world_fn(
+            self.uuid,
+            self.type_name,
+            validate_fn,
+        ));
+        self
+    }
+
+    pub fn has_validate_fn(&self) -> bool {
+        self.validate_fn.is_some()
+    }
+
+    /// Marks fields that prefab refs may not override, by path (i.e. `stats.health`). A locked
+    /// field also locks the fields within it. Overrides that change a locked field are rejected
+    /// when written and skipped when cooking, see `find_locked_field_overrides`.
+    pub fn with_locked_fields(
+        mut self,
+        locked_fields: &'static [&'static str],
+    ) -> Self {
+        self.locked_fields = locked_fields;
+        self
+    }
+
+    pub fn locked_fields(&self) -> &'static [&'static str] {
+        self.locked_fields
+    }
+
+    /// Sets how diffs of the component are stored, in transactions and in the overrides of prefab
+    /// refs. Diffs stored with one policy can't be applied with the other, so prefabs with
+    /// overrides of the component must be written again after it changes.
+    pub fn with_override_policy<
+        T: Clone
+            + Serialize
+            + SerdeDiff
+            + for<'de> Deserialize<'de>
+            + Send
+            + Sync
+            + Default
+            + legion::storage::Component
+            + 'static,
+    >(
+        mut self,
+        override_policy: OverridePolicy,
+    ) -> Self {
+        self.assert_type::<T>("override policy");
+        if self.marker {
+            // Diffs of markers only add or remove them
+            return self;
+        }
+
+        match override_policy {
+            OverridePolicy::FieldDiffs => {
+                let field_diffs = Self::of_with_uuid::<T>(self.uuid);
+                self.diff_single_fn = field_diffs.diff_single_fn;
+                self.apply_diff_fn = field_diffs.apply_diff_fn;
+                self.apply_diff_with_changes_fn = field_diffs.apply_diff_with_changes_fn;
+                self.apply_diff_batch_fn = field_diffs.apply_diff_batch_fn;
+            }
+            OverridePolicy::Replace => {
+                self.diff_single_fn =
+                    |ser, src_world, src_entity, dst_world, dst_entity, options| {
+                        let src_entity = diff_entry_ref(src_world, src_entity);
+                        let dst_entity = diff_entry_ref(dst_world, dst_entity);
+                        // Drift is found field by field, before the value is diffed as a whole
+                        let src_comp = diff_component::<T>(&src_entity);
+                        let dst_comp =
+                            without_float_drift(src_comp, diff_component(&dst_entity), options);
+                        diff_components(
+                            ser,
+                            src_comp.map(WholeValue::from_ref),
+                            dst_comp.as_deref().map(WholeValue::from_ref),
+                            options,
+                        )
+                    };
+                self.apply_diff_fn = |d, world, entity| {
+                    let mut e = world.entry(entity).unwrap();
+
+                    let comp = e
+                        .get_component_mut::<T>()
+                        .expect("expected component data when diffing");
+                    apply_whole_value_diff(d, comp);
+                };
+                self.apply_diff_with_changes_fn = |d, world, entity| {
+                    let mut e = world.entry(entity).unwrap();
+
+                    let comp = e
+                        .get_component_mut::<T>()
+                        .expect("expected component data when diffing");
+                    let before = comp.clone();
+                    apply_whole_value_diff(d, &mut *comp);
+                    // Reports the fields that changed, not the whole value
+                    changed_field_paths(&before, comp)
+                };
+                self.apply_diff_batch_fn = |world, diff_fn| {
+                    use legion::IntoQuery;
+                    let mut query = <(Entity, legion::Write<T>)>::query();
+                    for (entity, comp) in query.iter_mut(world) {
+                        diff_fn(*entity, &mut |d| {
+                            apply_whole_value_diff(d, &mut *comp);
+                        });
+                    }
+                };
+            }
         }
+
+        self.override_policy = override_policy;
+        self
+    }
+
+    pub fn override_policy(&self) -> OverridePolicy {
+        self.override_policy
     }
 }
 
@@ -484,6 +1229,116 @@ pub fn iter_component_registrations() -> impl Iterator<Item = &'static Component
     inventory::iter::<ComponentRegistration>.into_iter()
 }
 
+/// A set of component registrations, indexed both by UUID (used by the prefab format) and by
+/// legion's ComponentTypeId (used when cloning/merging worlds). Clone-only registrations are only
+/// indexed by ComponentTypeId.
+#[derive(Clone, Default)]
+pub struct ComponentRegistry {
+    by_uuid: HashMap<ComponentTypeUuid, ComponentRegistration>,
+    by_type_id: HashMap<ComponentTypeId, ComponentRegistration>,
+}
+
+impl ComponentRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry containing every component registered with `register_component_type!`
+    pub fn from_inventory() -> Self {
+        let mut registry = Self::new();
+        for registration in iter_component_registrations() {
+            registry.register(registration.clone());
+        }
+        registry
+    }
+
+    pub fn register(
+        &mut self,
+        registration: ComponentRegistration,
+    ) {
+        self.by_type_id
+            .insert(registration.component_type_id(), registration.clone());
+        if !registration.is_clone_only() {
+            self.by_uuid.insert(*registration.uuid(), registration);
+        }
+    }
+
+    pub fn get(
+        &self,
+        uuid: &ComponentTypeUuid,
+    ) -> Option<&ComponentRegistration> {
+        self.by_uuid.get(uuid)
+    }
+
+    pub fn get_by_type_id(
+        &self,
+        component_type_id: ComponentTypeId,
+    ) -> Option<&ComponentRegistration> {
+        self.by_type_id.get(&component_type_id)
+    }
+
+    pub fn contains(
+        &self,
+        uuid: &ComponentTypeUuid,
+    ) -> bool {
+        self.by_uuid.contains_key(uuid)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ComponentRegistration> {
+        self.by_uuid.values()
+    }
+
+    pub fn by_uuid(&self) -> &HashMap<ComponentTypeUuid, ComponentRegistration> {
+        &self.by_uuid
+    }
+
+    pub fn by_type_id(&self) -> &HashMap<ComponentTypeId, ComponentRegistration> {
+        &self.by_type_id
+    }
+
+    pub fn serde_context(&self) -> PrefabSerdeContext<RandomState> {
+        PrefabSerdeContext {
+            registered_components: &self.by_uuid,
+        }
+    }
+
+    pub fn copy_clone_impl(&self) -> CopyCloneImpl<RandomState> {
+        CopyCloneImpl::new(&self.by_type_id)
+    }
+}
+
+/// A registry of every component registered with `register_component_type!`. It's built the first
+/// time it's needed and shared after that, so (de)serializing many prefabs doesn't rebuild it.
+pub fn global_component_registry() -> &'static ComponentRegistry {
+    static REGISTRY: OnceCell<ComponentRegistry> = OnceCell::new();
+    REGISTRY.get_or_init(ComponentRegistry::from_inventory)
+}
+
+/// Registers a component type so that it can be stored in prefabs, cooked, diffed and cloned.
+/// legion 0.3 no longer has tags or shared components, so data that would have been a tag is
+/// registered and stored as a regular component.
+///
+/// `register_component_type!(Health, validate = validate_health)` also attaches a validate fn, see
+/// `ComponentRegistration::with_validate_fn`.
+///
+/// `register_component_type!(Health, uuid = "d4b83227-...")` registers the type with the given UUID
+/// instead of the one from its `TypeUuid` impl, which it then doesn't need. An invalid UUID panics
+/// when the registrations are collected.
+///
+/// `register_component_type!(Vec3, proxy = Vec3Def, uuid = "...")` registers a type without serde
+/// impls through a proxy type, see `ComponentRegistration::of_proxied`.
+///
+/// `register_component_type!(Transform, copy)` and `register_component_type!(Transform, pod)`
+/// register a type with `with_copy_clone` or `with_pod_packing`.
+///
+/// `register_component_type!(Collider, locked = ["shape", "layer"])` marks fields that prefab refs
+/// can't override, see `ComponentRegistration::with_locked_fields`.
+///
+/// `register_component_type!(Curve, replace)` stores diffs of the component as whole values, see
+/// `ComponentRegistration::with_override_policy`.
+///
+/// `register_component_type!(MeshHandle, clone_only)` registers a runtime-only type that is cloned
+/// with worlds but never serialized, see `ComponentRegistration::clone_only`.
 #[macro_export]
 macro_rules! register_component_type {
     ($component_type:ty) => {
@@ -495,4 +1350,109 @@ macro_rules! register_component_type {
             $crate::ComponentRegistration::of::<$component_type>()
         }
     };
+    ($component_type:ty, validate = $validate_fn:expr) => {
+        $crate::register_component_type!(legion_prefab; $component_type, validate = $validate_fn);
+    };
+    ($krate:ident; $component_type:ty, validate = $validate_fn:expr) => {
+        $crate::inventory::submit!{
+            #![crate = $krate]
+            $crate::ComponentRegistration::of::<$component_type>()
+                .with_validate_fn::<$component_type>($validate_fn)
+        }
+    };
+    ($component_type:ty, uuid = $uuid:literal) => {
+        $crate::register_component_type!(legion_prefab; $component_type, uuid = $uuid);
+    };
+    ($krate:ident; $component_type:ty, uuid = $uuid:literal) => {
+        $crate::inventory::submit!{
+            #![crate = $krate]
+            $crate::ComponentRegistration::of_with_uuid::<$component_type>(
+                $crate::parse_component_uuid($uuid)
+            )
+        }
+    };
+    ($component_type:ty, uuid = $uuid:literal, validate = $validate_fn:expr) => {
+        $crate::register_component_type!(
+            legion_prefab; $component_type, uuid = $uuid, validate = $validate_fn
+        );
+    };
+    ($krate:ident; $component_type:ty, uuid = $uuid:literal, validate = $validate_fn:expr) => {
+        $crate::inventory::submit!{
+            #![crate = $krate]
+            $crate::ComponentRegistration::of_with_uuid::<$component_type>(
+                $crate::parse_component_uuid($uuid)
+            )
+            .with_validate_fn::<$component_type>($validate_fn)
+        }
+    };
+    ($component_type:ty, proxy = $proxy_type:ty, uuid = $uuid:literal) => {
+        $crate::register_component_type!(
+            legion_prefab; $component_type, proxy = $proxy_type, uuid = $uuid
+        );
+    };
+    ($krate:ident; $component_type:ty, proxy = $proxy_type:ty, uuid = $uuid:literal) => {
+        $crate::inventory::submit!{
+            #![crate = $krate]
+            $crate::ComponentRegistration::of_proxied::<$component_type, $proxy_type>(
+                $crate::parse_component_uuid($uuid)
+            )
+        }
+    };
+    ($component_type:ty, clone_only) => {
+        $crate::register_component_type!(legion_prefab; $component_type, clone_only);
+    };
+    ($krate:ident; $component_type:ty, clone_only) => {
+        $crate::inventory::submit!{
+            #![crate = $krate]
+            $crate::ComponentRegistration::clone_only::<$component_type>()
+        }
+    };
+    ($component_type:ty, copy) => {
+        $crate::register_component_type!(legion_prefab; $component_type, copy);
+    };
+    ($krate:ident; $component_type:ty, copy) => {
+        $crate::inventory::submit!{
+            #![crate = $krate]
+            $crate::ComponentRegistration::of::<$component_type>()
+                .with_copy_clone::<$component_type>()
+        }
+    };
+    ($component_type:ty, locked = [$($field:literal),* $(,)?]) => {
+        $crate::register_component_type!(legion_prefab; $component_type, locked = [$($field),*]);
+    };
+    ($krate:ident; $component_type:ty, locked = [$($field:literal),* $(,)?]) => {
+        $crate::inventory::submit!{
+            #![crate = $krate]
+            $crate::ComponentRegistration::of::<$component_type>()
+                .with_locked_fields(&[$($field),*])
+        }
+    };
+    ($component_type:ty, replace) => {
+        $crate::register_component_type!(legion_prefab; $component_type, replace);
+    };
+    ($krate:ident; $component_type:ty, replace) => {
+        $crate::inventory::submit!{
+            #![crate = $krate]
+            $crate::ComponentRegistration::of::<$component_type>()
+                .with_override_policy::<$component_type>($crate::OverridePolicy::Replace)
+        }
+    };
+    ($component_type:ty, pod) => {
+        $crate::register_component_type!(legion_prefab; $component_type, pod);
+    };
+    ($krate:ident; $component_type:ty, pod) => {
+        $crate::inventory::submit!{
+            #![crate = $krate]
+            $crate::ComponentRegistration::of::<$component_type>()
+                .with_pod_packing::<$component_type>()
+        }
+    };
+}
+
+#[doc(hidden)]
+pub fn parse_component_uuid(uuid: &str) -> ComponentTypeUuid {
+    match uuid::Uuid::parse_str(uuid) {
+        Ok(uuid) => *uuid.as_bytes(),
+        Err(error) => panic!("invalid component UUID {:?}: {}", uuid, error),
+    }
 }
@@ -0,0 +1,282 @@
+use legion::Resources;
+use once_cell::sync::OnceCell;
+use serde::de::{DeserializeSeed, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use type_uuid::TypeUuid;
+
+pub type ResourceTypeUuid = type_uuid::Bytes;
+
+// Resources are stored type-erased and restored through their registration
+type BoxedResource = Box<dyn Any + Send + Sync>;
+
+type ResourceSerializeFn = fn(&BoxedResource, &mut dyn FnMut(&dyn erased_serde::Serialize));
+type ResourceDeserializeFn =
+    fn(&mut dyn erased_serde::Deserializer) -> Result<BoxedResource, erased_serde::Error>;
+type ResourceCloneFn = fn(&BoxedResource) -> BoxedResource;
+type InsertIntoResourcesFn = fn(&BoxedResource, &mut Resources);
+
+/// The resource equivalent of `ComponentRegistration`. Resource types must be registered with
+/// `register_resource_type!` to be stored in a prefab.
+#[derive(Clone)]
+pub struct ResourceRegistration {
+    uuid: ResourceTypeUuid,
+    ty: TypeId,
+    type_name: &'static str,
+    serialize_fn: ResourceSerializeFn,
+    deserialize_fn: ResourceDeserializeFn,
+    clone_fn: ResourceCloneFn,
+    insert_into_resources_fn: InsertIntoResourcesFn,
+}
+
+impl ResourceRegistration {
+    pub fn uuid(&self) -> &ResourceTypeUuid {
+        &self.uuid
+    }
+
+    pub fn ty(&self) -> TypeId {
+        self.ty
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    pub fn of<
+        T: TypeUuid + Clone + Serialize + for<'de> Deserialize<'de> + Send + Sync + 'static,
+    >() -> Self {
+        Self {
+            uuid: T::UUID,
+            ty: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+            serialize_fn: |resource, serialize_fn| {
+                serialize_fn(resource.downcast_ref::<T>().unwrap());
+            },
+            deserialize_fn: |deserializer| {
+                let resource = erased_serde::deserialize::<T>(deserializer)?;
+                Ok(Box::new(resource))
+            },
+            clone_fn: |resource| Box::new(resource.downcast_ref::<T>().unwrap().clone()),
+            insert_into_resources_fn: |resource, resources| {
+                resources.insert(resource.downcast_ref::<T>().unwrap().clone());
+            },
+        }
+    }
+}
+
+inventory::collect!(ResourceRegistration);
+
+pub fn iter_resource_registrations() -> impl Iterator<Item = &'static ResourceRegistration> {
+    inventory::iter::<ResourceRegistration>.into_iter()
+}
+
+// Every resource registered with `register_resource_type!`, by UUID
+fn registered_resources() -> &'static HashMap<ResourceTypeUuid, ResourceRegistration> {
+    static REGISTRY: OnceCell<HashMap<ResourceTypeUuid, ResourceRegistration>> = OnceCell::new();
+    REGISTRY.get_or_init(|| {
+        iter_resource_registrations()
+            .map(|registration| (registration.uuid, registration.clone()))
+            .collect()
+    })
+}
+
+fn registration(uuid: &ResourceTypeUuid) -> &'static ResourceRegistration {
+    registered_resources()
+        .get(uuid)
+        .expect("resource type was not registered with register_resource_type!")
+}
+
+/// Singleton data stored in a prefab alongside its entities, i.e. level settings. Holds at most
+/// one resource of each type. Every type stored must be registered with `register_resource_type!`.
+#[derive(Default)]
+pub struct PrefabResources {
+    resources: HashMap<ResourceTypeUuid, BoxedResource>,
+}
+
+impl PrefabResources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a resource, replacing any existing resource of the same type
+    pub fn insert<T: TypeUuid + Send + Sync + 'static>(
+        &mut self,
+        resource: T,
+    ) {
+        self.resources.insert(T::UUID, Box::new(resource));
+    }
+
+    pub fn get<T: TypeUuid + 'static>(&self) -> Option<&T> {
+        self.resources
+            .get(&T::UUID)
+            .and_then(|resource| resource.downcast_ref::<T>())
+    }
+
+    pub fn get_mut<T: TypeUuid + 'static>(&mut self) -> Option<&mut T> {
+        self.resources
+            .get_mut(&T::UUID)
+            .and_then(|resource| resource.downcast_mut::<T>())
+    }
+
+    pub fn remove<T: TypeUuid + 'static>(&mut self) -> Option<T> {
+        let resource = self.resources.remove(&T::UUID)?;
+        resource.downcast::<T>().ok().map(|resource| *resource)
+    }
+
+    pub fn contains(
+        &self,
+        uuid: &ResourceTypeUuid,
+    ) -> bool {
+        self.resources.contains_key(uuid)
+    }
+
+    /// The types of the resources stored
+    pub fn resource_types(&self) -> impl Iterator<Item = &ResourceTypeUuid> {
+        self.resources.keys()
+    }
+
+    pub fn len(&self) -> usize {
+        self.resources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+
+    /// Copies every resource in `other` into this, replacing resources of the same type. Used when
+    /// cooking so that a prefab's resources override those of the prefabs it references.
+    pub fn extend_from(
+        &mut self,
+        other: &PrefabResources,
+    ) {
+        for (uuid, resource) in &other.resources {
+            self.resources
+                .insert(*uuid, (registration(uuid).clone_fn)(resource));
+        }
+    }
+
+    /// Copies every resource into `resources`, i.e. when spawning a cooked prefab as a level
+    pub fn insert_into(
+        &self,
+        resources: &mut Resources,
+    ) {
+        for (uuid, resource) in &self.resources {
+            (registration(uuid).insert_into_resources_fn)(resource, resources);
+        }
+    }
+}
+
+impl Clone for PrefabResources {
+    fn clone(&self) -> Self {
+        let mut resources = PrefabResources::new();
+        resources.extend_from(self);
+        resources
+    }
+}
+
+// Serialized as a map of resource type UUID to resource data, sorted by UUID so the output is
+// stable
+impl Serialize for PrefabResources {
+    fn serialize<S>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut resource_types: Vec<_> = self.resources.keys().collect();
+        resource_types.sort();
+
+        let mut map = serializer.serialize_map(Some(resource_types.len()))?;
+        for uuid in resource_types {
+            let registration = registered_resources().get(uuid).ok_or_else(|| {
+                serde::ser::Error::custom(format!(
+                    "resource type {} was not registered",
+                    uuid::Uuid::from_bytes(*uuid)
+                ))
+            })?;
+
+            let mut result = Ok(());
+            (registration.serialize_fn)(&self.resources[uuid], &mut |resource| {
+                result = map.serialize_entry(&uuid::Uuid::from_bytes(*uuid), resource);
+            });
+            result?;
+        }
+        map.end()
+    }
+}
+
+struct ResourceSeed<'a>(&'a ResourceRegistration);
+
+impl<'de, 'a> DeserializeSeed<'de> for ResourceSeed<'a> {
+    type Value = BoxedResource;
+
+    fn deserialize<D>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut deserializer = erased_serde::Deserializer::erase(deserializer);
+        (self.0.deserialize_fn)(&mut deserializer).map_err(serde::de::Error::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for PrefabResources {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct PrefabResourcesVisitor;
+        impl<'de> Visitor<'de> for PrefabResourcesVisitor {
+            type Value = PrefabResources;
+
+            fn expecting(
+                &self,
+                formatter: &mut std::fmt::Formatter,
+            ) -> std::fmt::Result {
+                formatter.write_str("a map of resource type UUID to resource")
+            }
+
+            fn visit_map<V>(
+                self,
+                mut map: V,
+            ) -> Result<Self::Value, V::Error>
+            where
+                V: MapAccess<'de>,
+            {
+                let mut resources = PrefabResources::new();
+                while let Some(uuid) = map.next_key::<uuid::Uuid>()? {
+                    let registration =
+                        registered_resources().get(uuid.as_bytes()).ok_or_else(|| {
+                            serde::de::Error::custom(format!(
+                                "resource type {} was not registered",
+                                uuid
+                            ))
+                        })?;
+                    let resource = map.next_value_seed(ResourceSeed(registration))?;
+                    resources.resources.insert(*uuid.as_bytes(), resource);
+                }
+                Ok(resources)
+            }
+        }
+
+        deserializer.deserialize_map(PrefabResourcesVisitor)
+    }
+}
+
+#[macro_export]
+macro_rules! register_resource_type {
+    ($resource_type:ty) => {
+        $crate::register_resource_type!(legion_prefab; $resource_type);
+    };
+    ($krate:ident; $resource_type:ty) => {
+        $crate::inventory::submit!{
+            #![crate = $krate]
+            $crate::ResourceRegistration::of::<$resource_type>()
+        }
+    };
+}
@@ -0,0 +1,263 @@
+use crate::format::{ComponentTypeUuid, EntityUuid};
+use crate::world_serde::EntityUuidMapper;
+use crate::{
+    canonical_pretty_config, ComponentRegistration, Prefab, PrefabFormatDeserializer,
+    PrefabFormatSerializer, PrefabSerdeContext,
+};
+use legion::world::Allocate;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+/// A format prefab source data can be stored in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefabFormat {
+    Ron,
+    #[cfg(feature = "json")]
+    Json,
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    #[cfg(feature = "cbor")]
+    Cbor,
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+#[derive(Debug)]
+pub enum PrefabRoundtripError {
+    /// The given data couldn't be loaded
+    Load(String),
+    /// The loaded prefab couldn't be serialized
+    Save(String),
+    /// The serialized prefab couldn't be loaded again
+    Reload(String),
+    /// The reloaded prefab differs from the one that was loaded first
+    Mismatch(String),
+}
+
+/// Loads a prefab, saves it in the same format, loads the result and compares the two loaded
+/// prefabs. Downstream crates can use this in their tests to check that their component types
+/// survive the prefab format.
+///
+/// Entities, components (compared as RON text), prefab refs with their overrides, parameters,
+/// layers, hierarchy and blobs are compared. Resources aren't part of the prefab format.
+pub fn check_prefab_roundtrip<T: BuildHasher>(
+    bytes: &[u8],
+    format: PrefabFormat,
+    context: PrefabSerdeContext<T>,
+) -> Result<(), PrefabRoundtripError> {
+    let prefab = load(bytes, format, context).map_err(PrefabRoundtripError::Load)?;
+    let saved = save(&prefab, format, context).map_err(PrefabRoundtripError::Save)?;
+    let reloaded = load(&saved, format, context).map_err(PrefabRoundtripError::Reload)?;
+    match prefab_difference(&prefab, &reloaded, context.registered_components) {
+        Some(difference) => Err(PrefabRoundtripError::Mismatch(difference)),
+        None => Ok(()),
+    }
+}
+
+/// Like `check_prefab_roundtrip` with the global component registry, but panics with a
+/// description of the problem, for use in tests
+pub fn assert_prefab_roundtrip(
+    bytes: &[u8],
+    format: PrefabFormat,
+) {
+    let context = crate::registration::global_component_registry().serde_context();
+    if let Err(error) = check_prefab_roundtrip(bytes, format, context) {
+        panic!(
+            "prefab did not survive a {:?} round trip: {:?}",
+            format, error
+        );
+    }
+}
+
+fn load<T: BuildHasher>(
+    bytes: &[u8],
+    format: PrefabFormat,
+    context: PrefabSerdeContext<T>,
+) -> Result<Prefab, String> {
+    match format {
+        PrefabFormat::Ron => {
+            let mut de = ron::de::Deserializer::from_bytes(bytes).map_err(|e| e.to_string())?;
+            let prefab_deser = PrefabFormatDeserializer::new(context);
+            prefab_format::deserialize(&mut de, &prefab_deser).map_err(|e| e.to_string())?;
+            de.end().map_err(|e| e.to_string())?;
+            Ok(prefab_deser.prefab())
+        }
+        #[cfg(feature = "json")]
+        PrefabFormat::Json => {
+            let json = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+            crate::json::prefab_from_str(json, context).map_err(|e| e.to_string())
+        }
+        #[cfg(feature = "msgpack")]
+        PrefabFormat::MessagePack => {
+            crate::msgpack::prefab_from_slice(bytes, context).map_err(|e| e.to_string())
+        }
+        #[cfg(feature = "cbor")]
+        PrefabFormat::Cbor => {
+            crate::cbor::prefab_from_slice(bytes, context).map_err(|e| e.to_string())
+        }
+        #[cfg(feature = "yaml")]
+        PrefabFormat::Yaml => {
+            let yaml = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+            crate::yaml::prefab_from_str(yaml, context).map_err(|e| e.to_string())
+        }
+    }
+}
+
+fn save<T: BuildHasher>(
+    prefab: &Prefab,
+    format: PrefabFormat,
+    context: PrefabSerdeContext<T>,
+) -> Result<Vec<u8>, String> {
+    match format {
+        PrefabFormat::Ron => {
+            let mut ron_ser = ron::ser::Serializer::new(Some(canonical_pretty_config()), true);
+            let prefab_ser = PrefabFormatSerializer::new(context, prefab);
+            prefab_format::serialize(&mut ron_ser, &prefab_ser, prefab.prefab_id())
+                .map_err(|e| e.to_string())?;
+            Ok(ron_ser.into_output_string().into_bytes())
+        }
+        #[cfg(feature = "json")]
+        PrefabFormat::Json => crate::json::prefab_to_string(prefab, context)
+            .map(String::into_bytes)
+            .map_err(|e| e.to_string()),
+        #[cfg(feature = "msgpack")]
+        PrefabFormat::MessagePack => {
+            crate::msgpack::prefab_to_vec(prefab, context).map_err(|e| e.to_string())
+        }
+        #[cfg(feature = "cbor")]
+        PrefabFormat::Cbor => {
+            crate::cbor::prefab_to_vec(prefab, context).map_err(|e| e.to_string())
+        }
+        #[cfg(feature = "yaml")]
+        PrefabFormat::Yaml => crate::yaml::prefab_to_string(prefab, context)
+            .map(String::into_bytes)
+            .map_err(|e| e.to_string()),
+    }
+}
+
+// Describes the first difference found between two prefabs
+fn prefab_difference<S: BuildHasher>(
+    a: &Prefab,
+    b: &Prefab,
+    registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+) -> Option<String> {
+    let (a_meta, b_meta) = (&a.prefab_meta, &b.prefab_meta);
+    if a_meta.id != b_meta.id {
+        return Some("prefab ID differs".to_string());
+    }
+
+    let a_entities = sorted_keys(&a_meta.entities);
+    if a_entities != sorted_keys(&b_meta.entities) {
+        return Some("entities differ".to_string());
+    }
+
+    let mut component_types: Vec<_> = registered_components.keys().collect();
+    component_types.sort();
+    for entity in &a_entities {
+        for component_type in &component_types {
+            let a_data = component_text(a, entity, component_type, registered_components);
+            let b_data = component_text(b, entity, component_type, registered_components);
+            if a_data != b_data {
+                return Some(format!(
+                    "component {} of entity {} differs: {:?} != {:?}",
+                    uuid_str(component_type),
+                    uuid_str(entity),
+                    a_data,
+                    b_data
+                ));
+            }
+        }
+    }
+
+    let prefab_refs = sorted_keys(&a_meta.prefab_refs);
+    if prefab_refs != sorted_keys(&b_meta.prefab_refs) {
+        return Some("prefab refs differ".to_string());
+    }
+    for prefab_ref in &prefab_refs {
+        let (a_ref, b_ref) = (
+            &a_meta.prefab_refs[prefab_ref],
+            &b_meta.prefab_refs[prefab_ref],
+        );
+        if a_ref.parameter_values != b_ref.parameter_values || a_ref.transform != b_ref.transform {
+            return Some(format!("prefab ref {} differs", uuid_str(prefab_ref)));
+        }
+
+        let overridden_entities = sorted_keys(&a_ref.overrides);
+        if overridden_entities != sorted_keys(&b_ref.overrides) {
+            return Some(format!(
+                "overridden entities of prefab ref {} differ",
+                uuid_str(prefab_ref)
+            ));
+        }
+        for entity in &overridden_entities {
+            // Overrides are written sorted by component type, so they may be reloaded in a
+            // different order
+            let sorted_overrides =
+                |overrides: &HashMap<EntityUuid, Vec<crate::ComponentOverride>>| {
+                    let mut overrides: Vec<_> = overrides[entity]
+                        .iter()
+                        .map(|o| (o.component_type, o.data.clone()))
+                        .collect();
+                    overrides.sort();
+                    overrides
+                };
+            if sorted_overrides(&a_ref.overrides) != sorted_overrides(&b_ref.overrides) {
+                return Some(format!(
+                    "overrides of entity {} by prefab ref {} differ",
+                    uuid_str(entity),
+                    uuid_str(prefab_ref)
+                ));
+            }
+        }
+    }
+
+    if a_meta.parameters != b_meta.parameters {
+        return Some("parameters differ".to_string());
+    }
+    if a_meta.entity_layers != b_meta.entity_layers {
+        return Some("entity layers differ".to_string());
+    }
+    // Entities without children aren't written
+    let non_empty = |hierarchy: &HashMap<EntityUuid, Vec<EntityUuid>>| {
+        hierarchy
+            .iter()
+            .filter(|(_, children)| !children.is_empty())
+            .map(|(parent, children)| (*parent, children.clone()))
+            .collect::<HashMap<_, _>>()
+    };
+    if non_empty(&a_meta.hierarchy) != non_empty(&b_meta.hierarchy) {
+        return Some("hierarchy differs".to_string());
+    }
+    if a_meta.blobs != b_meta.blobs {
+        return Some("blobs differ".to_string());
+    }
+
+    None
+}
+
+// Entity references are written as UUIDs, so they compare equal across loads
+fn component_text<S: BuildHasher>(
+    prefab: &Prefab,
+    entity: &EntityUuid,
+    component_type: &ComponentTypeUuid,
+    registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+) -> Option<String> {
+    let mut entity_map = prefab.entity_uuids();
+    let mut allocator = Allocate::new();
+    let mapper = EntityUuidMapper {
+        entity_map: RefCell::new(&mut entity_map),
+        allocator: RefCell::new(&mut allocator),
+    };
+    mapper.scope(|| prefab.get_component_by_uuid(entity, component_type, registered_components))
+}
+
+fn sorted_keys<K: Copy + Ord, V>(map: &HashMap<K, V>) -> Vec<K> {
+    let mut keys: Vec<_> = map.keys().cloned().collect();
+    keys.sort();
+    keys
+}
+
+fn uuid_str(bytes: &uuid::Bytes) -> String {
+    uuid::Uuid::from_bytes(*bytes).to_string()
+}
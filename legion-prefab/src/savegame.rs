@@ -0,0 +1,377 @@
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid};
+use crate::{
+    spawn_cooked_prefab, ComponentRegistration, CookedPrefab, CopyCloneImpl, DiffSingleKind,
+    InstanceHandle, PrefabInstanceComponent, PrefabInstanceId, PrefabInstanceTracker,
+    PrefabResources, UuidEntityBimap,
+};
+use legion::world::Merger;
+use legion::{Entity, IntoQuery, World};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
+use type_uuid::TypeUuid;
+
+/// How a component of a saved entity differs from the cooked prefab it was spawned from. Diffs and
+/// data are encoded with bincode.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SavedComponent {
+    /// A serde_diff of the prefab's component
+    Changed {
+        #[serde(with = "prefab_format::uuid_bytes")]
+        component_type: ComponentTypeUuid,
+        diff: Vec<u8>,
+    },
+    /// A component the prefab entity doesn't have
+    Added {
+        #[serde(with = "prefab_format::uuid_bytes")]
+        component_type: ComponentTypeUuid,
+        data: Vec<u8>,
+    },
+    /// A component of the prefab entity that was removed
+    Removed {
+        #[serde(with = "prefab_format::uuid_bytes")]
+        component_type: ComponentTypeUuid,
+    },
+}
+
+impl SavedComponent {
+    pub fn component_type(&self) -> &ComponentTypeUuid {
+        match self {
+            SavedComponent::Changed { component_type, .. }
+            | SavedComponent::Added { component_type, .. }
+            | SavedComponent::Removed { component_type } => component_type,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedEntity {
+    #[serde(with = "prefab_format::uuid_bytes")]
+    pub entity: EntityUuid,
+    pub components: Vec<SavedComponent>,
+}
+
+/// One spawned prefab instance, stored as the prefab plus what changed since it was spawned
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedInstance {
+    #[serde(with = "prefab_format::uuid_bytes")]
+    pub prefab: PrefabUuid,
+    /// Entities with components that differ from the prefab. Unchanged entities aren't stored.
+    pub changed_entities: Vec<SavedEntity>,
+    /// Entities of the prefab that were despawned
+    #[serde(with = "prefab_format::uuid_bytes::vec")]
+    pub removed_entities: Vec<EntityUuid>,
+    /// Entities attached to the instance, by their UUID in `Savegame::loose_entities`. Only set by
+    /// `save_world`.
+    #[serde(default, with = "prefab_format::uuid_bytes::vec")]
+    pub attached_entities: Vec<EntityUuid>,
+}
+
+/// Saved prefab instances, see `save_snapshot`. Serialize it with any serde format.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SavegameSnapshot {
+    pub instances: Vec<SavedInstance>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The instance isn't tracked by the `PrefabInstanceTracker`
+    UntrackedInstance(PrefabInstanceId),
+    /// The cooked prefab lookup didn't have this prefab
+    MissingPrefab(PrefabUuid),
+    /// The snapshot has a component type that isn't registered
+    UnregisteredComponent(ComponentTypeUuid),
+}
+
+/// Saves prefab instances as the prefab they were spawned from plus per-entity diffs against the
+/// cooked prefab, which is much smaller than saving their component data when most of it is
+/// unchanged. `instances` is the region of the world to save, and `cooked_prefabs` must return
+/// the same cooked prefabs the instances were spawned from.
+///
+/// Only entities spawned from the prefab are saved. Attached entities (i.e. spawned children) and
+/// `PrefabInstanceComponent`s aren't, as the instance is spawned again when restoring.
+pub fn save_snapshot<'a, S: BuildHasher, F: FnMut(&PrefabUuid) -> Option<&'a CookedPrefab>>(
+    world: &World,
+    tracker: &PrefabInstanceTracker,
+    instances: &[PrefabInstanceId],
+    mut cooked_prefabs: F,
+    registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+) -> Result<SavegameSnapshot, SnapshotError> {
+    let mut snapshot = SavegameSnapshot::default();
+    for instance_id in instances {
+        let handle = tracker
+            .instance(*instance_id)
+            .ok_or(SnapshotError::UntrackedInstance(*instance_id))?;
+        let prefab = tracker.prefab_of_instance(*instance_id).unwrap();
+        let cooked = cooked_prefabs(&prefab).ok_or(SnapshotError::MissingPrefab(prefab))?;
+
+        let mut saved = SavedInstance {
+            prefab,
+            changed_entities: vec![],
+            removed_entities: vec![],
+            attached_entities: vec![],
+        };
+        let mut entity_uuids: Vec<_> = cooked.entities.uuids().copied().collect();
+        entity_uuids.sort();
+        for entity_uuid in entity_uuids {
+            match handle.entity(&entity_uuid) {
+                Some(entity) if world.contains(entity) => {
+                    let components = diff_entity(
+                        &cooked.world,
+                        cooked.entities.entity(&entity_uuid).unwrap(),
+                        world,
+                        entity,
+                        registered_components,
+                    );
+                    if !components.is_empty() {
+                        saved.changed_entities.push(SavedEntity {
+                            entity: entity_uuid,
+                            components,
+                        });
+                    }
+                }
+                _ => saved.removed_entities.push(entity_uuid),
+            }
+        }
+        snapshot.instances.push(saved);
+    }
+
+    Ok(snapshot)
+}
+
+fn diff_entity<S: BuildHasher>(
+    cooked_world: &World,
+    cooked_entity: legion::Entity,
+    world: &World,
+    entity: legion::Entity,
+    registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+) -> Vec<SavedComponent> {
+    let mut component_types: Vec<_> = registered_components
+        .keys()
+        .filter(|component_type| **component_type != PrefabInstanceComponent::UUID)
+        .collect();
+    component_types.sort();
+
+    let mut components = vec![];
+    let mut scratch = vec![];
+    for component_type in component_types {
+        scratch.clear();
+        let mut ser =
+            bincode::Serializer::new(&mut scratch, bincode::config::DefaultOptions::new());
+        let result = registered_components[component_type].diff_single(
+            &mut erased_serde::Serializer::erase(&mut ser),
+            cooked_world,
+            Some(cooked_entity),
+            world,
+            Some(entity),
+        );
+
+        let component_type = *component_type;
+        match result.kind {
+            DiffSingleKind::NoChange => {}
+            DiffSingleKind::Change => components.push(SavedComponent::Changed {
+                component_type,
+                diff: scratch.clone(),
+            }),
+            DiffSingleKind::Add => components.push(SavedComponent::Added {
+                component_type,
+                data: scratch.clone(),
+            }),
+            DiffSingleKind::Remove => components.push(SavedComponent::Removed { component_type }),
+        }
+    }
+    components
+}
+
+/// Spawns the instances of a snapshot made with `save_snapshot` and applies their diffs. Returns
+/// the prefab and handle of each instance in snapshot order, i.e. to `track` them again.
+///
+/// The instances are spawned from whatever `cooked_prefabs` returns, so a snapshot can be restored
+/// against newer versions of its prefabs: fields the save changed keep their saved values and the
+/// rest come from the current prefab. Entities added to a prefab since are spawned, and saved
+/// entities the prefab no longer has are skipped.
+pub fn restore_snapshot<
+    'a,
+    S: BuildHasher,
+    M: Merger,
+    F: FnMut(&PrefabUuid) -> Option<&'a CookedPrefab>,
+>(
+    world: &mut World,
+    snapshot: &SavegameSnapshot,
+    mut cooked_prefabs: F,
+    registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+    merger: &mut M,
+) -> Result<Vec<(PrefabUuid, InstanceHandle)>, SnapshotError> {
+    let mut restored = vec![];
+    for saved in &snapshot.instances {
+        let cooked =
+            cooked_prefabs(&saved.prefab).ok_or(SnapshotError::MissingPrefab(saved.prefab))?;
+        let mut handle = spawn_cooked_prefab(world, cooked, merger, Some(saved.prefab));
+
+        for saved_entity in &saved.changed_entities {
+            // The entity was removed from the prefab since the snapshot was saved
+            let entity = match handle.entity(&saved_entity.entity) {
+                Some(entity) => entity,
+                None => continue,
+            };
+            for component in &saved_entity.components {
+                let component_type = component.component_type();
+                let registration = registered_components
+                    .get(component_type)
+                    .ok_or(SnapshotError::UnregisteredComponent(*component_type))?;
+                match component {
+                    SavedComponent::Changed { diff, .. } => registration.apply_diff(
+                        &mut erased_serde::Deserializer::erase(&mut bincode_deserializer(diff)),
+                        world,
+                        entity,
+                    ),
+                    SavedComponent::Added { data, .. } => registration.add_to_entity(
+                        &mut erased_serde::Deserializer::erase(&mut bincode_deserializer(data)),
+                        world,
+                        entity,
+                    ),
+                    SavedComponent::Removed { .. } => {
+                        registration.remove_from_entity(world, entity)
+                    }
+                }
+            }
+        }
+
+        for entity_uuid in &saved.removed_entities {
+            if let Some(entity) = handle.entity(entity_uuid) {
+                world.remove(entity);
+                handle.detach(entity);
+            }
+        }
+
+        restored.push((saved.prefab, handle));
+    }
+
+    Ok(restored)
+}
+
+/// A whole world saved with `save_world`. Serialize it with any serde format; component data is
+/// written with the component registrations, like a cooked prefab.
+#[derive(Serialize, Deserialize)]
+pub struct Savegame {
+    /// The tracked prefab instances, as diffs against their prefabs
+    pub snapshot: SavegameSnapshot,
+    /// Every entity that isn't part of a tracked instance's prefab (including attached entities),
+    /// with all of its components
+    pub loose_entities: CookedPrefab,
+}
+
+/// Saves every entity in `world`. Entities of tracked prefab instances are saved as diffs against
+/// the cooked prefabs returned by `cooked_prefabs`, see `save_snapshot`, and all other entities
+/// are saved with all of their components. Which instance each attached entity belongs to is
+/// saved too, so `load_world` can rebuild the tracker.
+///
+/// Component data is copied as is, so components holding an `Entity` aren't remapped.
+pub fn save_world<'a, S: BuildHasher, F: FnMut(&PrefabUuid) -> Option<&'a CookedPrefab>>(
+    world: &World,
+    tracker: &PrefabInstanceTracker,
+    cooked_prefabs: F,
+    registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+) -> Result<Savegame, SnapshotError> {
+    let mut instances: Vec<PrefabInstanceId> = tracker
+        .prefabs()
+        .flat_map(|prefab| tracker.instances_of_prefab(prefab).iter().copied())
+        .collect();
+    instances.sort();
+    let mut snapshot = save_snapshot(
+        world,
+        tracker,
+        &instances,
+        cooked_prefabs,
+        registered_components,
+    )?;
+
+    let mut prefab_entities = HashSet::new();
+    for instance_id in &instances {
+        let handle = tracker.instance(*instance_id).unwrap();
+        prefab_entities.extend(handle.entities().entities().copied());
+    }
+
+    let components_by_type_id: HashMap<_, _> = registered_components
+        .values()
+        .map(|registration| (registration.component_type_id(), registration.clone()))
+        .collect();
+    let mut clone_impl = CopyCloneImpl::new_skipping_unregistered(&components_by_type_id);
+    let mut loose_world = World::default();
+    let mut loose_entities = UuidEntityBimap::new();
+    let mut loose_uuids = HashMap::new();
+    for entity in <Entity>::query().iter(world) {
+        if prefab_entities.contains(entity) {
+            continue;
+        }
+        let entity_uuid = *uuid::Uuid::new_v4().as_bytes();
+        let loose_entity = loose_world.clone_from_single(world, *entity, &mut clone_impl);
+        loose_entities.insert(entity_uuid, loose_entity);
+        loose_uuids.insert(*entity, entity_uuid);
+    }
+
+    for (instance_id, saved) in instances.iter().zip(&mut snapshot.instances) {
+        let handle = tracker.instance(*instance_id).unwrap();
+        saved.attached_entities = handle
+            .iter()
+            .filter_map(|entity| loose_uuids.get(&entity).copied())
+            .collect();
+    }
+
+    Ok(Savegame {
+        snapshot,
+        loose_entities: CookedPrefab {
+            world: loose_world,
+            entities: loose_entities,
+            parameters: vec![],
+            resources: PrefabResources::new(),
+            blobs: HashMap::new(),
+        },
+    })
+}
+
+/// Loads a world saved with `save_world` into `world`, binding the saved instances to the current
+/// versions of their prefabs (see `restore_snapshot`). Returns a tracker for the restored
+/// instances, including their attached entities.
+pub fn load_world<
+    'a,
+    S: BuildHasher,
+    M: Merger,
+    F: FnMut(&PrefabUuid) -> Option<&'a CookedPrefab>,
+>(
+    world: &mut World,
+    savegame: Savegame,
+    cooked_prefabs: F,
+    registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+    merger: &mut M,
+) -> Result<PrefabInstanceTracker, SnapshotError> {
+    let restored = restore_snapshot(
+        world,
+        &savegame.snapshot,
+        cooked_prefabs,
+        registered_components,
+        merger,
+    )?;
+
+    // Moving keeps the entity IDs, so the saved UUIDs still map to the moved entities
+    let mut loose_entities = savegame.loose_entities;
+    world.move_from(&mut loose_entities.world, &legion::query::any());
+
+    let mut tracker = PrefabInstanceTracker::new();
+    for (saved, (prefab, mut handle)) in savegame.snapshot.instances.iter().zip(restored) {
+        for entity_uuid in &saved.attached_entities {
+            if let Some(entity) = loose_entities.entities.entity(entity_uuid) {
+                handle.attach(entity);
+            }
+        }
+        tracker.track(prefab, handle);
+    }
+
+    Ok(tracker)
+}
+
+fn bincode_deserializer(
+    data: &[u8]
+) -> bincode::Deserializer<bincode::de::read::SliceReader, bincode::config::DefaultOptions> {
+    bincode::Deserializer::from_slice(data, bincode::config::DefaultOptions::new())
+}
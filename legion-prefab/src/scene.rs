@@ -0,0 +1,169 @@
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid};
+use crate::{
+    apply_prefab_parameters, ComponentOverride, ComponentRegistration, CookedPrefab, CopyCloneImpl,
+    ParameterError, PrefabResources, UuidEntityBimap,
+};
+use legion::storage::ComponentTypeId;
+use legion::World;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+
+/// A level made of prefabs. Each instance places a prefab in the scene with its own overrides.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Scene {
+    #[serde(with = "prefab_format::uuid_bytes")]
+    pub id: uuid::Bytes,
+
+    /// Instances are cooked in this order
+    pub instances: Vec<SceneInstance>,
+}
+
+/// One placement of a prefab in a scene
+#[derive(Serialize, Deserialize)]
+pub struct SceneInstance {
+    /// Identifies this instance within the scene. Kept when instances are reordered so the
+    /// entities of the instance keep their UUIDs (see `scene_entity_uuid`).
+    #[serde(with = "prefab_format::uuid_bytes")]
+    pub id: uuid::Bytes,
+
+    #[serde(with = "prefab_format::uuid_bytes")]
+    pub prefab: PrefabUuid,
+
+    /// Overrides for entities of the prefab that only apply to this instance, i.e. placement
+    #[serde(default, with = "prefab_format::uuid_bytes::map")]
+    pub overrides: HashMap<EntityUuid, Vec<ComponentOverride>>,
+
+    /// Values (RON text) for parameters of the prefab, by parameter name
+    #[serde(default)]
+    pub parameter_values: BTreeMap<String, String>,
+}
+
+impl SceneInstance {
+    pub fn new(prefab: PrefabUuid) -> Self {
+        SceneInstance {
+            id: *uuid::Uuid::new_v4().as_bytes(),
+            prefab,
+            overrides: HashMap::new(),
+            parameter_values: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    /// No cooked prefab was provided for an instance
+    MissingPrefab(PrefabUuid),
+    /// An override refers to an entity that isn't in the instance's prefab
+    MissingEntity {
+        instance: uuid::Bytes,
+        entity: EntityUuid,
+    },
+    /// An override refers to a component type that isn't registered
+    UnregisteredComponent(ComponentTypeUuid),
+    Parameter {
+        instance: uuid::Bytes,
+        error: ParameterError,
+    },
+}
+
+/// The UUID an entity of a prefab is given in a cooked scene. Each instance needs its own UUIDs,
+/// so they are derived from the instance ID and the entity's UUID in the prefab.
+pub fn scene_entity_uuid(
+    instance: &uuid::Bytes,
+    entity: &EntityUuid,
+) -> EntityUuid {
+    let mut scene_entity = *entity;
+    for (byte, instance_byte) in scene_entity.iter_mut().zip(instance.iter()) {
+        *byte ^= instance_byte;
+    }
+    scene_entity
+}
+
+/// Cooks every instance of a scene into a single world. `cooked_prefabs` must contain a cooked
+/// prefab for each prefab the scene places. The entities of the result are keyed by
+/// `scene_entity_uuid`, and resources of later instances replace those of earlier instances.
+pub fn cook_scene<S: BuildHasher, T: BuildHasher, U: BuildHasher>(
+    registered_components: &HashMap<ComponentTypeId, ComponentRegistration, S>,
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, T>,
+    scene: &Scene,
+    cooked_prefabs: &HashMap<PrefabUuid, CookedPrefab, U>,
+) -> Result<CookedPrefab, SceneError> {
+    let mut world = World::default();
+    let mut entities = UuidEntityBimap::new();
+    let mut resources = PrefabResources::new();
+    let mut blobs = HashMap::new();
+
+    for instance in &scene.instances {
+        let cooked_prefab = cooked_prefabs
+            .get(&instance.prefab)
+            .ok_or(SceneError::MissingPrefab(instance.prefab))?;
+
+        let mut clone_merge_impl = CopyCloneImpl::new(registered_components);
+        let result_mappings = world.clone_from(
+            &cooked_prefab.world,
+            &legion::query::any(),
+            &mut clone_merge_impl,
+        );
+
+        let instance_entities: UuidEntityBimap = cooked_prefab
+            .entities
+            .iter()
+            .map(|(entity_uuid, cooked_entity)| (*entity_uuid, result_mappings[cooked_entity]))
+            .collect();
+
+        for (entity_uuid, component_overrides) in &instance.overrides {
+            let missing_entity = || SceneError::MissingEntity {
+                instance: instance.id,
+                entity: *entity_uuid,
+            };
+            let entity = instance_entities
+                .entity(entity_uuid)
+                .ok_or_else(missing_entity)?;
+
+            for component_override in component_overrides {
+                let registration = registered_components_by_uuid
+                    .get(&component_override.component_type)
+                    .ok_or(SceneError::UnregisteredComponent(
+                        component_override.component_type,
+                    ))?;
+
+                let mut deserializer =
+                    ron::de::Deserializer::from_str(&component_override.data).unwrap();
+                let mut de = erased_serde::Deserializer::erase(&mut deserializer);
+                registration.apply_diff(&mut de, &mut world, entity);
+            }
+        }
+
+        apply_prefab_parameters(
+            registered_components_by_uuid,
+            &mut world,
+            &instance_entities,
+            &cooked_prefab.parameters,
+            &instance.parameter_values,
+        )
+        .map_err(|error| SceneError::Parameter {
+            instance: instance.id,
+            error,
+        })?;
+
+        for (entity_uuid, entity) in &instance_entities {
+            entities.insert(scene_entity_uuid(&instance.id, entity_uuid), *entity);
+        }
+        resources.extend_from(&cooked_prefab.resources);
+        blobs.extend(
+            cooked_prefab
+                .blobs
+                .iter()
+                .map(|(blob, data)| (*blob, data.clone())),
+        );
+    }
+
+    Ok(CookedPrefab {
+        world,
+        entities,
+        parameters: vec![],
+        resources,
+        blobs,
+    })
+}
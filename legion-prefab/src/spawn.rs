@@ -0,0 +1,271 @@
+use crate::format::{EntityUuid, PrefabUuid};
+use crate::{ComponentRegistration, CookedPrefab, UuidEntityBimap};
+use legion::storage::{Archetype, ArchetypeWriter, ComponentTypeId, Components, EntityLayout};
+use legion::world::{Allocate, EntityRewrite, Merger};
+use legion::{Entity, EntityStore, IntoQuery, Read, World};
+use std::ops::Range;
+use serde::{Deserialize, Serialize};
+use serde_diff::SerdeDiff;
+use type_uuid::TypeUuid;
+
+/// Records which prefab a spawned entity came from, and which entity in that prefab it is. Runtime
+/// systems can query for this to find all instances of a prefab, i.e. to update them when the
+/// prefab is reloaded.
+#[derive(
+    TypeUuid, Serialize, Deserialize, SerdeDiff, Clone, Copy, Default, Debug, PartialEq, Eq, Hash,
+)]
+#[uuid = "4c5e6d4a-3e8f-4b6f-9c3a-0d2b8e7f1a65"]
+#[serde_diff(opaque)]
+pub struct PrefabInstanceComponent {
+    #[serde(with = "prefab_format::uuid_bytes")]
+    pub prefab: PrefabUuid,
+    #[serde(with = "prefab_format::uuid_bytes")]
+    pub entity: EntityUuid,
+}
+
+// Registered so that worlds containing spawned entities can be cloned and serialized like any
+// other world
+inventory::submit! {
+    ComponentRegistration::of::<PrefabInstanceComponent>()
+}
+
+/// The entities created by spawning a prefab once, so they can be despawned together with
+/// `despawn_instance`
+#[derive(Debug, Clone, Default)]
+pub struct InstanceHandle {
+    entities: UuidEntityBimap,
+    // Entities that were spawned later and belong to this instance, i.e. children
+    attached: Vec<Entity>,
+}
+
+impl InstanceHandle {
+    /// The spawned entity for an entity in the prefab
+    pub fn entity(
+        &self,
+        entity_uuid: &EntityUuid,
+    ) -> Option<Entity> {
+        self.entities.entity(entity_uuid)
+    }
+
+    /// The spawned entity for each entity in the prefab. Doesn't include attached entities.
+    pub fn entities(&self) -> &UuidEntityBimap {
+        &self.entities
+    }
+
+    /// Makes an entity that was spawned separately part of this instance
+    pub fn attach(
+        &mut self,
+        entity: Entity,
+    ) {
+        self.attached.push(entity);
+    }
+
+    /// Makes all entities of another instance (i.e. a prefab spawned as a child of this one) part
+    /// of this instance
+    pub fn attach_instance(
+        &mut self,
+        instance: InstanceHandle,
+    ) {
+        self.attached.extend(instance.iter());
+    }
+
+    /// Removes an entity from this instance, i.e. because it was despawned on its own. Returns
+    /// false if the entity isn't part of the instance.
+    pub fn detach(
+        &mut self,
+        entity: Entity,
+    ) -> bool {
+        if self.entities.remove_entity(&entity).is_some() {
+            return true;
+        }
+
+        let attached_count = self.attached.len();
+        self.attached.retain(|attached| *attached != entity);
+        self.attached.len() != attached_count
+    }
+
+    pub fn contains(
+        &self,
+        entity: Entity,
+    ) -> bool {
+        self.entities.contains_entity(&entity) || self.attached.contains(&entity)
+    }
+
+    /// Every entity in the instance, including attached entities
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.entities
+            .entities()
+            .cloned()
+            .chain(self.attached.iter().cloned())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len() + self.attached.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Clones the entities of a cooked prefab into `world` using `merger` (i.e. a `SpawnCloneImpl`)
+/// and returns a handle to the spawned entities. If `instance_of` is set, a
+/// `PrefabInstanceComponent` for that prefab is added to every spawned entity.
+///
+/// Entities are cloned an archetype at a time, and `PrefabInstanceComponent` is written while
+/// cloning, so spawning scales with the number of entities rather than moving each one to a new
+/// archetype afterwards.
+pub fn spawn_cooked_prefab<M: Merger>(
+    world: &mut World,
+    cooked_prefab: &CookedPrefab,
+    merger: &mut M,
+    instance_of: Option<PrefabUuid>,
+) -> InstanceHandle {
+    let bulk_instance_of = instance_of.filter(|_| can_add_instances_while_cloning(cooked_prefab));
+    let result_mappings = match bulk_instance_of {
+        Some(prefab) => {
+            let mut instance_merger = InstanceMerger {
+                inner: merger,
+                entities: &cooked_prefab.entities,
+                prefab,
+            };
+            world.clone_from(
+                &cooked_prefab.world,
+                &legion::query::any(),
+                &mut instance_merger,
+            )
+        }
+        None => world.clone_from(&cooked_prefab.world, &legion::query::any(), merger),
+    };
+
+    let mut spawned = UuidEntityBimap::with_capacity(cooked_prefab.entities.len());
+    for (entity_uuid, cooked_entity) in cooked_prefab.entities.iter() {
+        spawned.insert(*entity_uuid, result_mappings[cooked_entity]);
+    }
+
+    if let (Some(prefab), None) = (instance_of, bulk_instance_of) {
+        for (entity_uuid, entity) in &spawned {
+            world
+                .entry(*entity)
+                .expect("spawned entity not in world")
+                .add_component(PrefabInstanceComponent {
+                    prefab,
+                    entity: *entity_uuid,
+                });
+        }
+    }
+
+    InstanceHandle {
+        entities: spawned,
+        attached: vec![],
+    }
+}
+
+// Adding the instance component while cloning writes it for whole archetypes, so it only works if
+// every entity has a UUID, and if the prefab doesn't already contain instance components
+fn can_add_instances_while_cloning(cooked_prefab: &CookedPrefab) -> bool {
+    let mut has_instance_component = <Read<PrefabInstanceComponent>>::query();
+    if has_instance_component
+        .iter(&cooked_prefab.world)
+        .next()
+        .is_some()
+    {
+        return false;
+    }
+
+    let mut entities = <Entity>::query();
+    entities
+        .iter(&cooked_prefab.world)
+        .all(|entity| cooked_prefab.entities.contains_entity(entity))
+}
+
+// Clones with another merger and adds a PrefabInstanceComponent to every cloned entity
+struct InstanceMerger<'a, M> {
+    inner: &'a mut M,
+    entities: &'a UuidEntityBimap,
+    prefab: PrefabUuid,
+}
+
+impl<'a, M: Merger> Merger for InstanceMerger<'a, M> {
+    fn prefers_new_archetype() -> bool {
+        M::prefers_new_archetype()
+    }
+
+    fn entity_map(&mut self) -> EntityRewrite {
+        self.inner.entity_map()
+    }
+
+    fn assign_id(
+        &mut self,
+        existing: Entity,
+        allocator: &mut Allocate,
+    ) -> Entity {
+        self.inner.assign_id(existing, allocator)
+    }
+
+    fn convert_layout(
+        &mut self,
+        source_layout: EntityLayout,
+    ) -> EntityLayout {
+        let mut dest_layout = self.inner.convert_layout(source_layout);
+        let instance_type = ComponentTypeId::of::<PrefabInstanceComponent>();
+        if !dest_layout.component_types().contains(&instance_type) {
+            dest_layout.register_component::<PrefabInstanceComponent>();
+        }
+        dest_layout
+    }
+
+    fn merge_archetype(
+        &mut self,
+        src_entity_range: Range<usize>,
+        src_arch: &Archetype,
+        src_components: &Components,
+        dst: &mut ArchetypeWriter,
+    ) {
+        self.inner
+            .merge_archetype(src_entity_range.clone(), src_arch, src_components, dst);
+
+        let instances: Vec<_> = src_arch.entities()[src_entity_range]
+            .iter()
+            .map(|entity| PrefabInstanceComponent {
+                prefab: self.prefab,
+                entity: self
+                    .entities
+                    .uuid(entity)
+                    .expect("cloned entity has no UUID"),
+            })
+            .collect();
+
+        let mut dst = dst.claim_components::<PrefabInstanceComponent>();
+        dst.ensure_capacity(instances.len());
+        unsafe {
+            dst.extend_memcopy(instances.as_ptr(), instances.len());
+        }
+    }
+}
+
+/// Removes every entity in an instance from the world, including attached entities. Entities that
+/// were already removed are skipped. Returns the number of entities removed.
+pub fn despawn_instance(
+    world: &mut World,
+    instance: InstanceHandle,
+) -> usize {
+    instance
+        .iter()
+        .filter(|entity| world.remove(*entity))
+        .count()
+}
+
+/// Finds every entity spawned from `prefab` with a `PrefabInstanceComponent`, along with the UUID
+/// of the prefab entity it was spawned from
+pub fn find_prefab_instances<S: EntityStore>(
+    world: &S,
+    prefab: &PrefabUuid,
+) -> Vec<(Entity, EntityUuid)> {
+    let mut query = <(Entity, Read<PrefabInstanceComponent>)>::query();
+    query
+        .iter(world)
+        .filter(|(_, instance)| instance.prefab == *prefab)
+        .map(|(entity, instance)| (*entity, instance.entity))
+        .collect()
+}
@@ -0,0 +1,76 @@
+use crate::{CookedPrefab, PrefabInstanceComponent};
+use legion::storage::ComponentTypeId;
+use legion::{Entity, EntityStore, IntoQuery};
+use std::collections::HashMap;
+
+/// An estimate of what spawning a cooked prefab adds to a world, see
+/// `CookedPrefab::estimated_spawn_cost`
+#[derive(Debug, Clone, Default)]
+pub struct SpawnCost {
+    pub entity_count: usize,
+    /// The component types that are spawned, ordered by type name
+    pub components: Vec<ComponentSpawnCost>,
+    /// The sum of the bytes of `components`
+    pub component_bytes: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ComponentSpawnCost {
+    pub component_type: ComponentTypeId,
+    /// None if the type isn't registered, in which case its size is unknown
+    pub type_name: Option<&'static str>,
+    /// The number of entities that get a component of this type
+    pub count: usize,
+    /// The size of the type times `count`, or zero if the type isn't registered
+    pub bytes: usize,
+}
+
+impl CookedPrefab {
+    /// Estimates the memory and number of entities that `spawn_cooked_prefab` would add to a world,
+    /// from the sizes of the registered component types, so that streaming can stay within a budget
+    /// before committing to a spawn. Includes the `PrefabInstanceComponent` that spawning adds to
+    /// every entity.
+    ///
+    /// Only component storage is counted. Memory that components own elsewhere, like the contents
+    /// of a `Vec`, and legion's own bookkeeping aren't included.
+    pub fn estimated_spawn_cost(&self) -> SpawnCost {
+        let mut counts: HashMap<ComponentTypeId, usize> = HashMap::new();
+        let mut entity_count = 0;
+        for entity in <Entity>::query().iter(&self.world) {
+            entity_count += 1;
+            let entry = self.world.entry_ref(*entity).unwrap();
+            for component_type in entry.archetype().layout().component_types() {
+                *counts.entry(*component_type).or_default() += 1;
+            }
+        }
+
+        // Spawning adds one to every entity, replacing any the cooked prefab has
+        counts.insert(
+            ComponentTypeId::of::<PrefabInstanceComponent>(),
+            entity_count,
+        );
+
+        let registry = crate::registration::global_component_registry();
+        let mut components: Vec<_> = counts
+            .into_iter()
+            .map(|(component_type, count)| {
+                let registration = registry.by_type_id().get(&component_type);
+                ComponentSpawnCost {
+                    component_type,
+                    type_name: registration.map(|registration| registration.type_name()),
+                    count,
+                    bytes: registration
+                        .map(|registration| registration.component_size() * count)
+                        .unwrap_or(0),
+                }
+            })
+            .collect();
+        components.sort_by_key(|component| component.type_name);
+
+        SpawnCost {
+            entity_count,
+            component_bytes: components.iter().map(|component| component.bytes).sum(),
+            components,
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid};
+use crate::{spawn_cooked_prefab, ComponentRegistration, CookedPrefab, InstanceHandle};
+use legion::world::Merger;
+use legion::{Entity, World};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_diff::SerdeDiff;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+use type_uuid::TypeUuid;
+
+/// Component data that replaces what one spawned entity was cooked with
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpawnOverride {
+    /// A diff (RON text) applied to the cooked component, like the overrides of a prefab ref. The
+    /// entity must have the component.
+    Diff {
+        component_type: ComponentTypeUuid,
+        data: String,
+    },
+    /// A whole component (RON text). It is added to the entity, replacing the cooked component if
+    /// there is one.
+    Component {
+        component_type: ComponentTypeUuid,
+        data: String,
+    },
+}
+
+impl SpawnOverride {
+    pub fn component_type(&self) -> &ComponentTypeUuid {
+        match self {
+            SpawnOverride::Diff { component_type, .. } => component_type,
+            SpawnOverride::Component { component_type, .. } => component_type,
+        }
+    }
+}
+
+/// Per-entity overrides supplied when spawning a cooked prefab, i.e. a randomized color or a team
+/// id. They only apply to the spawned entities, so the cooked prefab can be shared by every
+/// instance.
+#[derive(Debug, Clone, Default)]
+pub struct SpawnOverrides {
+    // Sorted so overrides are applied in the same order every time
+    overrides: BTreeMap<EntityUuid, Vec<SpawnOverride>>,
+}
+
+impl SpawnOverrides {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds an override for an entity of the prefab. Overrides of an entity are applied in the
+    /// order they are added.
+    pub fn add(
+        &mut self,
+        entity: EntityUuid,
+        spawn_override: SpawnOverride,
+    ) {
+        self.overrides
+            .entry(entity)
+            .or_default()
+            .push(spawn_override);
+    }
+
+    /// Replaces a component of an entity of the prefab (or adds it) with `component`
+    pub fn set_component<T: TypeUuid + Serialize>(
+        &mut self,
+        entity: EntityUuid,
+        component: &T,
+    ) -> Result<(), ron::ser::Error> {
+        self.add(
+            entity,
+            SpawnOverride::Component {
+                component_type: T::UUID,
+                data: ron::ser::to_string(component)?,
+            },
+        );
+        Ok(())
+    }
+
+    /// Changes the fields of a component of an entity of the prefab that differ between `old` and
+    /// `new`. Other fields keep their cooked value. Components registered with
+    /// `OverridePolicy::Replace` are replaced with `new` if anything differs. Nothing is added if
+    /// `old` and `new` are the same.
+    pub fn diff_component<T: TypeUuid + Serialize + DeserializeOwned + SerdeDiff>(
+        &mut self,
+        entity: EntityUuid,
+        old: &T,
+        new: &T,
+    ) -> Result<(), ron::ser::Error> {
+        if let Some(data) = crate::override_policy::ron_diff(old, new)? {
+            self.add(
+                entity,
+                SpawnOverride::Diff {
+                    component_type: T::UUID,
+                    data,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overrides.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&EntityUuid, &[SpawnOverride])> {
+        self.overrides
+            .iter()
+            .map(|(entity, overrides)| (entity, overrides.as_slice()))
+    }
+}
+
+#[derive(Debug)]
+pub enum SpawnOverrideError {
+    /// An override refers to an entity that isn't in the cooked prefab
+    MissingEntity(EntityUuid),
+    /// An override is for a component type that isn't registered
+    UnregisteredComponent(ComponentTypeUuid),
+    /// A diff is for a component the cooked entity doesn't have
+    MissingComponent {
+        entity: EntityUuid,
+        component_type: ComponentTypeUuid,
+    },
+}
+
+/// Spawns a cooked prefab like `spawn_cooked_prefab` and then applies `overrides` to the spawned
+/// entities. The overrides are checked against the cooked prefab first, so nothing is spawned if
+/// one of them can't be applied.
+pub fn spawn_cooked_prefab_with_overrides<M: Merger, S: BuildHasher>(
+    world: &mut World,
+    cooked_prefab: &CookedPrefab,
+    merger: &mut M,
+    instance_of: Option<PrefabUuid>,
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+    overrides: &SpawnOverrides,
+) -> Result<InstanceHandle, SpawnOverrideError> {
+    for (entity_uuid, entity_overrides) in overrides.iter() {
+        let cooked_entity = cooked_prefab
+            .entities
+            .entity(entity_uuid)
+            .ok_or(SpawnOverrideError::MissingEntity(*entity_uuid))?;
+
+        for spawn_override in entity_overrides {
+            let registration = registered_components_by_uuid
+                .get(spawn_override.component_type())
+                .ok_or(SpawnOverrideError::UnregisteredComponent(
+                    *spawn_override.component_type(),
+                ))?;
+
+            if let SpawnOverride::Diff { component_type, .. } = spawn_override {
+                if !has_component(&cooked_prefab.world, cooked_entity, registration) {
+                    return Err(SpawnOverrideError::MissingComponent {
+                        entity: *entity_uuid,
+                        component_type: *component_type,
+                    });
+                }
+            }
+        }
+    }
+
+    let instance = spawn_cooked_prefab(world, cooked_prefab, merger, instance_of);
+
+    for (entity_uuid, entity_overrides) in overrides.iter() {
+        let entity = instance.entity(entity_uuid).unwrap();
+        for spawn_override in entity_overrides {
+            let registration = &registered_components_by_uuid[spawn_override.component_type()];
+            match spawn_override {
+                SpawnOverride::Diff { data, .. } => {
+                    let mut deserializer = ron::de::Deserializer::from_str(data).unwrap();
+                    let mut de = erased_serde::Deserializer::erase(&mut deserializer);
+                    registration.apply_diff(&mut de, world, entity);
+                }
+                SpawnOverride::Component { data, .. } => {
+                    let mut deserializer = ron::de::Deserializer::from_str(data).unwrap();
+                    let mut de = erased_serde::Deserializer::erase(&mut deserializer);
+                    registration.add_to_entity(&mut de, world, entity);
+                }
+            }
+        }
+    }
+
+    Ok(instance)
+}
+
+fn has_component(
+    world: &World,
+    entity: Entity,
+    registration: &ComponentRegistration,
+) -> bool {
+    world
+        .entry_ref(entity)
+        .map(|entry| {
+            entry
+                .archetype()
+                .layout()
+                .has_component_by_id(registration.component_type_id())
+        })
+        .unwrap_or(false)
+}
@@ -0,0 +1,183 @@
+use crate::format::{EntityUuid, PrefabUuid};
+use crate::{despawn_instance, InstanceHandle};
+use legion::{Entity, World};
+use std::collections::HashMap;
+
+/// Identifies a single spawn of a prefab within a `PrefabInstanceTracker`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PrefabInstanceId(u64);
+
+struct TrackedInstance {
+    prefab: PrefabUuid,
+    handle: InstanceHandle,
+}
+
+/// Records which live entities were spawned from which prefab, and by which spawn. Add each
+/// `InstanceHandle` returned by `spawn_cooked_prefab` with `track`. This is plain data, so it can
+/// be stored as a legion resource.
+#[derive(Default)]
+pub struct PrefabInstanceTracker {
+    next_instance_id: u64,
+    instances: HashMap<PrefabInstanceId, TrackedInstance>,
+    instances_by_prefab: HashMap<PrefabUuid, Vec<PrefabInstanceId>>,
+    instances_by_entity: HashMap<Entity, PrefabInstanceId>,
+}
+
+impl PrefabInstanceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts tracking the entities of a spawned prefab
+    pub fn track(
+        &mut self,
+        prefab: PrefabUuid,
+        handle: InstanceHandle,
+    ) -> PrefabInstanceId {
+        let instance_id = PrefabInstanceId(self.next_instance_id);
+        self.next_instance_id += 1;
+
+        for entity in handle.iter() {
+            self.instances_by_entity.insert(entity, instance_id);
+        }
+        self.instances_by_prefab
+            .entry(prefab)
+            .or_default()
+            .push(instance_id);
+        self.instances
+            .insert(instance_id, TrackedInstance { prefab, handle });
+        instance_id
+    }
+
+    /// Stops tracking an instance, returning its handle (i.e. to pass to `despawn_instance`)
+    pub fn untrack(
+        &mut self,
+        instance_id: PrefabInstanceId,
+    ) -> Option<InstanceHandle> {
+        let instance = self.instances.remove(&instance_id)?;
+        for entity in instance.handle.iter() {
+            self.instances_by_entity.remove(&entity);
+        }
+
+        let prefab_instances = self.instances_by_prefab.get_mut(&instance.prefab).unwrap();
+        prefab_instances.retain(|id| *id != instance_id);
+        if prefab_instances.is_empty() {
+            self.instances_by_prefab.remove(&instance.prefab);
+        }
+
+        Some(instance.handle)
+    }
+
+    /// Stops tracking an instance and removes its entities from the world. Returns the number of
+    /// entities removed.
+    pub fn despawn(
+        &mut self,
+        world: &mut World,
+        instance_id: PrefabInstanceId,
+    ) -> usize {
+        self.untrack(instance_id)
+            .map(|handle| despawn_instance(world, handle))
+            .unwrap_or(0)
+    }
+
+    /// Adds an entity that was spawned later (i.e. a child) to an instance. Returns false if the
+    /// instance isn't tracked.
+    pub fn attach(
+        &mut self,
+        instance_id: PrefabInstanceId,
+        entity: Entity,
+    ) -> bool {
+        match self.instances.get_mut(&instance_id) {
+            Some(instance) => {
+                instance.handle.attach(entity);
+                self.instances_by_entity.insert(entity, instance_id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stops tracking a single entity, i.e. because it was despawned on its own
+    pub fn untrack_entity(
+        &mut self,
+        entity: Entity,
+    ) {
+        if let Some(instance_id) = self.instances_by_entity.remove(&entity) {
+            if let Some(instance) = self.instances.get_mut(&instance_id) {
+                instance.handle.detach(entity);
+            }
+        }
+    }
+
+    pub fn instance(
+        &self,
+        instance_id: PrefabInstanceId,
+    ) -> Option<&InstanceHandle> {
+        self.instances
+            .get(&instance_id)
+            .map(|instance| &instance.handle)
+    }
+
+    /// The prefab an instance was spawned from
+    pub fn prefab_of_instance(
+        &self,
+        instance_id: PrefabInstanceId,
+    ) -> Option<PrefabUuid> {
+        self.instances
+            .get(&instance_id)
+            .map(|instance| instance.prefab)
+    }
+
+    /// Every tracked instance of a prefab, in the order they were tracked
+    pub fn instances_of_prefab(
+        &self,
+        prefab: &PrefabUuid,
+    ) -> &[PrefabInstanceId] {
+        self.instances_by_prefab
+            .get(prefab)
+            .map(|instances| instances.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Every tracked entity spawned from a prefab, across all of its instances
+    pub fn entities_of_prefab(
+        &self,
+        prefab: &PrefabUuid,
+    ) -> Vec<Entity> {
+        self.instances_of_prefab(prefab)
+            .iter()
+            .flat_map(|instance_id| self.instances[instance_id].handle.iter())
+            .collect()
+    }
+
+    /// The instance an entity belongs to
+    pub fn instance_of_entity(
+        &self,
+        entity: Entity,
+    ) -> Option<PrefabInstanceId> {
+        self.instances_by_entity.get(&entity).cloned()
+    }
+
+    /// The prefab an entity was spawned from
+    pub fn prefab_of_entity(
+        &self,
+        entity: Entity,
+    ) -> Option<PrefabUuid> {
+        self.instance_of_entity(entity)
+            .and_then(|instance_id| self.prefab_of_instance(instance_id))
+    }
+
+    /// The UUID of the prefab entity an entity was spawned from. None for attached entities.
+    pub fn entity_uuid(
+        &self,
+        entity: Entity,
+    ) -> Option<EntityUuid> {
+        let instance_id = self.instance_of_entity(entity)?;
+        self.instances[&instance_id].handle.entities().uuid(&entity)
+    }
+
+    /// Every prefab with at least one tracked instance
+    pub fn prefabs(&self) -> impl Iterator<Item = &PrefabUuid> {
+        self.instances_by_prefab.keys()
+    }
+}
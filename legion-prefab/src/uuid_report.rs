@@ -0,0 +1,181 @@
+use crate::format::scan::PrefabSummary;
+use crate::format::{ComponentTypeUuid, EntityUuid, PrefabUuid};
+use crate::ComponentRegistry;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Whether a component type used by prefabs can still be loaded
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ComponentTypeStatus {
+    Registered,
+    /// Not registered, but a `ComponentMigration` converts it to `to_type`. Prefabs using it should
+    /// be upgraded.
+    Retired {
+        to_type: ComponentTypeUuid,
+    },
+    /// Neither registered nor migrated. Prefabs using it fail to load.
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentTypeUsage {
+    pub status: ComponentTypeStatus,
+    /// The registered type name, or the old type name for retired types
+    pub type_name: Option<&'static str>,
+    /// The prefabs with components or overrides of this type
+    pub prefabs: BTreeSet<PrefabUuid>,
+}
+
+/// Every UUID used by a set of prefabs, see `uuid_report`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UuidReport {
+    pub prefabs: BTreeSet<PrefabUuid>,
+    /// The prefabs each entity is declared in. An entity declared in more than one prefab has a
+    /// copied ID.
+    pub entities: BTreeMap<EntityUuid, BTreeSet<PrefabUuid>>,
+    /// The prefabs referencing each referenced prefab
+    pub prefab_refs: BTreeMap<PrefabUuid, BTreeSet<PrefabUuid>>,
+    pub component_types: BTreeMap<ComponentTypeUuid, ComponentTypeUsage>,
+}
+
+impl UuidReport {
+    /// Referenced prefabs that aren't in the set
+    pub fn unresolved_prefab_refs(&self) -> impl Iterator<Item = &PrefabUuid> {
+        self.prefab_refs
+            .keys()
+            .filter(move |prefab_ref| !self.prefabs.contains(*prefab_ref))
+    }
+
+    /// Entities declared in more than one prefab
+    pub fn duplicate_entities(&self) -> impl Iterator<Item = (&EntityUuid, &BTreeSet<PrefabUuid>)> {
+        self.entities
+            .iter()
+            .filter(|(_, prefabs)| prefabs.len() > 1)
+    }
+
+    /// Component types that are retired or unknown
+    pub fn flagged_component_types(
+        &self
+    ) -> impl Iterator<Item = (&ComponentTypeUuid, &ComponentTypeUsage)> {
+        self.component_types
+            .iter()
+            .filter(|(_, usage)| usage.status != ComponentTypeStatus::Registered)
+    }
+}
+
+/// Collects the prefab, entity and component type UUIDs used by a set of prefabs, and checks each
+/// component type against the registry and the registered migrations. Prefabs are summarized with
+/// `scan_prefab` (or `PrefabScanner` for other formats), so no component data is loaded. Useful
+/// before refactoring component types, and for building asset databases.
+pub fn uuid_report<'a, I: IntoIterator<Item = &'a PrefabSummary>>(
+    summaries: I,
+    registry: &ComponentRegistry,
+) -> UuidReport {
+    let mut report = UuidReport::default();
+    for summary in summaries {
+        report.prefabs.insert(summary.prefab_id);
+        for entity in &summary.entities {
+            report
+                .entities
+                .entry(*entity)
+                .or_default()
+                .insert(summary.prefab_id);
+        }
+        for prefab_ref in &summary.prefab_refs {
+            report
+                .prefab_refs
+                .entry(*prefab_ref)
+                .or_default()
+                .insert(summary.prefab_id);
+        }
+        for component_type in &summary.component_types {
+            report
+                .component_types
+                .entry(*component_type)
+                .or_insert_with(|| component_type_usage(component_type, registry))
+                .prefabs
+                .insert(summary.prefab_id);
+        }
+    }
+
+    report
+}
+
+fn component_type_usage(
+    component_type: &ComponentTypeUuid,
+    registry: &ComponentRegistry,
+) -> ComponentTypeUsage {
+    let (status, type_name) = if let Some(registration) = registry.get(component_type) {
+        (
+            ComponentTypeStatus::Registered,
+            Some(registration.type_name()),
+        )
+    } else if let Some(migration) =
+        crate::iter_component_migrations().find(|migration| migration.from_type() == component_type)
+    {
+        (
+            ComponentTypeStatus::Retired {
+                to_type: *migration.to_type(),
+            },
+            Some(migration.from_type_name()),
+        )
+    } else {
+        (ComponentTypeStatus::Unknown, None)
+    };
+
+    ComponentTypeUsage {
+        status,
+        type_name,
+        prefabs: BTreeSet::new(),
+    }
+}
+
+impl std::fmt::Display for UuidReport {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} prefabs, {} entities, {} component types",
+            self.prefabs.len(),
+            self.entities.len(),
+            self.component_types.len()
+        )?;
+        for (component_type, usage) in &self.component_types {
+            let status = match usage.status {
+                ComponentTypeStatus::Registered => String::new(),
+                ComponentTypeStatus::Retired { to_type } => {
+                    format!(
+                        " (retired, migrates to {})",
+                        uuid::Uuid::from_bytes(to_type)
+                    )
+                }
+                ComponentTypeStatus::Unknown => " (unknown)".to_string(),
+            };
+            writeln!(
+                f,
+                "component type {} {}{}: used by {} prefabs",
+                uuid::Uuid::from_bytes(*component_type),
+                usage.type_name.unwrap_or("?"),
+                status,
+                usage.prefabs.len()
+            )?;
+        }
+        for prefab_ref in self.unresolved_prefab_refs() {
+            writeln!(
+                f,
+                "prefab {} is referenced but not in the set",
+                uuid::Uuid::from_bytes(*prefab_ref)
+            )?;
+        }
+        for (entity, prefabs) in self.duplicate_entities() {
+            writeln!(
+                f,
+                "entity {} is declared in {} prefabs",
+                uuid::Uuid::from_bytes(*entity),
+                prefabs.len()
+            )?;
+        }
+        Ok(())
+    }
+}
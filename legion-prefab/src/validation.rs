@@ -0,0 +1,116 @@
+use crate::format::{ComponentTypeUuid, EntityUuid};
+use crate::{ComponentRegistration, CookedPrefab};
+use legion::world::{Entity, World};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+
+// Validate functions are attached to component registrations with
+// `ComponentRegistration::with_validate_fn` (or `register_component_type!(T, validate = f)`) and
+// run on cooked data, so that bad data is reported by the content pipeline along with the entity
+// and component it was found in.
+
+/// Where a component being validated is. Passed to validate functions.
+pub struct ValidationCtx {
+    entity: EntityUuid,
+    component_type: ComponentTypeUuid,
+    component_type_name: &'static str,
+}
+
+impl ValidationCtx {
+    pub fn entity(&self) -> &EntityUuid {
+        &self.entity
+    }
+
+    pub fn component_type(&self) -> &ComponentTypeUuid {
+        &self.component_type
+    }
+
+    pub fn component_type_name(&self) -> &'static str {
+        self.component_type_name
+    }
+
+    /// An error for the component being validated
+    pub fn error<T: Into<String>>(
+        &self,
+        message: T,
+    ) -> ValidationError {
+        ValidationError {
+            entity: self.entity,
+            component_type: self.component_type,
+            component_type_name: self.component_type_name,
+            message: message.into(),
+        }
+    }
+}
+
+/// A problem found in component data by a validate function, i.e. negative health
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub entity: EntityUuid,
+    pub component_type: ComponentTypeUuid,
+    pub component_type_name: &'static str,
+    pub message: String,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        write!(
+            f,
+            "entity {} {}: {}",
+            uuid::Uuid::from_bytes(self.entity),
+            self.component_type_name,
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// The type erased form of a validate function. Validates every component of the registered type
+/// in the world that belongs to an entity in the map.
+pub(crate) type ValidateWorldFn =
+    dyn Fn(&World, &HashMap<Entity, EntityUuid>, &mut Vec<ValidationError>) + Send + Sync;
+
+pub(crate) fn validate_world_fn<T: legion::storage::Component>(
+    component_type: ComponentTypeUuid,
+    component_type_name: &'static str,
+    validate_fn: fn(&T, &ValidationCtx) -> Vec<ValidationError>,
+) -> Arc<ValidateWorldFn> {
+    Arc::new(move |world, entity_to_uuid, errors| {
+        use legion::IntoQuery;
+        let mut query = <(Entity, legion::Read<T>)>::query();
+        for (entity, component) in query.iter(world) {
+            if let Some(entity_uuid) = entity_to_uuid.get(entity) {
+                let ctx = ValidationCtx {
+                    entity: *entity_uuid,
+                    component_type,
+                    component_type_name,
+                };
+                errors.extend(validate_fn(component, &ctx));
+            }
+        }
+    })
+}
+
+/// Runs the validate functions of the given registrations on every entity in a cooked prefab.
+/// Errors are sorted by entity and component type.
+pub fn validate_cooked_prefab<S: BuildHasher>(
+    cooked_prefab: &CookedPrefab,
+    registered_components_by_uuid: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+) -> Vec<ValidationError> {
+    let mut errors = vec![];
+    for registration in registered_components_by_uuid.values() {
+        registration.validate(
+            &cooked_prefab.world,
+            cooked_prefab.entities.entity_to_uuid(),
+            &mut errors,
+        );
+    }
+
+    errors.sort_by_key(|error| (error.entity, error.component_type));
+    errors
+}
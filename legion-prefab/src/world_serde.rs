@@ -1,17 +1,31 @@
-use crate::format::EntityUuid;
+use crate::format::{ComponentTypeUuid, EntityUuid};
 use crate::registration::ComponentRegistration;
+use crate::component_mask::ComponentMask;
+use crate::UuidEntityBimap;
+use crate::cooked_columns::EncodedColumn;
+use crate::registration::DecodedColumn;
+use crate::world_serialize_mode::{ModeDeserializer, ModeSerializer, WorldSerializeMode};
 use legion::serialize::{EntitySerializer, UnknownType};
 use legion::storage::{ArchetypeIndex, UnknownComponentStorage, UnknownComponentWriter};
 use legion::{
     storage::{ComponentTypeId, EntityLayout},
     *,
 };
+use serde::de::DeserializeSeed;
 use serde::{Deserialize, Deserializer, Serializer};
 use std::{cell::RefCell, collections::HashMap};
 
 pub struct CustomSerializer<'a> {
     pub comp_types: &'a HashMap<ComponentTypeId, ComponentRegistration>,
-    pub entity_map: RefCell<&'a mut HashMap<legion::Entity, EntityUuid>>,
+    pub entity_map: RefCell<&'a mut UuidEntityBimap>,
+    // Component types this returns false for are left out. None writes every registered type
+    pub component_filter: Option<&'a dyn Fn(&ComponentRegistration) -> bool>,
+    // In the packed layout, write each component slice as one length-prefixed block of bincode
+    // so it can be read back with a bulk copy. See `write_cooked_columns`
+    pub columnar: bool,
+    // With columnar, moves each component slice into this table and writes its index instead, so
+    // the slices can be decoded before the world is read
+    pub column_table: Option<&'a RefCell<Vec<EncodedColumn>>>,
 }
 
 impl<'a> legion::serialize::EntitySerializer for CustomSerializer<'a> {
@@ -20,12 +34,7 @@ impl<'a> legion::serialize::EntitySerializer for CustomSerializer<'a> {
         entity: Entity,
         serialize_fn: &mut dyn FnMut(&dyn erased_serde::Serialize),
     ) {
-        let mut entity_map = self.entity_map.borrow_mut();
-
-        let uuid = entity_map
-            .entry(entity)
-            .or_insert_with(|| *uuid::Uuid::new_v4().as_bytes());
-        serialize_fn(&uuid::Uuid::from_bytes(*uuid));
+        serialize_entity_uuid(&self.entity_map.borrow(), entity, serialize_fn);
     }
     fn deserialize(
         &self,
@@ -42,11 +51,20 @@ impl<'a> legion::serialize::WorldSerializer for CustomSerializer<'a> {
         &self,
         type_id: ComponentTypeId,
     ) -> Result<Self::TypeId, legion::serialize::UnknownType> {
-        let uuid = self.comp_types.get(&type_id).map(|x| *x.uuid());
+        let registration = self
+            .comp_types
+            .get(&type_id)
+            .ok_or(legion::serialize::UnknownType::Error)?;
+
+        if registration.is_clone_only() {
+            return Err(legion::serialize::UnknownType::Ignore);
+        }
 
-        match uuid {
-            Some(uuid) => Ok(uuid),
-            None => Err(legion::serialize::UnknownType::Error),
+        match self.component_filter {
+            Some(component_filter) if !component_filter(registration) => {
+                Err(legion::serialize::UnknownType::Ignore)
+            }
+            _ => Ok(*registration.uuid()),
         }
     }
 
@@ -84,6 +102,26 @@ impl<'a> legion::serialize::WorldSerializer for CustomSerializer<'a> {
         serializer: S,
     ) -> Result<S::Ok, S::Error> {
         if let Some(reg) = self.comp_types.get(&ty) {
+            if self.columnar {
+                use serde::ser::Error;
+                let mut column = None;
+                reg.comp_serialize_slice(storage, archetype, &mut |serializable| {
+                    column = Some(encode_column(serializable));
+                });
+                let column = column
+                    .expect("serialize can only be called once")
+                    .map_err(S::Error::custom)?;
+                if let Some(column_table) = self.column_table {
+                    let mut column_table = column_table.borrow_mut();
+                    column_table.push(EncodedColumn {
+                        component_type: *reg.uuid(),
+                        data: crate::format::bytes::ByteBuf(column),
+                    });
+                    return serializer.serialize_u32((column_table.len() - 1) as u32);
+                }
+                return serializer.serialize_bytes(&column);
+            }
+
             let mut serializer = Some(serializer);
             let mut result = None;
             let result_ref = &mut result;
@@ -112,11 +150,158 @@ impl<'a> legion::serialize::WorldSerializer for CustomSerializer<'a> {
     }
 }
 
+/// Serializes every entity in `world` using the given layout. Entity UUIDs are taken from
+/// `entity_map`. Entities without a UUID are given a new one, which is added to `entity_map`.
+/// Fails if a component refers to an entity that is neither in `world` nor in `entity_map`.
+pub fn serialize_world<S: Serializer>(
+    world: &World,
+    entity_map: &mut UuidEntityBimap,
+    mode: WorldSerializeMode,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serialize_world_filtered(
+        world,
+        legion::query::any(),
+        |_| true,
+        entity_map,
+        mode,
+        serializer,
+    )
+}
+
+/// Serializes only the entities of `world` that match `filter` (i.e.
+/// `legion::query::component::<Region>()`) and, of those, only the component types for which
+/// `include_component` returns true, without cloning the world first. The output can be read with
+/// `deserialize_world` using the same mode.
+///
+/// The filter selects whole archetypes, so entities are selected by the components they have.
+/// Entity UUIDs are taken from `entity_map`. Entities of `world` without a UUID are given a new
+/// one, which is added to `entity_map`, so the same map can be passed again to keep UUIDs stable
+/// between saves. Fails if a component refers to an entity that is neither in `world` nor in
+/// `entity_map`.
+pub fn serialize_world_filtered<F, C, S>(
+    world: &World,
+    filter: F,
+    include_component: C,
+    entity_map: &mut UuidEntityBimap,
+    mode: WorldSerializeMode,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    F: legion::query::LayoutFilter,
+    C: Fn(&ComponentRegistration) -> bool,
+    S: Serializer,
+{
+    let registry = crate::registration::global_component_registry();
+    assign_missing_uuids(world, entity_map);
+    let custom_serializer = CustomSerializer {
+        comp_types: registry.by_type_id(),
+        entity_map: RefCell::new(entity_map),
+        component_filter: Some(&include_component),
+        columnar: false,
+        column_table: None,
+    };
+
+    serde::Serialize::serialize(
+        &world.as_serializable(filter, &custom_serializer),
+        ModeSerializer { serializer, mode },
+    )
+}
+
+/// Serializes every entity in `world`, leaving out the component types excluded by `mask`, i.e. to
+/// send a world to a client without its server-only components
+pub fn serialize_world_masked<S: Serializer>(
+    world: &World,
+    mask: &ComponentMask,
+    entity_map: &mut UuidEntityBimap,
+    mode: WorldSerializeMode,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serialize_world_filtered(
+        world,
+        legion::query::any(),
+        |registration| mask.includes_registration(registration),
+        entity_map,
+        mode,
+        serializer,
+    )
+}
+
+/// Deserializes a world written by `serialize_world` or `serialize_world_filtered` with the same
+/// mode. Returns the world along with the entity each UUID was loaded as. Fails if the world has
+/// components of a type that isn't registered.
+///
+/// Entities are allocated in the order their UUIDs first appear in the serialized data, and
+/// archetypes and entities are iterated in the order they were written, so loading the same data
+/// always gives the same entities in the same order. Cooked prefabs are written in UUID order, see
+/// `cook_prefab`.
+pub fn deserialize_world<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    mode: WorldSerializeMode,
+) -> Result<(World, UuidEntityBimap), D::Error> {
+    let (world, entity_map, _) =
+        deserialize_world_with_policy(deserializer, mode, UnknownComponentPolicy::Error)?;
+    Ok((world, entity_map))
+}
+
+/// Like `deserialize_world`, but components of a type that isn't registered are handled according
+/// to `unknown_components`. Also returns the unknown component types that were skipped.
+pub fn deserialize_world_with_policy<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    mode: WorldSerializeMode,
+    unknown_components: UnknownComponentPolicy,
+) -> Result<(World, UuidEntityBimap, Vec<ComponentTypeUuid>), D::Error> {
+    let registry = crate::registration::global_component_registry();
+
+    let mut entity_map = UuidEntityBimap::new();
+    let custom_deserializer = CustomDeserializer {
+        comp_types: registry.by_type_id(),
+        comp_types_uuid: registry.by_uuid(),
+        entity_map: RefCell::new(&mut entity_map),
+        allocator: RefCell::new(legion::world::Allocate::new()),
+        columnar: false,
+        unknown_components,
+        skipped_components: RefCell::default(),
+        decoded_columns: None,
+    };
+
+    let seed = legion::serialize::DeserializeNewWorld(&custom_deserializer);
+    let world = seed.deserialize(ModeDeserializer { deserializer, mode })?;
+    let skipped_components = custom_deserializer.skipped_components.into_inner();
+
+    Ok((world, entity_map, skipped_components))
+}
+
+/// What to do with components of a type that isn't registered when deserializing a world or cooked
+/// prefab, i.e. because the feature that registered it was removed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnknownComponentPolicy {
+    /// Fail the whole load
+    Error,
+    /// Load the entities without the unknown components. The skipped types are returned so they
+    /// can be reported.
+    Skip,
+}
+
+impl Default for UnknownComponentPolicy {
+    fn default() -> Self {
+        UnknownComponentPolicy::Error
+    }
+}
+
 pub struct CustomDeserializer<'a> {
     pub comp_types_uuid: &'a HashMap<type_uuid::Bytes, ComponentRegistration>,
     pub comp_types: &'a HashMap<ComponentTypeId, ComponentRegistration>,
-    pub entity_map: RefCell<&'a mut HashMap<EntityUuid, Entity>>,
+    pub entity_map: RefCell<&'a mut UuidEntityBimap>,
     pub allocator: RefCell<legion::world::Allocate>,
+    // Must match `CustomSerializer::columnar` of the serialized world
+    pub columnar: bool,
+    pub unknown_components: UnknownComponentPolicy,
+    // Unknown component types that were left out, see `UnknownComponentPolicy::Skip`
+    pub skipped_components: RefCell<Vec<type_uuid::Bytes>>,
+    // The component slices of a world written with `CustomSerializer::column_table`, decoded in
+    // advance. Each is taken when the world refers to its index
+    pub decoded_columns: Option<RefCell<Vec<Option<DecodedColumn>>>>,
 }
 
 impl<'a> legion::serialize::EntitySerializer for CustomDeserializer<'a> {
@@ -131,12 +316,155 @@ impl<'a> legion::serialize::EntitySerializer for CustomDeserializer<'a> {
         &self,
         deserializer: &mut dyn erased_serde::Deserializer,
     ) -> Result<Entity, erased_serde::Error> {
-        let entity_uuid = <uuid::Uuid as Deserialize>::deserialize(deserializer)?;
-        let mut entity_map = self.entity_map.borrow_mut();
-        let entity = entity_map
-            .entry(*entity_uuid.as_bytes())
-            .or_insert_with(|| self.allocator.borrow_mut().next().unwrap());
-        Ok(*entity)
+        let uuid = <uuid::Uuid as Deserialize>::deserialize(deserializer)?;
+        Ok(uuid_entity(
+            &mut self.entity_map.borrow_mut(),
+            &mut self.allocator.borrow_mut(),
+            *uuid.as_bytes(),
+        ))
+    }
+}
+
+// Serializes a component slice on its own. Entities inside components still go through the entity
+// serializer of the world being written
+fn encode_column(serializable: &dyn erased_serde::Serialize) -> bincode::Result<Vec<u8>> {
+    let mut column = Vec::new();
+    let mut serializer =
+        bincode::Serializer::new(&mut column, bincode::config::DefaultOptions::new());
+    erased_serde::serialize(serializable, &mut serializer)?;
+    Ok(column)
+}
+
+/// Gives every entity of `world` that doesn't have a UUID yet a new one. The entity serializers
+/// only look UUIDs up, so this is done before serializing a world.
+pub(crate) fn assign_missing_uuids(
+    world: &World,
+    entity_map: &mut UuidEntityBimap,
+) {
+    let mut entities = <Entity>::query();
+    for entity in entities.iter(world) {
+        if !entity_map.contains_entity(entity) {
+            entity_map.insert(*uuid::Uuid::new_v4().as_bytes(), *entity);
+        }
+    }
+}
+
+// Writes an entity as its UUID. An entity without one isn't part of the data being written, and
+// giving it a new UUID would write a reference to an entity that doesn't exist, so this fails
+fn serialize_entity_uuid(
+    entity_map: &UuidEntityBimap,
+    entity: Entity,
+    serialize_fn: &mut dyn FnMut(&dyn erased_serde::Serialize),
+) {
+    match entity_map.uuid(&entity) {
+        Some(uuid) => serialize_fn(&uuid::Uuid::from_bytes(uuid)),
+        None => serialize_fn(&UnknownEntity(entity)),
+    }
+}
+
+struct UnknownEntity(Entity);
+
+impl serde::Serialize for UnknownEntity {
+    fn serialize<S: Serializer>(
+        &self,
+        _serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Err(serde::ser::Error::custom(format!(
+            "component refers to entity {:?}, which has no UUID",
+            self.0
+        )))
+    }
+}
+
+// The entity for a UUID, allocating a new one if the UUID hasn't been seen yet
+fn uuid_entity(
+    entity_map: &mut UuidEntityBimap,
+    allocator: &mut legion::world::Allocate,
+    uuid: EntityUuid,
+) -> Entity {
+    entity_map.entity(&uuid).unwrap_or_else(|| {
+        let entity = allocator.next().unwrap();
+        entity_map.insert(uuid, entity);
+        entity
+    })
+}
+
+/// Maps `Entity` values inside components to the UUIDs in `entity_map` and back, for component
+/// data that is (de)serialized on its own rather than as part of a world (i.e. by the prefab
+/// format). Like the world (de)serializers, serializing an entity that isn't in `entity_map` fails,
+/// and unknown UUIDs are given a new entity from `allocator`, which is added to `entity_map`.
+pub(crate) struct EntityUuidMapper<'a> {
+    pub entity_map: RefCell<&'a mut UuidEntityBimap>,
+    pub allocator: RefCell<&'a mut legion::world::Allocate>,
+}
+
+impl<'a> EntityUuidMapper<'a> {
+    /// Runs `f` with entities being (de)serialized through this mapper
+    pub fn scope<R, F: FnOnce() -> R>(
+        &self,
+        f: F,
+    ) -> R {
+        let mut result = None;
+        legion::serialize::set_entity_serializer(self, || result = Some(f()));
+        result.expect("entity serializer scope did not run")
+    }
+}
+
+impl<'a> legion::serialize::EntitySerializer for EntityUuidMapper<'a> {
+    fn serialize(
+        &self,
+        entity: Entity,
+        serialize_fn: &mut dyn FnMut(&dyn erased_serde::Serialize),
+    ) {
+        serialize_entity_uuid(&self.entity_map.borrow(), entity, serialize_fn);
+    }
+    fn deserialize(
+        &self,
+        deserializer: &mut dyn erased_serde::Deserializer,
+    ) -> Result<Entity, erased_serde::Error> {
+        let uuid = <uuid::Uuid as Deserialize>::deserialize(deserializer)?;
+        Ok(uuid_entity(
+            &mut self.entity_map.borrow_mut(),
+            &mut self.allocator.borrow_mut(),
+            *uuid.as_bytes(),
+        ))
+    }
+}
+
+/// Like `EntityUuidMapper`, but can be shared by threads that deserialize component data at the
+/// same time. New entities are allocated in the order the threads happen to get to them.
+pub(crate) struct SharedEntityUuidMapper<'a> {
+    pub state: std::sync::Mutex<(&'a mut UuidEntityBimap, &'a mut legion::world::Allocate)>,
+}
+
+impl<'a> SharedEntityUuidMapper<'a> {
+    /// Runs `f` with entities being deserialized through this mapper on the current thread
+    pub fn scope<R, F: FnOnce() -> R>(
+        &self,
+        f: F,
+    ) -> R {
+        let mut result = None;
+        legion::serialize::set_entity_serializer(self, || result = Some(f()));
+        result.expect("entity serializer scope did not run")
+    }
+}
+
+impl<'a> legion::serialize::EntitySerializer for SharedEntityUuidMapper<'a> {
+    fn serialize(
+        &self,
+        _entity: Entity,
+        _serialize_fn: &mut dyn FnMut(&dyn erased_serde::Serialize),
+    ) {
+        panic!("SharedEntityUuidMapper can only be used to deserialize")
+    }
+    fn deserialize(
+        &self,
+        deserializer: &mut dyn erased_serde::Deserializer,
+    ) -> Result<Entity, erased_serde::Error> {
+        let uuid = <uuid::Uuid as Deserialize>::deserialize(deserializer)?;
+        let mut state = self.state.lock().unwrap();
+        let (entity_map, allocator) = &mut *state;
+        Ok(uuid_entity(entity_map, allocator, *uuid.as_bytes()))
     }
 }
 
@@ -152,9 +480,16 @@ impl<'r> legion::serialize::WorldDeserializer for CustomDeserializer<'r> {
             .get(type_id)
             .map(|x| x.component_type_id());
 
-        match uuid {
-            Some(component_type_id) => Ok(component_type_id),
-            None => Err(legion::serialize::UnknownType::Error),
+        match (uuid, self.unknown_components) {
+            (Some(component_type_id), _) => Ok(component_type_id),
+            (None, UnknownComponentPolicy::Error) => Err(legion::serialize::UnknownType::Error),
+            (None, UnknownComponentPolicy::Skip) => {
+                let mut skipped_components = self.skipped_components.borrow_mut();
+                if !skipped_components.contains(type_id) {
+                    skipped_components.push(*type_id);
+                }
+                Err(legion::serialize::UnknownType::Ignore)
+            }
         }
     }
 
@@ -194,6 +529,35 @@ impl<'r> legion::serialize::WorldDeserializer for CustomDeserializer<'r> {
     ) -> Result<(), D::Error> {
         if let Some(reg) = self.comp_types.get(&type_id) {
             use serde::de::Error;
+            if let Some(decoded_columns) = &self.decoded_columns {
+                let index = u32::deserialize(deserializer)? as usize;
+                let column = decoded_columns
+                    .borrow_mut()
+                    .get_mut(index)
+                    .and_then(Option::take)
+                    .ok_or_else(|| D::Error::custom(format!("missing column {}", index)))?;
+                if column.component_type_id() != type_id {
+                    return Err(D::Error::custom(format!(
+                        "column {} has a different component type",
+                        index
+                    )));
+                }
+                column.write(writer);
+                return Ok(());
+            }
+
+            if self.columnar {
+                let column = crate::format::bytes::ByteBuf::deserialize(deserializer)?;
+                let mut column_deserializer = bincode::Deserializer::from_slice(
+                    &column.0,
+                    bincode::config::DefaultOptions::new(),
+                );
+                let mut deserializer = erased_serde::Deserializer::erase(&mut column_deserializer);
+                return reg
+                    .comp_deserialize_slice(writer, &mut deserializer)
+                    .map_err(D::Error::custom);
+            }
+
             let mut deserializer = erased_serde::Deserializer::erase(deserializer);
             reg.comp_deserialize_slice(writer, &mut deserializer)
                 .map_err(D::Error::custom)
@@ -0,0 +1,252 @@
+use serde::de::Visitor;
+use serde::{Deserializer, Serialize, Serializer};
+
+/// How the components of a world are laid out when it is serialized. legion picks one based on
+/// `is_human_readable()` of the format, which is what `Prefab` and `CookedPrefab` do. The world
+/// serialization functions take a mode so it can be chosen explicitly. The same mode must be used
+/// to deserialize the world again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorldSerializeMode {
+    /// Each entity is written with all of its components. Easier to read and diff, and used by
+    /// default for text formats
+    HumanReadable,
+    /// Components are written per archetype, a slice of each component type at a time. Smaller
+    /// and faster, and used by default for binary formats
+    Packed,
+}
+
+impl WorldSerializeMode {
+    /// The mode legion would use for a format
+    pub fn for_format(is_human_readable: bool) -> Self {
+        if is_human_readable {
+            WorldSerializeMode::HumanReadable
+        } else {
+            WorldSerializeMode::Packed
+        }
+    }
+
+    pub fn is_human_readable(self) -> bool {
+        self == WorldSerializeMode::HumanReadable
+    }
+}
+
+// Forwards everything to the wrapped serializer, except that it reports whether it is human
+// readable based on the mode. legion only checks this at the top level of a world
+pub(crate) struct ModeSerializer<S> {
+    pub serializer: S,
+    pub mode: WorldSerializeMode,
+}
+
+macro_rules! forward_serialize {
+    ($($method:ident($ty:ty)),*) => {
+        $(
+            fn $method(
+                self,
+                v: $ty,
+            ) -> Result<S::Ok, S::Error> {
+                self.serializer.$method(v)
+            }
+        )*
+    };
+}
+
+impl<S: Serializer> Serializer for ModeSerializer<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = S::SerializeSeq;
+    type SerializeTuple = S::SerializeTuple;
+    type SerializeTupleStruct = S::SerializeTupleStruct;
+    type SerializeTupleVariant = S::SerializeTupleVariant;
+    type SerializeMap = S::SerializeMap;
+    type SerializeStruct = S::SerializeStruct;
+    type SerializeStructVariant = S::SerializeStructVariant;
+
+    forward_serialize!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_unit_struct(&'static str)
+    );
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.serializer.serialize_none()
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(
+        self,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        self.serializer.serialize_some(value)
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.serializer.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<S::Ok, S::Error> {
+        self.serializer
+            .serialize_unit_variant(name, variant_index, variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        self.serializer.serialize_newtype_struct(name, value)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        self.serializer
+            .serialize_newtype_variant(name, variant_index, variant, value)
+    }
+
+    fn serialize_seq(
+        self,
+        len: Option<usize>,
+    ) -> Result<S::SerializeSeq, S::Error> {
+        self.serializer.serialize_seq(len)
+    }
+
+    fn serialize_tuple(
+        self,
+        len: usize,
+    ) -> Result<S::SerializeTuple, S::Error> {
+        self.serializer.serialize_tuple(len)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<S::SerializeTupleStruct, S::Error> {
+        self.serializer.serialize_tuple_struct(name, len)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<S::SerializeTupleVariant, S::Error> {
+        self.serializer
+            .serialize_tuple_variant(name, variant_index, variant, len)
+    }
+
+    fn serialize_map(
+        self,
+        len: Option<usize>,
+    ) -> Result<S::SerializeMap, S::Error> {
+        self.serializer.serialize_map(len)
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<S::SerializeStruct, S::Error> {
+        self.serializer.serialize_struct(name, len)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<S::SerializeStructVariant, S::Error> {
+        self.serializer
+            .serialize_struct_variant(name, variant_index, variant, len)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.mode.is_human_readable()
+    }
+}
+
+// The deserializing counterpart of ModeSerializer
+pub(crate) struct ModeDeserializer<D> {
+    pub deserializer: D,
+    pub mode: WorldSerializeMode,
+}
+
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*)),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(
+                self,
+                $($arg: $ty,)*
+                visitor: V,
+            ) -> Result<V::Value, D::Error> {
+                self.deserializer.$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for ModeDeserializer<D> {
+    type Error = D::Error;
+
+    forward_deserialize!(
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_i64(),
+        deserialize_i128(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_u64(),
+        deserialize_u128(),
+        deserialize_f32(),
+        deserialize_f64(),
+        deserialize_char(),
+        deserialize_str(),
+        deserialize_string(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_option(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_map(),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+        deserialize_identifier(),
+        deserialize_ignored_any()
+    );
+
+    fn is_human_readable(&self) -> bool {
+        self.mode.is_human_readable()
+    }
+}
@@ -0,0 +1,34 @@
+//! Helpers for loading and saving legion prefabs as YAML
+use crate::{
+    CookedPrefab, Prefab, PrefabFormatDeserializer, PrefabFormatSerializer, PrefabSerdeContext,
+};
+use std::hash::BuildHasher;
+
+/// Loads a prefab from source data in YAML prefab format
+pub fn prefab_from_str<T: BuildHasher>(
+    yaml: &str,
+    context: PrefabSerdeContext<T>,
+) -> Result<Prefab, serde_yaml::Error> {
+    let prefab_deser = PrefabFormatDeserializer::new(context);
+    prefab_format::yaml::from_str(yaml, &prefab_deser)?;
+    Ok(prefab_deser.prefab())
+}
+
+/// Saves a prefab as source data in YAML prefab format
+pub fn prefab_to_string<T: BuildHasher>(
+    prefab: &Prefab,
+    context: PrefabSerdeContext<T>,
+) -> Result<String, serde_yaml::Error> {
+    let prefab_ser = PrefabFormatSerializer::new(context, prefab);
+    prefab_format::yaml::to_string(&prefab_ser, prefab.prefab_id())
+}
+
+/// Loads a cooked prefab from YAML
+pub fn cooked_prefab_from_str(yaml: &str) -> Result<CookedPrefab, serde_yaml::Error> {
+    serde_yaml::from_str(yaml)
+}
+
+/// Saves a cooked prefab as YAML
+pub fn cooked_prefab_to_string(cooked_prefab: &CookedPrefab) -> Result<String, serde_yaml::Error> {
+    serde_yaml::to_string(cooked_prefab)
+}
@@ -0,0 +1,29 @@
+#![cfg(feature = "cbor")]
+
+mod common;
+
+use legion_prefab::cbor;
+
+#[test]
+fn prefab_meta_round_trips() {
+    common::assert_prefab_meta_round_trips(
+        |meta| serde_cbor::to_vec(meta).unwrap(),
+        |bytes| serde_cbor::from_slice(bytes).unwrap(),
+    );
+}
+
+#[test]
+fn prefab_round_trips() {
+    common::assert_prefab_round_trips(
+        |prefab, context| cbor::prefab_to_vec(prefab, context).unwrap(),
+        |bytes, context| cbor::prefab_from_slice(bytes, context).unwrap(),
+    );
+}
+
+#[test]
+fn cooked_prefab_round_trips() {
+    common::assert_cooked_prefab_round_trips(
+        |cooked_prefab| cbor::cooked_prefab_to_vec(cooked_prefab).unwrap(),
+        |bytes| cbor::cooked_prefab_from_slice(bytes).unwrap(),
+    );
+}
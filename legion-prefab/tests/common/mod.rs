@@ -0,0 +1,214 @@
+// Prefabs shared by the format round-trip tests, and the round trips themselves. Each format's
+// tests pass in how to save and load with that format.
+use legion::{EntityStore, World};
+use legion_prefab::{
+    canonical_pretty_config, global_component_registry, prefab_component, ComponentOverride,
+    CookedPrefab, Prefab, PrefabFormatDeserializer, PrefabFormatSerializer, PrefabMeta, PrefabRef,
+    PrefabSerdeContext,
+};
+use prefab_format::{EntityUuid, PrefabParameter, PrefabRefTransform, PrefabUuid};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+
+#[prefab_component(uuid = "2f3c8d3e-5a0b-4c64-9d6a-0a5e6f1b7c01")]
+#[derive(Debug, PartialEq)]
+pub struct Position {
+    pub x: f32,
+    pub y: f32,
+}
+
+const PREFAB: PrefabUuid = [0x10; 16];
+const BASE_PREFAB: PrefabUuid = [0x20; 16];
+const ENTITY_A: EntityUuid = [0x01; 16];
+const ENTITY_B: EntityUuid = [0x02; 16];
+
+pub fn prefab_meta() -> PrefabMeta {
+    let mut overrides = HashMap::new();
+    overrides.insert(
+        ENTITY_A,
+        vec![ComponentOverride {
+            component_type: <Position as type_uuid::TypeUuid>::UUID,
+            data: "[Enter(Field(\"x\")),Value(3.0)]".to_string(),
+        }],
+    );
+    let mut parameter_values = BTreeMap::new();
+    parameter_values.insert("speed".to_string(), "2.5".to_string());
+    let mut prefab_refs = HashMap::new();
+    prefab_refs.insert(
+        BASE_PREFAB,
+        PrefabRef {
+            overrides,
+            parameter_values,
+            transform: PrefabRefTransform {
+                position: [1.0, 2.0, 3.0],
+                ..PrefabRefTransform::IDENTITY
+            },
+        },
+    );
+
+    let mut entity_layers = HashMap::new();
+    entity_layers.insert(ENTITY_B, "lighting".to_string());
+    let mut hierarchy = HashMap::new();
+    hierarchy.insert(ENTITY_A, vec![ENTITY_B]);
+
+    PrefabMeta {
+        id: PREFAB,
+        prefab_refs,
+        parameters: vec![PrefabParameter {
+            name: "speed".to_string(),
+            type_name: "f32".to_string(),
+            default: "1.0".to_string(),
+            bindings: vec![],
+        }],
+        entity_layers,
+        hierarchy,
+        blobs: HashMap::new(),
+        extends: Some(BASE_PREFAB),
+        entities: HashMap::new(),
+        entity_refs: HashMap::new(),
+    }
+}
+
+// PrefabMeta isn't PartialEq, its RON is compared instead. Maps keyed by UUID are written in key
+// order, so equal metadata gives equal RON.
+fn assert_prefab_meta_eq(
+    actual: &PrefabMeta,
+    expected: &PrefabMeta,
+) {
+    assert_eq!(
+        ron::ser::to_string(actual).unwrap(),
+        ron::ser::to_string(expected).unwrap()
+    );
+}
+
+// Entities with component data, and a prefab ref that overrides an entity of the referenced
+// prefab
+const PREFAB_SOURCE: &str = r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [
+        Entity(PrefabEntity(
+            id: "01010101-0101-0101-0101-010101010101",
+            components: [
+                EntityComponent(
+                    type: "2f3c8d3e-5a0b-4c64-9d6a-0a5e6f1b7c01",
+                    data: (x: 1.0, y: 2.0),
+                ),
+            ],
+        )),
+        Entity(PrefabEntity(
+            id: "02020202-0202-0202-0202-020202020202",
+            components: [
+                EntityComponent(
+                    type: "2f3c8d3e-5a0b-4c64-9d6a-0a5e6f1b7c01",
+                    data: (x: -3.5, y: 0.25),
+                ),
+            ],
+        )),
+        PrefabRef(PrefabRef(
+            prefab_id: "20202020-2020-2020-2020-202020202020",
+            parameter_values: {
+                "speed": "2.5",
+            },
+            transform: (
+                position: (1.0, 2.0, 3.0),
+            ),
+            entity_overrides: [
+                (
+                    entity_id: "03030303-0303-0303-0303-030303030303",
+                    component_overrides: [
+                        ComponentOverride(
+                            component_type: "2f3c8d3e-5a0b-4c64-9d6a-0a5e6f1b7c01",
+                            diff: "[Enter(Field(\"x\")),Value(3.0)]",
+                        ),
+                    ],
+                ),
+            ],
+        )),
+    ],
+)"#;
+
+fn prefab() -> Prefab {
+    let prefab_deser = PrefabFormatDeserializer::new(global_component_registry().serde_context());
+    let mut deserializer = ron::de::Deserializer::from_str(PREFAB_SOURCE).unwrap();
+    prefab_format::deserialize(&mut deserializer, &prefab_deser).unwrap();
+    prefab_deser.prefab()
+}
+
+// Prefab isn't PartialEq either. Prefabs are saved in UUID order, so equal prefabs save to equal
+// RON.
+fn prefab_ron(prefab: &Prefab) -> String {
+    let prefab_ser =
+        PrefabFormatSerializer::new(global_component_registry().serde_context(), prefab);
+    let mut ron_ser = ron::ser::Serializer::new(Some(canonical_pretty_config()), true);
+    prefab_format::serialize(&mut ron_ser, &prefab_ser, prefab.prefab_id()).unwrap();
+    ron_ser.into_output_string()
+}
+
+fn assert_prefab_eq(
+    actual: &Prefab,
+    expected: &Prefab,
+) {
+    assert_eq!(prefab_ron(actual), prefab_ron(expected));
+}
+
+fn cooked_prefab() -> CookedPrefab {
+    let mut world = World::default();
+    let entity_a = world.push((Position { x: 1.0, y: 2.0 },));
+    let entity_b = world.push((Position { x: -3.5, y: 0.25 },));
+    CookedPrefab {
+        world,
+        entities: vec![(ENTITY_A, entity_a), (ENTITY_B, entity_b)]
+            .into_iter()
+            .collect(),
+        parameters: vec![],
+        resources: Default::default(),
+        blobs: HashMap::new(),
+    }
+}
+
+fn positions(cooked_prefab: &CookedPrefab) -> Vec<(EntityUuid, Position)> {
+    let mut positions: Vec<_> = cooked_prefab
+        .entities
+        .uuid_to_entity()
+        .iter()
+        .map(|(uuid, entity)| {
+            let entry = cooked_prefab.world.entry_ref(*entity).unwrap();
+            (*uuid, entry.get_component::<Position>().unwrap().clone())
+        })
+        .collect();
+    positions.sort_by_key(|(uuid, _)| *uuid);
+    positions
+}
+
+fn assert_cooked_prefab_eq(
+    actual: &CookedPrefab,
+    expected: &CookedPrefab,
+) {
+    assert_eq!(positions(actual), positions(expected));
+    assert_eq!(actual.parameters, expected.parameters);
+}
+
+pub fn assert_prefab_meta_round_trips<D>(
+    save: impl Fn(&PrefabMeta) -> D,
+    load: impl Fn(&D) -> PrefabMeta,
+) {
+    let meta = prefab_meta();
+    assert_prefab_meta_eq(&load(&save(&meta)), &meta);
+}
+
+pub fn assert_prefab_round_trips<D>(
+    save: impl Fn(&Prefab, PrefabSerdeContext<RandomState>) -> D,
+    load: impl Fn(&D, PrefabSerdeContext<RandomState>) -> Prefab,
+) {
+    let context = global_component_registry().serde_context();
+    let prefab = prefab();
+    assert_prefab_eq(&load(&save(&prefab, context), context), &prefab);
+}
+
+pub fn assert_cooked_prefab_round_trips<D>(
+    save: impl Fn(&CookedPrefab) -> D,
+    load: impl Fn(&D) -> CookedPrefab,
+) {
+    let cooked_prefab = cooked_prefab();
+    assert_cooked_prefab_eq(&load(&save(&cooked_prefab)), &cooked_prefab);
+}
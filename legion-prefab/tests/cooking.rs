@@ -0,0 +1,174 @@
+// Merging prefabs and applying the overrides of prefab refs while cooking
+use legion::{Entity, EntityStore, IntoQuery, Read};
+use legion_prefab::{
+    cook_prefab, cook_prefab_validated, global_component_registry, prefab_component, Prefab,
+    PrefabFormatDeserializer,
+};
+use prefab_format::{EntityUuid, PrefabUuid};
+use std::collections::HashMap;
+
+#[prefab_component(uuid = "5b2e9f14-7c3a-4d8e-b061-2f4a9c7e1d35")]
+#[derive(Debug, PartialEq)]
+pub struct Position {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[prefab_component(uuid = "c84a1e6d-0f52-4b9b-8e27-6d3f1a5c9b48")]
+#[derive(Debug, PartialEq)]
+pub struct Velocity {
+    pub x: f32,
+    pub y: f32,
+}
+
+const ROOT_PREFAB: PrefabUuid = [0x10; 16];
+const BASE_PREFAB: PrefabUuid = [0x20; 16];
+const UNSORTED_PREFAB: PrefabUuid = [0x30; 16];
+const ENTITY: EntityUuid = [0x03; 16];
+
+const BASE_SOURCE: &str = r#"Prefab(
+    id: "20202020-2020-2020-2020-202020202020",
+    objects: [
+        Entity(PrefabEntity(
+            id: "03030303-0303-0303-0303-030303030303",
+            components: [
+                EntityComponent(
+                    type: "5b2e9f14-7c3a-4d8e-b061-2f4a9c7e1d35",
+                    data: (x: 1.0, y: 2.0),
+                ),
+            ],
+        )),
+    ],
+)"#;
+
+// Overrides the position of the base prefab's entity, and a velocity it doesn't have
+const ROOT_SOURCE: &str = r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [
+        PrefabRef(PrefabRef(
+            prefab_id: "20202020-2020-2020-2020-202020202020",
+            entity_overrides: [
+                (
+                    entity_id: "03030303-0303-0303-0303-030303030303",
+                    component_overrides: [
+                        ComponentOverride(
+                            component_type: "5b2e9f14-7c3a-4d8e-b061-2f4a9c7e1d35",
+                            diff: "[Enter(Field(\"x\")),Value(3.0)]",
+                        ),
+                        ComponentOverride(
+                            component_type: "c84a1e6d-0f52-4b9b-8e27-6d3f1a5c9b48",
+                            diff: "[Enter(Field(\"y\")),Value(4.0)]",
+                        ),
+                    ],
+                ),
+            ],
+        )),
+    ],
+)"#;
+
+// Entities that aren't in UUID order, with the same components as the base prefab's entity
+const UNSORTED_SOURCE: &str = r#"Prefab(
+    id: "30303030-3030-3030-3030-303030303030",
+    objects: [
+        Entity(PrefabEntity(
+            id: "04040404-0404-0404-0404-040404040404",
+            components: [
+                EntityComponent(
+                    type: "5b2e9f14-7c3a-4d8e-b061-2f4a9c7e1d35",
+                    data: (x: 4.0, y: 4.0),
+                ),
+            ],
+        )),
+        Entity(PrefabEntity(
+            id: "02020202-0202-0202-0202-020202020202",
+            components: [
+                EntityComponent(
+                    type: "5b2e9f14-7c3a-4d8e-b061-2f4a9c7e1d35",
+                    data: (x: 2.0, y: 2.0),
+                ),
+            ],
+        )),
+    ],
+)"#;
+
+fn load(source: &str) -> Prefab {
+    let prefab_deser = PrefabFormatDeserializer::new(global_component_registry().serde_context());
+    let mut deserializer = ron::de::Deserializer::from_str(source).unwrap();
+    prefab_format::deserialize(&mut deserializer, &prefab_deser).unwrap();
+    prefab_deser.prefab()
+}
+
+#[test]
+fn overrides_apply_to_the_overridden_entities() {
+    let root = load(ROOT_SOURCE);
+    let base = load(BASE_SOURCE);
+    let mut prefab_lookup = HashMap::new();
+    prefab_lookup.insert(ROOT_PREFAB, &root);
+    prefab_lookup.insert(BASE_PREFAB, &base);
+
+    let registry = global_component_registry();
+    let cooked_prefab = cook_prefab(
+        registry.by_type_id(),
+        registry.by_uuid(),
+        &[BASE_PREFAB, ROOT_PREFAB],
+        &prefab_lookup,
+    )
+    .unwrap();
+
+    let entity = cooked_prefab.entities.entity(&ENTITY).unwrap();
+    let entry = cooked_prefab.world.entry_ref(entity).unwrap();
+    assert_eq!(
+        entry.get_component::<Position>().unwrap(),
+        &Position { x: 3.0, y: 2.0 }
+    );
+    assert!(entry.get_component::<Velocity>().is_err());
+}
+
+#[test]
+fn overrides_of_missing_components_are_reported() {
+    let root = load(ROOT_SOURCE);
+    let base = load(BASE_SOURCE);
+    let mut prefab_lookup = HashMap::new();
+    prefab_lookup.insert(ROOT_PREFAB, &root);
+    prefab_lookup.insert(BASE_PREFAB, &base);
+
+    let registry = global_component_registry();
+    let errors = cook_prefab_validated(
+        registry.by_type_id(),
+        registry.by_uuid(),
+        &[BASE_PREFAB, ROOT_PREFAB],
+        &prefab_lookup,
+    )
+    .err()
+    .unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].entity, ENTITY);
+    assert_eq!(
+        errors[0].component_type,
+        <Velocity as type_uuid::TypeUuid>::UUID
+    );
+}
+
+#[test]
+fn cooked_entities_are_in_uuid_order() {
+    let unsorted = load(UNSORTED_SOURCE);
+    let base = load(BASE_SOURCE);
+    let mut prefab_lookup = HashMap::new();
+    prefab_lookup.insert(UNSORTED_PREFAB, &unsorted);
+    prefab_lookup.insert(BASE_PREFAB, &base);
+
+    let registry = global_component_registry();
+    let cooked_prefab = cook_prefab(
+        registry.by_type_id(),
+        registry.by_uuid(),
+        &[BASE_PREFAB, UNSORTED_PREFAB],
+        &prefab_lookup,
+    )
+    .unwrap();
+
+    let entity_uuids: Vec<_> = <(Entity, Read<Position>)>::query()
+        .iter(&cooked_prefab.world)
+        .map(|(entity, _)| cooked_prefab.entities.uuid(entity).unwrap())
+        .collect();
+    assert_eq!(entity_uuids, vec![[0x02; 16], ENTITY, [0x04; 16]]);
+}
@@ -0,0 +1,161 @@
+// Entity fields of components that refer to entities of other prefabs
+use legion::{Entity, EntityStore, World};
+use legion_prefab::{
+    cook_prefab, cook_prefab_validated, global_component_registry, prefab_component, Prefab,
+    PrefabFormatDeserializer, PrefabFormatSerializer, UuidEntityBimap, WorldSerializeMode,
+};
+use prefab_format::{EntityUuid, PrefabUuid};
+use std::collections::HashMap;
+
+#[prefab_component(uuid = "6d1f0c2a-8e4b-4f37-a1c9-3b5e7d2f9a10")]
+#[derive(Debug, PartialEq)]
+pub struct Target {
+    #[serde_diff(opaque)]
+    pub entity: Option<Entity>,
+}
+
+const ROOT_PREFAB: PrefabUuid = [0x10; 16];
+const TARGET_PREFAB: PrefabUuid = [0x20; 16];
+const ROOT_ENTITY: EntityUuid = [0x01; 16];
+const TARGET_ENTITY: EntityUuid = [0x02; 16];
+
+const TARGET_SOURCE: &str = r#"Prefab(
+    id: "20202020-2020-2020-2020-202020202020",
+    objects: [
+        Entity(PrefabEntity(
+            id: "02020202-0202-0202-0202-020202020202",
+            components: [
+                EntityComponent(
+                    type: "6d1f0c2a-8e4b-4f37-a1c9-3b5e7d2f9a10",
+                    data: (entity: None),
+                ),
+            ],
+        )),
+    ],
+)"#;
+
+// The root entity points at the entity of the referenced prefab
+const ROOT_SOURCE: &str = r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [
+        Entity(PrefabEntity(
+            id: "01010101-0101-0101-0101-010101010101",
+            components: [
+                EntityComponent(
+                    type: "6d1f0c2a-8e4b-4f37-a1c9-3b5e7d2f9a10",
+                    data: (entity: Some("02020202-0202-0202-0202-020202020202")),
+                ),
+            ],
+        )),
+        PrefabRef(PrefabRef(
+            prefab_id: "20202020-2020-2020-2020-202020202020",
+            entity_overrides: [],
+        )),
+    ],
+)"#;
+
+fn load(source: &str) -> Prefab {
+    let prefab_deser = PrefabFormatDeserializer::new(global_component_registry().serde_context());
+    let mut deserializer = ron::de::Deserializer::from_str(source).unwrap();
+    prefab_format::deserialize(&mut deserializer, &prefab_deser).unwrap();
+    prefab_deser.prefab()
+}
+
+fn target(
+    world: &World,
+    entity: Entity,
+) -> Option<Entity> {
+    world
+        .entry_ref(entity)
+        .unwrap()
+        .get_component::<Target>()
+        .unwrap()
+        .entity
+}
+
+#[test]
+fn references_to_other_prefabs_are_kept_when_loading() {
+    let root = load(ROOT_SOURCE);
+    assert!(!root.prefab_meta.entities.contains_key(&TARGET_ENTITY));
+    let held_entity = root.prefab_meta.entity_refs[&TARGET_ENTITY];
+    let root_entity = root.prefab_meta.entities[&ROOT_ENTITY];
+    assert_eq!(target(&root.world, root_entity), Some(held_entity));
+
+    let prefab_ser =
+        PrefabFormatSerializer::new(global_component_registry().serde_context(), &root);
+    let mut ron_ser = ron::ser::Serializer::new(None, true);
+    prefab_format::serialize(&mut ron_ser, &prefab_ser, root.prefab_id()).unwrap();
+    let saved = ron_ser.into_output_string();
+    assert!(saved.contains("02020202-0202-0202-0202-020202020202"));
+
+    let reloaded = load(&saved);
+    assert!(reloaded
+        .prefab_meta
+        .entity_refs
+        .contains_key(&TARGET_ENTITY));
+}
+
+#[test]
+fn cooking_points_references_at_cooked_entities() {
+    let root = load(ROOT_SOURCE);
+    let target_prefab = load(TARGET_SOURCE);
+    let mut prefab_lookup = HashMap::new();
+    prefab_lookup.insert(ROOT_PREFAB, &root);
+    prefab_lookup.insert(TARGET_PREFAB, &target_prefab);
+
+    let registry = global_component_registry();
+    let cooked_prefab = cook_prefab(
+        registry.by_type_id(),
+        registry.by_uuid(),
+        &[TARGET_PREFAB, ROOT_PREFAB],
+        &prefab_lookup,
+    )
+    .unwrap();
+
+    let cooked_root = cooked_prefab.entities.entity(&ROOT_ENTITY).unwrap();
+    let cooked_target = cooked_prefab.entities.entity(&TARGET_ENTITY).unwrap();
+    assert_eq!(
+        target(&cooked_prefab.world, cooked_root),
+        Some(cooked_target)
+    );
+}
+
+#[test]
+fn references_to_entities_that_arent_cooked_are_reported() {
+    let mut root = load(ROOT_SOURCE);
+    root.prefab_meta.prefab_refs.clear();
+    let mut prefab_lookup = HashMap::new();
+    prefab_lookup.insert(ROOT_PREFAB, &root);
+
+    let registry = global_component_registry();
+    let errors = cook_prefab_validated(
+        registry.by_type_id(),
+        registry.by_uuid(),
+        &[ROOT_PREFAB],
+        &prefab_lookup,
+    )
+    .err()
+    .unwrap();
+    assert!(errors.iter().any(|error| error.entity == TARGET_ENTITY));
+}
+
+#[test]
+fn entities_without_a_uuid_fail_to_serialize() {
+    let mut other_world = World::default();
+    let elsewhere = other_world.push(());
+
+    let mut world = World::default();
+    world.push((Target {
+        entity: Some(elsewhere),
+    },));
+
+    let mut entity_map = UuidEntityBimap::new();
+    let mut ron_ser = ron::ser::Serializer::new(None, true);
+    let result = legion_prefab::serialize_world(
+        &world,
+        &mut entity_map,
+        WorldSerializeMode::HumanReadable,
+        &mut ron_ser,
+    );
+    assert!(result.is_err());
+}
@@ -0,0 +1,37 @@
+#![cfg(feature = "json")]
+
+mod common;
+
+use legion_prefab::json;
+
+#[test]
+fn prefab_meta_round_trips() {
+    common::assert_prefab_meta_round_trips(
+        |meta| serde_json::to_string(meta).unwrap(),
+        |json| serde_json::from_str(json).unwrap(),
+    );
+}
+
+#[test]
+fn prefab_meta_writes_uuids_as_strings() {
+    let json = serde_json::to_value(&common::prefab_meta()).unwrap();
+    assert_eq!(json["id"], "10101010-1010-1010-1010-101010101010");
+    assert_eq!(json["extends"], "20202020-2020-2020-2020-202020202020");
+    assert!(json["prefab_refs"]["20202020-2020-2020-2020-202020202020"].is_object());
+}
+
+#[test]
+fn prefab_round_trips() {
+    common::assert_prefab_round_trips(
+        |prefab, context| json::prefab_to_string(prefab, context).unwrap(),
+        |json, context| json::prefab_from_str(json, context).unwrap(),
+    );
+}
+
+#[test]
+fn cooked_prefab_round_trips() {
+    common::assert_cooked_prefab_round_trips(
+        |cooked_prefab| json::cooked_prefab_to_string(cooked_prefab).unwrap(),
+        |json| json::cooked_prefab_from_str(json).unwrap(),
+    );
+}
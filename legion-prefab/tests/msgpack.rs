@@ -0,0 +1,29 @@
+#![cfg(feature = "msgpack")]
+
+mod common;
+
+use legion_prefab::msgpack;
+
+#[test]
+fn prefab_meta_round_trips() {
+    common::assert_prefab_meta_round_trips(
+        |meta| rmp_serde::to_vec_named(meta).unwrap(),
+        |bytes| rmp_serde::from_read_ref(bytes).unwrap(),
+    );
+}
+
+#[test]
+fn prefab_round_trips() {
+    common::assert_prefab_round_trips(
+        |prefab, context| msgpack::prefab_to_vec(prefab, context).unwrap(),
+        |bytes, context| msgpack::prefab_from_slice(bytes, context).unwrap(),
+    );
+}
+
+#[test]
+fn cooked_prefab_round_trips() {
+    common::assert_cooked_prefab_round_trips(
+        |cooked_prefab| msgpack::cooked_prefab_to_vec(cooked_prefab).unwrap(),
+        |bytes| msgpack::cooked_prefab_from_slice(bytes).unwrap(),
+    );
+}
@@ -0,0 +1,111 @@
+// Differences in the prefab metadata that isn't entities or overrides
+use legion_prefab::{
+    diff_prefabs, global_component_registry, ExtendsChange, HierarchyChange, LayerChange, Prefab,
+    PrefabDiffReport, PrefabFormatDeserializer,
+};
+use prefab_format::blobs::BlobData;
+use prefab_format::{EntityUuid, PrefabParameter, PrefabRefTransform, PrefabUuid};
+
+const OTHER_PREFAB: PrefabUuid = [0x20; 16];
+const ENTITY_A: EntityUuid = [0x01; 16];
+const ENTITY_B: EntityUuid = [0x02; 16];
+
+const SOURCE: &str = r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [
+        PrefabRef(PrefabRef(
+            prefab_id: "20202020-2020-2020-2020-202020202020",
+            parameter_values: {
+                "speed": "2.5",
+            },
+            entity_overrides: [],
+        )),
+    ],
+)"#;
+
+fn load() -> Prefab {
+    let prefab_deser = PrefabFormatDeserializer::new(global_component_registry().serde_context());
+    let mut deserializer = ron::de::Deserializer::from_str(SOURCE).unwrap();
+    prefab_format::deserialize(&mut deserializer, &prefab_deser).unwrap();
+    prefab_deser.prefab()
+}
+
+#[test]
+fn unchanged_prefabs_have_no_differences() {
+    let context = global_component_registry().serde_context();
+    let diff = diff_prefabs(&load(), &load(), context);
+    assert!(diff.is_empty());
+    assert_eq!(
+        PrefabDiffReport::new(diff, context).to_string(),
+        "No differences\n"
+    );
+}
+
+#[test]
+fn metadata_changes_are_reported() {
+    let before = load();
+    let mut after = load();
+    let meta = &mut after.prefab_meta;
+    meta.hierarchy.insert(ENTITY_A, vec![ENTITY_B]);
+    meta.parameters.push(PrefabParameter {
+        name: "size".to_string(),
+        type_name: "f32".to_string(),
+        default: "1.0".to_string(),
+        bindings: vec![],
+    });
+    meta.entity_layers.insert(ENTITY_B, "lighting".to_string());
+    meta.blobs.insert([0xb0; 16], BlobData(vec![1, 2, 3]));
+    meta.extends = Some([0x30; 16]);
+    let prefab_ref = meta.prefab_refs.get_mut(&OTHER_PREFAB).unwrap();
+    prefab_ref.transform = PrefabRefTransform {
+        position: [1.0, 0.0, 0.0],
+        ..PrefabRefTransform::IDENTITY
+    };
+    prefab_ref
+        .parameter_values
+        .insert("speed".to_string(), "3.0".to_string());
+
+    let context = global_component_registry().serde_context();
+    let diff = diff_prefabs(&before, &after, context);
+    assert_eq!(
+        diff.hierarchy_changed,
+        vec![HierarchyChange {
+            parent: ENTITY_A,
+            before: vec![],
+            after: vec![ENTITY_B],
+        }]
+    );
+    assert_eq!(diff.parameters_changed.len(), 1);
+    assert_eq!(diff.parameters_changed[0].name, "size");
+    assert!(diff.parameters_changed[0].before.is_none());
+    assert_eq!(
+        diff.layers_changed,
+        vec![LayerChange {
+            entity: ENTITY_B,
+            before: None,
+            after: Some("lighting".to_string()),
+        }]
+    );
+    assert_eq!(diff.ref_transforms_changed.len(), 1);
+    assert_eq!(diff.parameter_values_changed.len(), 1);
+    assert_eq!(
+        diff.parameter_values_changed[0].before.as_deref(),
+        Some("2.5")
+    );
+    assert_eq!(diff.blobs_added, vec![[0xb0; 16]]);
+    assert_eq!(
+        diff.extends_changed,
+        Some(ExtendsChange {
+            before: None,
+            after: Some([0x30; 16]),
+        })
+    );
+
+    let report = PrefabDiffReport::new(diff, context).to_string();
+    assert!(report.contains(
+        "~ parameter value speed in prefab ref 20202020-2020-2020-2020-202020202020: 2.5 -> 3.0"
+    ));
+    assert!(report
+        .contains("~ layer of entity 02020202-0202-0202-0202-020202020202: (default) -> lighting"));
+    assert!(report.contains("+ extends: (none) -> 30303030-3030-3030-3030-303030303030"));
+}
@@ -0,0 +1,98 @@
+// Checking that prefabs survive being saved and loaded again
+use legion_prefab::{
+    assert_prefab_roundtrip, check_prefab_roundtrip, global_component_registry, prefab_component,
+    PrefabFormat, PrefabRoundtripError,
+};
+use serde::Serializer;
+
+#[prefab_component(uuid = "9e47c2b1-3d6f-4a08-b5c2-71e8d0f4a963")]
+#[derive(Debug, PartialEq)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+// Written one higher than its value, so every save changes it
+#[prefab_component(uuid = "0a6d5f38-e2b4-4c71-9f1e-8b3c6a2d7e54")]
+#[derive(Debug, PartialEq)]
+pub struct DriftingCounter {
+    #[serde(serialize_with = "serialize_incremented")]
+    pub count: u32,
+}
+
+fn serialize_incremented<S: Serializer>(
+    count: &u32,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u32(count + 1)
+}
+
+const HEALTH_SOURCE: &str = r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [
+        Entity(PrefabEntity(
+            id: "01010101-0101-0101-0101-010101010101",
+            components: [
+                EntityComponent(
+                    type: "9e47c2b1-3d6f-4a08-b5c2-71e8d0f4a963",
+                    data: (current: 80, max: 100),
+                ),
+            ],
+        )),
+        PrefabRef(PrefabRef(
+            prefab_id: "20202020-2020-2020-2020-202020202020",
+            entity_overrides: [
+                (
+                    entity_id: "02020202-0202-0202-0202-020202020202",
+                    component_overrides: [
+                        ComponentOverride(
+                            component_type: "9e47c2b1-3d6f-4a08-b5c2-71e8d0f4a963",
+                            diff: "[Enter(Field(\"max\")),Value(150)]",
+                        ),
+                    ],
+                ),
+            ],
+        )),
+    ],
+)"#;
+
+const DRIFTING_SOURCE: &str = r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [
+        Entity(PrefabEntity(
+            id: "01010101-0101-0101-0101-010101010101",
+            components: [
+                EntityComponent(
+                    type: "0a6d5f38-e2b4-4c71-9f1e-8b3c6a2d7e54",
+                    data: (count: 1),
+                ),
+            ],
+        )),
+    ],
+)"#;
+
+#[test]
+fn prefabs_that_survive_a_round_trip_pass() {
+    let context = global_component_registry().serde_context();
+    let result = check_prefab_roundtrip(HEALTH_SOURCE.as_bytes(), PrefabFormat::Ron, context);
+    assert!(result.is_ok(), "{:?}", result);
+    assert_prefab_roundtrip(HEALTH_SOURCE.as_bytes(), PrefabFormat::Ron);
+}
+
+#[test]
+fn components_that_change_when_saved_are_reported() {
+    let context = global_component_registry().serde_context();
+    let result = check_prefab_roundtrip(DRIFTING_SOURCE.as_bytes(), PrefabFormat::Ron, context);
+    match result {
+        Err(PrefabRoundtripError::Mismatch(difference)) => {
+            assert!(difference.contains("0a6d5f38-e2b4-4c71-9f1e-8b3c6a2d7e54"));
+        }
+        result => panic!("expected a mismatch, got {:?}", result),
+    }
+}
+
+#[test]
+#[should_panic(expected = "prefab did not survive a Ron round trip")]
+fn assert_prefab_roundtrip_panics_on_a_mismatch() {
+    assert_prefab_roundtrip(DRIFTING_SOURCE.as_bytes(), PrefabFormat::Ron);
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "yaml")]
+
+mod common;
+
+use legion_prefab::{global_component_registry, yaml};
+
+#[test]
+fn prefab_meta_round_trips() {
+    common::assert_prefab_meta_round_trips(
+        |meta| serde_yaml::to_string(meta).unwrap(),
+        |yaml| serde_yaml::from_str(yaml).unwrap(),
+    );
+}
+
+#[test]
+fn prefab_round_trips() {
+    common::assert_prefab_round_trips(
+        |prefab, context| yaml::prefab_to_string(prefab, context).unwrap(),
+        |yaml, context| yaml::prefab_from_str(yaml, context).unwrap(),
+    );
+}
+
+#[test]
+fn cooked_prefab_round_trips() {
+    common::assert_cooked_prefab_round_trips(
+        |cooked_prefab| yaml::cooked_prefab_to_string(cooked_prefab).unwrap(),
+        |yaml| yaml::cooked_prefab_from_str(yaml).unwrap(),
+    );
+}
+
+// A list with nothing after it reads as null
+#[test]
+fn empty_lists_may_be_left_blank() {
+    let context = global_component_registry().serde_context();
+
+    let no_objects = "id: 10101010-1010-1010-1010-101010101010\nobjects:\n";
+    let prefab = yaml::prefab_from_str(no_objects, context).unwrap();
+    assert!(prefab.prefab_meta.entities.is_empty());
+
+    let no_components = "\
+id: 10101010-1010-1010-1010-101010101010
+objects:
+  - Entity:
+      id: 01010101-0101-0101-0101-010101010101
+      components:
+";
+    let prefab = yaml::prefab_from_str(no_components, context).unwrap();
+    assert_eq!(prefab.prefab_meta.entities.len(), 1);
+    assert!(prefab.prefab_meta.entities.contains_key(&[0x01; 16]));
+}
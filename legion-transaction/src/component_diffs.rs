@@ -3,19 +3,28 @@ use legion_prefab::CookedPrefab;
 use legion_prefab::Prefab;
 use std::collections::HashMap;
 use legion::*;
-use legion_prefab::DiffSingleResult;
+use legion::storage::{Archetype, ArchetypeWriter, Components, EntityLayout};
+use legion::world::{Allocate, Merger};
+use legion_prefab::{DiffSingleKind, DiffSingleResult};
 use legion_prefab::ComponentRegistration;
+use legion_prefab::DecodedColumn;
 use legion_prefab::CopyCloneImpl;
+use legion_prefab::ComponentMask;
 use std::hash::BuildHasher;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+// Diffs can be serialized so that undo history can be saved or sent to another process (i.e. a
+// running game being edited live)
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum EntityDiffOp {
     Add,
     Remove,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct EntityDiff {
+    #[serde(with = "prefab_format::uuid_bytes")]
     entity_uuid: EntityUuid,
     op: EntityDiffOp,
 }
@@ -38,10 +47,10 @@ impl EntityDiff {
 }
 
 // This is somewhat of a mirror of DiffSingleResult
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ComponentDiffOp {
-    Change(Vec<u8>),
-    Add(Vec<u8>),
+    Change(#[serde(with = "prefab_format::bytes")] Vec<u8>),
+    Add(#[serde(with = "prefab_format::bytes")] Vec<u8>),
     Remove,
 }
 
@@ -50,18 +59,20 @@ impl ComponentDiffOp {
         diff_single_result: DiffSingleResult,
         data: Vec<u8>,
     ) -> Option<ComponentDiffOp> {
-        match diff_single_result {
-            DiffSingleResult::Add => Some(ComponentDiffOp::Add(data)),
-            DiffSingleResult::Change => Some(ComponentDiffOp::Change(data)),
-            DiffSingleResult::Remove => Some(ComponentDiffOp::Remove),
-            DiffSingleResult::NoChange => None,
+        match diff_single_result.kind {
+            DiffSingleKind::Add => Some(ComponentDiffOp::Add(data)),
+            DiffSingleKind::Change => Some(ComponentDiffOp::Change(data)),
+            DiffSingleKind::Remove => Some(ComponentDiffOp::Remove),
+            DiffSingleKind::NoChange => None,
         }
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ComponentDiff {
+    #[serde(with = "prefab_format::uuid_bytes")]
     entity_uuid: EntityUuid,
+    #[serde(with = "prefab_format::uuid_bytes")]
     component_type: ComponentTypeUuid,
     op: ComponentDiffOp,
 }
@@ -102,7 +113,7 @@ impl ComponentDiff {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WorldDiff {
     entity_diffs: Vec<EntityDiff>,
     component_diffs: Vec<ComponentDiff>,
@@ -130,11 +141,40 @@ impl WorldDiff {
     pub fn component_diffs(&self) -> &Vec<ComponentDiff> {
         &self.component_diffs
     }
+
+    /// A copy of this diff without the component types excluded by the mask, i.e. before sending
+    /// a transaction made on the server to clients
+    pub fn masked(
+        &self,
+        component_mask: &ComponentMask,
+    ) -> WorldDiff {
+        WorldDiff {
+            entity_diffs: self.entity_diffs.clone(),
+            component_diffs: self
+                .component_diffs
+                .iter()
+                .filter(|x| component_mask.includes(x.component_type()))
+                .cloned()
+                .collect(),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum ApplyDiffToPrefabError {
     PrefabHasOverrides,
+    ApplyDiff(ApplyDiffError),
+}
+
+/// Why a diff couldn't be applied. Diffs are applied to a copy of the world, so nothing is changed.
+#[derive(Debug)]
+pub enum ApplyDiffError {
+    /// The data of an added component couldn't be deserialized
+    InvalidComponentData {
+        entity: EntityUuid,
+        component_type: ComponentTypeUuid,
+        error: erased_serde::Error,
+    },
 }
 
 /// Applies a world diff to a prefab
@@ -157,17 +197,25 @@ pub fn apply_diff_to_prefab<S: BuildHasher, T: BuildHasher>(
         diff,
         registered_components,
         clone_impl,
-    );
+    )
+    .map_err(ApplyDiffToPrefabError::ApplyDiff)?;
 
     let prefab_meta = legion_prefab::PrefabMeta {
         id: prefab.prefab_meta.id,
         prefab_refs: Default::default(),
         entities: uuid_to_new_entities,
+        parameters: prefab.prefab_meta.parameters.clone(),
+        entity_layers: prefab.prefab_meta.entity_layers.clone(),
+        hierarchy: prefab.prefab_meta.hierarchy.clone(),
+        blobs: prefab.prefab_meta.blobs.clone(),
+        extends: None,
+        entity_refs: prefab.prefab_meta.entity_refs.clone(),
     };
 
     Ok(legion_prefab::Prefab {
         world: new_world,
         prefab_meta,
+        resources: prefab.resources.clone(),
     })
 }
 
@@ -177,28 +225,83 @@ pub fn apply_diff_to_cooked_prefab<S: BuildHasher, T: BuildHasher>(
     diff: &WorldDiff,
     registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, T>,
     clone_impl: CopyCloneImpl<S>,
-) -> CookedPrefab {
+) -> Result<CookedPrefab, ApplyDiffError> {
     let (new_world, uuid_to_new_entities) = apply_diff(
         &cooked_prefab.world,
-        &cooked_prefab.entities,
+        cooked_prefab.entities.uuid_to_entity(),
         diff,
         registered_components,
         clone_impl,
-    );
+    )?;
 
-    CookedPrefab {
+    Ok(CookedPrefab {
         world: new_world,
-        entities: uuid_to_new_entities,
-    }
+        entities: uuid_to_new_entities.into(),
+        parameters: cooked_prefab.parameters.clone(),
+        resources: cooked_prefab.resources.clone(),
+        blobs: cooked_prefab.blobs.clone(),
+    })
 }
 
 pub fn apply_diff<S: BuildHasher, U: BuildHasher, T: BuildHasher>(
+    world: &World,
+    uuid_to_entity: &HashMap<EntityUuid, Entity, T>,
+    diff: &WorldDiff,
+    registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, U>,
+    clone_impl: CopyCloneImpl<S>,
+) -> Result<(World, HashMap<EntityUuid, Entity>), ApplyDiffError> {
+    apply_diff_impl(
+        world,
+        uuid_to_entity,
+        diff,
+        registered_components,
+        clone_impl,
+        None,
+    )
+}
+
+/// The fields of a component that were changed by applying a diff, see `apply_diff_with_changes`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangedFields {
+    pub entity_uuid: EntityUuid,
+    pub component_type: ComponentTypeUuid,
+    /// Paths of the changed fields, i.e. `stats.health` or `waypoints[2]`. An empty path means
+    /// the whole component was replaced.
+    pub paths: Vec<String>,
+}
+
+/// Like `apply_diff`, but also reports which fields of each changed component were modified, so
+/// editors can highlight them and hot reloading can limit side effects to what changed (i.e. only
+/// rebuild physics when collider fields changed). Added and removed components are only reported
+/// by the diff itself. Slower than `apply_diff`, as changed components are compared before and
+/// after the diff is applied.
+pub fn apply_diff_with_changes<S: BuildHasher, U: BuildHasher, T: BuildHasher>(
+    world: &World,
+    uuid_to_entity: &HashMap<EntityUuid, Entity, T>,
+    diff: &WorldDiff,
+    registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, U>,
+    clone_impl: CopyCloneImpl<S>,
+) -> Result<(World, HashMap<EntityUuid, Entity>, Vec<ChangedFields>), ApplyDiffError> {
+    let mut changed_fields = vec![];
+    let (new_world, uuid_to_new_entities) = apply_diff_impl(
+        world,
+        uuid_to_entity,
+        diff,
+        registered_components,
+        clone_impl,
+        Some(&mut changed_fields),
+    )?;
+    Ok((new_world, uuid_to_new_entities, changed_fields))
+}
+
+fn apply_diff_impl<S: BuildHasher, U: BuildHasher, T: BuildHasher>(
     world: &World,
     uuid_to_entity: &HashMap<EntityUuid, Entity, T>,
     diff: &WorldDiff,
     registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, U>,
     mut clone_impl: CopyCloneImpl<S>,
-) -> (World, HashMap<EntityUuid, Entity>) {
+    changed_fields: Option<&mut Vec<ChangedFields>>,
+) -> Result<(World, HashMap<EntityUuid, Entity>), ApplyDiffError> {
     // Create an empty world to populate
     let mut new_world = World::default();
 
@@ -229,6 +332,18 @@ pub fn apply_diff<S: BuildHasher, U: BuildHasher, T: BuildHasher>(
         }
     }
 
+    // Changes are grouped by component type and applied in batches after adds and removes. An
+    // entity may have several changes to the same component (i.e. when a diff undoes two
+    // transactions at once), so they are kept in order. A later add or remove of the component
+    // replaces the value the changes were made to, so it discards them.
+    let mut changes: HashMap<ComponentTypeUuid, HashMap<Entity, Vec<&[u8]>>> = HashMap::new();
+
+    // Added components are grouped by entity so each entity changes archetype once. Only the last
+    // add of a component type counts, and a later remove cancels it. They are deserialized up
+    // front, so bad data fails the diff before anything is added.
+    let mut additions: HashMap<Entity, Vec<(&ComponentRegistration, DecodedColumn)>> =
+        HashMap::new();
+
     for component_diff in &diff.component_diffs {
         if let Some(new_prefab_entity) = uuid_to_new_entities.get(component_diff.entity_uuid()) {
             if let Some(component_registration) =
@@ -237,37 +352,39 @@ pub fn apply_diff<S: BuildHasher, U: BuildHasher, T: BuildHasher>(
                 match component_diff.op() {
                     ComponentDiffOp::Change(data) => {
                         //TODO: Detect if we need to make the change in the world or as an override
-                        let mut deserializer =
-                            bincode::Deserializer::<bincode::de::read::SliceReader, _>::from_slice(
-                                data.as_slice(),
-                                bincode::config::DefaultOptions::new(),
-                            );
-                        let mut de_erased = erased_serde::Deserializer::erase(&mut deserializer);
-
-                        component_registration.apply_diff(
-                            &mut de_erased,
-                            &mut new_world,
-                            *new_prefab_entity,
-                        );
+                        changes
+                            .entry(*component_diff.component_type())
+                            .or_default()
+                            .entry(*new_prefab_entity)
+                            .or_default()
+                            .push(data.as_slice());
                     }
                     ComponentDiffOp::Add(data) => {
                         //TODO: Detect if we need to make the change in the world or as an override
-                        let mut deserializer =
-                            bincode::Deserializer::<bincode::de::read::SliceReader, _>::from_slice(
-                                data,
-                                bincode::config::DefaultOptions::new(),
-                            );
-                        let mut de_erased = erased_serde::Deserializer::erase(&mut deserializer);
-
-                        component_registration.add_to_entity(
-                            &mut de_erased,
-                            &mut new_world,
-                            *new_prefab_entity,
-                        );
+                        let component = component_registration
+                            .comp_decode(&mut erased_serde::Deserializer::erase(
+                                &mut bincode_deserializer(data),
+                            ))
+                            .map_err(|error| ApplyDiffError::InvalidComponentData {
+                                entity: *component_diff.entity_uuid(),
+                                component_type: *component_diff.component_type(),
+                                error,
+                            })?;
+                        discard_changes(&mut changes, component_diff, new_prefab_entity);
+                        let entity_additions = additions.entry(*new_prefab_entity).or_default();
+                        entity_additions.retain(|(registration, _)| {
+                            registration.uuid() != component_registration.uuid()
+                        });
+                        entity_additions.push((component_registration, component));
                     }
                     ComponentDiffOp::Remove => {
                         //TODO: Detect if we need to make the change in the world or as an override
-                        //TODO: propagate error
+                        discard_changes(&mut changes, component_diff, new_prefab_entity);
+                        if let Some(entity_additions) = additions.get_mut(new_prefab_entity) {
+                            entity_additions.retain(|(registration, _)| {
+                                registration.uuid() != component_registration.uuid()
+                            });
+                        }
                         component_registration
                             .remove_from_entity(&mut new_world, *new_prefab_entity);
                     }
@@ -276,5 +393,410 @@ pub fn apply_diff<S: BuildHasher, U: BuildHasher, T: BuildHasher>(
         }
     }
 
-    (new_world, uuid_to_new_entities)
+    add_components(&mut new_world, additions, &mut clone_impl);
+
+    if let Some(changed_fields) = changed_fields {
+        // Changes are applied one entity at a time so the fields they touch can be found
+        let entity_to_uuid: HashMap<Entity, EntityUuid> = uuid_to_new_entities
+            .iter()
+            .map(|(uuid, entity)| (*entity, *uuid))
+            .collect();
+        for (component_type, entity_changes) in &changes {
+            let registration = &registered_components[component_type];
+            for (entity, entity_changes) in entity_changes {
+                let mut paths = vec![];
+                for data in entity_changes {
+                    paths.extend(registration.apply_diff_with_changes(
+                        &mut erased_serde::Deserializer::erase(&mut bincode_deserializer(data)),
+                        &mut new_world,
+                        *entity,
+                    ));
+                }
+                paths.sort();
+                paths.dedup();
+
+                changed_fields.push(ChangedFields {
+                    entity_uuid: entity_to_uuid[entity],
+                    component_type: *component_type,
+                    paths,
+                });
+            }
+        }
+        return Ok((new_world, uuid_to_new_entities));
+    }
+
+    for (component_type, entity_changes) in &changes {
+        registered_components[component_type].apply_diff_batch(
+            &mut new_world,
+            &mut |entity, apply| {
+                for data in entity_changes.get(&entity).into_iter().flatten() {
+                    let mut deserializer =
+                        bincode::Deserializer::<bincode::de::read::SliceReader, _>::from_slice(
+                            data,
+                            bincode::config::DefaultOptions::new(),
+                        );
+                    apply(&mut erased_serde::Deserializer::erase(&mut deserializer));
+                }
+            },
+        );
+    }
+
+    Ok((new_world, uuid_to_new_entities))
+}
+
+fn discard_changes(
+    changes: &mut HashMap<ComponentTypeUuid, HashMap<Entity, Vec<&[u8]>>>,
+    component_diff: &ComponentDiff,
+    entity: &Entity,
+) {
+    if let Some(entity_changes) = changes.get_mut(component_diff.component_type()) {
+        entity_changes.remove(entity);
+    }
+}
+
+// Applies the "Add" commands of a diff. Adding components one at a time moves the entity to a new
+// archetype for each of them, so entities that get several new component types are instead cloned
+// once into a staging world along with all of them, keeping their IDs. Entities that end up with
+// the same layout share a staging archetype, and the staging world is moved back in bulk.
+// Components an entity already has are replaced in place. Entities with a component that
+// `clone_impl` can't clone get their new components one at a time, so that it isn't lost.
+fn add_components<S: BuildHasher>(
+    world: &mut World,
+    additions: HashMap<Entity, Vec<(&ComponentRegistration, DecodedColumn)>>,
+    clone_impl: &mut CopyCloneImpl<S>,
+) {
+    let mut staging = World::default();
+    let mut staged = Vec::new();
+    for (entity, entity_additions) in additions {
+        let (replaced, added): (Vec<_>, Vec<_>) = entity_additions
+            .into_iter()
+            .partition(|(registration, _)| has_component(world, entity, registration));
+
+        for (_, component) in replaced {
+            component.add_to_entity(world, entity);
+        }
+
+        if added.len() > 1 && can_clone_entity(world, entity, clone_impl) {
+            let mut merger = AddComponentsMerger {
+                clone_impl: &mut *clone_impl,
+                components: added,
+            };
+            staging.clone_from_single(world, entity, &mut merger);
+            staged.push(entity);
+        } else {
+            for (_, component) in added {
+                component.add_to_entity(world, entity);
+            }
+        }
+    }
+
+    if !staged.is_empty() {
+        for entity in staged {
+            world.remove(entity);
+        }
+        world.move_from(&mut staging, &legion::query::any());
+    }
+}
+
+fn can_clone_entity<S: BuildHasher>(
+    world: &World,
+    entity: Entity,
+    clone_impl: &CopyCloneImpl<S>,
+) -> bool {
+    world
+        .entry_ref(entity)
+        .map(|entry| {
+            entry
+                .archetype()
+                .layout()
+                .component_types()
+                .iter()
+                .all(|component_type| clone_impl.can_clone(component_type))
+        })
+        .unwrap_or(false)
+}
+
+fn has_component(
+    world: &World,
+    entity: Entity,
+    registration: &ComponentRegistration,
+) -> bool {
+    world
+        .entry_ref(entity)
+        .map(|entry| {
+            entry
+                .archetype()
+                .layout()
+                .has_component_by_id(registration.component_type_id())
+        })
+        .unwrap_or(false)
+}
+
+fn bincode_deserializer(
+    data: &[u8]
+) -> bincode::Deserializer<bincode::de::read::SliceReader, bincode::config::DefaultOptions> {
+    bincode::Deserializer::from_slice(data, bincode::config::DefaultOptions::new())
+}
+
+// Clones an entity with `clone_impl` and writes the given components, which it doesn't have yet,
+// alongside its existing ones. The entity keeps its ID so it can be moved back afterwards.
+struct AddComponentsMerger<'a, 'b, S: BuildHasher> {
+    clone_impl: &'a mut CopyCloneImpl<'b, S>,
+    components: Vec<(&'a ComponentRegistration, DecodedColumn)>,
+}
+
+impl<'a, 'b, S: BuildHasher> Merger for AddComponentsMerger<'a, 'b, S> {
+    fn prefers_new_archetype() -> bool {
+        false
+    }
+
+    fn assign_id(
+        &mut self,
+        existing: Entity,
+        _allocator: &mut Allocate,
+    ) -> Entity {
+        existing
+    }
+
+    fn convert_layout(
+        &mut self,
+        source_layout: EntityLayout,
+    ) -> EntityLayout {
+        let mut dest_layout = self.clone_impl.convert_layout(source_layout);
+        for (registration, _) in &self.components {
+            registration.register_component(&mut dest_layout);
+        }
+        dest_layout
+    }
+
+    fn merge_archetype(
+        &mut self,
+        src_entity_range: std::ops::Range<usize>,
+        src_arch: &Archetype,
+        src_components: &Components,
+        dst: &mut ArchetypeWriter,
+    ) {
+        self.clone_impl
+            .merge_archetype(src_entity_range, src_arch, src_components, dst);
+
+        for (registration, component) in self.components.drain(..) {
+            component.write(dst.claim_components_unknown(registration.component_type_id()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::Options;
+    use legion::storage::ComponentTypeId;
+    use serde_diff::SerdeDiff;
+    use type_uuid::TypeUuid;
+
+    #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, SerdeDiff, TypeUuid)]
+    #[uuid = "6b6a5a53-3d0c-4d51-9a7c-8b3f0c4ad001"]
+    struct Position {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, SerdeDiff, TypeUuid)]
+    #[uuid = "6b6a5a53-3d0c-4d51-9a7c-8b3f0c4ad002"]
+    struct Velocity {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, SerdeDiff, TypeUuid)]
+    #[uuid = "6b6a5a53-3d0c-4d51-9a7c-8b3f0c4ad003"]
+    struct Health {
+        value: u32,
+    }
+
+    const ENTITY: EntityUuid = [0x01; 16];
+
+    struct Registry {
+        by_uuid: HashMap<ComponentTypeUuid, ComponentRegistration>,
+        by_type: HashMap<ComponentTypeId, ComponentRegistration>,
+    }
+
+    impl Registry {
+        fn new() -> Self {
+            let registrations = vec![
+                ComponentRegistration::of::<Position>(),
+                ComponentRegistration::of::<Velocity>(),
+                ComponentRegistration::of::<Health>(),
+            ];
+            Registry {
+                by_uuid: registrations
+                    .iter()
+                    .map(|registration| (*registration.uuid(), registration.clone()))
+                    .collect(),
+                by_type: registrations
+                    .iter()
+                    .map(|registration| (registration.component_type_id(), registration.clone()))
+                    .collect(),
+            }
+        }
+
+        // Applies the diff to a world with one entity that has a Position
+        fn apply(
+            &self,
+            component_diffs: Vec<ComponentDiff>,
+        ) -> Result<(World, Entity), ApplyDiffError> {
+            let mut world = World::default();
+            let entity = world.push((Position { x: 1.0, y: 2.0 },));
+            let mut uuid_to_entity = HashMap::new();
+            uuid_to_entity.insert(ENTITY, entity);
+
+            let diff = WorldDiff::new(vec![], component_diffs);
+            let (world, uuid_to_entity) = apply_diff(
+                &world,
+                &uuid_to_entity,
+                &diff,
+                &self.by_uuid,
+                CopyCloneImpl::new(&self.by_type),
+            )?;
+            let entity = uuid_to_entity[&ENTITY];
+            Ok((world, entity))
+        }
+    }
+
+    fn add<T: Serialize + TypeUuid>(value: &T) -> ComponentDiff {
+        let data = bincode::DefaultOptions::new().serialize(value).unwrap();
+        ComponentDiff::new(ENTITY, T::UUID, ComponentDiffOp::Add(data))
+    }
+
+    fn change<T: SerdeDiff + TypeUuid>(
+        before: &T,
+        after: &T,
+    ) -> ComponentDiff {
+        let diff = serde_diff::Diff::serializable(before, after);
+        let data = bincode::DefaultOptions::new().serialize(&diff).unwrap();
+        ComponentDiff::new(ENTITY, T::UUID, ComponentDiffOp::Change(data))
+    }
+
+    fn remove<T: TypeUuid>() -> ComponentDiff {
+        ComponentDiff::new(ENTITY, T::UUID, ComponentDiffOp::Remove)
+    }
+
+    fn component<T: legion::storage::Component + Clone>(
+        world: &World,
+        entity: Entity,
+    ) -> Option<T> {
+        world
+            .entry_ref(entity)
+            .unwrap()
+            .get_component::<T>()
+            .ok()
+            .cloned()
+    }
+
+    #[test]
+    fn add_after_change_replaces_the_changed_value() {
+        let registry = Registry::new();
+        let (world, entity) = registry
+            .apply(vec![
+                change(&Position { x: 1.0, y: 2.0 }, &Position { x: 5.0, y: 2.0 }),
+                add(&Position { x: 7.0, y: 8.0 }),
+            ])
+            .unwrap();
+        assert_eq!(
+            component::<Position>(&world, entity),
+            Some(Position { x: 7.0, y: 8.0 })
+        );
+    }
+
+    #[test]
+    fn change_after_add_is_applied_to_the_added_value() {
+        let registry = Registry::new();
+        let (world, entity) = registry
+            .apply(vec![
+                add(&Health { value: 10 }),
+                change(&Health { value: 10 }, &Health { value: 3 }),
+            ])
+            .unwrap();
+        assert_eq!(
+            component::<Health>(&world, entity),
+            Some(Health { value: 3 })
+        );
+    }
+
+    #[test]
+    fn remove_after_add_cancels_the_add() {
+        let registry = Registry::new();
+        let (world, entity) = registry
+            .apply(vec![add(&Health { value: 10 }), remove::<Health>()])
+            .unwrap();
+        assert_eq!(component::<Health>(&world, entity), None);
+        assert_eq!(
+            component::<Position>(&world, entity),
+            Some(Position { x: 1.0, y: 2.0 })
+        );
+    }
+
+    #[test]
+    fn adding_several_components_keeps_the_existing_ones() {
+        let registry = Registry::new();
+        let (world, entity) = registry
+            .apply(vec![
+                add(&Velocity { x: 3.0, y: 4.0 }),
+                add(&Health { value: 10 }),
+            ])
+            .unwrap();
+        assert_eq!(
+            component::<Position>(&world, entity),
+            Some(Position { x: 1.0, y: 2.0 })
+        );
+        assert_eq!(
+            component::<Velocity>(&world, entity),
+            Some(Velocity { x: 3.0, y: 4.0 })
+        );
+        assert_eq!(
+            component::<Health>(&world, entity),
+            Some(Health { value: 10 })
+        );
+    }
+
+    #[test]
+    fn adding_components_the_clone_impl_cant_clone_keeps_the_existing_ones() {
+        let mut registry = Registry::new();
+        registry.by_type.remove(&ComponentTypeId::of::<Velocity>());
+        registry.by_type.remove(&ComponentTypeId::of::<Health>());
+        let (world, entity) = registry
+            .apply(vec![
+                add(&Velocity { x: 3.0, y: 4.0 }),
+                add(&Health { value: 10 }),
+            ])
+            .unwrap();
+        assert_eq!(
+            component::<Position>(&world, entity),
+            Some(Position { x: 1.0, y: 2.0 })
+        );
+        assert_eq!(
+            component::<Velocity>(&world, entity),
+            Some(Velocity { x: 3.0, y: 4.0 })
+        );
+        assert_eq!(
+            component::<Health>(&world, entity),
+            Some(Health { value: 10 })
+        );
+    }
+
+    #[test]
+    fn invalid_component_data_is_an_error() {
+        let registry = Registry::new();
+        let invalid = ComponentDiff::new(ENTITY, Health::UUID, ComponentDiffOp::Add(vec![0xff]));
+        match registry.apply(vec![add(&Velocity { x: 3.0, y: 4.0 }), invalid]) {
+            Err(ApplyDiffError::InvalidComponentData {
+                entity,
+                component_type,
+                ..
+            }) => {
+                assert_eq!(entity, ENTITY);
+                assert_eq!(component_type, Health::UUID);
+            }
+            _ => panic!("expected InvalidComponentData"),
+        }
+    }
 }
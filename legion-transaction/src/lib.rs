@@ -6,9 +6,12 @@ pub use component_diffs::EntityDiff;
 pub use component_diffs::EntityDiffOp;
 pub use component_diffs::WorldDiff;
 pub use component_diffs::apply_diff;
+pub use component_diffs::apply_diff_with_changes;
+pub use component_diffs::ChangedFields;
 pub use component_diffs::apply_diff_to_prefab;
 pub use component_diffs::apply_diff_to_cooked_prefab;
 pub use component_diffs::ApplyDiffToPrefabError;
+pub use component_diffs::ApplyDiffError;
 
 // Generates diffs by comparing legion worlds
 mod transactions;
@@ -16,3 +19,13 @@ pub use transactions::TransactionBuilder;
 pub use transactions::Transaction;
 pub use transactions::TransactionDiffs;
 pub use transactions::TransactionEntityInfo;
+pub use transactions::TransactionMetadata;
+
+// Keeps worlds on remote clients in sync by sending diffs
+mod world_replication;
+pub use world_replication::ReplicationBaseline;
+pub use world_replication::ReplicatedWorld;
+
+// Full copies of a world that later states can be stored as deltas against
+mod world_snapshot;
+pub use world_snapshot::WorldSnapshot;
@@ -3,10 +3,16 @@ use prefab_format::{EntityUuid, ComponentTypeUuid};
 
 use std::collections::HashMap;
 use std::collections::HashSet;
-use legion_prefab::{ComponentRegistration, DiffSingleResult};
-use crate::component_diffs::{ComponentDiff, EntityDiff, EntityDiffOp, WorldDiff};
+use legion_prefab::{ComponentMask, ComponentRegistration, DiffOptions};
+use crate::component_diffs::{
+    apply_diff, ApplyDiffError, ComponentDiff, EntityDiff, EntityDiffOp, WorldDiff,
+};
 use legion_prefab::CopyCloneImpl;
 use std::hash::BuildHasher;
+use std::time::SystemTime;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
 struct TransactionBuilderEntityInfo {
     entity_uuid: EntityUuid,
@@ -16,6 +22,7 @@ struct TransactionBuilderEntityInfo {
 #[derive(Default)]
 pub struct TransactionBuilder {
     entities: Vec<TransactionBuilderEntityInfo>,
+    label: Option<String>,
 }
 
 impl TransactionBuilder {
@@ -35,6 +42,16 @@ impl TransactionBuilder {
         self
     }
 
+    /// Sets the label that is stored in the metadata of the transaction's diffs, i.e. "Move 12
+    /// entities"
+    pub fn label<T: Into<String>>(
+        mut self,
+        label: T,
+    ) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
     pub fn begin<S: BuildHasher>(
         self,
         src_world: &World,
@@ -63,6 +80,7 @@ impl TransactionBuilder {
             before_world,
             after_world,
             uuid_to_entities,
+            label: self.label,
         }
     }
 }
@@ -103,22 +121,98 @@ pub struct Transaction {
 
     // All known entities throughout the transaction
     uuid_to_entities: HashMap<EntityUuid, TransactionEntityInfo>,
+
+    // Copied into the metadata of the diffs
+    label: Option<String>,
+}
+
+/// Describes a transaction for display in an undo history. It is serialized along with the diffs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransactionMetadata {
+    label: Option<String>,
+    timestamp: SystemTime,
+    #[serde(with = "prefab_format::uuid_bytes::vec")]
+    affected_entities: Vec<EntityUuid>,
+}
+
+impl TransactionMetadata {
+    /// Metadata timestamped now, with the affected entities taken from the given diff
+    pub fn new(
+        label: Option<String>,
+        apply_diff: &WorldDiff,
+    ) -> Self {
+        TransactionMetadata {
+            label,
+            timestamp: SystemTime::now(),
+            affected_entities: affected_entities(apply_diff),
+        }
+    }
+
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    pub fn set_label(
+        &mut self,
+        label: Option<String>,
+    ) {
+        self.label = label;
+    }
+
+    /// When the diffs were created
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    /// The UUIDs of entities that were created, destroyed or modified, sorted
+    pub fn affected_entities(&self) -> &[EntityUuid] {
+        &self.affected_entities
+    }
 }
 
-#[derive(Clone)]
+// Every entity that an entity or component diff refers to, sorted and without duplicates
+fn affected_entities(world_diff: &WorldDiff) -> Vec<EntityUuid> {
+    let mut entity_uuids: Vec<EntityUuid> = world_diff
+        .entity_diffs()
+        .iter()
+        .map(|x| *x.entity_uuid())
+        .chain(
+            world_diff
+                .component_diffs()
+                .iter()
+                .map(|x| *x.entity_uuid()),
+        )
+        .collect();
+    entity_uuids.sort();
+    entity_uuids.dedup();
+    entity_uuids
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TransactionDiffs {
     apply_diff: WorldDiff,
     revert_diff: WorldDiff,
+    metadata: TransactionMetadata,
 }
 
 impl TransactionDiffs {
     pub fn new(
         apply_diff: WorldDiff,
         revert_diff: WorldDiff,
+    ) -> Self {
+        let metadata = TransactionMetadata::new(None, &apply_diff);
+        Self::new_with_metadata(apply_diff, revert_diff, metadata)
+    }
+
+    pub fn new_with_metadata(
+        apply_diff: WorldDiff,
+        revert_diff: WorldDiff,
+        metadata: TransactionMetadata,
     ) -> Self {
         TransactionDiffs {
             apply_diff,
             revert_diff,
+            metadata,
         }
     }
 
@@ -130,9 +224,115 @@ impl TransactionDiffs {
         &self.revert_diff
     }
 
+    pub fn metadata(&self) -> &TransactionMetadata {
+        &self.metadata
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut TransactionMetadata {
+        &mut self.metadata
+    }
+
     pub fn reverse(&mut self) {
         std::mem::swap(&mut self.apply_diff, &mut self.revert_diff);
     }
+
+    /// Combines `other`, the diffs of the transaction that came right after this one, into these
+    /// diffs. This is used to turn the many small transactions of a gizmo drag into a single undo
+    /// step or network message. Repeated edits to the same component become a single diff, and
+    /// entities or components that were added and then removed again are dropped entirely.
+    ///
+    /// `world` and `uuid_to_entity` must hold (at least) the touched entities as they are after
+    /// both transactions, i.e. the world of the later `Transaction`. The state before both
+    /// transactions is rebuilt from it with the revert diffs, and the two states are diffed. Fails
+    /// if the revert diffs hold component data that can't be read, leaving these diffs unchanged.
+    pub fn merge<S: BuildHasher, T: BuildHasher, U: BuildHasher>(
+        &mut self,
+        other: &TransactionDiffs,
+        world: &World,
+        uuid_to_entity: &HashMap<EntityUuid, Entity, T>,
+        registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, U>,
+        clone_impl: CopyCloneImpl<S>,
+    ) -> Result<(), ApplyDiffError> {
+        // Undo the later transaction, then this one
+        let revert_both = WorldDiff::new(
+            other
+                .revert_diff
+                .entity_diffs()
+                .iter()
+                .chain(self.revert_diff.entity_diffs())
+                .cloned()
+                .collect(),
+            other
+                .revert_diff
+                .component_diffs()
+                .iter()
+                .chain(self.revert_diff.component_diffs())
+                .cloned()
+                .collect(),
+        );
+        let (before_world, before_uuid_to_entity) = apply_diff(
+            world,
+            uuid_to_entity,
+            &revert_both,
+            registered_components,
+            clone_impl,
+        )?;
+
+        // Only entities touched by one of the transactions can differ
+        let mut entity_uuids = affected_entities(&self.apply_diff);
+        entity_uuids.extend(affected_entities(&other.apply_diff));
+        entity_uuids.sort();
+        entity_uuids.dedup();
+
+        let mut component_types: Vec<_> = registered_components.iter().collect();
+        component_types.sort_by_key(|(component_type, _)| **component_type);
+
+        let mut apply_entity_diffs = vec![];
+        let mut revert_entity_diffs = vec![];
+        let mut apply_component_diffs = vec![];
+        let mut revert_component_diffs = vec![];
+        for entity_uuid in entity_uuids {
+            let entity_info = TransactionEntityInfo::new(
+                before_uuid_to_entity.get(&entity_uuid).copied(),
+                uuid_to_entity
+                    .get(&entity_uuid)
+                    .copied()
+                    .filter(|entity| world.contains(*entity)),
+            );
+
+            match (entity_info.before_entity, entity_info.after_entity) {
+                (None, Some(_)) => {
+                    apply_entity_diffs.push(EntityDiff::new(entity_uuid, EntityDiffOp::Add));
+                    revert_entity_diffs.push(EntityDiff::new(entity_uuid, EntityDiffOp::Remove));
+                }
+                (Some(_), None) => {
+                    apply_entity_diffs.push(EntityDiff::new(entity_uuid, EntityDiffOp::Remove));
+                    revert_entity_diffs.push(EntityDiff::new(entity_uuid, EntityDiffOp::Add));
+                }
+                _ => {}
+            }
+
+            let component_diffs = diff_entity_components(
+                entity_uuid,
+                &entity_info,
+                &component_types,
+                &before_world,
+                world,
+                &DiffOptions::default(),
+            );
+            for (apply_component_diff, revert_component_diff) in component_diffs {
+                apply_component_diffs.push(apply_component_diff);
+                revert_component_diffs.push(revert_component_diff);
+            }
+        }
+
+        self.apply_diff = WorldDiff::new(apply_entity_diffs, apply_component_diffs);
+        self.revert_diff = WorldDiff::new(revert_entity_diffs, revert_component_diffs);
+
+        // The merged transaction keeps the label and start time of the first one
+        self.metadata.affected_entities = affected_entities(&self.apply_diff);
+        Ok(())
+    }
 }
 
 impl Transaction {
@@ -151,9 +351,71 @@ impl Transaction {
         self.uuid_to_entities[&uuid].after_entity()
     }
 
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// Sets the label of diffs created after this call, i.e. once it is known how many entities a
+    /// drag moved
+    pub fn set_label(
+        &mut self,
+        label: Option<String>,
+    ) {
+        self.label = label;
+    }
+
+    /// Adds an empty entity to the transaction's world and allocates a UUID for it. Components can
+    /// be added to it with `world_mut`. The diffs will create the entity when applied and destroy
+    /// it when reverted.
+    pub fn create_entity(&mut self) -> (EntityUuid, Entity) {
+        let entity_uuid = *uuid::Uuid::new_v4().as_bytes();
+        let entity = self.create_entity_with_uuid(entity_uuid);
+        (entity_uuid, entity)
+    }
+
+    /// Like `create_entity`, but uses the given UUID, i.e. when pasting entities that should keep
+    /// the UUIDs they were copied with
+    pub fn create_entity_with_uuid(
+        &mut self,
+        entity_uuid: EntityUuid,
+    ) -> Entity {
+        let entity = self.after_world.extend(vec![()])[0];
+        self.uuid_to_entities
+            .insert(entity_uuid, TransactionEntityInfo::new(None, Some(entity)));
+        entity
+    }
+
+    /// Removes an entity from the transaction's world. The diffs will destroy the entity when
+    /// applied and re-create it with all its components when reverted. Returns false if the entity
+    /// is not in the transaction.
+    pub fn destroy_entity(
+        &mut self,
+        entity_uuid: EntityUuid,
+    ) -> bool {
+        let after_entity = self
+            .uuid_to_entities
+            .get(&entity_uuid)
+            .and_then(|entity_info| entity_info.after_entity);
+
+        match after_entity {
+            Some(after_entity) => self.after_world.remove(after_entity),
+            None => false,
+        }
+    }
+
     pub fn create_transaction_diffs<S: BuildHasher>(
         &mut self,
         registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+    ) -> TransactionDiffs {
+        self.create_transaction_diffs_with_options(registered_components, &DiffOptions::default())
+    }
+
+    /// Like `create_transaction_diffs`, but i.e. can ignore tiny float changes so they don't
+    /// produce noisy transactions
+    pub fn create_transaction_diffs_with_options<S: BuildHasher>(
+        &mut self,
+        registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, S>,
+        diff_options: &DiffOptions,
     ) -> TransactionDiffs {
         log::trace!("create diffs for {} entities", self.uuid_to_entities.len());
 
@@ -161,15 +423,30 @@ impl Transaction {
         let mut apply_entity_diffs = vec![];
         let mut revert_entity_diffs = vec![];
 
-        // Find the entities that have been deleted
+        // Find the entities that have been deleted, and the ones created with `create_entity`
         let mut preexisting_after_entities = HashSet::new();
         let mut removed_entity_uuids = HashSet::new();
         for (entity_uuid, entity_info) in &self.uuid_to_entities {
             if let Some(after_entity) = entity_info.after_entity {
-                if !self.after_world.contains(after_entity) {
-                    removed_entity_uuids.insert(*entity_uuid);
-                    revert_entity_diffs.push(EntityDiff::new(*entity_uuid, EntityDiffOp::Add));
-                    apply_entity_diffs.push(EntityDiff::new(*entity_uuid, EntityDiffOp::Remove));
+                let existed_before = entity_info.before_entity.is_some();
+                let exists_after = self.after_world.contains(after_entity);
+                match (existed_before, exists_after) {
+                    (true, false) => {
+                        removed_entity_uuids.insert(*entity_uuid);
+                        revert_entity_diffs.push(EntityDiff::new(*entity_uuid, EntityDiffOp::Add));
+                        apply_entity_diffs
+                            .push(EntityDiff::new(*entity_uuid, EntityDiffOp::Remove));
+                    }
+                    (false, true) => {
+                        apply_entity_diffs.push(EntityDiff::new(*entity_uuid, EntityDiffOp::Add));
+                        revert_entity_diffs
+                            .push(EntityDiff::new(*entity_uuid, EntityDiffOp::Remove));
+                    }
+                    // Created and destroyed within this transaction, so there's nothing to record
+                    (false, false) => {
+                        removed_entity_uuids.insert(*entity_uuid);
+                    }
+                    (true, true) => {}
                 }
 
                 preexisting_after_entities.insert(after_entity);
@@ -210,65 +487,41 @@ impl Transaction {
         //   diffs. This is still a little suboptimal if multiple components are added, but it's
         //   likely not the common case and something we can try to do something about later
 
-        let mut apply_component_diffs = vec![];
-        let mut revert_component_diffs = vec![];
-
         // Iterate the entities in the selection world and prefab world and genereate diffs for
-        // each component type.
-        for (entity_uuid, entity_info) in &self.uuid_to_entities {
-            // Do diffs for each component type
-            for (component_type, registration) in registered_components {
-                let mut apply_data = vec![];
-                let mut apply_ser = bincode::Serializer::new(
-                    &mut apply_data,
-                    bincode::config::DefaultOptions::new(),
-                );
-                let mut apply_ser_erased = erased_serde::Serializer::erase(&mut apply_ser);
-
-                let apply_result = registration.diff_single(
-                    &mut apply_ser_erased,
-                    &self.before_world,
-                    entity_info.before_entity,
-                    &self.after_world,
-                    entity_info.after_entity,
-                );
+        // each component type. With the `parallel` feature, entities are diffed in parallel.
+        // Entities and component types are sorted by UUID first so that the resulting diffs are in
+        // the same order regardless of how the work was split up.
+        let mut entities: Vec<_> = self.uuid_to_entities.iter().collect();
+        entities.sort_by_key(|(entity_uuid, _)| **entity_uuid);
+
+        let mut component_types: Vec<_> = registered_components.iter().collect();
+        component_types.sort_by_key(|(component_type, _)| **component_type);
+
+        let before_world = &self.before_world;
+        let after_world = &self.after_world;
+        #[cfg(feature = "parallel")]
+        let entity_iter = entities.par_iter();
+        #[cfg(not(feature = "parallel"))]
+        let entity_iter = entities.iter();
+        let entity_component_diffs: Vec<_> = entity_iter
+            .map(|(entity_uuid, entity_info)| {
+                diff_entity_components(
+                    **entity_uuid,
+                    entity_info,
+                    &component_types,
+                    before_world,
+                    after_world,
+                    diff_options,
+                )
+            })
+            .collect();
 
-                if apply_result != DiffSingleResult::NoChange {
-                    let mut revert_data = vec![];
-                    let mut revert_ser = bincode::Serializer::new(
-                        &mut revert_data,
-                        bincode::config::DefaultOptions::new(),
-                    );
-                    let mut revert_ser_erased = erased_serde::Serializer::erase(&mut revert_ser);
-
-                    let revert_result = registration.diff_single(
-                        &mut revert_ser_erased,
-                        &self.after_world,
-                        entity_info.after_entity,
-                        &self.before_world,
-                        entity_info.before_entity,
-                    );
-
-                    apply_component_diffs.push(
-                        ComponentDiff::new_from_diff_single_result(
-                            *entity_uuid,
-                            *component_type,
-                            apply_result,
-                            apply_data,
-                        )
-                        .unwrap(),
-                    );
-
-                    revert_component_diffs.push(
-                        ComponentDiff::new_from_diff_single_result(
-                            *entity_uuid,
-                            *component_type,
-                            revert_result,
-                            revert_data,
-                        )
-                        .unwrap(),
-                    );
-                }
+        let mut apply_component_diffs = vec![];
+        let mut revert_component_diffs = vec![];
+        for component_diffs in entity_component_diffs {
+            for (apply_component_diff, revert_component_diff) in component_diffs {
+                apply_component_diffs.push(apply_component_diff);
+                revert_component_diffs.push(revert_component_diff);
             }
         }
 
@@ -281,6 +534,144 @@ impl Transaction {
         let apply_diff = WorldDiff::new(apply_entity_diffs, apply_component_diffs);
         let revert_diff = WorldDiff::new(revert_entity_diffs, revert_component_diffs);
 
-        TransactionDiffs::new(apply_diff, revert_diff)
+        let metadata = TransactionMetadata::new(self.label.clone(), &apply_diff);
+        TransactionDiffs::new_with_metadata(apply_diff, revert_diff, metadata)
     }
 }
+
+// Produces the diff that turns the entities of one world into the entities of another. Entities are
+// matched by UUID, and only entities in one of the maps are compared. Component types excluded by
+// the mask are not diffed at all.
+pub(crate) fn diff_worlds<S: BuildHasher, T: BuildHasher, U: BuildHasher>(
+    before_world: &World,
+    before_uuid_to_entity: &HashMap<EntityUuid, Entity, S>,
+    after_world: &World,
+    after_uuid_to_entity: &HashMap<EntityUuid, Entity, T>,
+    registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, U>,
+    component_mask: &ComponentMask,
+    diff_options: &DiffOptions,
+) -> WorldDiff {
+    // Sorted so that the diff is in the same order regardless of hashing
+    let mut entity_uuids: Vec<EntityUuid> = before_uuid_to_entity
+        .keys()
+        .chain(after_uuid_to_entity.keys())
+        .copied()
+        .collect();
+    entity_uuids.sort();
+    entity_uuids.dedup();
+
+    let mut component_types: Vec<_> = registered_components
+        .iter()
+        .filter(|(component_type, _)| component_mask.includes(component_type))
+        .collect();
+    component_types.sort_by_key(|(component_type, _)| **component_type);
+
+    let mut entity_diffs = vec![];
+    let mut component_diffs = vec![];
+    for entity_uuid in entity_uuids {
+        let entity_info = TransactionEntityInfo::new(
+            before_uuid_to_entity
+                .get(&entity_uuid)
+                .copied()
+                .filter(|entity| before_world.contains(*entity)),
+            after_uuid_to_entity
+                .get(&entity_uuid)
+                .copied()
+                .filter(|entity| after_world.contains(*entity)),
+        );
+
+        match (entity_info.before_entity, entity_info.after_entity) {
+            (None, Some(_)) => entity_diffs.push(EntityDiff::new(entity_uuid, EntityDiffOp::Add)),
+            (Some(_), None) => {
+                entity_diffs.push(EntityDiff::new(entity_uuid, EntityDiffOp::Remove))
+            }
+            _ => {}
+        }
+
+        // Callers of this only move forward, so the revert diffs are dropped
+        let entity_component_diffs = diff_entity_components(
+            entity_uuid,
+            &entity_info,
+            &component_types,
+            before_world,
+            after_world,
+            diff_options,
+        );
+        component_diffs.extend(entity_component_diffs.into_iter().map(|(apply, _)| apply));
+    }
+
+    WorldDiff::new(entity_diffs, component_diffs)
+}
+
+// Produces an apply and revert diff for each component type that differs between the before and
+// after state of an entity
+pub(crate) fn diff_entity_components(
+    entity_uuid: EntityUuid,
+    entity_info: &TransactionEntityInfo,
+    component_types: &[(&ComponentTypeUuid, &ComponentRegistration)],
+    before_world: &World,
+    after_world: &World,
+    diff_options: &DiffOptions,
+) -> Vec<(ComponentDiff, ComponentDiff)> {
+    let mut component_diffs = vec![];
+
+    // Diffs are serialized into this buffer and then copied out, so each diff costs one
+    // exactly-sized allocation rather than one per time the buffer would grow
+    let mut scratch = vec![];
+
+    // Do diffs for each component type
+    for (component_type, registration) in component_types {
+        scratch.clear();
+        let mut apply_ser =
+            bincode::Serializer::new(&mut scratch, bincode::config::DefaultOptions::new());
+        let mut apply_ser_erased = erased_serde::Serializer::erase(&mut apply_ser);
+
+        let apply_result = registration.diff_single_with_options(
+            &mut apply_ser_erased,
+            before_world,
+            entity_info.before_entity,
+            after_world,
+            entity_info.after_entity,
+            diff_options,
+        );
+
+        if !apply_result.is_no_change() {
+            let apply_data = scratch.clone();
+
+            scratch.clear();
+            let mut revert_ser =
+                bincode::Serializer::new(&mut scratch, bincode::config::DefaultOptions::new());
+            let mut revert_ser_erased = erased_serde::Serializer::erase(&mut revert_ser);
+
+            let revert_result = registration.diff_single_with_options(
+                &mut revert_ser_erased,
+                after_world,
+                entity_info.after_entity,
+                before_world,
+                entity_info.before_entity,
+                diff_options,
+            );
+            let revert_data = scratch.clone();
+
+            let apply_component_diff = ComponentDiff::new_from_diff_single_result(
+                entity_uuid,
+                **component_type,
+                apply_result,
+                apply_data,
+            )
+            .unwrap();
+
+            let revert_component_diff = ComponentDiff::new_from_diff_single_result(
+                entity_uuid,
+                **component_type,
+                revert_result,
+                revert_data,
+            )
+            .unwrap();
+
+            component_diffs.push((apply_component_diff, revert_component_diff));
+        }
+    }
+
+    component_diffs
+}
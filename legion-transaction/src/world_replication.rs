@@ -0,0 +1,121 @@
+use legion::*;
+use prefab_format::{ComponentTypeUuid, EntityUuid};
+use legion_prefab::{ComponentMask, ComponentRegistration, CopyCloneImpl, DiffOptions};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use crate::component_diffs::{apply_diff, ApplyDiffError, WorldDiff};
+use crate::transactions::diff_worlds;
+
+// Replication uses the same diffs as transactions. The sending side keeps a ReplicationBaseline per
+// client, which mirrors the world that client has. Each tick it diffs the authoritative world
+// against the baseline and sends the result. The receiving side applies it to a ReplicatedWorld.
+// Entities are matched by UUID on both sides, since legion Entity handles are not stable across
+// worlds.
+
+/// A world built up by applying diffs, i.e. on a client receiving a replicated world
+#[derive(Default)]
+pub struct ReplicatedWorld {
+    world: World,
+    uuid_to_entity: HashMap<EntityUuid, Entity>,
+}
+
+impl ReplicatedWorld {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn uuid_to_entity(&self) -> &HashMap<EntityUuid, Entity> {
+        &self.uuid_to_entity
+    }
+
+    /// Applies a diff made by `ReplicationBaseline::create_tick_diff`. The world is left unchanged
+    /// if the diff holds component data that can't be read.
+    pub fn apply_tick_diff<S: BuildHasher, T: BuildHasher>(
+        &mut self,
+        diff: &WorldDiff,
+        registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, T>,
+        clone_impl: CopyCloneImpl<S>,
+    ) -> Result<(), ApplyDiffError> {
+        if !diff.has_changes() {
+            return Ok(());
+        }
+
+        let (world, uuid_to_entity) = apply_diff(
+            &self.world,
+            &self.uuid_to_entity,
+            diff,
+            registered_components,
+            clone_impl,
+        )?;
+        self.world = world;
+        self.uuid_to_entity = uuid_to_entity;
+        Ok(())
+    }
+}
+
+/// The state of the world that a client has. The sending side keeps one of these per client.
+#[derive(Default)]
+pub struct ReplicationBaseline {
+    client_world: ReplicatedWorld,
+    component_mask: ComponentMask,
+}
+
+impl ReplicationBaseline {
+    /// A baseline for a client that has nothing yet, so the first diff sends every entity
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Like `new`, but diffs for this client never include the component types excluded by the
+    /// mask, i.e. server-only components
+    pub fn new_with_component_mask(component_mask: ComponentMask) -> Self {
+        ReplicationBaseline {
+            client_world: ReplicatedWorld::default(),
+            component_mask,
+        }
+    }
+
+    pub fn component_mask(&self) -> &ComponentMask {
+        &self.component_mask
+    }
+
+    pub fn client_world(&self) -> &ReplicatedWorld {
+        &self.client_world
+    }
+
+    /// Produces the diff that brings the client's world up to date with `world`. Only entities in
+    /// `uuid_to_entity` are replicated.
+    ///
+    /// The baseline assumes the client receives every diff in order (i.e. over a reliable channel).
+    /// If a client misses a diff, replace its baseline with a new one to send it a full update.
+    pub fn create_tick_diff<S: BuildHasher, T: BuildHasher, U: BuildHasher>(
+        &mut self,
+        world: &World,
+        uuid_to_entity: &HashMap<EntityUuid, Entity, T>,
+        registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, U>,
+        clone_impl: CopyCloneImpl<S>,
+        diff_options: &DiffOptions,
+    ) -> Result<WorldDiff, ApplyDiffError> {
+        let diff = diff_worlds(
+            &self.client_world.world,
+            &self.client_world.uuid_to_entity,
+            world,
+            uuid_to_entity,
+            registered_components,
+            &self.component_mask,
+            diff_options,
+        );
+
+        // Update the baseline the same way the client will update its world. This keeps changes
+        // skipped by diff_options from getting lost, since they will be diffed against the value
+        // the client actually has.
+        self.client_world
+            .apply_tick_diff(&diff, registered_components, clone_impl)?;
+
+        Ok(diff)
+    }
+}
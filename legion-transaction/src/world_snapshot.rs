@@ -0,0 +1,127 @@
+use legion::*;
+use prefab_format::{ComponentTypeUuid, EntityUuid};
+use legion_prefab::{ComponentMask, ComponentRegistration, CopyCloneImpl, DiffOptions};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use crate::component_diffs::{apply_diff, ApplyDiffError, WorldDiff};
+use crate::transactions::diff_worlds;
+
+/// A copy of the entities of a world at some point in time, i.e. the first frame of a replay or
+/// the last confirmed frame in rollback netcode. Later states of the world are stored as deltas
+/// against it with `delta`, and rebuilt with `reconstruct`.
+///
+/// The snapshot itself is serialized by converting it to a diff from an empty world with
+/// `to_diff`, so snapshots and deltas are stored the same way.
+#[derive(Default)]
+pub struct WorldSnapshot {
+    world: World,
+    uuid_to_entity: HashMap<EntityUuid, Entity>,
+}
+
+impl WorldSnapshot {
+    /// Copies the entities in `uuid_to_entity` out of `world`
+    pub fn capture<S: BuildHasher, T: BuildHasher>(
+        world: &World,
+        uuid_to_entity: &HashMap<EntityUuid, Entity, T>,
+        mut clone_impl: CopyCloneImpl<S>,
+    ) -> Self {
+        let mut snapshot = WorldSnapshot::default();
+        for (entity_uuid, entity) in uuid_to_entity {
+            if world.contains(*entity) {
+                let snapshot_entity =
+                    snapshot
+                        .world
+                        .clone_from_single(world, *entity, &mut clone_impl);
+                snapshot
+                    .uuid_to_entity
+                    .insert(*entity_uuid, snapshot_entity);
+            }
+        }
+
+        snapshot
+    }
+
+    /// Rebuilds a snapshot from a diff created by `to_diff`
+    pub fn from_diff<S: BuildHasher, T: BuildHasher>(
+        diff: &WorldDiff,
+        registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, T>,
+        clone_impl: CopyCloneImpl<S>,
+    ) -> Result<Self, ApplyDiffError> {
+        let (world, uuid_to_entity) = apply_diff(
+            &World::default(),
+            &HashMap::<EntityUuid, Entity>::new(),
+            diff,
+            registered_components,
+            clone_impl,
+        )?;
+
+        Ok(WorldSnapshot {
+            world,
+            uuid_to_entity,
+        })
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn uuid_to_entity(&self) -> &HashMap<EntityUuid, Entity> {
+        &self.uuid_to_entity
+    }
+
+    /// The snapshot as a diff that adds all of its entities to an empty world. Unlike the
+    /// snapshot, the diff can be serialized.
+    pub fn to_diff<T: BuildHasher>(
+        &self,
+        registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, T>,
+    ) -> WorldDiff {
+        diff_worlds(
+            &World::default(),
+            &HashMap::<EntityUuid, Entity>::new(),
+            &self.world,
+            &self.uuid_to_entity,
+            registered_components,
+            &ComponentMask::All,
+            &DiffOptions::default(),
+        )
+    }
+
+    /// The diff from this snapshot to the entities in `uuid_to_entity` in `world`. It only holds
+    /// components that changed, so it is much smaller than a new snapshot. Component types excluded
+    /// by `component_mask` are left out, i.e. for a replay that is shared with other players.
+    pub fn delta<T: BuildHasher, U: BuildHasher>(
+        &self,
+        world: &World,
+        uuid_to_entity: &HashMap<EntityUuid, Entity, T>,
+        registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, U>,
+        component_mask: &ComponentMask,
+        diff_options: &DiffOptions,
+    ) -> WorldDiff {
+        diff_worlds(
+            &self.world,
+            &self.uuid_to_entity,
+            world,
+            uuid_to_entity,
+            registered_components,
+            component_mask,
+            diff_options,
+        )
+    }
+
+    /// Rebuilds the world a delta was made from. The snapshot is left unchanged, so any number of
+    /// deltas can be applied to it.
+    pub fn reconstruct<S: BuildHasher, T: BuildHasher>(
+        &self,
+        delta: &WorldDiff,
+        registered_components: &HashMap<ComponentTypeUuid, ComponentRegistration, T>,
+        clone_impl: CopyCloneImpl<S>,
+    ) -> Result<(World, HashMap<EntityUuid, Entity>), ApplyDiffError> {
+        apply_diff(
+            &self.world,
+            &self.uuid_to_entity,
+            delta,
+            registered_components,
+            clone_impl,
+        )
+    }
+}
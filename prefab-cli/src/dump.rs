@@ -0,0 +1,166 @@
+use crate::{load_prefab, CliError};
+use legion::world::{Entity, World};
+use legion_prefab::{ComponentRegistration, CookedPrefab, Prefab, PrefabSerdeContext};
+use prefab_format::PrefabUuid;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::path::{Path, PathBuf};
+
+// Finds the file of every prefab under a directory by its ID. Files that can't be scanned are
+// skipped, they only matter if the root prefab references them
+fn find_prefabs_by_id(dir: &Path) -> Result<HashMap<PrefabUuid, PathBuf>, CliError> {
+    let files =
+        legion_prefab::find_prefab_files(dir).map_err(|e| CliError::Io(dir.to_path_buf(), e))?;
+
+    let mut prefabs = HashMap::new();
+    for file in files {
+        let bytes = std::fs::read(&file).map_err(|e| CliError::Io(file.clone(), e))?;
+        if let Ok(summary) = prefab_format::scan::scan_prefab(&bytes) {
+            prefabs.insert(summary.prefab_id, file);
+        }
+    }
+    Ok(prefabs)
+}
+
+/// Cooks a prefab with every prefab it references, found under `search_dir`, and prints the
+/// resolved entities and component values
+pub fn dump_prefab<T: BuildHasher>(
+    root: &Path,
+    search_dir: &Path,
+    json: bool,
+    context: PrefabSerdeContext<T>,
+) -> Result<(), CliError> {
+    let root_prefab = load_prefab(root, context)?;
+    let root_id = root_prefab.prefab_meta.id;
+    let prefab_files = find_prefabs_by_id(search_dir)?;
+
+    // Referenced prefabs are loaded as the cook order finds them
+    let mut prefabs: HashMap<PrefabUuid, Prefab> = HashMap::new();
+    prefabs.insert(root_id, root_prefab);
+    let mut load_error = None;
+    let cook_order = legion_prefab::prefab_cook_order(&root_id, |prefab_id| {
+        if !prefabs.contains_key(prefab_id) {
+            let path = prefab_files.get(prefab_id)?;
+            match load_prefab(path, context) {
+                Ok(prefab) => prefabs.insert(*prefab_id, prefab),
+                Err(e) => {
+                    load_error.get_or_insert(e);
+                    return None;
+                }
+            };
+        }
+        Some(
+            prefabs[prefab_id]
+                .prefab_meta
+                .prefab_refs
+                .keys()
+                .copied()
+                .collect(),
+        )
+    });
+    if let Some(e) = load_error {
+        return Err(e);
+    }
+    let cook_order = cook_order.map_err(|e| CliError::Cook(root.to_path_buf(), e))?;
+
+    let registered_components: HashMap<_, _> = context
+        .registered_components
+        .values()
+        .map(|registration| (registration.component_type_id(), registration.clone()))
+        .collect();
+    let prefab_lookup: HashMap<PrefabUuid, &Prefab> =
+        prefabs.iter().map(|(id, prefab)| (*id, prefab)).collect();
+    let cooked = legion_prefab::cook_prefab(
+        &registered_components,
+        context.registered_components,
+        &cook_order,
+        &prefab_lookup,
+    )
+    .map_err(|e| {
+        CliError::Dump(
+            root.to_path_buf(),
+            format!("failed to apply parameter: {:?}", e),
+        )
+    })?;
+
+    let mut registrations: Vec<&ComponentRegistration> =
+        context.registered_components.values().collect();
+    registrations.sort_by_key(|registration| registration.type_name());
+    let dumped = DumpedPrefab {
+        cooked: &cooked,
+        registrations,
+    };
+
+    let dump_error = |e: String| CliError::Dump(root.to_path_buf(), e);
+    let text = if json {
+        serde_json::to_string_pretty(&dumped).map_err(|e| dump_error(e.to_string()))?
+    } else {
+        ron::ser::to_string_pretty(&dumped, legion_prefab::canonical_pretty_config())
+            .map_err(|e| dump_error(e.to_string()))?
+    };
+    println!("{}", text);
+    Ok(())
+}
+
+// The cooked entities by UUID, each a map of component type name to component value
+struct DumpedPrefab<'a> {
+    cooked: &'a CookedPrefab,
+    registrations: Vec<&'a ComponentRegistration>,
+}
+
+impl Serialize for DumpedPrefab<'_> {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut entities: Vec<_> = self.cooked.entities.iter().collect();
+        entities.sort_by_key(|(entity_uuid, _)| **entity_uuid);
+
+        let mut map = serializer.serialize_map(Some(entities.len()))?;
+        for (entity_uuid, entity) in entities {
+            map.serialize_entry(
+                &uuid::Uuid::from_bytes(*entity_uuid).to_string(),
+                &DumpedEntity {
+                    world: &self.cooked.world,
+                    entity: *entity,
+                    registrations: &self.registrations,
+                },
+            )?;
+        }
+        map.end()
+    }
+}
+
+struct DumpedEntity<'a> {
+    world: &'a World,
+    entity: Entity,
+    registrations: &'a [&'a ComponentRegistration],
+}
+
+impl Serialize for DumpedEntity<'_> {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let entry = self
+            .world
+            .entry_ref(self.entity)
+            .expect("cooked entity should be in the cooked world");
+        let layout = entry.archetype().layout();
+
+        let mut map = serializer.serialize_map(None)?;
+        for registration in self.registrations {
+            if !layout.has_component_by_id(registration.component_type_id()) {
+                continue;
+            }
+
+            let mut result = Ok(());
+            registration.serialize_single(self.world, self.entity, &mut |component| {
+                result = map.serialize_entry(registration.type_name(), component);
+            });
+            result?;
+        }
+        map.end()
+    }
+}
@@ -0,0 +1,279 @@
+//! Command line tools for working with prefab source files.
+//!
+//! Prefabs can only be loaded when their component types are registered, and registrations are
+//! collected from every crate linked into the binary. The `prefab` binary in this crate has no
+//! components of its own, so games should build their own binary that links in their component
+//! crates and calls `run_from_args`:
+//!
+//! ```ignore
+//! // Make sure the crate registering your components is linked
+//! use my_game_components as _;
+//!
+//! fn main() {
+//!     prefab_cli::run_from_args();
+//! }
+//! ```
+use legion_prefab::{
+    ComponentRegistration, FormatPrefabError, Prefab, PrefabCookOrderError, PrefabDiffReport,
+    PrefabSerdeContext,
+};
+use prefab_format::rewrite::RewriteError;
+use prefab_format::ron_patch::RonPatchError;
+use prefab_format::ComponentTypeUuid;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+mod dump;
+mod merge;
+mod upgrade;
+
+#[derive(StructOpt)]
+#[structopt(name = "prefab", about = "Tools for working with prefab files")]
+pub enum Command {
+    /// Prints the entities, components and fields that differ between two prefab files
+    Diff {
+        #[structopt(parse(from_os_str))]
+        before: PathBuf,
+        #[structopt(parse(from_os_str))]
+        after: PathBuf,
+    },
+    /// Merges the changes made in two versions of a prefab relative to their common ancestor.
+    /// The merged prefab keeps the layout of OURS. If there are conflicts, they are printed and
+    /// nothing is written. Can be used as a git merge driver with `prefab merge %O %A %B -o %A`
+    Merge {
+        #[structopt(parse(from_os_str))]
+        base: PathBuf,
+        #[structopt(parse(from_os_str))]
+        ours: PathBuf,
+        #[structopt(parse(from_os_str))]
+        theirs: PathBuf,
+        /// Where to write the merged prefab. Printed to stdout if not set
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Applies format and registered component migrations to every .prefab file in a directory,
+    /// rewriting the files in place. Files that are already current are left alone, so it's safe
+    /// to run again after fixing files that failed
+    Upgrade {
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+        /// Print what would change without writing any files
+        #[structopt(long)]
+        dry_run: bool,
+        /// Also write the report to this file
+        #[structopt(long, parse(from_os_str))]
+        report: Option<PathBuf>,
+    },
+    /// Cooks a prefab with all the prefabs it references and prints the resolved entities and
+    /// component values, with every override applied
+    Dump {
+        #[structopt(parse(from_os_str))]
+        root: PathBuf,
+        /// Where to look for referenced prefabs. Defaults to the directory of ROOT
+        #[structopt(long, parse(from_os_str))]
+        search_dir: Option<PathBuf>,
+        /// Print JSON instead of RON
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Changes the UUID of a component type in every .prefab file in a directory, after the
+    /// component's TypeUuid was changed. Component data is kept as is
+    RewriteComponent {
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+        #[structopt(parse(try_from_str = uuid::Uuid::parse_str))]
+        old: uuid::Uuid,
+        #[structopt(parse(try_from_str = uuid::Uuid::parse_str))]
+        new: uuid::Uuid,
+        /// Print the files that would change without writing them
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Lists the prefab, entity and component type UUIDs used by every .prefab file in a
+    /// directory, flagging component types that are retired or not registered
+    Uuids {
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    Io(PathBuf, std::io::Error),
+    Load(PathBuf, ron::de::Error),
+    UpgradeFailed(usize),
+    Save(PathBuf, FormatPrefabError),
+    Scan(PathBuf, RonPatchError),
+    Cook(PathBuf, PrefabCookOrderError),
+    Dump(PathBuf, String),
+    MergeConflicts(usize),
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        match self {
+            CliError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            CliError::Load(path, e) => {
+                write!(f, "{}: failed to load prefab: {}", path.display(), e)
+            }
+            CliError::UpgradeFailed(count) => {
+                write!(f, "{} prefab file(s) failed to upgrade", count)
+            }
+            CliError::Save(path, e) => {
+                write!(f, "{}: failed to save prefab: {:?}", path.display(), e)
+            }
+            CliError::Scan(path, e) => {
+                write!(f, "{}: failed to scan prefab: {:?}", path.display(), e)
+            }
+            CliError::Cook(path, e) => {
+                write!(
+                    f,
+                    "{}: failed to resolve referenced prefabs: {:?}",
+                    path.display(),
+                    e
+                )
+            }
+            CliError::Dump(path, e) => {
+                write!(f, "{}: failed to print prefab: {}", path.display(), e)
+            }
+            CliError::MergeConflicts(count) => write!(f, "merge failed with {} conflict(s)", count),
+        }
+    }
+}
+
+/// Runs a command, writing its output to stdout
+pub fn run<T: BuildHasher>(
+    command: Command,
+    context: PrefabSerdeContext<T>,
+) -> Result<(), CliError> {
+    match command {
+        Command::Diff { before, after } => {
+            let before_prefab = load_prefab(&before, context)?;
+            let after_prefab = load_prefab(&after, context)?;
+            let prefab_diff = legion_prefab::diff_prefabs(&before_prefab, &after_prefab, context);
+            print!("{}", PrefabDiffReport::new(prefab_diff, context));
+        }
+        Command::Merge {
+            base,
+            ours,
+            theirs,
+            output,
+        } => merge::merge_files(&base, &ours, &theirs, output.as_deref(), context)?,
+        Command::Upgrade {
+            dir,
+            dry_run,
+            report,
+        } => upgrade::upgrade_directory(&dir, dry_run, report.as_deref())?,
+        Command::Dump {
+            root,
+            search_dir,
+            json,
+        } => {
+            let search_dir = search_dir
+                .or_else(|| root.parent().map(Path::to_path_buf))
+                .unwrap_or_default();
+            dump::dump_prefab(&root, &search_dir, json, context)?
+        }
+        Command::RewriteComponent {
+            dir,
+            old,
+            new,
+            dry_run,
+        } => rewrite_component(&dir, old, new, dry_run)?,
+        Command::Uuids { dir } => print_uuid_report(&dir)?,
+    }
+
+    Ok(())
+}
+
+/// Parses the command line and runs the command using every component registered in the binary.
+/// Exits the process with an error code if the command fails.
+pub fn run_from_args() {
+    let command = Command::from_args();
+
+    let registered_components: HashMap<ComponentTypeUuid, ComponentRegistration> =
+        legion_prefab::iter_component_registrations()
+            .filter(|reg| !reg.is_clone_only())
+            .map(|reg| (*reg.uuid(), reg.clone()))
+            .collect();
+    let context = PrefabSerdeContext {
+        registered_components: &registered_components,
+    };
+
+    if let Err(e) = run(command, context) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Rewrites a component type UUID in every `.prefab` file under a directory, printing the files
+/// that changed
+pub fn rewrite_component(
+    dir: &Path,
+    old: uuid::Uuid,
+    new: uuid::Uuid,
+    dry_run: bool,
+) -> Result<(), CliError> {
+    let files =
+        legion_prefab::find_prefab_files(dir).map_err(|e| CliError::Io(dir.to_path_buf(), e))?;
+
+    let changed = prefab_format::rewrite::rewrite_component_uuid_in_files(
+        &files,
+        old.as_bytes(),
+        new.as_bytes(),
+        dry_run,
+    )
+    .map_err(|e| match e {
+        RewriteError::Io(path, e) => CliError::Io(path, e),
+        RewriteError::Parse(path, e) => CliError::Scan(path, e),
+    })?;
+    for file in &changed {
+        println!("{}", file.display());
+    }
+
+    let verb = if dry_run {
+        "would be rewritten"
+    } else {
+        "rewritten"
+    };
+    println!("{} of {} prefab files {}", changed.len(), files.len(), verb);
+    Ok(())
+}
+
+/// Prints the UUIDs used by every `.prefab` file under a directory
+pub fn print_uuid_report(dir: &Path) -> Result<(), CliError> {
+    let files =
+        legion_prefab::find_prefab_files(dir).map_err(|e| CliError::Io(dir.to_path_buf(), e))?;
+
+    let mut summaries = vec![];
+    for file in &files {
+        let bytes = std::fs::read(file).map_err(|e| CliError::Io(file.to_path_buf(), e))?;
+        let summary = prefab_format::scan::scan_prefab(&bytes)
+            .map_err(|e| CliError::Scan(file.to_path_buf(), e))?;
+        summaries.push(summary);
+    }
+
+    let report = legion_prefab::uuid_report(&summaries, legion_prefab::global_component_registry());
+    print!("{}", report);
+    Ok(())
+}
+
+/// Loads a RON prefab source file
+pub fn load_prefab<T: BuildHasher>(
+    path: &Path,
+    context: PrefabSerdeContext<T>,
+) -> Result<Prefab, CliError> {
+    let source = std::fs::read_to_string(path).map_err(|e| CliError::Io(path.to_path_buf(), e))?;
+    let load_error = |e| CliError::Load(path.to_path_buf(), e);
+
+    let mut de = ron::de::Deserializer::from_str(&source).map_err(load_error)?;
+    let prefab_deser = legion_prefab::PrefabFormatDeserializer::new(context);
+    prefab_format::deserialize(&mut de, &prefab_deser).map_err(load_error)?;
+    de.end().map_err(load_error)?;
+    Ok(prefab_deser.prefab())
+}
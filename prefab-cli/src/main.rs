@@ -0,0 +1,5 @@
+// This binary only knows about components registered by the crates it links. See the crate docs
+// for building a binary that includes your own components.
+fn main() {
+    prefab_cli::run_from_args();
+}
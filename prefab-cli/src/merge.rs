@@ -0,0 +1,118 @@
+use crate::{load_prefab, CliError};
+use legion_prefab::{MergeConflict, PrefabSerdeContext};
+use std::hash::BuildHasher;
+use std::path::Path;
+
+fn describe_conflict(conflict: &MergeConflict) -> String {
+    let uuid_str = |bytes: &uuid::Bytes| uuid::Uuid::from_bytes(*bytes).to_string();
+    match conflict {
+        MergeConflict::EntityRemovedAndChanged { entity } => format!(
+            "entity {} was removed on one side and changed on the other",
+            uuid_str(entity)
+        ),
+        MergeConflict::ComponentAddedDifferently {
+            entity,
+            component_type,
+        } => format!(
+            "component {} was added to entity {} differently on both sides",
+            uuid_str(component_type),
+            uuid_str(entity)
+        ),
+        MergeConflict::ComponentRemovedAndChanged {
+            entity,
+            component_type,
+        } => format!(
+            "component {} on entity {} was removed on one side and changed on the other",
+            uuid_str(component_type),
+            uuid_str(entity)
+        ),
+        MergeConflict::FieldChangedDifferently {
+            entity,
+            component_type,
+            path,
+        } => format!(
+            "field {} of component {} on entity {} was changed differently on both sides",
+            path,
+            uuid_str(component_type),
+            uuid_str(entity)
+        ),
+        MergeConflict::PrefabRefRemovedAndChanged { prefab_ref } => format!(
+            "prefab ref {} was removed on one side and its overrides changed on the other",
+            uuid_str(prefab_ref)
+        ),
+        MergeConflict::OverrideChangedDifferently {
+            prefab_ref,
+            entity,
+            component_type,
+        } => format!(
+            "override of component {} on entity {} in prefab ref {} was changed differently on both sides",
+            uuid_str(component_type),
+            uuid_str(entity),
+            uuid_str(prefab_ref)
+        ),
+        MergeConflict::ParametersChangedDifferently => {
+            "the parameter declarations were changed differently on both sides".to_string()
+        }
+        MergeConflict::ExtendsChangedDifferently => {
+            "the prefab was changed to extend different prefabs on both sides".to_string()
+        }
+        MergeConflict::ParameterValueChangedDifferently { prefab_ref, name } => format!(
+            "parameter {} of prefab ref {} was set to different values on both sides",
+            name,
+            uuid_str(prefab_ref)
+        ),
+        MergeConflict::LayerChangedDifferently { entity } => format!(
+            "entity {} was moved to different layers on both sides",
+            uuid_str(entity)
+        ),
+        MergeConflict::BlobChangedDifferently { blob } => format!(
+            "blob {} was changed differently on both sides",
+            uuid_str(blob)
+        ),
+        MergeConflict::ChildrenChangedDifferently { parent } => format!(
+            "the children of entity {} were changed differently on both sides",
+            uuid_str(parent)
+        ),
+        MergeConflict::PrefabRefTransformChangedDifferently { prefab_ref } => format!(
+            "the offset of prefab ref {} was changed differently on both sides",
+            uuid_str(prefab_ref)
+        ),
+    }
+}
+
+pub fn merge_files<T: BuildHasher>(
+    base: &Path,
+    ours: &Path,
+    theirs: &Path,
+    output: Option<&Path>,
+    context: PrefabSerdeContext<T>,
+) -> Result<(), CliError> {
+    let base_prefab = load_prefab(base, context)?;
+    let our_prefab = load_prefab(ours, context)?;
+    let their_prefab = load_prefab(theirs, context)?;
+
+    let merged =
+        match legion_prefab::merge_prefabs(&base_prefab, &our_prefab, &their_prefab, context) {
+            Ok(merged) => merged,
+            Err(conflicts) => {
+                for conflict in &conflicts {
+                    eprintln!("conflict: {}", describe_conflict(conflict));
+                }
+                return Err(CliError::MergeConflicts(conflicts.len()));
+            }
+        };
+
+    // Written over our version of the file so its comments and layout are kept where possible
+    let our_source =
+        std::fs::read_to_string(ours).map_err(|e| CliError::Io(ours.to_path_buf(), e))?;
+    let merged_source = legion_prefab::save_prefab_preserving_format(&our_source, &merged, context)
+        .map_err(|e| CliError::Save(ours.to_path_buf(), e))?;
+
+    match output {
+        Some(output) => std::fs::write(output, merged_source)
+            .map_err(|e| CliError::Io(output.to_path_buf(), e))?,
+        None => print!("{}", merged_source),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use crate::CliError;
+use legion_prefab::ComponentMigration;
+use prefab_format::ComponentTypeUuid;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Upgrades every `.prefab` file under a directory with the registered migrations, printing what
+/// changed and optionally writing the same report to a file
+pub fn upgrade_directory(
+    dir: &Path,
+    dry_run: bool,
+    report_path: Option<&Path>,
+) -> Result<(), CliError> {
+    let migrations: HashMap<ComponentTypeUuid, ComponentMigration> =
+        legion_prefab::iter_component_migrations()
+            .map(|migration| (*migration.from_type(), migration.clone()))
+            .collect();
+
+    let report = legion_prefab::migrate_prefab_directory(dir, &migrations, dry_run)
+        .map_err(|e| CliError::Io(dir.to_path_buf(), e))?;
+    print!("{}", report);
+    if let Some(report_path) = report_path {
+        std::fs::write(report_path, report.to_string())
+            .map_err(|e| CliError::Io(report_path.to_path_buf(), e))?;
+    }
+
+    if report.failed.is_empty() {
+        Ok(())
+    } else {
+        Err(CliError::UpgradeFailed(report.failed.len()))
+    }
+}
@@ -0,0 +1,78 @@
+//! References from component data to large binary payloads (baked meshes, heightmaps) that are
+//! kept out of the component data itself.
+//!
+//! A component holds a `BlobRef`, which is only an ID. The data is either stored in the `blobs`
+//! section of the prefab (see `StorageDeserializer::declare_blob` and
+//! `StorageSerializer::blobs`), or in a file next to the prefab (see `external_blob_path`).
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_diff::SerdeDiff;
+use std::path::{Path, PathBuf};
+
+pub type BlobId = uuid::Bytes;
+
+/// A reference to binary data stored outside of the component that holds it
+#[derive(Serialize, Deserialize, SerdeDiff, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde_diff(opaque)]
+pub struct BlobRef(#[serde(with = "crate::uuid_bytes")] pub BlobId);
+
+impl BlobRef {
+    pub fn id(&self) -> &BlobId {
+        &self.0
+    }
+}
+
+/// The contents of a blob. Serialized with `crate::bytes`, so text formats write it as base64.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlobData(pub Vec<u8>);
+
+impl Serialize for BlobData {
+    fn serialize<S>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        crate::bytes::serialize(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BlobData {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        crate::bytes::deserialize(deserializer).map(BlobData)
+    }
+}
+
+/// Where a blob that isn't stored in the prefab is kept: `<prefab file>.blobs/<blob id>.bin`
+pub fn external_blob_path(
+    prefab_path: &Path,
+    blob: &BlobId,
+) -> PathBuf {
+    let mut blob_dir = prefab_path.as_os_str().to_owned();
+    blob_dir.push(".blobs");
+    PathBuf::from(blob_dir).join(format!("{}.bin", uuid::Uuid::from_bytes(*blob)))
+}
+
+/// Reads a blob stored next to a prefab file
+pub fn read_external_blob(
+    prefab_path: &Path,
+    blob: &BlobId,
+) -> std::io::Result<BlobData> {
+    std::fs::read(external_blob_path(prefab_path, blob)).map(BlobData)
+}
+
+/// Writes a blob next to a prefab file, creating the blob directory if needed
+pub fn write_external_blob(
+    prefab_path: &Path,
+    blob: &BlobId,
+    data: &BlobData,
+) -> std::io::Result<()> {
+    let path = external_blob_path(prefab_path, blob);
+    if let Some(blob_dir) = path.parent() {
+        std::fs::create_dir_all(blob_dir)?;
+    }
+    std::fs::write(path, &data.0)
+}
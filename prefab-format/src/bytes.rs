@@ -0,0 +1,117 @@
+//! Serde helpers for byte buffers in component data.
+//!
+//! A plain `Vec<u8>` serializes as a list of integers, which makes binary-ish component data
+//! several times larger than it needs to be in text formats. Human-readable formats get a base64
+//! string instead, and binary formats get raw bytes. Integer lists are still accepted when
+//! deserializing, so existing prefabs keep loading.
+//!
+//! Use with `#[serde(with = "prefab_format::bytes")]` on a `Vec<u8>` field, or use `ByteBuf`,
+//! which also keeps component override diffs compact.
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_diff::SerdeDiff;
+
+pub fn serialize<S: Serializer>(
+    bytes: &[u8],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&base64::encode(bytes))
+    } else {
+        serializer.serialize_bytes(bytes)
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    ByteBuf::deserialize(deserializer).map(|x| x.0)
+}
+
+/// A `Vec<u8>` wrapper that implements the (de)serialization described in the module docs. Diffs
+/// of it replace the whole buffer rather than listing changed elements.
+#[derive(SerdeDiff, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[serde_diff(opaque)]
+pub struct ByteBuf(pub Vec<u8>);
+
+impl Serialize for ByteBuf {
+    fn serialize<S>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ByteBufVisitor;
+        impl<'de> Visitor<'de> for ByteBufVisitor {
+            type Value = ByteBuf;
+
+            fn expecting(
+                &self,
+                formatter: &mut std::fmt::Formatter,
+            ) -> std::fmt::Result {
+                formatter.write_str("a base64 string or bytes")
+            }
+
+            fn visit_str<E>(
+                self,
+                value: &str,
+            ) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                base64::decode(value).map(ByteBuf).map_err(E::custom)
+            }
+
+            fn visit_bytes<E>(
+                self,
+                value: &[u8],
+            ) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(ByteBuf(value.to_vec()))
+            }
+
+            fn visit_byte_buf<E>(
+                self,
+                value: Vec<u8>,
+            ) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(ByteBuf(value))
+            }
+
+            // Byte buffers written before this module was used are lists of integers
+            fn visit_seq<A>(
+                self,
+                mut seq: A,
+            ) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(ByteBuf(bytes))
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(ByteBufVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(ByteBufVisitor)
+        }
+    }
+}
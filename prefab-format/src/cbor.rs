@@ -0,0 +1,36 @@
+//! Helpers for reading and writing prefabs as CBOR.
+//!
+//! Structs are written with named fields and prefab objects as single-entry maps (serde_cbor's
+//! defaults). Packed encodings are not supported because the prefab deserializer identifies
+//! fields by name.
+use crate::{PrefabUuid, StorageDeserializer, StorageSerializer};
+
+/// Reads a CBOR prefab from a byte slice, calling into `storage` as objects are encountered
+pub fn from_slice<'a, S: StorageDeserializer>(
+    bytes: &'a [u8],
+    storage: &'a S,
+) -> Result<(), serde_cbor::Error> {
+    let mut deserializer = serde_cbor::Deserializer::from_slice(bytes);
+    crate::deserialize(&mut deserializer, storage)?;
+    deserializer.end()
+}
+
+/// Writes the prefab provided by `storage` as CBOR
+pub fn to_writer<W: std::io::Write, SS: StorageSerializer>(
+    writer: W,
+    storage: &SS,
+    prefab_id: PrefabUuid,
+) -> Result<(), serde_cbor::Error> {
+    let mut serializer = serde_cbor::Serializer::new(serde_cbor::ser::IoWrite::new(writer));
+    crate::serialize(&mut serializer, storage, prefab_id)
+}
+
+/// Serializes the prefab provided by `storage` to a CBOR byte vector
+pub fn to_vec<SS: StorageSerializer>(
+    storage: &SS,
+    prefab_id: PrefabUuid,
+) -> Result<Vec<u8>, serde_cbor::Error> {
+    let mut bytes = Vec::new();
+    to_writer(&mut bytes, storage, prefab_id)?;
+    Ok(bytes)
+}
@@ -0,0 +1,992 @@
+//! Prefab documents with the `StorageDeserializer` calls they must produce, so that other
+//! implementations of the format (or of a back-end that reads it) can check that they interpret it
+//! the same way as this crate.
+//!
+//! ```ignore
+//! for case in prefab_format::conformance::cases() {
+//!     let result = prefab_format::conformance::run_case(&case, |source, storage| {
+//!         let mut de = ron::de::Deserializer::from_str(source)?;
+//!         prefab_format::deserialize(&mut de, storage)
+//!     });
+//!     assert!(result.is_ok(), "{}: {:?}", case.name, result);
+//! }
+//! ```
+//!
+//! Documents are RON. Component data is not part of what's checked, only that it is delivered for
+//! the right entity and component type.
+use crate::blobs::{BlobData, BlobId};
+use crate::{
+    ComponentTypeUuid, EntityUuid, ParameterBinding, PrefabParameter, PrefabRefTransform,
+    PrefabUuid, StorageDeserializer, TemplateUuid,
+};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer};
+use std::cell::RefCell;
+
+/// A call made to `StorageDeserializer`
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageEvent {
+    BeginPrefab(PrefabUuid),
+    BeginEntityObject {
+        prefab: PrefabUuid,
+        entity: EntityUuid,
+    },
+    EndEntityObject {
+        prefab: PrefabUuid,
+        entity: EntityUuid,
+    },
+    Component {
+        prefab: PrefabUuid,
+        entity: EntityUuid,
+        component_type: ComponentTypeUuid,
+    },
+    BeginPrefabRef {
+        prefab: PrefabUuid,
+        target_prefab: PrefabUuid,
+    },
+    EndPrefabRef {
+        prefab: PrefabUuid,
+        target_prefab: PrefabUuid,
+    },
+    ComponentDiff {
+        parent_prefab: PrefabUuid,
+        prefab_ref: PrefabUuid,
+        entity: EntityUuid,
+        component_type: ComponentTypeUuid,
+        diff: String,
+    },
+    Parameter {
+        prefab: PrefabUuid,
+        parameter: PrefabParameter,
+    },
+    ParameterValue {
+        parent_prefab: PrefabUuid,
+        prefab_ref: PrefabUuid,
+        name: String,
+        value: String,
+    },
+    PrefabRefTransform {
+        parent_prefab: PrefabUuid,
+        prefab_ref: PrefabUuid,
+        transform: PrefabRefTransform,
+    },
+    Layer {
+        prefab: PrefabUuid,
+        name: String,
+        entities: Vec<EntityUuid>,
+    },
+    Children {
+        prefab: PrefabUuid,
+        parent: EntityUuid,
+        children: Vec<EntityUuid>,
+    },
+    ComponentTypeName {
+        prefab: PrefabUuid,
+        name: String,
+        component_type: ComponentTypeUuid,
+    },
+    Blob {
+        prefab: PrefabUuid,
+        blob: BlobId,
+        data: BlobData,
+    },
+    BasePrefab {
+        prefab: PrefabUuid,
+        base_prefab: PrefabUuid,
+    },
+    TemplateComponent {
+        prefab: PrefabUuid,
+        template: TemplateUuid,
+        component_type: ComponentTypeUuid,
+    },
+    InstantiateTemplate {
+        prefab: PrefabUuid,
+        entity: EntityUuid,
+        template: TemplateUuid,
+    },
+    TemplateDiff {
+        prefab: PrefabUuid,
+        entity: EntityUuid,
+        component_type: ComponentTypeUuid,
+        diff: String,
+    },
+}
+
+/// A `StorageDeserializer` that records every call made to it
+#[derive(Default)]
+pub struct RecordingStorage {
+    events: RefCell<Vec<StorageEvent>>,
+}
+
+impl RecordingStorage {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn into_events(self) -> Vec<StorageEvent> {
+        self.events.into_inner()
+    }
+
+    fn record(
+        &self,
+        event: StorageEvent,
+    ) {
+        self.events.borrow_mut().push(event);
+    }
+}
+
+impl StorageDeserializer for RecordingStorage {
+    fn begin_prefab(
+        &self,
+        prefab: &PrefabUuid,
+    ) {
+        self.record(StorageEvent::BeginPrefab(*prefab));
+    }
+    fn begin_entity_object(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+    ) {
+        self.record(StorageEvent::BeginEntityObject {
+            prefab: *prefab,
+            entity: *entity,
+        });
+    }
+    fn end_entity_object(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+    ) {
+        self.record(StorageEvent::EndEntityObject {
+            prefab: *prefab,
+            entity: *entity,
+        });
+    }
+    fn deserialize_component<'de, D: Deserializer<'de>>(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        IgnoredAny::deserialize(deserializer)?;
+        self.record(StorageEvent::Component {
+            prefab: *prefab,
+            entity: *entity,
+            component_type: *component_type,
+        });
+        Ok(())
+    }
+    fn begin_prefab_ref(
+        &self,
+        prefab: &PrefabUuid,
+        target_prefab: &PrefabUuid,
+    ) {
+        self.record(StorageEvent::BeginPrefabRef {
+            prefab: *prefab,
+            target_prefab: *target_prefab,
+        });
+    }
+    fn end_prefab_ref(
+        &self,
+        prefab: &PrefabUuid,
+        target_prefab: &PrefabUuid,
+    ) {
+        self.record(StorageEvent::EndPrefabRef {
+            prefab: *prefab,
+            target_prefab: *target_prefab,
+        });
+    }
+    fn apply_component_diff<'de, D: Deserializer<'de>>(
+        &self,
+        parent_prefab: &PrefabUuid,
+        prefab_ref: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        let diff = String::deserialize(deserializer)?;
+        self.record(StorageEvent::ComponentDiff {
+            parent_prefab: *parent_prefab,
+            prefab_ref: *prefab_ref,
+            entity: *entity,
+            component_type: *component_type,
+            diff,
+        });
+        Ok(())
+    }
+    fn declare_parameter(
+        &self,
+        prefab: &PrefabUuid,
+        parameter: PrefabParameter,
+    ) {
+        self.record(StorageEvent::Parameter {
+            prefab: *prefab,
+            parameter,
+        });
+    }
+    fn set_parameter_value(
+        &self,
+        parent_prefab: &PrefabUuid,
+        prefab_ref: &PrefabUuid,
+        name: &str,
+        value: &str,
+    ) {
+        self.record(StorageEvent::ParameterValue {
+            parent_prefab: *parent_prefab,
+            prefab_ref: *prefab_ref,
+            name: name.to_string(),
+            value: value.to_string(),
+        });
+    }
+    fn set_prefab_ref_transform(
+        &self,
+        parent_prefab: &PrefabUuid,
+        prefab_ref: &PrefabUuid,
+        transform: &PrefabRefTransform,
+    ) {
+        self.record(StorageEvent::PrefabRefTransform {
+            parent_prefab: *parent_prefab,
+            prefab_ref: *prefab_ref,
+            transform: *transform,
+        });
+    }
+    fn declare_layer(
+        &self,
+        prefab: &PrefabUuid,
+        name: &str,
+        entities: &[EntityUuid],
+    ) {
+        self.record(StorageEvent::Layer {
+            prefab: *prefab,
+            name: name.to_string(),
+            entities: entities.to_vec(),
+        });
+    }
+    fn declare_children(
+        &self,
+        prefab: &PrefabUuid,
+        parent: &EntityUuid,
+        children: &[EntityUuid],
+    ) {
+        self.record(StorageEvent::Children {
+            prefab: *prefab,
+            parent: *parent,
+            children: children.to_vec(),
+        });
+    }
+    fn declare_component_type_name(
+        &self,
+        prefab: &PrefabUuid,
+        name: &str,
+        component_type: &ComponentTypeUuid,
+    ) {
+        self.record(StorageEvent::ComponentTypeName {
+            prefab: *prefab,
+            name: name.to_string(),
+            component_type: *component_type,
+        });
+    }
+    fn declare_blob(
+        &self,
+        prefab: &PrefabUuid,
+        blob: &BlobId,
+        data: BlobData,
+    ) {
+        self.record(StorageEvent::Blob {
+            prefab: *prefab,
+            blob: *blob,
+            data,
+        });
+    }
+    fn set_base_prefab(
+        &self,
+        prefab: &PrefabUuid,
+        base_prefab: &PrefabUuid,
+    ) {
+        self.record(StorageEvent::BasePrefab {
+            prefab: *prefab,
+            base_prefab: *base_prefab,
+        });
+    }
+    fn deserialize_template_component<'de, D: Deserializer<'de>>(
+        &self,
+        prefab: &PrefabUuid,
+        template: &TemplateUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        IgnoredAny::deserialize(deserializer)?;
+        self.record(StorageEvent::TemplateComponent {
+            prefab: *prefab,
+            template: *template,
+            component_type: *component_type,
+        });
+        Ok(())
+    }
+    fn instantiate_template(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+        template: &TemplateUuid,
+    ) {
+        self.record(StorageEvent::InstantiateTemplate {
+            prefab: *prefab,
+            entity: *entity,
+            template: *template,
+        });
+    }
+    fn apply_template_diff<'de, D: Deserializer<'de>>(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        let diff = String::deserialize(deserializer)?;
+        self.record(StorageEvent::TemplateDiff {
+            prefab: *prefab,
+            entity: *entity,
+            component_type: *component_type,
+            diff,
+        });
+        Ok(())
+    }
+}
+
+/// A prefab document and how it must be interpreted
+#[derive(Debug, Clone)]
+pub struct ConformanceCase {
+    pub name: &'static str,
+    /// RON source text
+    pub source: &'static str,
+    /// The calls the document produces, in order, or None if it must be rejected
+    pub expected: Option<Vec<StorageEvent>>,
+}
+
+/// Deserializes a case with `deserialize` and compares the calls it made to the expected ones.
+/// Returns a description of the first mismatch.
+pub fn run_case<E: std::fmt::Debug, F>(
+    case: &ConformanceCase,
+    deserialize: F,
+) -> Result<(), String>
+where
+    F: FnOnce(&str, &RecordingStorage) -> Result<(), E>,
+{
+    let storage = RecordingStorage::new();
+    let result = deserialize(case.source, &storage);
+    match (&case.expected, result) {
+        (None, Ok(())) => Err("the document was accepted but must be rejected".to_string()),
+        (None, Err(_)) => Ok(()),
+        (Some(_), Err(error)) => Err(format!("the document was rejected: {:?}", error)),
+        (Some(expected), Ok(())) => {
+            let events = storage.into_events();
+            for (index, (expected, event)) in expected.iter().zip(&events).enumerate() {
+                if expected != event {
+                    return Err(format!(
+                        "call {} was {:?}, expected {:?}",
+                        index, event, expected
+                    ));
+                }
+            }
+            if events.len() != expected.len() {
+                return Err(format!(
+                    "{} calls were made, expected {}",
+                    events.len(),
+                    expected.len()
+                ));
+            }
+            Ok(())
+        }
+    }
+}
+
+const PREFAB: PrefabUuid = [0x10; 16];
+const OTHER_PREFAB: PrefabUuid = [0x20; 16];
+const ENTITY_A: EntityUuid = [0x01; 16];
+const ENTITY_B: EntityUuid = [0x02; 16];
+const POSITION: ComponentTypeUuid = [0xa1; 16];
+const VELOCITY: ComponentTypeUuid = [0xa2; 16];
+const TEMPLATE: TemplateUuid = [0x30; 16];
+
+/// All conformance cases
+pub fn cases() -> Vec<ConformanceCase> {
+    use StorageEvent::*;
+    vec![
+        ConformanceCase {
+            name: "empty prefab",
+            source: r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [],
+)"#,
+            expected: Some(vec![BeginPrefab(PREFAB)]),
+        },
+        ConformanceCase {
+            name: "current version",
+            source: r#"Prefab(
+    version: 1,
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [],
+)"#,
+            expected: Some(vec![BeginPrefab(PREFAB)]),
+        },
+        ConformanceCase {
+            name: "entities with components",
+            source: r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [
+        Entity(PrefabEntity(
+            id: "01010101-0101-0101-0101-010101010101",
+            components: [
+                EntityComponent(
+                    type: "a1a1a1a1-a1a1-a1a1-a1a1-a1a1a1a1a1a1",
+                    data: (x: 1.0, y: 2.0),
+                ),
+                EntityComponent(
+                    type: "a2a2a2a2-a2a2-a2a2-a2a2-a2a2a2a2a2a2",
+                    data: (x: 0.0, y: -1.0),
+                ),
+            ],
+        )),
+        Entity(PrefabEntity(
+            id: "02020202-0202-0202-0202-020202020202",
+            components: [],
+        )),
+    ],
+)"#,
+            expected: Some(vec![
+                BeginPrefab(PREFAB),
+                BeginEntityObject {
+                    prefab: PREFAB,
+                    entity: ENTITY_A,
+                },
+                Component {
+                    prefab: PREFAB,
+                    entity: ENTITY_A,
+                    component_type: POSITION,
+                },
+                Component {
+                    prefab: PREFAB,
+                    entity: ENTITY_A,
+                    component_type: VELOCITY,
+                },
+                EndEntityObject {
+                    prefab: PREFAB,
+                    entity: ENTITY_A,
+                },
+                BeginEntityObject {
+                    prefab: PREFAB,
+                    entity: ENTITY_B,
+                },
+                EndEntityObject {
+                    prefab: PREFAB,
+                    entity: ENTITY_B,
+                },
+            ]),
+        },
+        ConformanceCase {
+            name: "prefab ref with overrides",
+            source: r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [
+        PrefabRef(PrefabRef(
+            prefab_id: "20202020-2020-2020-2020-202020202020",
+            parameter_values: {
+                "speed": "2.5",
+            },
+            transform: (
+                position: (1.0, 0.0, 0.0),
+            ),
+            entity_overrides: [
+                (
+                    entity_id: "01010101-0101-0101-0101-010101010101",
+                    component_overrides: [
+                        ComponentOverride(
+                            component_type: "a1a1a1a1-a1a1-a1a1-a1a1-a1a1a1a1a1a1",
+                            diff: "[Enter(Field(\"x\")),Value(3.0)]",
+                        ),
+                    ],
+                ),
+            ],
+        )),
+    ],
+)"#,
+            expected: Some(vec![
+                BeginPrefab(PREFAB),
+                BeginPrefabRef {
+                    prefab: PREFAB,
+                    target_prefab: OTHER_PREFAB,
+                },
+                ParameterValue {
+                    parent_prefab: PREFAB,
+                    prefab_ref: OTHER_PREFAB,
+                    name: "speed".to_string(),
+                    value: "2.5".to_string(),
+                },
+                PrefabRefTransform {
+                    parent_prefab: PREFAB,
+                    prefab_ref: OTHER_PREFAB,
+                    transform: crate::PrefabRefTransform::from_position([1.0, 0.0, 0.0]),
+                },
+                ComponentDiff {
+                    parent_prefab: PREFAB,
+                    prefab_ref: OTHER_PREFAB,
+                    entity: ENTITY_A,
+                    component_type: POSITION,
+                    diff: "[Enter(Field(\"x\")),Value(3.0)]".to_string(),
+                },
+                EndPrefabRef {
+                    prefab: PREFAB,
+                    target_prefab: OTHER_PREFAB,
+                },
+            ]),
+        },
+        ConformanceCase {
+            name: "parameters, layers and hierarchy",
+            source: r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    parameters: [
+        (
+            name: "speed",
+            type: "f32",
+            default: "1.0",
+            bindings: [
+                (
+                    entity: "01010101-0101-0101-0101-010101010101",
+                    component_type: "a2a2a2a2-a2a2-a2a2-a2a2-a2a2a2a2a2a2",
+                    field: "x",
+                ),
+            ],
+        ),
+    ],
+    layers: {
+        "lighting": ["02020202-0202-0202-0202-020202020202"],
+    },
+    hierarchy: {
+        "01010101-0101-0101-0101-010101010101": ["02020202-0202-0202-0202-020202020202"],
+    },
+    objects: [],
+)"#,
+            expected: Some(vec![
+                BeginPrefab(PREFAB),
+                Parameter {
+                    prefab: PREFAB,
+                    parameter: PrefabParameter {
+                        name: "speed".to_string(),
+                        type_name: "f32".to_string(),
+                        default: "1.0".to_string(),
+                        bindings: vec![ParameterBinding {
+                            entity: ENTITY_A,
+                            component_type: VELOCITY,
+                            field: "x".to_string(),
+                        }],
+                    },
+                },
+                Layer {
+                    prefab: PREFAB,
+                    name: "lighting".to_string(),
+                    entities: vec![ENTITY_B],
+                },
+                Children {
+                    prefab: PREFAB,
+                    parent: ENTITY_A,
+                    children: vec![ENTITY_B],
+                },
+            ]),
+        },
+        ConformanceCase {
+            name: "extends",
+            source: r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    extends: PrefabRef(
+        prefab_id: "20202020-2020-2020-2020-202020202020",
+        entity_overrides: [
+            EntityOverride(
+                entity_id: "01010101-0101-0101-0101-010101010101",
+                component_overrides: [
+                    ComponentOverride(
+                        component_type: "a1a1a1a1-a1a1-a1a1-a1a1-a1a1a1a1a1a1",
+                        diff: "[Enter(Field(\"x\")),Value(3.0)]",
+                    ),
+                ],
+            ),
+        ],
+    ),
+    objects: [],
+)"#,
+            expected: Some(vec![
+                BeginPrefab(PREFAB),
+                BeginPrefabRef {
+                    prefab: PREFAB,
+                    target_prefab: OTHER_PREFAB,
+                },
+                BasePrefab {
+                    prefab: PREFAB,
+                    base_prefab: OTHER_PREFAB,
+                },
+                ComponentDiff {
+                    parent_prefab: PREFAB,
+                    prefab_ref: OTHER_PREFAB,
+                    entity: ENTITY_A,
+                    component_type: POSITION,
+                    diff: "[Enter(Field(\"x\")),Value(3.0)]".to_string(),
+                },
+                EndPrefabRef {
+                    prefab: PREFAB,
+                    target_prefab: OTHER_PREFAB,
+                },
+            ]),
+        },
+        ConformanceCase {
+            name: "entity templates",
+            source: r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    templates: {
+        "30303030-3030-3030-3030-303030303030": [
+            EntityComponent(
+                type: "a1a1a1a1-a1a1-a1a1-a1a1-a1a1a1a1a1a1",
+                data: (x: 0.0, y: 0.0),
+            ),
+        ],
+    },
+    objects: [
+        Entity(PrefabEntity(
+            id: "01010101-0101-0101-0101-010101010101",
+            template: "30303030-3030-3030-3030-303030303030",
+            template_diffs: [
+                TemplateDiff(
+                    component_type: "a1a1a1a1-a1a1-a1a1-a1a1-a1a1a1a1a1a1",
+                    diff: "[Enter(Field(\"x\")),Value(3.0)]",
+                ),
+            ],
+            components: [
+                EntityComponent(
+                    type: "a2a2a2a2-a2a2-a2a2-a2a2-a2a2a2a2a2a2",
+                    data: (x: 1.0, y: 0.0),
+                ),
+            ],
+        )),
+    ],
+)"#,
+            expected: Some(vec![
+                BeginPrefab(PREFAB),
+                TemplateComponent {
+                    prefab: PREFAB,
+                    template: TEMPLATE,
+                    component_type: POSITION,
+                },
+                BeginEntityObject {
+                    prefab: PREFAB,
+                    entity: ENTITY_A,
+                },
+                InstantiateTemplate {
+                    prefab: PREFAB,
+                    entity: ENTITY_A,
+                    template: TEMPLATE,
+                },
+                TemplateDiff {
+                    prefab: PREFAB,
+                    entity: ENTITY_A,
+                    component_type: POSITION,
+                    diff: "[Enter(Field(\"x\")),Value(3.0)]".to_string(),
+                },
+                Component {
+                    prefab: PREFAB,
+                    entity: ENTITY_A,
+                    component_type: VELOCITY,
+                },
+                EndEntityObject {
+                    prefab: PREFAB,
+                    entity: ENTITY_A,
+                },
+            ]),
+        },
+        ConformanceCase {
+            name: "component types by name",
+            source: r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    type_map: {
+        "Position": "a1a1a1a1-a1a1-a1a1-a1a1-a1a1a1a1a1a1",
+    },
+    objects: [
+        Entity(PrefabEntity(
+            id: "01010101-0101-0101-0101-010101010101",
+            components: [
+                EntityComponent(
+                    type: "Position",
+                    data: (x: 1.0, y: 2.0),
+                ),
+            ],
+        )),
+    ],
+)"#,
+            expected: Some(vec![
+                BeginPrefab(PREFAB),
+                ComponentTypeName {
+                    prefab: PREFAB,
+                    name: "Position".to_string(),
+                    component_type: POSITION,
+                },
+                BeginEntityObject {
+                    prefab: PREFAB,
+                    entity: ENTITY_A,
+                },
+                Component {
+                    prefab: PREFAB,
+                    entity: ENTITY_A,
+                    component_type: POSITION,
+                },
+                EndEntityObject {
+                    prefab: PREFAB,
+                    entity: ENTITY_A,
+                },
+            ]),
+        },
+        ConformanceCase {
+            name: "marker component without data",
+            source: r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [
+        Entity(PrefabEntity(
+            id: "01010101-0101-0101-0101-010101010101",
+            components: [
+                EntityComponent(
+                    type: "a1a1a1a1-a1a1-a1a1-a1a1-a1a1a1a1a1a1",
+                ),
+            ],
+        )),
+    ],
+)"#,
+            expected: Some(vec![
+                BeginPrefab(PREFAB),
+                BeginEntityObject {
+                    prefab: PREFAB,
+                    entity: ENTITY_A,
+                },
+                Component {
+                    prefab: PREFAB,
+                    entity: ENTITY_A,
+                    component_type: POSITION,
+                },
+                EndEntityObject {
+                    prefab: PREFAB,
+                    entity: ENTITY_A,
+                },
+            ]),
+        },
+        ConformanceCase {
+            name: "blob section",
+            source: r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [],
+    blobs: {
+        "b0b0b0b0-b0b0-b0b0-b0b0-b0b0b0b0b0b0": "AQID",
+    },
+)"#,
+            expected: Some(vec![
+                BeginPrefab(PREFAB),
+                Blob {
+                    prefab: PREFAB,
+                    blob: [0xb0; 16],
+                    data: BlobData(vec![1, 2, 3]),
+                },
+            ]),
+        },
+        ConformanceCase {
+            name: "missing prefab id",
+            source: r#"Prefab(
+    objects: [],
+)"#,
+            expected: None,
+        },
+        ConformanceCase {
+            name: "objects before prefab id",
+            source: r#"Prefab(
+    objects: [],
+    id: "10101010-1010-1010-1010-101010101010",
+)"#,
+            expected: None,
+        },
+        ConformanceCase {
+            name: "unsupported version",
+            source: r#"Prefab(
+    version: 2,
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [],
+)"#,
+            expected: None,
+        },
+        ConformanceCase {
+            name: "component data before type",
+            source: r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [
+        Entity(PrefabEntity(
+            id: "01010101-0101-0101-0101-010101010101",
+            components: [
+                EntityComponent(
+                    data: (x: 1.0, y: 2.0),
+                    type: "a1a1a1a1-a1a1-a1a1-a1a1-a1a1a1a1a1a1",
+                ),
+            ],
+        )),
+    ],
+)"#,
+            expected: None,
+        },
+        ConformanceCase {
+            name: "component type name not in type map",
+            source: r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [
+        Entity(PrefabEntity(
+            id: "01010101-0101-0101-0101-010101010101",
+            components: [
+                EntityComponent(
+                    type: "Position",
+                    data: (x: 1.0, y: 2.0),
+                ),
+            ],
+        )),
+    ],
+)"#,
+            expected: None,
+        },
+        ConformanceCase {
+            name: "duplicate prefab id",
+            source: r#"Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    id: "20202020-2020-2020-2020-202020202020",
+    objects: [],
+)"#,
+            expected: None,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{InMemoryPrefab, InMemoryStorage};
+
+    fn load<S: StorageDeserializer>(
+        source: &str,
+        storage: &S,
+    ) -> Result<(), ron::de::Error> {
+        let mut de = ron::de::Deserializer::from_str(source)?;
+        crate::deserialize(&mut de, storage)
+    }
+
+    fn load_in_memory(source: &str) -> Result<InMemoryPrefab<ron::Value>, ron::de::Error> {
+        let storage = InMemoryStorage::new();
+        load(source, &storage)?;
+        Ok(storage.into_prefab().expect("nothing was deserialized"))
+    }
+
+    #[test]
+    fn every_case_conforms() {
+        let failures: Vec<_> = cases()
+            .iter()
+            .filter_map(|case| {
+                run_case(case, load)
+                    .err()
+                    .map(|error| format!("{}: {}", case.name, error))
+            })
+            .collect();
+        assert!(failures.is_empty(), "{:#?}", failures);
+    }
+
+    // The entities and their components, and the overrides of each prefab ref, in the order the
+    // events deliver them
+    #[allow(clippy::type_complexity)]
+    fn expected_contents(
+        events: &[StorageEvent]
+    ) -> (
+        Vec<(EntityUuid, Vec<ComponentTypeUuid>)>,
+        Vec<(PrefabUuid, EntityUuid, ComponentTypeUuid, String)>,
+    ) {
+        let mut entities: Vec<(EntityUuid, Vec<ComponentTypeUuid>)> = vec![];
+        let mut overrides = vec![];
+        for event in events {
+            match event {
+                StorageEvent::BeginEntityObject { entity, .. } => entities.push((*entity, vec![])),
+                StorageEvent::Component { component_type, .. } => {
+                    entities.last_mut().unwrap().1.push(*component_type)
+                }
+                StorageEvent::ComponentDiff {
+                    prefab_ref,
+                    entity,
+                    component_type,
+                    diff,
+                    ..
+                } => overrides.push((*prefab_ref, *entity, *component_type, diff.clone())),
+                _ => {}
+            }
+        }
+        (entities, overrides)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn in_memory_contents(
+        prefab: &InMemoryPrefab<ron::Value>
+    ) -> (
+        Vec<(EntityUuid, Vec<ComponentTypeUuid>)>,
+        Vec<(PrefabUuid, EntityUuid, ComponentTypeUuid, String)>,
+    ) {
+        let entities = prefab
+            .entities
+            .iter()
+            .map(|entity| {
+                let component_types = entity
+                    .components
+                    .iter()
+                    .map(|component| component.component_type)
+                    .collect();
+                (entity.id, component_types)
+            })
+            .collect();
+        let mut overrides = vec![];
+        for prefab_ref in &prefab.prefab_refs {
+            for (entity, component_overrides) in &prefab_ref.entity_overrides {
+                for component_override in component_overrides {
+                    overrides.push((
+                        prefab_ref.prefab_id,
+                        *entity,
+                        component_override.component_type,
+                        component_override.diff.clone(),
+                    ));
+                }
+            }
+        }
+        (entities, overrides)
+    }
+
+    // The in-memory storage must read every case the same way, and write back what it read
+    #[test]
+    fn in_memory_storage_reads_and_writes_every_case() {
+        for case in cases() {
+            let prefab = load_in_memory(case.source);
+            let expected = match &case.expected {
+                Some(expected) => expected,
+                None => {
+                    assert!(prefab.is_err(), "{}: was accepted", case.name);
+                    continue;
+                }
+            };
+            let prefab = prefab.unwrap_or_else(|e| panic!("{}: {}", case.name, e));
+            assert_eq!(
+                in_memory_contents(&prefab),
+                expected_contents(expected),
+                "{}",
+                case.name
+            );
+
+            let mut ron_ser = ron::ser::Serializer::new(None, true);
+            crate::serialize(&mut ron_ser, &prefab, prefab.id).unwrap();
+            let written = ron_ser.into_output_string();
+            let reloaded = load_in_memory(&written)
+                .unwrap_or_else(|e| panic!("{}: {} in {}", case.name, e, written));
+            assert_eq!(reloaded, prefab, "{}", case.name);
+        }
+    }
+}
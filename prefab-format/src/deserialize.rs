@@ -1,8 +1,31 @@
-use crate::{ComponentTypeUuid, EntityUuid, PrefabUuid};
+use crate::blobs::{BlobData, BlobId};
+use crate::type_map::{ComponentTypeKeySeed, TypeMap};
+use crate::{
+    ComponentTypeUuid, EntityUuid, PrefabUuid, PrefabParameter, PrefabRefTransform, TemplateUuid,
+};
 use serde::{
-    de::{self, DeserializeSeed, Visitor},
+    de::{self, DeserializeSeed, IgnoredAny, Visitor},
     Deserialize, Deserializer,
 };
+use std::collections::{BTreeMap, HashSet};
+use std::rc::Rc;
+
+/// Limits which entities are delivered to Storage. Entities and entity overrides with an ID not in
+/// the set are skipped without deserializing their components.
+pub type EntityFilter = HashSet<EntityUuid>;
+
+fn is_included(
+    entity_filter: Option<&EntityFilter>,
+    entity_id: &EntityUuid,
+) -> bool {
+    entity_filter.map_or(true, |filter| filter.contains(entity_id))
+}
+
+/// Receives the contents of a prefab as it is deserialized. Methods take `&self`, so
+/// implementations keep what they receive behind interior mutability. A deserialization calls
+/// them in order from the thread it runs on. Storage that several prefabs are deserialized into at
+/// the same time must be `Sync` and keep its state per prefab, which every method is given.
+/// `shared_storage::SharedStorage` does this for any storage type.
 pub trait Storage {
     /// Called when the deserializer encouters the top-level prefab object.
     fn begin_prefab(
@@ -26,6 +49,7 @@ pub trait Storage {
     /// Called when the deserializer encounters component data.
     /// The Storage implementation must handle deserialization of the data,
     /// using the ComponentTypeUuid to identify the type to deserialize as.
+    /// Components written without data (zero-sized markers) get a unit deserializer.
     fn deserialize_component<'de, D: Deserializer<'de>>(
         &self,
         prefab: &PrefabUuid,
@@ -59,6 +83,112 @@ pub trait Storage {
         component_type: &ComponentTypeUuid,
         deserializer: D,
     ) -> Result<(), D::Error>;
+    /// Called when the deserializer encounters a parameter declared by the prefab.
+    fn declare_parameter(
+        &self,
+        _prefab: &PrefabUuid,
+        _parameter: PrefabParameter,
+    ) {
+    }
+    /// Called when the deserializer encounters a parameter value set by a prefab reference. The
+    /// value is RON text. Always called before any `apply_component_diff` calls for the reference.
+    fn set_parameter_value(
+        &self,
+        _parent_prefab: &PrefabUuid,
+        _prefab_ref: &PrefabUuid,
+        _name: &str,
+        _value: &str,
+    ) {
+    }
+    /// Called when the deserializer encounters the offset a prefab reference applies to the root
+    /// entities of the referenced prefab. Not called if the reference doesn't set one. Always
+    /// called before any `apply_component_diff` calls for the reference.
+    fn set_prefab_ref_transform(
+        &self,
+        _parent_prefab: &PrefabUuid,
+        _prefab_ref: &PrefabUuid,
+        _transform: &PrefabRefTransform,
+    ) {
+    }
+    /// Called when the deserializer encounters an entity layer of the prefab, with the entities
+    /// the layer contains.
+    fn declare_layer(
+        &self,
+        _prefab: &PrefabUuid,
+        _name: &str,
+        _entities: &[EntityUuid],
+    ) {
+    }
+    /// Called when the deserializer encounters an entry of the prefab's hierarchy, with the
+    /// children of `parent` in order
+    fn declare_children(
+        &self,
+        _prefab: &PrefabUuid,
+        _parent: &EntityUuid,
+        _children: &[EntityUuid],
+    ) {
+    }
+    /// Called when the deserializer encounters an entry of the prefab's type map, which gives the
+    /// name the file uses for a component type. Always called before any components are
+    /// delivered.
+    fn declare_component_type_name(
+        &self,
+        _prefab: &PrefabUuid,
+        _name: &str,
+        _component_type: &ComponentTypeUuid,
+    ) {
+    }
+    /// Called right after `begin_prefab_ref` for the prefab this prefab extends. The base is
+    /// otherwise delivered like a prefab ref, with its overrides being the changes this prefab
+    /// makes to the base's entities, so storage that ignores this still cooks the same entities.
+    fn set_base_prefab(
+        &self,
+        _prefab: &PrefabUuid,
+        _base_prefab: &PrefabUuid,
+    ) {
+    }
+    /// Called when the deserializer encounters a blob in the prefab's blob section
+    fn declare_blob(
+        &self,
+        _prefab: &PrefabUuid,
+        _blob: &BlobId,
+        _data: BlobData,
+    ) {
+    }
+    /// Called when the deserializer encounters a component of an entity template in the prefab's
+    /// `templates` section. Templates are always declared before any objects. Storage that doesn't
+    /// implement this can't load prefabs with templates.
+    fn deserialize_template_component<'de, D: Deserializer<'de>>(
+        &self,
+        _prefab: &PrefabUuid,
+        _template: &TemplateUuid,
+        _component_type: &ComponentTypeUuid,
+        _deserializer: D,
+    ) -> Result<(), D::Error> {
+        Err(de::Error::custom("entity templates are not supported"))
+    }
+    /// Called after `begin_entity_object` for an entity that instantiates a template. The entity
+    /// gets a copy of every component of the template. Its template diffs are then applied to
+    /// those, and its own components are added on top, replacing template components of the same
+    /// type.
+    fn instantiate_template(
+        &self,
+        _prefab: &PrefabUuid,
+        _entity: &EntityUuid,
+        _template: &TemplateUuid,
+    ) {
+    }
+    /// Called when the deserializer encounters a diff that an entity applies to a component it got
+    /// from its template. Always called after `instantiate_template`.
+    fn apply_template_diff<'de, D: Deserializer<'de>>(
+        &self,
+        _prefab: &PrefabUuid,
+        _entity: &EntityUuid,
+        _component_type: &ComponentTypeUuid,
+        _deserializer: D,
+    ) -> Result<(), D::Error> {
+        Err(de::Error::custom("entity templates are not supported"))
+    }
 }
 struct ComponentOverrideData<'a, S: Storage> {
     pub storage: &'a S,
@@ -92,6 +222,7 @@ struct ComponentOverride<'a, S: Storage> {
     pub parent_id: PrefabUuid,
     pub prefab_ref_id: PrefabUuid,
     pub entity_id: EntityUuid,
+    pub type_map: Rc<TypeMap>,
 }
 impl<'a, S: Storage> Clone for ComponentOverride<'a, S> {
     fn clone(&self) -> Self {
@@ -100,6 +231,7 @@ impl<'a, S: Storage> Clone for ComponentOverride<'a, S> {
             parent_id: self.parent_id,
             prefab_ref_id: self.prefab_ref_id,
             entity_id: self.entity_id,
+            type_map: self.type_map.clone(),
         }
     }
 }
@@ -143,7 +275,8 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for ComponentOverride<'a, S> {
                             if component_type_id.is_some() {
                                 return Err(de::Error::duplicate_field("component_type"));
                             }
-                            component_type_id = Some(*map.next_value::<uuid::Uuid>()?.as_bytes());
+                            component_type_id =
+                                Some(map.next_value_seed(ComponentTypeKeySeed(&self.type_map))?);
                         }
                         ComponentOverrideField::Diff => {
                             map.next_value_seed(ComponentOverrideData {
@@ -168,10 +301,113 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for ComponentOverride<'a, S> {
         deserializer.deserialize_struct("ComponentOverride", FIELDS, self)
     }
 }
+struct TemplateDiffData<'a, S: Storage> {
+    storage: &'a S,
+    prefab_id: PrefabUuid,
+    entity_id: EntityUuid,
+    component_type_id: ComponentTypeUuid,
+}
+impl<'de, 'a, S: Storage> DeserializeSeed<'de> for TemplateDiffData<'a, S> {
+    type Value = ();
+
+    fn deserialize<D>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        <S as Storage>::apply_template_diff(
+            self.storage,
+            &self.prefab_id,
+            &self.entity_id,
+            &self.component_type_id,
+            deserializer,
+        )
+    }
+}
+// A diff an entity applies to a component of its template. Written like a ComponentOverride
+struct TemplateDiff<'a, S: Storage> {
+    storage: &'a S,
+    prefab_id: PrefabUuid,
+    entity_id: EntityUuid,
+    type_map: Rc<TypeMap>,
+}
+impl<'a, S: Storage> Clone for TemplateDiff<'a, S> {
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage,
+            prefab_id: self.prefab_id,
+            entity_id: self.entity_id,
+            type_map: self.type_map.clone(),
+        }
+    }
+}
+impl<'de, 'a, S: Storage> DeserializeSeed<'de> for TemplateDiff<'a, S> {
+    type Value = ();
+
+    fn deserialize<D>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        impl<'a, 'de, S: Storage> Visitor<'de> for TemplateDiff<'a, S> {
+            type Value = ();
+
+            fn expecting(
+                &self,
+                formatter: &mut std::fmt::Formatter,
+            ) -> std::fmt::Result {
+                formatter.write_str("struct TemplateDiff")
+            }
+
+            fn visit_map<V>(
+                self,
+                mut map: V,
+            ) -> Result<Self::Value, V::Error>
+            where
+                V: de::MapAccess<'de>,
+            {
+                let mut component_type_id = None;
+                while let Some(key) = map.next_key()? {
+                    match key {
+                        ComponentOverrideField::ComponentType => {
+                            if component_type_id.is_some() {
+                                return Err(de::Error::duplicate_field("component_type"));
+                            }
+                            component_type_id =
+                                Some(map.next_value_seed(ComponentTypeKeySeed(&self.type_map))?);
+                        }
+                        ComponentOverrideField::Diff => {
+                            map.next_value_seed(TemplateDiffData {
+                                storage: self.storage,
+                                prefab_id: self.prefab_id,
+                                entity_id: self.entity_id,
+                                component_type_id: component_type_id.ok_or_else(|| {
+                                    de::Error::missing_field(
+                                        "component_type must be serialized before diff",
+                                    )
+                                })?,
+                            })?;
+                            return Ok(());
+                        }
+                    }
+                }
+                Err(de::Error::missing_field("diff"))
+            }
+        }
+        const FIELDS: &[&str] = &["component_type", "diff"];
+        deserializer.deserialize_struct("TemplateDiff", FIELDS, self)
+    }
+}
 struct EntityOverride<'a, S: Storage> {
     pub storage: &'a S,
     pub parent_id: PrefabUuid,
     pub prefab_ref_id: PrefabUuid,
+    pub entity_filter: Option<&'a EntityFilter>,
+    pub type_map: Rc<TypeMap>,
 }
 impl<'a, S: Storage> Clone for EntityOverride<'a, S> {
     fn clone(&self) -> Self {
@@ -179,6 +415,8 @@ impl<'a, S: Storage> Clone for EntityOverride<'a, S> {
             storage: self.storage,
             parent_id: self.parent_id,
             prefab_ref_id: self.prefab_ref_id,
+            entity_filter: self.entity_filter,
+            type_map: self.type_map.clone(),
         }
     }
 }
@@ -225,16 +463,22 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for EntityOverride<'a, S> {
                             entity_id = Some(*map.next_value::<uuid::Uuid>()?.as_bytes());
                         }
                         EntityOverrideField::ComponentOverrides => {
-                            map.next_value_seed(SeqDeserializer(ComponentOverride {
-                                parent_id: self.parent_id,
-                                prefab_ref_id: self.prefab_ref_id,
-                                entity_id: entity_id.ok_or_else(|| {
-                                    de::Error::missing_field(
-                                        "entity_id must be serialized before component_overrides",
-                                    )
-                                })?,
-                                storage: self.storage,
-                            }))?;
+                            let entity_id = entity_id.ok_or_else(|| {
+                                de::Error::missing_field(
+                                    "entity_id must be serialized before component_overrides",
+                                )
+                            })?;
+                            if is_included(self.entity_filter, &entity_id) {
+                                map.next_value_seed(SeqDeserializer(ComponentOverride {
+                                    parent_id: self.parent_id,
+                                    prefab_ref_id: self.prefab_ref_id,
+                                    entity_id,
+                                    storage: self.storage,
+                                    type_map: self.type_map.clone(),
+                                }))?;
+                            } else {
+                                map.next_value::<IgnoredAny>()?;
+                            }
                             return Ok(());
                         }
                     }
@@ -249,11 +493,17 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for EntityOverride<'a, S> {
 struct PrefabRef<'a, S: Storage> {
     pub storage: &'a S,
     pub parent_id: PrefabUuid,
+    pub entity_filter: Option<&'a EntityFilter>,
+    pub type_map: Rc<TypeMap>,
+    // True for the prefab's `extends` field
+    pub is_base: bool,
 }
 #[derive(Deserialize, Debug)]
 #[serde(field_identifier, rename_all = "snake_case")]
 enum PrefabRefField {
     PrefabId,
+    ParameterValues,
+    Transform,
     EntityOverrides,
 }
 impl<'de, 'a, S: Storage> DeserializeSeed<'de> for PrefabRef<'a, S> {
@@ -284,6 +534,8 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for PrefabRef<'a, S> {
                 V: de::MapAccess<'de>,
             {
                 let mut prefab_id = None;
+                let mut parameter_values = BTreeMap::new();
+                let mut transform = None;
                 while let Some(key) = map.next_key()? {
                     match key {
                         PrefabRefField::PrefabId => {
@@ -292,6 +544,14 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for PrefabRef<'a, S> {
                             }
                             prefab_id = Some(*map.next_value::<uuid::Uuid>()?.as_bytes());
                         }
+                        // Must come before entity_overrides, which ends the prefab ref
+                        PrefabRefField::ParameterValues => {
+                            parameter_values = map.next_value::<BTreeMap<String, String>>()?;
+                        }
+                        // Must also come before entity_overrides
+                        PrefabRefField::Transform => {
+                            transform = Some(map.next_value::<PrefabRefTransform>()?);
+                        }
                         PrefabRefField::EntityOverrides => {
                             let prefab_ref_id = prefab_id.ok_or_else(|| {
                                 de::Error::missing_field(
@@ -300,10 +560,31 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for PrefabRef<'a, S> {
                             })?;
                             self.storage
                                 .begin_prefab_ref(&self.parent_id, &prefab_ref_id);
+                            if self.is_base {
+                                self.storage
+                                    .set_base_prefab(&self.parent_id, &prefab_ref_id);
+                            }
+                            for (name, value) in &parameter_values {
+                                self.storage.set_parameter_value(
+                                    &self.parent_id,
+                                    &prefab_ref_id,
+                                    name,
+                                    value,
+                                );
+                            }
+                            if let Some(transform) = &transform {
+                                self.storage.set_prefab_ref_transform(
+                                    &self.parent_id,
+                                    &prefab_ref_id,
+                                    transform,
+                                );
+                            }
                             map.next_value_seed(SeqDeserializer(EntityOverride {
                                 parent_id: self.parent_id,
                                 prefab_ref_id,
                                 storage: self.storage,
+                                entity_filter: self.entity_filter,
+                                type_map: self.type_map.clone(),
                             }))?;
                             self.storage.end_prefab_ref(&self.parent_id, &prefab_ref_id);
                             return Ok(());
@@ -313,7 +594,12 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for PrefabRef<'a, S> {
                 Err(de::Error::missing_field("component_overrides"))
             }
         }
-        const FIELDS: &[&str] = &["prefab_id", "entity_overrides"];
+        const FIELDS: &[&str] = &[
+            "prefab_id",
+            "parameter_values",
+            "transform",
+            "entity_overrides",
+        ];
         deserializer.deserialize_struct("PrefabRef", FIELDS, self)
     }
 }
@@ -321,12 +607,16 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for PrefabRef<'a, S> {
 struct PrefabObjectDeserializer<'a, S: Storage> {
     pub prefab_id: PrefabUuid,
     pub storage: &'a S,
+    pub entity_filter: Option<&'a EntityFilter>,
+    pub type_map: Rc<TypeMap>,
 }
 impl<'a, S: Storage> Clone for PrefabObjectDeserializer<'a, S> {
     fn clone(&self) -> Self {
         Self {
             prefab_id: self.prefab_id,
             storage: self.storage,
+            entity_filter: self.entity_filter,
+            type_map: self.type_map.clone(),
         }
     }
 }
@@ -336,9 +626,34 @@ enum ComponentField {
     Type,
     Data,
 }
+// What a component in the file belongs to
+#[derive(Clone, Copy)]
+enum ComponentOwner {
+    Entity(EntityUuid),
+    Template(TemplateUuid),
+}
+fn deliver_component<'de, S: Storage, D: Deserializer<'de>>(
+    storage: &S,
+    prefab_id: &PrefabUuid,
+    owner: &ComponentOwner,
+    component_type: &ComponentTypeUuid,
+    deserializer: D,
+) -> Result<(), D::Error> {
+    match owner {
+        ComponentOwner::Entity(entity) => {
+            storage.deserialize_component(prefab_id, entity, component_type, deserializer)
+        }
+        ComponentOwner::Template(template) => storage.deserialize_template_component(
+            prefab_id,
+            template,
+            component_type,
+            deserializer,
+        ),
+    }
+}
 struct EntityComponentData<'a, S: Storage> {
     prefab_id: PrefabUuid,
-    entity_id: EntityUuid,
+    owner: ComponentOwner,
     component_id: ComponentTypeUuid,
     storage: &'a S,
 }
@@ -352,10 +667,10 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for EntityComponentData<'a, S> {
     where
         D: Deserializer<'de>,
     {
-        <S as Storage>::deserialize_component(
+        deliver_component(
             self.storage,
             &self.prefab_id,
-            &self.entity_id,
+            &self.owner,
             &self.component_id,
             deserializer,
         )
@@ -363,15 +678,17 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for EntityComponentData<'a, S> {
 }
 struct EntityComponent<'a, S: Storage> {
     prefab_id: PrefabUuid,
-    entity_id: EntityUuid,
+    owner: ComponentOwner,
     storage: &'a S,
+    type_map: Rc<TypeMap>,
 }
 impl<'a, S: Storage> Clone for EntityComponent<'a, S> {
     fn clone(&self) -> Self {
         Self {
             prefab_id: self.prefab_id,
-            entity_id: self.entity_id,
+            owner: self.owner,
             storage: self.storage,
+            type_map: self.type_map.clone(),
         }
     }
 }
@@ -409,13 +726,14 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for EntityComponent<'a, S> {
                             if component_id.is_some() {
                                 return Err(de::Error::duplicate_field("type"));
                             }
-                            component_id = Some(*map.next_value::<uuid::Uuid>()?.as_bytes());
+                            component_id =
+                                Some(map.next_value_seed(ComponentTypeKeySeed(&self.type_map))?);
                         }
                         ComponentField::Data => {
                             map.next_value_seed(EntityComponentData {
                                 storage: self.storage,
                                 prefab_id: self.prefab_id,
-                                entity_id: self.entity_id,
+                                owner: self.owner,
                                 component_id: component_id.ok_or_else(|| {
                                     de::Error::missing_field(
                                         "component type must be serialized before data",
@@ -426,7 +744,16 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for EntityComponent<'a, S> {
                         }
                     }
                 }
-                Err(de::Error::missing_field("data"))
+
+                // Marker components are written without data
+                let component_id = component_id.ok_or_else(|| de::Error::missing_field("type"))?;
+                deliver_component(
+                    self.storage,
+                    &self.prefab_id,
+                    &self.owner,
+                    &component_id,
+                    de::IntoDeserializer::into_deserializer(()),
+                )
             }
         }
         const FIELDS: &[&str] = &["id", "components"];
@@ -444,6 +771,9 @@ impl<'a, S: Storage> Clone for EntityPrefabObject<'a, S> {
 #[serde(field_identifier, rename_all = "lowercase")]
 enum EntityPrefabObjectField {
     Id,
+    Template,
+    #[serde(rename = "template_diffs")]
+    TemplateDiffs,
     Components,
 }
 impl<'de, 'a, S: Storage> DeserializeSeed<'de> for EntityPrefabObject<'a, S> {
@@ -474,6 +804,9 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for EntityPrefabObject<'a, S> {
                 V: de::MapAccess<'de>,
             {
                 let mut entity_id = None;
+                // The template and template diffs come before the components, so the entity is
+                // begun by whichever of them is first
+                let mut begun = false;
                 while let Some(key) = map.next_key()? {
                     match key {
                         EntityPrefabObjectField::Id => {
@@ -482,19 +815,69 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for EntityPrefabObject<'a, S> {
                             }
                             entity_id = Some(*map.next_value::<uuid::Uuid>()?.as_bytes());
                         }
+                        EntityPrefabObjectField::Template => {
+                            let entity_id = entity_id.ok_or_else(|| {
+                                de::Error::missing_field(
+                                    "entity id must be serialized before template",
+                                )
+                            })?;
+                            let template = *map.next_value::<uuid::Uuid>()?.as_bytes();
+                            if is_included(self.0.entity_filter, &entity_id) {
+                                if !begun {
+                                    self.0
+                                        .storage
+                                        .begin_entity_object(&self.0.prefab_id, &entity_id);
+                                    begun = true;
+                                }
+                                self.0.storage.instantiate_template(
+                                    &self.0.prefab_id,
+                                    &entity_id,
+                                    &template,
+                                );
+                            }
+                        }
+                        EntityPrefabObjectField::TemplateDiffs => {
+                            let entity_id = entity_id.ok_or_else(|| {
+                                de::Error::missing_field(
+                                    "entity id must be serialized before template_diffs",
+                                )
+                            })?;
+                            if !is_included(self.0.entity_filter, &entity_id) {
+                                map.next_value::<IgnoredAny>()?;
+                                continue;
+                            }
+                            if !begun {
+                                return Err(de::Error::custom(
+                                    "template_diffs must come after the entity's template",
+                                ));
+                            }
+                            map.next_value_seed(SeqDeserializer(TemplateDiff {
+                                storage: self.0.storage,
+                                prefab_id: self.0.prefab_id,
+                                entity_id,
+                                type_map: self.0.type_map.clone(),
+                            }))?;
+                        }
                         EntityPrefabObjectField::Components => {
                             let entity_id = entity_id.ok_or_else(|| {
                                 de::Error::missing_field(
                                     "entity id must be serialized before components",
                                 )
                             })?;
-                            self.0
-                                .storage
-                                .begin_entity_object(&self.0.prefab_id, &entity_id);
+                            if !is_included(self.0.entity_filter, &entity_id) {
+                                map.next_value::<IgnoredAny>()?;
+                                return Ok(self.0);
+                            }
+                            if !begun {
+                                self.0
+                                    .storage
+                                    .begin_entity_object(&self.0.prefab_id, &entity_id);
+                            }
                             map.next_value_seed(SeqDeserializer(EntityComponent {
                                 prefab_id: self.0.prefab_id,
-                                entity_id,
+                                owner: ComponentOwner::Entity(entity_id),
                                 storage: self.0.storage,
+                                type_map: self.0.type_map.clone(),
                             }))?;
                             self.0
                                 .storage
@@ -506,7 +889,7 @@ impl<'de, 'a, S: Storage> DeserializeSeed<'de> for EntityPrefabObject<'a, S> {
                 Err(de::Error::missing_field("components"))
             }
         }
-        const FIELDS: &[&str] = &["id", "components"];
+        const FIELDS: &[&str] = &["id", "template", "template_diffs", "components"];
         deserializer.deserialize_struct("PrefabEntity", FIELDS, self)
     }
 }
@@ -561,6 +944,9 @@ impl<'a, 'de, S: Storage> Visitor<'de> for PrefabObjectDeserializer<'a, S> {
                     PrefabRef {
                         parent_id: self.prefab_id,
                         storage: self.storage,
+                        entity_filter: self.entity_filter,
+                        type_map: self.type_map,
+                        is_base: false,
                     },
                 )?;
                 Ok(())
@@ -568,6 +954,121 @@ impl<'a, 'de, S: Storage> Visitor<'de> for PrefabObjectDeserializer<'a, S> {
         }
     }
 }
+// The `templates` section, a map of template UUIDs to the components of each template
+struct TemplatesDeserializer<'a, S: Storage> {
+    prefab_id: PrefabUuid,
+    storage: &'a S,
+    type_map: Rc<TypeMap>,
+}
+impl<'de, 'a, S: Storage> DeserializeSeed<'de> for TemplatesDeserializer<'a, S> {
+    type Value = ();
+
+    fn deserialize<D>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+}
+impl<'de, 'a, S: Storage> Visitor<'de> for TemplatesDeserializer<'a, S> {
+    type Value = ();
+
+    fn expecting(
+        &self,
+        formatter: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        formatter.write_str("map of templates")
+    }
+    fn visit_map<V>(
+        self,
+        mut map: V,
+    ) -> Result<Self::Value, V::Error>
+    where
+        V: de::MapAccess<'de>,
+    {
+        while let Some(template) = map.next_key::<uuid::Uuid>()? {
+            map.next_value_seed(SeqDeserializer(EntityComponent {
+                prefab_id: self.prefab_id,
+                owner: ComponentOwner::Template(*template.as_bytes()),
+                storage: self.storage,
+                type_map: self.type_map.clone(),
+            }))?;
+        }
+        Ok(())
+    }
+}
+pub struct VariantDeserializer<'a, S: Storage> {
+    pub storage: &'a S,
+}
+#[derive(Deserialize, Debug)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum VariantField {
+    Id,
+    Base,
+}
+impl<'de, 'a, S: Storage> DeserializeSeed<'de> for VariantDeserializer<'a, S> {
+    type Value = ();
+
+    fn deserialize<D>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        const FIELDS: &[&str] = &["id", "base"];
+        deserializer.deserialize_struct("PrefabVariant", FIELDS, self)
+    }
+}
+impl<'de, 'a, S: Storage> Visitor<'de> for VariantDeserializer<'a, S> {
+    type Value = ();
+
+    fn expecting(
+        &self,
+        formatter: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        formatter.write_str("struct PrefabVariant")
+    }
+
+    fn visit_map<V>(
+        self,
+        mut map: V,
+    ) -> Result<Self::Value, V::Error>
+    where
+        V: de::MapAccess<'de>,
+    {
+        let mut prefab_id = None;
+        while let Some(key) = map.next_key()? {
+            match key {
+                VariantField::Id => {
+                    if prefab_id.is_some() {
+                        return Err(de::Error::duplicate_field("id"));
+                    }
+                    let id = *map.next_value::<uuid::Uuid>()?.as_bytes();
+                    self.storage.begin_prefab(&id);
+                    prefab_id = Some(id);
+                }
+                // Read like the `extends` field of a prefab
+                VariantField::Base => {
+                    map.next_value_seed(PrefabRef {
+                        parent_id: prefab_id.ok_or_else(|| {
+                            de::Error::missing_field("variant ID must be serialized before base")
+                        })?,
+                        storage: self.storage,
+                        entity_filter: None,
+                        type_map: Rc::new(TypeMap::new()),
+                        is_base: true,
+                    })?;
+                    return Ok(());
+                }
+            }
+        }
+        Err(de::Error::missing_field("base"))
+    }
+}
 pub struct SeqDeserializer<T>(T);
 
 impl<'de, T: DeserializeSeed<'de> + Clone> DeserializeSeed<'de> for SeqDeserializer<T> {
@@ -602,10 +1103,25 @@ impl<'de, T: DeserializeSeed<'de> + Clone> Visitor<'de> for SeqDeserializer<T> {
         while seq.next_element_seed::<T>(self.0.clone())?.is_some() {}
         Ok(())
     }
+    // Some formats (e.g. YAML) read a list with nothing in it as null
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(())
+    }
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(())
+    }
 }
 
 pub struct PrefabDeserializer<'a, S: Storage> {
     pub storage: &'a S,
+    /// If set, only these entities (and overrides of these entities) are delivered to storage
+    pub entity_filter: Option<&'a EntityFilter>,
 }
 impl<'de, 'a: 'de, S: Storage> DeserializeSeed<'de> for PrefabDeserializer<'a, S> {
     type Value = ();
@@ -617,7 +1133,18 @@ impl<'de, 'a: 'de, S: Storage> DeserializeSeed<'de> for PrefabDeserializer<'a, S
     where
         D: Deserializer<'de>,
     {
-        const FIELDS: &[&str] = &["id", "objects"];
+        const FIELDS: &[&str] = &[
+            "version",
+            "id",
+            "type_map",
+            "parameters",
+            "layers",
+            "hierarchy",
+            "extends",
+            "templates",
+            "objects",
+            "blobs",
+        ];
         deserializer.deserialize_struct("Prefab", FIELDS, self)
     }
 }
@@ -625,7 +1152,16 @@ impl<'de, 'a: 'de, S: Storage> DeserializeSeed<'de> for PrefabDeserializer<'a, S
 #[derive(Deserialize, Debug)]
 #[serde(field_identifier, rename_all = "lowercase")]
 enum PrefabField {
+    Version,
     Id,
+    #[serde(rename = "type_map")]
+    TypeMap,
+    Parameters,
+    Layers,
+    Hierarchy,
+    Extends,
+    Templates,
+    Blobs,
     Objects,
 }
 impl<'a: 'de, 'de, S: Storage> Visitor<'de> for PrefabDeserializer<'a, S> {
@@ -647,8 +1183,20 @@ impl<'a: 'de, 'de, S: Storage> Visitor<'de> for PrefabDeserializer<'a, S> {
     {
         let mut prefab_id = None;
         let mut prefab = None;
+        let mut type_map = Rc::new(TypeMap::new());
+        let mut extends = false;
         while let Some(key) = map.next_key()? {
             match key {
+                PrefabField::Version => {
+                    // Older versions must be upgraded (see the migrations module) before loading
+                    let version = map.next_value::<u32>()?;
+                    if version != crate::FORMAT_VERSION {
+                        return Err(de::Error::invalid_value(
+                            de::Unexpected::Unsigned(version.into()),
+                            &"the current prefab format version",
+                        ));
+                    }
+                }
                 PrefabField::Id => {
                     if prefab_id.is_some() {
                         return Err(de::Error::duplicate_field("id"));
@@ -657,6 +1205,93 @@ impl<'a: 'de, 'de, S: Storage> Visitor<'de> for PrefabDeserializer<'a, S> {
                     self.storage.begin_prefab(&id);
                     prefab_id = Some(id);
                 }
+                // Must come before objects, which may name component types from it
+                PrefabField::TypeMap => {
+                    let prefab_id = prefab_id.ok_or_else(|| {
+                        de::Error::missing_field("prefab ID must be serialized before type_map")
+                    })?;
+                    let names = map.next_value::<BTreeMap<String, uuid::Uuid>>()?;
+                    for (name, component_type) in &names {
+                        self.storage.declare_component_type_name(
+                            &prefab_id,
+                            name,
+                            component_type.as_bytes(),
+                        );
+                    }
+                    type_map = Rc::new(
+                        names
+                            .into_iter()
+                            .map(|(name, component_type)| (name, *component_type.as_bytes()))
+                            .collect(),
+                    );
+                }
+                PrefabField::Parameters => {
+                    let prefab_id = prefab_id.ok_or_else(|| {
+                        de::Error::missing_field("prefab ID must be serialized before parameters")
+                    })?;
+                    for parameter in map.next_value::<Vec<PrefabParameter>>()? {
+                        self.storage.declare_parameter(&prefab_id, parameter);
+                    }
+                }
+                PrefabField::Layers => {
+                    let prefab_id = prefab_id.ok_or_else(|| {
+                        de::Error::missing_field("prefab ID must be serialized before layers")
+                    })?;
+                    let layers = map.next_value::<BTreeMap<String, Vec<uuid::Uuid>>>()?;
+                    for (name, entities) in layers {
+                        let entities: Vec<EntityUuid> =
+                            entities.iter().map(|entity| *entity.as_bytes()).collect();
+                        self.storage.declare_layer(&prefab_id, &name, &entities);
+                    }
+                }
+                PrefabField::Hierarchy => {
+                    let prefab_id = prefab_id.ok_or_else(|| {
+                        de::Error::missing_field("prefab ID must be serialized before hierarchy")
+                    })?;
+                    let hierarchy = map.next_value::<BTreeMap<uuid::Uuid, Vec<uuid::Uuid>>>()?;
+                    for (parent, children) in hierarchy {
+                        let children: Vec<EntityUuid> =
+                            children.iter().map(|child| *child.as_bytes()).collect();
+                        self.storage
+                            .declare_children(&prefab_id, parent.as_bytes(), &children);
+                    }
+                }
+                // The prefab this prefab inherits all entities from. Written like a PrefabRef
+                PrefabField::Extends => {
+                    if extends {
+                        return Err(de::Error::duplicate_field("extends"));
+                    }
+                    extends = true;
+                    map.next_value_seed(PrefabRef {
+                        parent_id: prefab_id.ok_or_else(|| {
+                            de::Error::missing_field("prefab ID must be serialized before extends")
+                        })?,
+                        storage: self.storage,
+                        entity_filter: self.entity_filter,
+                        type_map: type_map.clone(),
+                        is_base: true,
+                    })?;
+                }
+                // Must come before objects, which may instantiate the templates
+                PrefabField::Templates => {
+                    map.next_value_seed(TemplatesDeserializer {
+                        prefab_id: prefab_id.ok_or_else(|| {
+                            de::Error::missing_field(
+                                "prefab ID must be serialized before templates",
+                            )
+                        })?,
+                        storage: self.storage,
+                        type_map: type_map.clone(),
+                    })?;
+                }
+                PrefabField::Blobs => {
+                    let prefab_id = prefab_id.ok_or_else(|| {
+                        de::Error::missing_field("prefab ID must be serialized before blobs")
+                    })?;
+                    for (blob, data) in map.next_value::<BTreeMap<uuid::Uuid, BlobData>>()? {
+                        self.storage.declare_blob(&prefab_id, blob.as_bytes(), data);
+                    }
+                }
                 PrefabField::Objects => {
                     prefab = Some(map.next_value_seed(SeqDeserializer(
                         PrefabObjectDeserializer {
@@ -666,6 +1301,8 @@ impl<'a: 'de, 'de, S: Storage> Visitor<'de> for PrefabDeserializer<'a, S> {
                                 )
                             })?,
                             storage: self.storage,
+                            entity_filter: self.entity_filter,
+                            type_map: type_map.clone(),
                         },
                     ))?);
                 }
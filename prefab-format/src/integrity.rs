@@ -0,0 +1,136 @@
+//! Optional integrity trailers for saved prefab and cooked prefab files.
+//!
+//! A trailer is a single line appended after the serialized data, holding a SHA-256 hash of the
+//! data and the version of the tool that wrote it:
+//!
+//! ```text
+//! #prefab-integrity sha256=<hex> tool=<tool version>
+//! ```
+//!
+//! It works the same for every format, since it is added to the bytes after serializing and
+//! removed before deserializing. A hash can only detect corruption or edits made without
+//! recomputing it. It doesn't prove who wrote the file, so it isn't a substitute for signing.
+use sha2::{Digest, Sha256};
+
+const TRAILER_MARKER: &[u8] = b"\n#prefab-integrity ";
+
+/// What an integrity trailer records about the file
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntegrityInfo {
+    /// The SHA-256 hash of the data and tool version, as lowercase hex
+    pub hash: String,
+    pub tool_version: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntegrityError {
+    /// The file has no integrity trailer
+    Missing,
+    /// The file ends with something that looks like a trailer but can't be read
+    Malformed,
+    /// The hash in the trailer doesn't match the data
+    Corrupted { tool_version: String },
+}
+
+impl std::fmt::Display for IntegrityError {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        match self {
+            IntegrityError::Missing => write!(f, "file has no integrity hash"),
+            IntegrityError::Malformed => write!(f, "file has an unreadable integrity hash"),
+            IntegrityError::Corrupted { tool_version } => write!(
+                f,
+                "file corrupted or tampered: integrity hash doesn't match (written by {})",
+                tool_version
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IntegrityError {}
+
+fn integrity_hash(
+    data: &[u8],
+    tool_version: &str,
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.update(b"\n");
+    hasher.update(tool_version.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Appends an integrity trailer to serialized data. `tool_version` identifies what wrote the
+/// file (i.e. `concat!("my-editor ", env!("CARGO_PKG_VERSION"))`) and can't contain newlines.
+pub fn append_integrity_trailer(
+    data: &mut Vec<u8>,
+    tool_version: &str,
+) {
+    assert!(
+        !tool_version.contains('\n'),
+        "tool version can't contain newlines"
+    );
+    let hash = integrity_hash(data, tool_version);
+    data.extend_from_slice(TRAILER_MARKER);
+    data.extend_from_slice(format!("sha256={} tool={}\n", hash, tool_version).as_bytes());
+}
+
+/// Splits off and checks the integrity trailer, if there is one. Returns the data to
+/// deserialize, and the trailer's contents if it had one. Files saved without a trailer are
+/// returned unchanged.
+pub fn split_integrity_trailer(
+    data: &[u8]
+) -> Result<(&[u8], Option<IntegrityInfo>), IntegrityError> {
+    let marker_start = match data
+        .windows(TRAILER_MARKER.len())
+        .rposition(|window| window == TRAILER_MARKER)
+    {
+        Some(marker_start) => marker_start,
+        None => return Ok((data, None)),
+    };
+
+    // The marker only starts a trailer if it's on the last line
+    let trailer = &data[marker_start + TRAILER_MARKER.len()..];
+    let trailer_line = match trailer.split_last() {
+        Some((b'\n', line)) if !line.contains(&b'\n') => line,
+        _ => return Ok((data, None)),
+    };
+
+    let trailer_line = std::str::from_utf8(trailer_line).map_err(|_| IntegrityError::Malformed)?;
+    let (hash, tool_version) = parse_trailer_line(trailer_line).ok_or(IntegrityError::Malformed)?;
+
+    let content = &data[..marker_start];
+    if integrity_hash(content, tool_version) != hash {
+        return Err(IntegrityError::Corrupted {
+            tool_version: tool_version.to_string(),
+        });
+    }
+
+    Ok((
+        content,
+        Some(IntegrityInfo {
+            hash: hash.to_string(),
+            tool_version: tool_version.to_string(),
+        }),
+    ))
+}
+
+/// Like `split_integrity_trailer`, but files without a trailer are rejected. Use this when loading
+/// files that must have been saved with one, i.e. user-generated content.
+pub fn verify_integrity(data: &[u8]) -> Result<(&[u8], IntegrityInfo), IntegrityError> {
+    match split_integrity_trailer(data)? {
+        (content, Some(info)) => Ok((content, info)),
+        (_, None) => Err(IntegrityError::Missing),
+    }
+}
+
+// Reads `sha256=<hex> tool=<tool version>`
+fn parse_trailer_line(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix("sha256=")?;
+    let hash_end = rest.find(' ')?;
+    let hash = &rest[..hash_end];
+    let tool_version = rest[hash_end + 1..].strip_prefix("tool=")?;
+    Some((hash, tool_version))
+}
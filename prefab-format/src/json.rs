@@ -0,0 +1,66 @@
+//! Helpers for reading and writing prefabs as JSON.
+//!
+//! Prefab objects use serde's default externally-tagged enum representation, so an entity is
+//! written as `{ "Entity": { "id": "...", "components": [...] } }`. UUIDs are written as
+//! hyphenated strings.
+use crate::{PrefabUuid, StorageDeserializer, StorageSerializer};
+
+/// Reads a JSON prefab from a byte slice, calling into `storage` as objects are encountered
+pub fn from_slice<'a, S: StorageDeserializer>(
+    bytes: &'a [u8],
+    storage: &'a S,
+) -> Result<(), serde_json::Error> {
+    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+    crate::deserialize(&mut deserializer, storage)?;
+    deserializer.end()
+}
+
+/// Reads a JSON prefab from a string, calling into `storage` as objects are encountered
+pub fn from_str<'a, S: StorageDeserializer>(
+    string: &'a str,
+    storage: &'a S,
+) -> Result<(), serde_json::Error> {
+    from_slice(string.as_bytes(), storage)
+}
+
+/// Writes the prefab provided by `storage` as compact JSON
+pub fn to_writer<W: std::io::Write, SS: StorageSerializer>(
+    writer: W,
+    storage: &SS,
+    prefab_id: PrefabUuid,
+) -> Result<(), serde_json::Error> {
+    let mut serializer = serde_json::Serializer::new(writer);
+    crate::serialize(&mut serializer, storage, prefab_id)
+}
+
+/// Writes the prefab provided by `storage` as indented JSON
+pub fn to_writer_pretty<W: std::io::Write, SS: StorageSerializer>(
+    writer: W,
+    storage: &SS,
+    prefab_id: PrefabUuid,
+) -> Result<(), serde_json::Error> {
+    let mut serializer = serde_json::Serializer::pretty(writer);
+    crate::serialize(&mut serializer, storage, prefab_id)
+}
+
+/// Serializes the prefab provided by `storage` to a compact JSON string
+pub fn to_string<SS: StorageSerializer>(
+    storage: &SS,
+    prefab_id: PrefabUuid,
+) -> Result<String, serde_json::Error> {
+    let mut bytes = Vec::new();
+    to_writer(&mut bytes, storage, prefab_id)?;
+    // serde_json only emits valid UTF-8
+    Ok(String::from_utf8(bytes).unwrap())
+}
+
+/// Serializes the prefab provided by `storage` to an indented JSON string
+pub fn to_string_pretty<SS: StorageSerializer>(
+    storage: &SS,
+    prefab_id: PrefabUuid,
+) -> Result<String, serde_json::Error> {
+    let mut bytes = Vec::new();
+    to_writer_pretty(&mut bytes, storage, prefab_id)?;
+    // serde_json only emits valid UTF-8
+    Ok(String::from_utf8(bytes).unwrap())
+}
@@ -1,22 +1,121 @@
 use serde::{Serializer, Deserializer};
 mod deserialize;
 mod serialize;
+pub mod uuid_bytes;
+pub mod bytes;
+pub mod source;
+pub mod ron_patch;
+pub mod migrations;
+pub mod raw;
+pub mod scan;
+pub mod ref_index;
+pub mod rewrite;
+pub mod shared_storage;
+pub mod integrity;
+pub mod blobs;
+pub mod type_map;
+pub mod memory;
+pub mod conformance;
+mod parameters;
+mod transform;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+#[cfg(feature = "yaml")]
+pub mod yaml;
 pub use deserialize::Storage as StorageDeserializer;
+pub use deserialize::EntityFilter;
+pub use parameters::{PrefabParameter, ParameterBinding, field_assignment_diff, FieldPathError};
 pub use serialize::StorageSerializer;
+pub use transform::PrefabRefTransform;
 pub type PrefabUuid = uuid::Bytes;
 pub type EntityUuid = uuid::Bytes;
 pub type ComponentTypeUuid = type_uuid::Bytes;
+pub type TemplateUuid = uuid::Bytes;
+
+/// The version of the prefab source format read and written by this crate. Files without a
+/// `version` field are treated as version 1.
+pub const FORMAT_VERSION: u32 = 1;
 pub fn deserialize<'de, 'a: 'de, D: Deserializer<'de>, S: StorageDeserializer>(
     deserializer: D,
     storage: &'a S,
 ) -> Result<(), D::Error> {
-    let prefab_deserializer = crate::deserialize::PrefabDeserializer { storage };
+    let prefab_deserializer = crate::deserialize::PrefabDeserializer {
+        storage,
+        entity_filter: None,
+    };
+    <deserialize::PrefabDeserializer<'a, S> as serde::de::DeserializeSeed>::deserialize(
+        prefab_deserializer,
+        deserializer,
+    )
+}
+
+/// Like `deserialize`, but only entities in `entity_filter` (and overrides of those entities in
+/// referenced prefabs) are delivered to storage. Everything else is skipped without being
+/// deserialized, which allows streaming in parts of large prefabs.
+pub fn deserialize_entities<'de, 'a: 'de, D: Deserializer<'de>, S: StorageDeserializer>(
+    deserializer: D,
+    storage: &'a S,
+    entity_filter: &'a EntityFilter,
+) -> Result<(), D::Error> {
+    let prefab_deserializer = crate::deserialize::PrefabDeserializer {
+        storage,
+        entity_filter: Some(entity_filter),
+    };
     <deserialize::PrefabDeserializer<'a, S> as serde::de::DeserializeSeed>::deserialize(
         prefab_deserializer,
         deserializer,
     )
 }
 
+/// Reads a prefab variant, a lightweight document with only a base prefab and overrides of the
+/// base's entities (i.e. a red barrel made from the barrel prefab):
+///
+/// ```text
+/// PrefabVariant(
+///     id: "...",
+///     base: PrefabRef(
+///         prefab_id: "...",
+///         entity_overrides: [ ... ],
+///     ),
+/// )
+/// ```
+///
+/// It's delivered to storage like a prefab that extends the base and has no objects of its own,
+/// so it cooks like the base with the overrides applied.
+pub fn deserialize_variant<'de, 'a: 'de, D: Deserializer<'de>, S: StorageDeserializer>(
+    deserializer: D,
+    storage: &'a S,
+) -> Result<(), D::Error> {
+    let variant_deserializer = crate::deserialize::VariantDeserializer { storage };
+    <deserialize::VariantDeserializer<'a, S> as serde::de::DeserializeSeed>::deserialize(
+        variant_deserializer,
+        deserializer,
+    )
+}
+
+/// Writes a prefab that only extends a base prefab as a variant, see `deserialize_variant`. Fails
+/// if the prefab has anything else, like entities of its own.
+pub fn serialize_variant<'a, S: Serializer, SS: StorageSerializer>(
+    serializer: S,
+    storage: &'a SS,
+    prefab_id: PrefabUuid,
+) -> Result<S::Ok, S::Error> {
+    let variant_serializer = crate::serialize::VariantSerializer::new(prefab_id, storage);
+    <serialize::VariantSerializer<'a, SS> as serde::ser::Serialize>::serialize(
+        &variant_serializer,
+        serializer,
+    )
+}
+
+/// True if RON source text is a prefab variant rather than a prefab
+pub fn is_variant_ron(source: &str) -> bool {
+    crate::ron_patch::document_struct_name(source) == Some("PrefabVariant")
+}
+
 pub fn serialize<'a, S: Serializer, SS: StorageSerializer>(
     serializer: S,
     storage: &'a SS,
@@ -0,0 +1,573 @@
+//! A `StorageDeserializer` that collects a prefab into plain data structures, for tools and tests
+//! that don't have their own storage. It also implements `StorageSerializer`, so a prefab can be
+//! loaded, inspected or changed, and written back.
+//!
+//! Component data is deserialized as `V`, which should be a self-describing value type of the
+//! format being read, i.e. `serde_json::Value` or `ron::Value`. Override diffs are kept as the
+//! text stored in the file.
+use crate::blobs::{BlobData, BlobId};
+use crate::{
+    ComponentTypeUuid, EntityUuid, PrefabParameter, PrefabRefTransform, PrefabUuid,
+    StorageDeserializer, StorageSerializer, TemplateUuid,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InMemoryComponent<V> {
+    pub component_type: ComponentTypeUuid,
+    pub data: V,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InMemoryEntity<V> {
+    pub id: EntityUuid,
+    /// The template the entity instantiates. Its components are not copied into `components`.
+    pub template: Option<TemplateUuid>,
+    /// Diffs of the template's components, as stored in the file
+    pub template_diffs: Vec<InMemoryComponentOverride>,
+    /// Components in the order they appear in the file
+    pub components: Vec<InMemoryComponent<V>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InMemoryComponentOverride {
+    pub component_type: ComponentTypeUuid,
+    /// The serde_diff of the component, as stored in the file
+    pub diff: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InMemoryPrefabRef {
+    pub prefab_id: PrefabUuid,
+    /// Parameter values (RON text) set on the referenced prefab
+    pub parameter_values: BTreeMap<String, String>,
+    pub transform: PrefabRefTransform,
+    /// Overridden entities of the referenced prefab, in the order they appear in the file
+    pub entity_overrides: Vec<(EntityUuid, Vec<InMemoryComponentOverride>)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InMemoryPrefab<V> {
+    pub id: PrefabUuid,
+    /// The names the file gives component types in its type map. They are written back when the
+    /// prefab is serialized.
+    pub type_names: BTreeMap<ComponentTypeUuid, String>,
+    pub parameters: Vec<PrefabParameter>,
+    pub layers: BTreeMap<String, Vec<EntityUuid>>,
+    pub hierarchy: BTreeMap<EntityUuid, Vec<EntityUuid>>,
+    pub blobs: BTreeMap<BlobId, BlobData>,
+    /// The prefab this prefab extends. The base is also in `prefab_refs`, which holds the changes
+    /// this prefab makes to its entities.
+    pub base_prefab: Option<PrefabUuid>,
+    /// The components of each entity template, in the order they appear in the file
+    pub templates: BTreeMap<TemplateUuid, Vec<InMemoryComponent<V>>>,
+    /// Entities in the order they appear in the file
+    pub entities: Vec<InMemoryEntity<V>>,
+    /// Prefab refs in the order they appear in the file
+    pub prefab_refs: Vec<InMemoryPrefabRef>,
+}
+
+impl<V> InMemoryPrefab<V> {
+    pub fn new(id: PrefabUuid) -> Self {
+        InMemoryPrefab {
+            id,
+            type_names: BTreeMap::new(),
+            parameters: vec![],
+            layers: BTreeMap::new(),
+            hierarchy: BTreeMap::new(),
+            blobs: BTreeMap::new(),
+            base_prefab: None,
+            templates: BTreeMap::new(),
+            entities: vec![],
+            prefab_refs: vec![],
+        }
+    }
+
+    pub fn entity(
+        &self,
+        id: &EntityUuid,
+    ) -> Option<&InMemoryEntity<V>> {
+        self.entities.iter().find(|entity| entity.id == *id)
+    }
+
+    pub fn prefab_ref(
+        &self,
+        prefab_id: &PrefabUuid,
+    ) -> Option<&InMemoryPrefabRef> {
+        self.prefab_refs
+            .iter()
+            .find(|prefab_ref| prefab_ref.prefab_id == *prefab_id)
+    }
+
+    fn entity_mut(
+        &mut self,
+        id: &EntityUuid,
+    ) -> &mut InMemoryEntity<V> {
+        self.entities
+            .iter_mut()
+            .rev()
+            .find(|entity| entity.id == *id)
+            .expect("component delivered without begin_entity_object")
+    }
+
+    fn prefab_ref_mut(
+        &mut self,
+        prefab_id: &PrefabUuid,
+    ) -> &mut InMemoryPrefabRef {
+        self.prefab_refs
+            .iter_mut()
+            .rev()
+            .find(|prefab_ref| prefab_ref.prefab_id == *prefab_id)
+            .expect("prefab ref data delivered without begin_prefab_ref")
+    }
+}
+
+/// Collects the prefab being deserialized. See the module documentation.
+pub struct InMemoryStorage<V> {
+    prefab: RefCell<Option<InMemoryPrefab<V>>>,
+}
+
+impl<V> Default for InMemoryStorage<V> {
+    fn default() -> Self {
+        InMemoryStorage {
+            prefab: RefCell::new(None),
+        }
+    }
+}
+
+impl<V> InMemoryStorage<V> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The deserialized prefab, or None if nothing was deserialized
+    pub fn into_prefab(self) -> Option<InMemoryPrefab<V>> {
+        self.prefab.into_inner()
+    }
+
+    fn with_prefab<R>(
+        &self,
+        prefab: &PrefabUuid,
+        f: impl FnOnce(&mut InMemoryPrefab<V>) -> R,
+    ) -> R {
+        let mut cell = self.prefab.borrow_mut();
+        let prefab = cell.get_or_insert_with(|| InMemoryPrefab::new(*prefab));
+        f(prefab)
+    }
+}
+
+impl<V: DeserializeOwned> StorageDeserializer for InMemoryStorage<V> {
+    fn begin_prefab(
+        &self,
+        prefab: &PrefabUuid,
+    ) {
+        self.with_prefab(prefab, |_| ());
+    }
+    fn begin_entity_object(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+    ) {
+        self.with_prefab(prefab, |prefab| {
+            prefab.entities.push(InMemoryEntity {
+                id: *entity,
+                template: None,
+                template_diffs: vec![],
+                components: vec![],
+            })
+        });
+    }
+    fn end_entity_object(
+        &self,
+        _prefab: &PrefabUuid,
+        _entity: &EntityUuid,
+    ) {
+    }
+    fn deserialize_component<'de, D: Deserializer<'de>>(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        let data = V::deserialize(deserializer)?;
+        self.with_prefab(prefab, |prefab| {
+            prefab
+                .entity_mut(entity)
+                .components
+                .push(InMemoryComponent {
+                    component_type: *component_type,
+                    data,
+                })
+        });
+        Ok(())
+    }
+    fn begin_prefab_ref(
+        &self,
+        prefab: &PrefabUuid,
+        target_prefab: &PrefabUuid,
+    ) {
+        self.with_prefab(prefab, |prefab| {
+            prefab.prefab_refs.push(InMemoryPrefabRef {
+                prefab_id: *target_prefab,
+                parameter_values: BTreeMap::new(),
+                transform: PrefabRefTransform::IDENTITY,
+                entity_overrides: vec![],
+            })
+        });
+    }
+    fn end_prefab_ref(
+        &self,
+        _prefab: &PrefabUuid,
+        _target_prefab: &PrefabUuid,
+    ) {
+    }
+    fn apply_component_diff<'de, D: Deserializer<'de>>(
+        &self,
+        parent_prefab: &PrefabUuid,
+        prefab_ref: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        let diff = String::deserialize(deserializer)?;
+        self.with_prefab(parent_prefab, |prefab| {
+            let entity_overrides = &mut prefab.prefab_ref_mut(prefab_ref).entity_overrides;
+            if entity_overrides.last().map(|(id, _)| id) != Some(entity) {
+                entity_overrides.push((*entity, vec![]));
+            }
+            entity_overrides
+                .last_mut()
+                .unwrap()
+                .1
+                .push(InMemoryComponentOverride {
+                    component_type: *component_type,
+                    diff,
+                });
+        });
+        Ok(())
+    }
+    fn declare_parameter(
+        &self,
+        prefab: &PrefabUuid,
+        parameter: PrefabParameter,
+    ) {
+        self.with_prefab(prefab, |prefab| prefab.parameters.push(parameter));
+    }
+    fn set_parameter_value(
+        &self,
+        parent_prefab: &PrefabUuid,
+        prefab_ref: &PrefabUuid,
+        name: &str,
+        value: &str,
+    ) {
+        self.with_prefab(parent_prefab, |prefab| {
+            prefab
+                .prefab_ref_mut(prefab_ref)
+                .parameter_values
+                .insert(name.to_string(), value.to_string());
+        });
+    }
+    fn set_prefab_ref_transform(
+        &self,
+        parent_prefab: &PrefabUuid,
+        prefab_ref: &PrefabUuid,
+        transform: &PrefabRefTransform,
+    ) {
+        self.with_prefab(parent_prefab, |prefab| {
+            prefab.prefab_ref_mut(prefab_ref).transform = *transform;
+        });
+    }
+    fn declare_layer(
+        &self,
+        prefab: &PrefabUuid,
+        name: &str,
+        entities: &[EntityUuid],
+    ) {
+        self.with_prefab(prefab, |prefab| {
+            prefab
+                .layers
+                .entry(name.to_string())
+                .or_default()
+                .extend_from_slice(entities);
+        });
+    }
+    fn declare_children(
+        &self,
+        prefab: &PrefabUuid,
+        parent: &EntityUuid,
+        children: &[EntityUuid],
+    ) {
+        self.with_prefab(prefab, |prefab| {
+            prefab.hierarchy.insert(*parent, children.to_vec());
+        });
+    }
+    fn declare_component_type_name(
+        &self,
+        prefab: &PrefabUuid,
+        name: &str,
+        component_type: &ComponentTypeUuid,
+    ) {
+        self.with_prefab(prefab, |prefab| {
+            prefab.type_names.insert(*component_type, name.to_string());
+        });
+    }
+    fn declare_blob(
+        &self,
+        prefab: &PrefabUuid,
+        blob: &BlobId,
+        data: BlobData,
+    ) {
+        self.with_prefab(prefab, |prefab| {
+            prefab.blobs.insert(*blob, data);
+        });
+    }
+    fn set_base_prefab(
+        &self,
+        prefab: &PrefabUuid,
+        base_prefab: &PrefabUuid,
+    ) {
+        self.with_prefab(prefab, |prefab| prefab.base_prefab = Some(*base_prefab));
+    }
+    fn deserialize_template_component<'de, D: Deserializer<'de>>(
+        &self,
+        prefab: &PrefabUuid,
+        template: &TemplateUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        let data = V::deserialize(deserializer)?;
+        self.with_prefab(prefab, |prefab| {
+            prefab
+                .templates
+                .entry(*template)
+                .or_default()
+                .push(InMemoryComponent {
+                    component_type: *component_type,
+                    data,
+                })
+        });
+        Ok(())
+    }
+    fn instantiate_template(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+        template: &TemplateUuid,
+    ) {
+        self.with_prefab(prefab, |prefab| {
+            prefab.entity_mut(entity).template = Some(*template);
+        });
+    }
+    fn apply_template_diff<'de, D: Deserializer<'de>>(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        let diff = String::deserialize(deserializer)?;
+        self.with_prefab(prefab, |prefab| {
+            prefab
+                .entity_mut(entity)
+                .template_diffs
+                .push(InMemoryComponentOverride {
+                    component_type: *component_type,
+                    diff,
+                })
+        });
+        Ok(())
+    }
+}
+
+impl<V: Serialize> StorageSerializer for InMemoryPrefab<V> {
+    fn entities(&self) -> Vec<EntityUuid> {
+        self.entities.iter().map(|entity| entity.id).collect()
+    }
+    fn component_types(
+        &self,
+        entity: &EntityUuid,
+    ) -> Vec<ComponentTypeUuid> {
+        self.entity(entity)
+            .map(|entity| {
+                entity
+                    .components
+                    .iter()
+                    .map(|component| component.component_type)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    fn serialize_entity_component<S: Serializer>(
+        &self,
+        serializer: S,
+        entity: &EntityUuid,
+        component: &ComponentTypeUuid,
+    ) -> Result<S::Ok, S::Error> {
+        self.entity(entity)
+            .and_then(|entity| {
+                entity
+                    .components
+                    .iter()
+                    .find(|c| c.component_type == *component)
+            })
+            .expect("invalid component type when serializing entity component")
+            .data
+            .serialize(serializer)
+    }
+    fn prefab_refs(&self) -> Vec<PrefabUuid> {
+        self.prefab_refs
+            .iter()
+            .map(|prefab_ref| prefab_ref.prefab_id)
+            .collect()
+    }
+    fn prefab_ref_overrides(
+        &self,
+        uuid: &PrefabUuid,
+    ) -> Vec<(EntityUuid, Vec<ComponentTypeUuid>)> {
+        self.prefab_ref(uuid)
+            .map(|prefab_ref| {
+                prefab_ref
+                    .entity_overrides
+                    .iter()
+                    .map(|(entity, overrides)| {
+                        (
+                            *entity,
+                            overrides.iter().map(|o| o.component_type).collect(),
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    fn serialize_component_override_diff<S: Serializer>(
+        &self,
+        serializer: S,
+        prefab_ref: &PrefabUuid,
+        entity: &EntityUuid,
+        component: &ComponentTypeUuid,
+    ) -> Result<S::Ok, S::Error> {
+        self.prefab_ref(prefab_ref)
+            .and_then(|prefab_ref| {
+                prefab_ref
+                    .entity_overrides
+                    .iter()
+                    .find(|(id, _)| id == entity)
+            })
+            .and_then(|(_, overrides)| overrides.iter().find(|o| o.component_type == *component))
+            .expect("invalid component type when serializing component override diff")
+            .diff
+            .serialize(serializer)
+    }
+    fn parameters(&self) -> Vec<PrefabParameter> {
+        self.parameters.clone()
+    }
+    fn prefab_ref_parameter_values(
+        &self,
+        uuid: &PrefabUuid,
+    ) -> BTreeMap<String, String> {
+        self.prefab_ref(uuid)
+            .map(|prefab_ref| prefab_ref.parameter_values.clone())
+            .unwrap_or_default()
+    }
+    fn prefab_ref_transform(
+        &self,
+        uuid: &PrefabUuid,
+    ) -> PrefabRefTransform {
+        self.prefab_ref(uuid)
+            .map_or(PrefabRefTransform::IDENTITY, |prefab_ref| {
+                prefab_ref.transform
+            })
+    }
+    fn layers(&self) -> BTreeMap<String, Vec<EntityUuid>> {
+        self.layers.clone()
+    }
+    fn hierarchy(&self) -> BTreeMap<EntityUuid, Vec<EntityUuid>> {
+        self.hierarchy.clone()
+    }
+    fn blobs(&self) -> Vec<(BlobId, &BlobData)> {
+        self.blobs
+            .iter()
+            .map(|(blob, data)| (*blob, data))
+            .collect()
+    }
+    fn component_type_name(
+        &self,
+        component_type: &ComponentTypeUuid,
+    ) -> Option<String> {
+        self.type_names.get(component_type).cloned()
+    }
+    fn base_prefab(&self) -> Option<PrefabUuid> {
+        self.base_prefab
+    }
+    fn templates(&self) -> Vec<TemplateUuid> {
+        self.templates.keys().copied().collect()
+    }
+    fn template_component_types(
+        &self,
+        template: &TemplateUuid,
+    ) -> Vec<ComponentTypeUuid> {
+        self.templates
+            .get(template)
+            .map(|components| {
+                components
+                    .iter()
+                    .map(|component| component.component_type)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    fn serialize_template_component<S: Serializer>(
+        &self,
+        serializer: S,
+        template: &TemplateUuid,
+        component: &ComponentTypeUuid,
+    ) -> Result<S::Ok, S::Error> {
+        self.templates
+            .get(template)
+            .and_then(|components| components.iter().find(|c| c.component_type == *component))
+            .expect("invalid component type when serializing template component")
+            .data
+            .serialize(serializer)
+    }
+    fn entity_template(
+        &self,
+        entity: &EntityUuid,
+    ) -> Option<TemplateUuid> {
+        self.entity(entity).and_then(|entity| entity.template)
+    }
+    fn template_diff_component_types(
+        &self,
+        entity: &EntityUuid,
+    ) -> Vec<ComponentTypeUuid> {
+        self.entity(entity)
+            .map(|entity| {
+                entity
+                    .template_diffs
+                    .iter()
+                    .map(|diff| diff.component_type)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    fn serialize_template_diff<S: Serializer>(
+        &self,
+        serializer: S,
+        entity: &EntityUuid,
+        component: &ComponentTypeUuid,
+    ) -> Result<S::Ok, S::Error> {
+        self.entity(entity)
+            .and_then(|entity| {
+                entity
+                    .template_diffs
+                    .iter()
+                    .find(|diff| diff.component_type == *component)
+            })
+            .expect("invalid component type when serializing template diff")
+            .diff
+            .serialize(serializer)
+    }
+}
@@ -0,0 +1,60 @@
+//! Upgrades RON prefab source text written with an older version of the prefab format.
+//!
+//! Whenever `FORMAT_VERSION` is bumped, add a migration from the previous version to
+//! `RON_FORMAT_MIGRATIONS`. The deserializer only accepts the current version, so files have to be
+//! upgraded (i.e. with `prefab upgrade`) before they can be loaded.
+use crate::ron_patch::{RonPatchError, RonPrefabDocument};
+use crate::FORMAT_VERSION;
+
+/// Rewrites prefab source text from `from_version` to `from_version + 1`. The migrated text must
+/// declare the new version.
+pub struct FormatMigration {
+    pub from_version: u32,
+    pub migrate_fn: fn(&str) -> Result<String, String>,
+}
+
+/// Migrations for RON prefab source text, ordered by version
+pub const RON_FORMAT_MIGRATIONS: &[FormatMigration] = &[];
+
+#[derive(Debug)]
+pub enum FormatMigrationError {
+    /// The source text could not be scanned
+    Parse(RonPatchError),
+    /// The file was written by a newer version of the format than this crate supports
+    UnsupportedVersion(u32),
+    /// There is no migration registered from this version
+    MissingMigration(u32),
+    /// The migration from this version failed
+    MigrationFailed(u32, String),
+}
+
+/// Returns the format version of RON prefab source text
+pub fn ron_format_version(source: &str) -> Result<u32, RonPatchError> {
+    Ok(RonPrefabDocument::parse(source)?
+        .format_version()
+        .unwrap_or(1))
+}
+
+/// Upgrades RON prefab source text to `FORMAT_VERSION`. Returns the version the source was
+/// upgraded from and the new text, or None if the source is already current.
+pub fn upgrade_ron_format(source: &str) -> Result<Option<(u32, String)>, FormatMigrationError> {
+    let original_version = ron_format_version(source).map_err(FormatMigrationError::Parse)?;
+    if original_version > FORMAT_VERSION {
+        return Err(FormatMigrationError::UnsupportedVersion(original_version));
+    }
+    if original_version == FORMAT_VERSION {
+        return Ok(None);
+    }
+
+    let mut text = source.to_string();
+    for version in original_version..FORMAT_VERSION {
+        let migration = RON_FORMAT_MIGRATIONS
+            .iter()
+            .find(|m| m.from_version == version)
+            .ok_or(FormatMigrationError::MissingMigration(version))?;
+        text = (migration.migrate_fn)(&text)
+            .map_err(|e| FormatMigrationError::MigrationFailed(version, e))?;
+    }
+
+    Ok(Some((original_version, text)))
+}
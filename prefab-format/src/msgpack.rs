@@ -0,0 +1,34 @@
+//! Helpers for reading and writing prefabs as MessagePack.
+//!
+//! Structs are always written as maps (rather than rmp-serde's default of arrays) because the
+//! prefab deserializer identifies fields by name.
+use crate::{PrefabUuid, StorageDeserializer, StorageSerializer};
+
+/// Reads a MessagePack prefab from a byte slice, calling into `storage` as objects are encountered
+pub fn from_slice<'a, S: StorageDeserializer>(
+    bytes: &'a [u8],
+    storage: &'a S,
+) -> Result<(), rmp_serde::decode::Error> {
+    let mut deserializer = rmp_serde::Deserializer::from_read_ref(bytes);
+    crate::deserialize(&mut deserializer, storage)
+}
+
+/// Writes the prefab provided by `storage` as MessagePack
+pub fn to_writer<W: std::io::Write, SS: StorageSerializer>(
+    writer: &mut W,
+    storage: &SS,
+    prefab_id: PrefabUuid,
+) -> Result<(), rmp_serde::encode::Error> {
+    let mut serializer = rmp_serde::Serializer::new(writer).with_struct_map();
+    crate::serialize(&mut serializer, storage, prefab_id)
+}
+
+/// Serializes the prefab provided by `storage` to a MessagePack byte vector
+pub fn to_vec<SS: StorageSerializer>(
+    storage: &SS,
+    prefab_id: PrefabUuid,
+) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    let mut bytes = Vec::new();
+    to_writer(&mut bytes, storage, prefab_id)?;
+    Ok(bytes)
+}
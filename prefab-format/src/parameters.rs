@@ -0,0 +1,114 @@
+//! Named parameters that a prefab exposes so that instances can customize it without writing
+//! overrides for each component.
+//!
+//! A parameter is declared in the prefab's `parameters` list and bound to component fields. Prefab
+//! refs (and spawn calls) set values by name with `parameter_values`, and every bound field is set
+//! to the value when the prefab is cooked or spawned:
+//!
+//! ```text
+//! parameters: [
+//!     (
+//!         name: "health",
+//!         type: "f32",
+//!         default: "100.0",
+//!         bindings: [
+//!             (entity: "...", component_type: "...", field: "max_health"),
+//!         ],
+//!     ),
+//! ],
+//! ```
+//!
+//! Values are RON text, like component override diffs.
+use crate::{ComponentTypeUuid, EntityUuid};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrefabParameter {
+    pub name: String,
+    /// The type of the value, for display in tools. Values are checked against the types of the
+    /// bound fields when they are applied.
+    #[serde(rename = "type")]
+    pub type_name: String,
+    /// Used when an instance doesn't set a value
+    pub default: String,
+    #[serde(default)]
+    pub bindings: Vec<ParameterBinding>,
+}
+
+/// A component field that is set to the value of a parameter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParameterBinding {
+    #[serde(with = "crate::uuid_bytes")]
+    pub entity: EntityUuid,
+    #[serde(with = "crate::uuid_bytes")]
+    pub component_type: ComponentTypeUuid,
+    /// Path to the field within the component, i.e. `stats.max_health` or `waypoints[2]`
+    pub field: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldPathError {
+    Empty,
+    /// An element of the path is empty or a collection index isn't a number
+    InvalidElement(String),
+}
+
+/// Builds a RON-encoded serde_diff that sets the field at `field_path` to `value` (RON text). The
+/// result can be applied the same way as a component override.
+pub fn field_assignment_diff(
+    field_path: &str,
+    value: &str,
+) -> Result<String, FieldPathError> {
+    let elements = parse_field_path(field_path)?;
+    let mut diff = String::from("[");
+    for element in &elements {
+        match element {
+            FieldPathElement::Field(name) => diff.push_str(&format!("Enter(Field({:?})),", name)),
+            FieldPathElement::Index(index) => {
+                diff.push_str(&format!("Enter(CollectionIndex({})),", index))
+            }
+        }
+    }
+    diff.push_str(&format!("Value({}),", value));
+    for _ in &elements {
+        diff.push_str("Exit,");
+    }
+    diff.push(']');
+    Ok(diff)
+}
+
+enum FieldPathElement<'a> {
+    Field(&'a str),
+    Index(usize),
+}
+
+fn parse_field_path(field_path: &str) -> Result<Vec<FieldPathElement<'_>>, FieldPathError> {
+    if field_path.is_empty() {
+        return Err(FieldPathError::Empty);
+    }
+
+    let invalid = |element: &str| FieldPathError::InvalidElement(element.to_string());
+    let mut elements = vec![];
+    for part in field_path.split('.') {
+        // A part is a field name followed by any number of [index] suffixes
+        let (name, mut indices) = match part.find('[') {
+            Some(bracket) => (&part[..bracket], &part[bracket..]),
+            None => (part, ""),
+        };
+        if name.is_empty() {
+            return Err(invalid(part));
+        }
+        elements.push(FieldPathElement::Field(name));
+
+        while !indices.is_empty() {
+            let end = indices.find(']').ok_or_else(|| invalid(part))?;
+            let index = indices[1..end].parse().map_err(|_| invalid(part))?;
+            elements.push(FieldPathElement::Index(index));
+            indices = &indices[end + 1..];
+            if !indices.is_empty() && !indices.starts_with('[') {
+                return Err(invalid(part));
+            }
+        }
+    }
+    Ok(elements)
+}
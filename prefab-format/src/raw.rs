@@ -0,0 +1,319 @@
+//! An untyped representation of a prefab source file.
+//!
+//! Unlike loading a prefab through `StorageDeserializer`, this doesn't need any component types to
+//! be registered and keeps everything exactly as written in the file, including duplicate IDs and
+//! object order. This makes it suitable for tools that inspect prefabs, like linters.
+use crate::ron_patch::{indent_continuation_lines, RonPatchError, RonPrefabDocument};
+use crate::type_map::{type_names, TypeMap};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use crate::{ComponentTypeUuid, EntityUuid, PrefabUuid, TemplateUuid};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefabRaw {
+    pub id: PrefabUuid,
+    /// The names the file uses for component types. Component types are written by name when they
+    /// are in the map.
+    pub type_map: TypeMap,
+    /// The RON text of the parameters list, if the prefab declares any
+    pub parameters: Option<String>,
+    /// The RON text of the entity layers map, if the prefab has any
+    pub layers: Option<String>,
+    /// The RON text of the hierarchy map, if any entity has children
+    pub hierarchy: Option<String>,
+    /// The prefab this prefab extends, with the changes it makes to the base's entities
+    pub extends: Option<PrefabRefRaw>,
+    /// The RON text of the entity templates map, if the prefab has any
+    pub templates: Option<String>,
+    /// Objects in the order they appear in the file
+    pub objects: Vec<PrefabObjectRaw>,
+    /// The RON text of the blob section, if the prefab has one
+    pub blobs: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrefabObjectRaw {
+    Entity(EntityRaw),
+    PrefabRef(PrefabRefRaw),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityRaw {
+    pub id: EntityUuid,
+    /// The template the entity instantiates, if any
+    pub template: Option<TemplateUuid>,
+    /// The RON text of the diffs the entity applies to its template's components, if any
+    pub template_diffs: Option<String>,
+    pub components: Vec<EntityComponentRaw>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityComponentRaw {
+    pub component_type: ComponentTypeUuid,
+    /// The component data as RON text. Components written without data (zero-sized markers) have
+    /// `()`, and are written without data again.
+    pub data: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefabRefRaw {
+    pub prefab_id: PrefabUuid,
+    /// The RON text of the parameter values map, if the prefab ref sets any
+    pub parameter_values: Option<String>,
+    /// The RON text of the offset applied to the referenced prefab, if the prefab ref sets one
+    pub transform: Option<String>,
+    pub entity_overrides: Vec<EntityOverrideRaw>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityOverrideRaw {
+    pub entity_id: EntityUuid,
+    pub component_overrides: Vec<ComponentOverrideRaw>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentOverrideRaw {
+    pub component_type: ComponentTypeUuid,
+    /// The RON-encoded serde_diff (already unquoted from the string it's stored in)
+    pub diff: String,
+}
+
+impl PrefabRaw {
+    /// Reads a RON prefab source file
+    pub fn from_ron_str(source: &str) -> Result<PrefabRaw, RonPatchError> {
+        RonPrefabDocument::parse(source)?.to_raw()
+    }
+
+    /// Writes the prefab as RON in the layout used by the canonical formatter. Component data and
+    /// diffs are copied as text, so nothing needs to be deserialized and no component types need
+    /// to be registered.
+    pub fn to_ron_string(&self) -> String {
+        let mut out = String::new();
+        // Writing to a String can't fail
+        self.write_ron(&mut out).unwrap();
+        out
+    }
+
+    fn write_ron(
+        &self,
+        out: &mut String,
+    ) -> std::fmt::Result {
+        writeln!(out, "Prefab(")?;
+        writeln!(out, "    id: \"{}\",", uuid_str(&self.id))?;
+        if !self.type_map.is_empty() {
+            writeln!(out, "    type_map: {{")?;
+            for (name, component_type) in &self.type_map {
+                writeln!(
+                    out,
+                    "        \"{}\": \"{}\",",
+                    name.escape_debug(),
+                    uuid_str(component_type)
+                )?;
+            }
+            writeln!(out, "    }},")?;
+        }
+        let type_names = type_names(&self.type_map);
+        if let Some(parameters) = &self.parameters {
+            writeln!(
+                out,
+                "    parameters: {},",
+                indent_continuation_lines(parameters, "    ")
+            )?;
+        }
+        if let Some(layers) = &self.layers {
+            writeln!(
+                out,
+                "    layers: {},",
+                indent_continuation_lines(layers, "    ")
+            )?;
+        }
+        if let Some(hierarchy) = &self.hierarchy {
+            writeln!(
+                out,
+                "    hierarchy: {},",
+                indent_continuation_lines(hierarchy, "    ")
+            )?;
+        }
+        if let Some(base_prefab) = &self.extends {
+            writeln!(out, "    extends: PrefabRef(")?;
+            write_prefab_ref_fields(out, base_prefab, &type_names, "        ")?;
+            writeln!(out, "    ),")?;
+        }
+        if let Some(templates) = &self.templates {
+            writeln!(
+                out,
+                "    templates: {},",
+                indent_continuation_lines(templates, "    ")
+            )?;
+        }
+        writeln!(out, "    objects: [")?;
+        for object in &self.objects {
+            match object {
+                PrefabObjectRaw::Entity(entity) => {
+                    writeln!(out, "        Entity(PrefabEntity(")?;
+                    writeln!(out, "            id: \"{}\",", uuid_str(&entity.id))?;
+                    if let Some(template) = &entity.template {
+                        writeln!(out, "            template: \"{}\",", uuid_str(template))?;
+                    }
+                    if let Some(template_diffs) = &entity.template_diffs {
+                        writeln!(
+                            out,
+                            "            template_diffs: {},",
+                            indent_continuation_lines(template_diffs, "            ")
+                        )?;
+                    }
+                    if entity.components.is_empty() {
+                        writeln!(out, "            components: [],")?;
+                    } else {
+                        writeln!(out, "            components: [")?;
+                        for component in &entity.components {
+                            let data =
+                                indent_continuation_lines(&component.data, "                    ");
+                            writeln!(out, "                EntityComponent(")?;
+                            writeln!(
+                                out,
+                                "                    type: \"{}\",",
+                                component_type_str(&type_names, &component.component_type)
+                            )?;
+                            if !is_unit(&component.data) {
+                                writeln!(out, "                    data: {},", data)?;
+                            }
+                            writeln!(out, "                ),")?;
+                        }
+                        writeln!(out, "            ],")?;
+                    }
+                    writeln!(out, "        )),")?;
+                }
+                PrefabObjectRaw::PrefabRef(prefab_ref) => {
+                    writeln!(out, "        PrefabRef(PrefabRef(")?;
+                    write_prefab_ref_fields(out, prefab_ref, &type_names, "            ")?;
+                    writeln!(out, "        )),")?;
+                }
+            }
+        }
+        writeln!(out, "    ],")?;
+        if let Some(blobs) = &self.blobs {
+            writeln!(
+                out,
+                "    blobs: {},",
+                indent_continuation_lines(blobs, "    ")
+            )?;
+        }
+        write!(out, ")")
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = &EntityRaw> {
+        self.objects.iter().filter_map(|object| match object {
+            PrefabObjectRaw::Entity(entity) => Some(entity),
+            PrefabObjectRaw::PrefabRef(_) => None,
+        })
+    }
+
+    pub fn prefab_refs(&self) -> impl Iterator<Item = &PrefabRefRaw> {
+        self.objects.iter().filter_map(|object| match object {
+            PrefabObjectRaw::Entity(_) => None,
+            PrefabObjectRaw::PrefabRef(prefab_ref) => Some(prefab_ref),
+        })
+    }
+
+    /// The first entity with the given ID
+    pub fn entity(
+        &self,
+        id: &EntityUuid,
+    ) -> Option<&EntityRaw> {
+        self.entities().find(|entity| entity.id == *id)
+    }
+}
+
+impl EntityRaw {
+    /// The first component of the given type
+    pub fn component(
+        &self,
+        component_type: &ComponentTypeUuid,
+    ) -> Option<&EntityComponentRaw> {
+        self.components
+            .iter()
+            .find(|component| component.component_type == *component_type)
+    }
+}
+
+// The fields of a prefab ref, each line starting with `indent`
+fn write_prefab_ref_fields(
+    out: &mut String,
+    prefab_ref: &PrefabRefRaw,
+    type_names: &BTreeMap<ComponentTypeUuid, &str>,
+    indent: &str,
+) -> std::fmt::Result {
+    writeln!(
+        out,
+        "{}prefab_id: \"{}\",",
+        indent,
+        uuid_str(&prefab_ref.prefab_id)
+    )?;
+    if let Some(parameter_values) = &prefab_ref.parameter_values {
+        writeln!(
+            out,
+            "{}parameter_values: {},",
+            indent,
+            indent_continuation_lines(parameter_values, indent)
+        )?;
+    }
+    if let Some(transform) = &prefab_ref.transform {
+        writeln!(
+            out,
+            "{}transform: {},",
+            indent,
+            indent_continuation_lines(transform, indent)
+        )?;
+    }
+    writeln!(out, "{}entity_overrides: [", indent)?;
+    for entity_override in &prefab_ref.entity_overrides {
+        writeln!(out, "{}    EntityOverride(", indent)?;
+        writeln!(
+            out,
+            "{}        entity_id: \"{}\",",
+            indent,
+            uuid_str(&entity_override.entity_id)
+        )?;
+        writeln!(out, "{}        component_overrides: [", indent)?;
+        for component_override in &entity_override.component_overrides {
+            writeln!(out, "{}            ComponentOverride(", indent)?;
+            writeln!(
+                out,
+                "{}                component_type: \"{}\",",
+                indent,
+                component_type_str(type_names, &component_override.component_type)
+            )?;
+            // Escaped the same way as RON strings
+            writeln!(
+                out,
+                "{}                diff: \"{}\",",
+                indent,
+                component_override.diff.escape_debug()
+            )?;
+            writeln!(out, "{}            ),", indent)?;
+        }
+        writeln!(out, "{}        ],", indent)?;
+        writeln!(out, "{}    ),", indent)?;
+    }
+    writeln!(out, "{}],", indent)
+}
+
+fn uuid_str(bytes: &uuid::Bytes) -> String {
+    uuid::Uuid::from_bytes(*bytes).to_string()
+}
+
+fn component_type_str(
+    type_names: &BTreeMap<ComponentTypeUuid, &str>,
+    component_type: &ComponentTypeUuid,
+) -> String {
+    match type_names.get(component_type) {
+        Some(name) => name.escape_debug().to_string(),
+        None => uuid_str(component_type),
+    }
+}
+
+// Marker components have no data, which is written as `()` or left out
+pub(crate) fn is_unit(data: &str) -> bool {
+    data.trim() == "()"
+}
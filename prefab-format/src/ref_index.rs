@@ -0,0 +1,115 @@
+//! Tracks which prefabs reference each prefab, so that editors can warn before a prefab that is
+//! used elsewhere is deleted or changed.
+use crate::scan::PrefabSummary;
+use crate::PrefabUuid;
+use std::collections::{BTreeSet, HashMap};
+
+/// Maps each prefab to the prefabs that reference or extend it. Built from `PrefabSummary`s, and
+/// kept up to date by calling `update` when a prefab is saved and `remove` when it's deleted.
+#[derive(Debug, Clone, Default)]
+pub struct PrefabRefIndex {
+    references: HashMap<PrefabUuid, Vec<PrefabUuid>>,
+    referenced_by: HashMap<PrefabUuid, BTreeSet<PrefabUuid>>,
+}
+
+impl PrefabRefIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_summaries<'a, I: IntoIterator<Item = &'a PrefabSummary>>(summaries: I) -> Self {
+        let mut index = Self::new();
+        for summary in summaries {
+            index.update(summary);
+        }
+        index
+    }
+
+    /// Adds a prefab, replacing what was indexed for it before
+    pub fn update(
+        &mut self,
+        summary: &PrefabSummary,
+    ) {
+        self.remove(&summary.prefab_id);
+        for prefab_ref in &summary.prefab_refs {
+            self.referenced_by
+                .entry(*prefab_ref)
+                .or_default()
+                .insert(summary.prefab_id);
+        }
+        self.references
+            .insert(summary.prefab_id, summary.prefab_refs.clone());
+    }
+
+    /// Removes a prefab's references. Prefabs that still reference it keep doing so.
+    pub fn remove(
+        &mut self,
+        prefab: &PrefabUuid,
+    ) {
+        for prefab_ref in self.references.remove(prefab).unwrap_or_default() {
+            if let Some(referenced_by) = self.referenced_by.get_mut(&prefab_ref) {
+                referenced_by.remove(prefab);
+                if referenced_by.is_empty() {
+                    self.referenced_by.remove(&prefab_ref);
+                }
+            }
+        }
+    }
+
+    pub fn contains(
+        &self,
+        prefab: &PrefabUuid,
+    ) -> bool {
+        self.references.contains_key(prefab)
+    }
+
+    /// The prefabs a prefab references, including the prefab it extends
+    pub fn references(
+        &self,
+        prefab: &PrefabUuid,
+    ) -> &[PrefabUuid] {
+        self.references
+            .get(prefab)
+            .map(|references| references.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// The prefabs that reference or extend a prefab directly, ordered by UUID
+    pub fn referenced_by(
+        &self,
+        prefab: &PrefabUuid,
+    ) -> impl Iterator<Item = &PrefabUuid> {
+        self.referenced_by.get(prefab).into_iter().flatten()
+    }
+
+    /// Every prefab that a change to `prefab` affects, directly or through other prefabs, ordered
+    /// by UUID. Doesn't include `prefab` itself unless it's part of a reference cycle.
+    pub fn referenced_by_transitive(
+        &self,
+        prefab: &PrefabUuid,
+    ) -> Vec<PrefabUuid> {
+        let mut found = BTreeSet::new();
+        let mut pending = vec![*prefab];
+        while let Some(current) = pending.pop() {
+            for referencing in self.referenced_by(&current) {
+                if found.insert(*referencing) {
+                    pending.push(*referencing);
+                }
+            }
+        }
+        found.into_iter().collect()
+    }
+
+    /// Referenced prefabs that haven't been indexed, i.e. were deleted or are missing, ordered by
+    /// UUID
+    pub fn missing_prefabs(&self) -> Vec<PrefabUuid> {
+        let mut missing: Vec<PrefabUuid> = self
+            .referenced_by
+            .keys()
+            .filter(|prefab| !self.references.contains_key(*prefab))
+            .copied()
+            .collect();
+        missing.sort();
+        missing
+    }
+}
@@ -0,0 +1,92 @@
+//! Changes the UUID of a component type in prefab source files, for when a component's `TypeUuid`
+//! is changed on purpose. Unlike a component migration, the component data is kept as is.
+use crate::ron_patch::{RonPatchError, RonPrefabDocument};
+use crate::ComponentTypeUuid;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum RewriteError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, RonPatchError),
+}
+
+/// Rewrites a RON prefab so that components, templates, template diffs, overrides and component
+/// type names of type `old_uuid` use `new_uuid`. Returns None if the prefab doesn't use
+/// `old_uuid`. Only the type UUIDs are edited, so component data, comments and layout are kept,
+/// and nothing needs to be registered.
+pub fn rewrite_component_uuid(
+    prefab_bytes: &[u8],
+    old_uuid: &ComponentTypeUuid,
+    new_uuid: &ComponentTypeUuid,
+) -> Result<Option<String>, RonPatchError> {
+    let source = std::str::from_utf8(prefab_bytes)
+        .map_err(|e| RonPatchError::Parse(e.valid_up_to(), "invalid UTF-8"))?;
+    let mut doc = RonPrefabDocument::parse(source)?;
+    let mut changed = false;
+
+    for entity in doc.entities() {
+        if doc.component_types(&entity).contains(old_uuid) {
+            doc.replace_component_type(&entity, old_uuid, new_uuid)?;
+            changed = true;
+        }
+        if doc.template_diff_types(&entity).contains(old_uuid) {
+            doc.replace_template_diff_component_type(&entity, old_uuid, new_uuid)?;
+            changed = true;
+        }
+    }
+
+    for template in doc.templates() {
+        if doc.template_component_types(&template).contains(old_uuid) {
+            doc.replace_template_component_type(&template, old_uuid, new_uuid)?;
+            changed = true;
+        }
+    }
+
+    for prefab_ref in doc.base_prefab().into_iter().chain(doc.prefab_refs()) {
+        for (entity, component_types) in doc.prefab_ref_overrides(&prefab_ref) {
+            if component_types.contains(old_uuid) {
+                doc.replace_override_component_type(&prefab_ref, &entity, old_uuid, new_uuid)?;
+                changed = true;
+            }
+        }
+    }
+
+    if doc.type_map().values().any(|t| t == old_uuid) {
+        doc.replace_type_map_component_type(old_uuid, new_uuid)?;
+        changed = true;
+    }
+
+    Ok(if changed { Some(doc.finish()) } else { None })
+}
+
+/// Runs `rewrite_component_uuid` on every file, writing back the ones that changed unless
+/// `dry_run` is set. Returns the files that changed (or would change). Every file is read and
+/// rewritten before any is written, so a file that can't be read or parsed leaves all of them
+/// unchanged. Each file is written to a temporary file first and then renamed over the original.
+pub fn rewrite_component_uuid_in_files<P: AsRef<Path>, I: IntoIterator<Item = P>>(
+    prefab_paths: I,
+    old_uuid: &ComponentTypeUuid,
+    new_uuid: &ComponentTypeUuid,
+    dry_run: bool,
+) -> Result<Vec<PathBuf>, RewriteError> {
+    let mut rewritten_files = vec![];
+    for path in prefab_paths {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| RewriteError::Io(path.to_path_buf(), e))?;
+        let rewritten = rewrite_component_uuid(&bytes, old_uuid, new_uuid)
+            .map_err(|e| RewriteError::Parse(path.to_path_buf(), e))?;
+        if let Some(rewritten) = rewritten {
+            rewritten_files.push((path.to_path_buf(), rewritten));
+        }
+    }
+
+    if !dry_run {
+        for (path, rewritten) in &rewritten_files {
+            let temp_path = path.with_extension("prefab.rewriting");
+            std::fs::write(&temp_path, rewritten).map_err(|e| RewriteError::Io(path.clone(), e))?;
+            std::fs::rename(&temp_path, path).map_err(|e| RewriteError::Io(path.clone(), e))?;
+        }
+    }
+
+    Ok(rewritten_files.into_iter().map(|(path, _)| path).collect())
+}
@@ -0,0 +1,1841 @@
+//! Span-level editing of RON prefab source text.
+//!
+//! `RonPrefabDocument` scans a RON prefab file and records where every object, component and
+//! override lives in the text. Edits replace, insert or remove only those spans, so comments and
+//! layout in the rest of the file are left untouched. Anything the document can't express as a
+//! span edit returns `RonPatchError::Unsupported`, in which case the caller should fall back to
+//! rewriting the whole file.
+use crate::raw::{
+    is_unit, ComponentOverrideRaw, EntityComponentRaw, EntityOverrideRaw, EntityRaw,
+    PrefabObjectRaw, PrefabRaw, PrefabRefRaw,
+};
+use crate::type_map::{resolve_component_type, TypeMap};
+use crate::{ComponentTypeUuid, EntityUuid, PrefabUuid, TemplateUuid};
+use std::ops::Range;
+
+#[derive(Debug)]
+pub enum RonPatchError {
+    /// The source text could not be scanned as a prefab. Contains the byte offset and a message
+    Parse(usize, &'static str),
+    /// The entity/component/override targeted by an edit does not exist in the document
+    NotFound,
+    /// The edit would overlap a span that was already edited
+    OverlappingEdit,
+    /// The change can't be expressed as a span edit
+    Unsupported,
+}
+
+type Result<T> = std::result::Result<T, RonPatchError>;
+
+// A bracketed list in the source text
+#[derive(Clone, Debug)]
+struct ListSpan {
+    // Byte offset of the opening bracket
+    open: usize,
+    // Byte offset of the closing bracket
+    close: usize,
+}
+
+#[derive(Clone, Debug)]
+struct ItemSpan {
+    // The whole element, from its first to last character
+    span: Range<usize>,
+    // The value of interest inside the element (component data or override diff)
+    value: Range<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct ComponentSpans {
+    component_type: ComponentTypeUuid,
+    type_span: Range<usize>,
+    item: ItemSpan,
+    // False for marker components written without data. The value span is then empty.
+    has_value: bool,
+}
+
+#[derive(Clone, Debug)]
+struct EntitySpans {
+    id: EntityUuid,
+    span: Range<usize>,
+    template: Option<TemplateUuid>,
+    template_diffs: Option<Range<usize>>,
+    template_diff_items: Vec<ComponentSpans>,
+    components: ListSpan,
+    component_items: Vec<ComponentSpans>,
+}
+
+#[derive(Clone, Debug)]
+struct TemplateSpans {
+    id: TemplateUuid,
+    component_items: Vec<ComponentSpans>,
+}
+
+#[derive(Clone, Debug)]
+struct EntityOverrideSpans {
+    entity_id: EntityUuid,
+    component_overrides: ListSpan,
+    component_override_items: Vec<ComponentSpans>,
+}
+
+#[derive(Clone, Debug)]
+struct PrefabRefSpans {
+    prefab_id: PrefabUuid,
+    span: Range<usize>,
+    parameter_values: Option<Range<usize>>,
+    transform: Option<Range<usize>>,
+    entity_overrides: Vec<EntityOverrideSpans>,
+}
+
+struct Edit {
+    range: Range<usize>,
+    text: String,
+    // Text emitted after `text`. List insertions at the same point are merged by appending to
+    // `text`, so anything that has to come after all of the inserted items goes here.
+    tail: String,
+    is_list_insert: bool,
+}
+
+pub struct RonPrefabDocument<'a> {
+    source: &'a str,
+    prefab_id: PrefabUuid,
+    format_version: Option<u32>,
+    type_map: TypeMap,
+    type_map_span: Option<Range<usize>>,
+    parameters: Option<Range<usize>>,
+    layers: Option<Range<usize>>,
+    hierarchy: Option<Range<usize>>,
+    templates: Option<Range<usize>>,
+    template_items: Vec<TemplateSpans>,
+    blobs: Option<Range<usize>>,
+    objects: ListSpan,
+    entities: Vec<EntitySpans>,
+    prefab_refs: Vec<PrefabRefSpans>,
+    // The prefab this prefab extends, written like a prefab ref
+    base_prefab: Option<PrefabRefSpans>,
+    edits: Vec<Edit>,
+}
+
+impl<'a> RonPrefabDocument<'a> {
+    /// Scans RON prefab source text. This only checks the structure of the prefab document,
+    /// component data and diffs are skipped over without being validated.
+    pub fn parse(source: &'a str) -> Result<Self> {
+        let mut scanner = Scanner::new(source);
+        let mut prefab_id = None;
+        let mut format_version = None;
+        let mut type_map = TypeMap::new();
+        let mut type_map_span = None;
+        let mut parameters = None;
+        let mut layers = None;
+        let mut hierarchy = None;
+        let mut templates = None;
+        let mut template_items = vec![];
+        let mut blobs = None;
+        let mut objects = None;
+        let mut entities = vec![];
+        let mut prefab_refs = vec![];
+        let mut base_prefab = None;
+
+        scanner.struct_start()?;
+        while let Some(field) = scanner.next_field()? {
+            match field {
+                "id" => prefab_id = Some(scanner.uuid()?),
+                "version" => {
+                    let start = scanner.pos;
+                    scanner.skip_value()?;
+                    let version = source[start..scanner.last_token_end].parse().ok();
+                    format_version =
+                        Some(version.ok_or(RonPatchError::Parse(start, "invalid version"))?);
+                }
+                // Must come before objects, which may name component types from it
+                "type_map" => {
+                    let start = scanner.pos;
+                    type_map = parse_type_map(&mut scanner)?;
+                    type_map_span = Some(start..scanner.last_token_end);
+                }
+                "parameters" => {
+                    let start = scanner.pos;
+                    scanner.skip_value()?;
+                    parameters = Some(start..scanner.last_token_end);
+                }
+                "layers" => {
+                    let start = scanner.pos;
+                    scanner.skip_value()?;
+                    layers = Some(start..scanner.last_token_end);
+                }
+                "hierarchy" => {
+                    let start = scanner.pos;
+                    scanner.skip_value()?;
+                    hierarchy = Some(start..scanner.last_token_end);
+                }
+                "extends" => {
+                    let start = scanner.pos;
+                    base_prefab = Some(parse_prefab_ref(&mut scanner, start, &type_map, false)?);
+                }
+                "templates" => {
+                    let start = scanner.pos;
+                    template_items = parse_templates(&mut scanner, &type_map)?;
+                    templates = Some(start..scanner.last_token_end);
+                }
+                "blobs" => {
+                    let start = scanner.pos;
+                    scanner.skip_value()?;
+                    blobs = Some(start..scanner.last_token_end);
+                }
+                "objects" => {
+                    objects = Some(scanner.list(|scanner| {
+                        let start = scanner.pos;
+                        match scanner.ident() {
+                            Some("Entity") => {
+                                scanner.expect(b'(')?;
+                                let entity = parse_entity(scanner, start, &type_map)?;
+                                entities.push(entity);
+                            }
+                            Some("PrefabRef") => {
+                                scanner.expect(b'(')?;
+                                let prefab_ref = parse_prefab_ref(scanner, start, &type_map, true)?;
+                                prefab_refs.push(prefab_ref);
+                            }
+                            _ => return scanner.err("expected Entity or PrefabRef"),
+                        }
+                        Ok(())
+                    })?)
+                }
+                _ => scanner.skip_value()?,
+            }
+            scanner.field_end()?;
+        }
+
+        Ok(RonPrefabDocument {
+            source,
+            prefab_id: prefab_id.ok_or(RonPatchError::Parse(0, "missing prefab id"))?,
+            format_version,
+            type_map,
+            type_map_span,
+            parameters,
+            layers,
+            hierarchy,
+            templates,
+            template_items,
+            blobs,
+            objects: objects.ok_or(RonPatchError::Parse(0, "missing prefab objects"))?,
+            entities,
+            prefab_refs,
+            base_prefab,
+            edits: vec![],
+        })
+    }
+
+    pub fn prefab_id(&self) -> PrefabUuid {
+        self.prefab_id
+    }
+
+    /// The format version declared by the document, or None if it has no `version` field
+    pub fn format_version(&self) -> Option<u32> {
+        self.format_version
+    }
+
+    /// The names the document uses for component types. Component types returned by the document
+    /// are always UUIDs, whichever form the file uses.
+    pub fn type_map(&self) -> &TypeMap {
+        &self.type_map
+    }
+
+    /// The entities defined in the document, in source order
+    pub fn entities(&self) -> Vec<EntityUuid> {
+        self.entities.iter().map(|e| e.id).collect()
+    }
+
+    /// The prefabs referenced by objects, in source order. Doesn't include the prefab this prefab
+    /// extends.
+    pub fn prefab_refs(&self) -> Vec<PrefabUuid> {
+        self.prefab_refs.iter().map(|r| r.prefab_id).collect()
+    }
+
+    /// The prefab this prefab extends. Its overrides are accessed and edited like those of a
+    /// prefab ref.
+    pub fn base_prefab(&self) -> Option<PrefabUuid> {
+        self.base_prefab.as_ref().map(|r| r.prefab_id)
+    }
+
+    /// The entity templates declared by the document, in source order
+    pub fn templates(&self) -> Vec<TemplateUuid> {
+        self.template_items.iter().map(|t| t.id).collect()
+    }
+
+    /// The component types of an entity template, in source order
+    pub fn template_component_types(
+        &self,
+        template: &TemplateUuid,
+    ) -> Vec<ComponentTypeUuid> {
+        self.find_template(template)
+            .map(|t| t.component_items.iter().map(|c| c.component_type).collect())
+            .unwrap_or_default()
+    }
+
+    /// The template an entity instantiates, if it has one
+    pub fn entity_template(
+        &self,
+        entity: &EntityUuid,
+    ) -> Option<TemplateUuid> {
+        self.find_entity(entity).and_then(|e| e.template)
+    }
+
+    /// The component types an entity's template diffs apply to, in source order
+    pub fn template_diff_types(
+        &self,
+        entity: &EntityUuid,
+    ) -> Vec<ComponentTypeUuid> {
+        self.find_entity(entity)
+            .map(|e| {
+                e.template_diff_items
+                    .iter()
+                    .map(|c| c.component_type)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The component types on an entity, in source order
+    pub fn component_types(
+        &self,
+        entity: &EntityUuid,
+    ) -> Vec<ComponentTypeUuid> {
+        self.find_entity(entity)
+            .map(|e| e.component_items.iter().map(|c| c.component_type).collect())
+            .unwrap_or_default()
+    }
+
+    /// The (entity, component types) pairs overridden by a prefab ref, in source order
+    pub fn prefab_ref_overrides(
+        &self,
+        prefab_ref: &PrefabUuid,
+    ) -> Vec<(EntityUuid, Vec<ComponentTypeUuid>)> {
+        self.find_prefab_ref(prefab_ref)
+            .map(|r| {
+                r.entity_overrides
+                    .iter()
+                    .map(|o| {
+                        (
+                            o.entity_id,
+                            o.component_override_items
+                                .iter()
+                                .map(|c| c.component_type)
+                                .collect(),
+                        )
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Converts the scanned source text (ignoring any pending edits) to an untyped prefab. Fails
+    /// if an override diff is not a string.
+    pub fn to_raw(&self) -> Result<PrefabRaw> {
+        let mut objects = vec![];
+        for entity in &self.entities {
+            let components = entity
+                .component_items
+                .iter()
+                .map(|c| EntityComponentRaw {
+                    component_type: c.component_type,
+                    data: self.component_data(c),
+                })
+                .collect();
+            objects.push((
+                entity.span.start,
+                PrefabObjectRaw::Entity(EntityRaw {
+                    id: entity.id,
+                    template: entity.template,
+                    template_diffs: entity
+                        .template_diffs
+                        .clone()
+                        .map(|range| self.dedented_text(range)),
+                    components,
+                }),
+            ));
+        }
+
+        for prefab_ref in &self.prefab_refs {
+            objects.push((
+                prefab_ref.span.start,
+                PrefabObjectRaw::PrefabRef(self.prefab_ref_raw(prefab_ref)?),
+            ));
+        }
+
+        objects.sort_by_key(|(start, _)| *start);
+        Ok(PrefabRaw {
+            id: self.prefab_id,
+            type_map: self.type_map.clone(),
+            parameters: self
+                .parameters
+                .clone()
+                .map(|range| self.dedented_text(range)),
+            layers: self.layers.clone().map(|range| self.dedented_text(range)),
+            hierarchy: self
+                .hierarchy
+                .clone()
+                .map(|range| self.dedented_text(range)),
+            extends: self
+                .base_prefab
+                .as_ref()
+                .map(|base_prefab| self.prefab_ref_raw(base_prefab))
+                .transpose()?,
+            templates: self
+                .templates
+                .clone()
+                .map(|range| self.dedented_text(range)),
+            objects: objects.into_iter().map(|(_, object)| object).collect(),
+            blobs: self.blobs.clone().map(|range| self.dedented_text(range)),
+        })
+    }
+
+    fn prefab_ref_raw(
+        &self,
+        prefab_ref: &PrefabRefSpans,
+    ) -> Result<PrefabRefRaw> {
+        let mut entity_overrides = vec![];
+        for entity_override in &prefab_ref.entity_overrides {
+            let mut component_overrides = vec![];
+            for c in &entity_override.component_override_items {
+                component_overrides.push(ComponentOverrideRaw {
+                    component_type: c.component_type,
+                    diff: unescape_string(self.source, c.item.value.clone())?,
+                });
+            }
+            entity_overrides.push(EntityOverrideRaw {
+                entity_id: entity_override.entity_id,
+                component_overrides,
+            });
+        }
+        Ok(PrefabRefRaw {
+            prefab_id: prefab_ref.prefab_id,
+            parameter_values: prefab_ref
+                .parameter_values
+                .clone()
+                .map(|range| self.dedented_text(range)),
+            transform: prefab_ref
+                .transform
+                .clone()
+                .map(|range| self.dedented_text(range)),
+            entity_overrides,
+        })
+    }
+
+    // Text returned by the accessors below has the indentation of its first line removed from
+    // the following lines, which is also what the edit functions expect to be given. This lets
+    // text be moved between documents with different nesting.
+
+    /// The full source text of an entity object, i.e. `Entity(( ... ))`
+    pub fn entity_text(
+        &self,
+        entity: &EntityUuid,
+    ) -> Option<String> {
+        self.find_entity(entity)
+            .map(|e| self.dedented_text(e.span.clone()))
+    }
+
+    /// The source text of a component's data
+    pub fn component_data_text(
+        &self,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+    ) -> Option<String> {
+        self.find_component(entity, component_type)
+            .map(|c| self.component_data(c))
+    }
+
+    // Marker components written without data read as `()`
+    fn component_data(
+        &self,
+        component: &ComponentSpans,
+    ) -> String {
+        if component.has_value {
+            self.dedented_text(component.item.value.clone())
+        } else {
+            "()".to_string()
+        }
+    }
+
+    /// The source text of a template component's data
+    pub fn template_component_data_text(
+        &self,
+        template: &TemplateUuid,
+        component_type: &ComponentTypeUuid,
+    ) -> Option<String> {
+        self.find_template_component(template, component_type)
+            .map(|c| self.component_data(c))
+    }
+
+    /// The source text of the diff an entity applies to a component of its template
+    pub fn template_diff_text(
+        &self,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+    ) -> Option<String> {
+        self.find_template_diff(entity, component_type)
+            .map(|c| self.dedented_text(c.item.value.clone()))
+    }
+
+    /// The source text of a component override's diff
+    pub fn override_diff_text(
+        &self,
+        prefab_ref: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+    ) -> Option<String> {
+        self.find_component_override(prefab_ref, entity, component_type)
+            .map(|c| self.dedented_text(c.item.value.clone()))
+    }
+
+    /// The source text of the prefab's component type names, if it has any
+    pub fn type_map_text(&self) -> Option<String> {
+        self.type_map_span
+            .clone()
+            .map(|range| self.dedented_text(range))
+    }
+
+    /// The source text of the prefab's parameter declarations, if it has any
+    pub fn parameters_text(&self) -> Option<String> {
+        self.parameters
+            .clone()
+            .map(|range| self.dedented_text(range))
+    }
+
+    /// The source text of the prefab's entity layers, if it has any
+    pub fn layers_text(&self) -> Option<String> {
+        self.layers.clone().map(|range| self.dedented_text(range))
+    }
+
+    /// The source text of the prefab's hierarchy, if any entity has children
+    pub fn hierarchy_text(&self) -> Option<String> {
+        self.hierarchy
+            .clone()
+            .map(|range| self.dedented_text(range))
+    }
+
+    /// The source text of the prefab's entity templates, if it has any
+    pub fn templates_text(&self) -> Option<String> {
+        self.templates
+            .clone()
+            .map(|range| self.dedented_text(range))
+    }
+
+    /// The source text of the prefab's blob section, if it has one
+    pub fn blobs_text(&self) -> Option<String> {
+        self.blobs.clone().map(|range| self.dedented_text(range))
+    }
+
+    /// The source text of the parameter values set by a prefab ref, if it sets any
+    pub fn parameter_values_text(
+        &self,
+        prefab_ref: &PrefabUuid,
+    ) -> Option<String> {
+        self.find_prefab_ref(prefab_ref)
+            .and_then(|r| r.parameter_values.clone())
+            .map(|range| self.dedented_text(range))
+    }
+
+    /// The source text of the offset a prefab ref applies to the referenced prefab, if it sets one
+    pub fn prefab_ref_transform_text(
+        &self,
+        prefab_ref: &PrefabUuid,
+    ) -> Option<String> {
+        self.find_prefab_ref(prefab_ref)
+            .and_then(|r| r.transform.clone())
+            .map(|range| self.dedented_text(range))
+    }
+
+    /// Replaces a component's data with the given RON text
+    pub fn replace_component_data(
+        &mut self,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        data: &str,
+    ) -> Result<()> {
+        let component = self
+            .find_component(entity, component_type)
+            .ok_or(RonPatchError::NotFound)?
+            .clone();
+        self.replace_data(&component, data)
+    }
+
+    /// Changes the type UUID of a component, i.e. when migrating it to a new component type
+    pub fn replace_component_type(
+        &mut self,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        new_component_type: &ComponentTypeUuid,
+    ) -> Result<()> {
+        let range = self
+            .find_component(entity, component_type)
+            .ok_or(RonPatchError::NotFound)?
+            .type_span
+            .clone();
+        let text = format!("\"{}\"", uuid::Uuid::from_bytes(*new_component_type));
+        self.push_edit(range, text)
+    }
+
+    /// Replaces the data of a template component with the given RON text
+    pub fn replace_template_component_data(
+        &mut self,
+        template: &TemplateUuid,
+        component_type: &ComponentTypeUuid,
+        data: &str,
+    ) -> Result<()> {
+        let component = self
+            .find_template_component(template, component_type)
+            .ok_or(RonPatchError::NotFound)?
+            .clone();
+        self.replace_data(&component, data)
+    }
+
+    /// Changes the type UUID of a template component
+    pub fn replace_template_component_type(
+        &mut self,
+        template: &TemplateUuid,
+        component_type: &ComponentTypeUuid,
+        new_component_type: &ComponentTypeUuid,
+    ) -> Result<()> {
+        let range = self
+            .find_template_component(template, component_type)
+            .ok_or(RonPatchError::NotFound)?
+            .type_span
+            .clone();
+        let text = format!("\"{}\"", uuid::Uuid::from_bytes(*new_component_type));
+        self.push_edit(range, text)
+    }
+
+    /// Replaces the diff an entity applies to a component of its template with the given RON text
+    pub fn replace_template_diff(
+        &mut self,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        diff: &str,
+    ) -> Result<()> {
+        let range = self
+            .find_template_diff(entity, component_type)
+            .ok_or(RonPatchError::NotFound)?
+            .item
+            .value
+            .clone();
+        self.replace(range, diff)
+    }
+
+    /// Changes the type UUID of a template diff
+    pub fn replace_template_diff_component_type(
+        &mut self,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        new_component_type: &ComponentTypeUuid,
+    ) -> Result<()> {
+        let range = self
+            .find_template_diff(entity, component_type)
+            .ok_or(RonPatchError::NotFound)?
+            .type_span
+            .clone();
+        let text = format!("\"{}\"", uuid::Uuid::from_bytes(*new_component_type));
+        self.push_edit(range, text)
+    }
+
+    /// Adds a component with the given RON data text to the end of an entity's component list
+    pub fn insert_component(
+        &mut self,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        data: &str,
+    ) -> Result<()> {
+        let entity_spans = self.find_entity(entity).ok_or(RonPatchError::NotFound)?;
+        let list = entity_spans.components.clone();
+        let last = entity_spans
+            .component_items
+            .last()
+            .map(|c| c.item.span.clone());
+        let text = if is_unit(data) {
+            format!(
+                "(\n    type: \"{}\",\n)",
+                uuid::Uuid::from_bytes(*component_type)
+            )
+        } else {
+            format!(
+                "(\n    type: \"{}\",\n    data: {},\n)",
+                uuid::Uuid::from_bytes(*component_type),
+                indent_continuation_lines(data, "    ")
+            )
+        };
+        self.insert_list_item(&list, last, &text)
+    }
+
+    /// Removes a component from an entity
+    pub fn remove_component(
+        &mut self,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+    ) -> Result<()> {
+        let span = self
+            .find_component(entity, component_type)
+            .ok_or(RonPatchError::NotFound)?
+            .item
+            .span
+            .clone();
+        self.remove_list_item(span)
+    }
+
+    /// Adds an object (`Entity(( ... ))` or `PrefabRef(( ... ))` text) to the end of the prefab's
+    /// object list
+    pub fn insert_object(
+        &mut self,
+        object: &str,
+    ) -> Result<()> {
+        let list = self.objects.clone();
+        let last = self
+            .entities
+            .iter()
+            .map(|e| e.span.clone())
+            .chain(self.prefab_refs.iter().map(|r| r.span.clone()))
+            .max_by_key(|span| span.start);
+        self.insert_list_item(&list, last, object)
+    }
+
+    /// Removes an entity object from the prefab
+    pub fn remove_entity(
+        &mut self,
+        entity: &EntityUuid,
+    ) -> Result<()> {
+        let span = self
+            .find_entity(entity)
+            .ok_or(RonPatchError::NotFound)?
+            .span
+            .clone();
+        self.remove_list_item(span)
+    }
+
+    /// Replaces the diff of an existing component override with the given RON text
+    pub fn replace_override_diff(
+        &mut self,
+        prefab_ref: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        diff: &str,
+    ) -> Result<()> {
+        let range = self
+            .find_component_override(prefab_ref, entity, component_type)
+            .ok_or(RonPatchError::NotFound)?
+            .item
+            .value
+            .clone();
+        self.replace(range, diff)
+    }
+
+    /// Changes the type UUID of a component override
+    pub fn replace_override_component_type(
+        &mut self,
+        prefab_ref: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        new_component_type: &ComponentTypeUuid,
+    ) -> Result<()> {
+        let range = self
+            .find_component_override(prefab_ref, entity, component_type)
+            .ok_or(RonPatchError::NotFound)?
+            .type_span
+            .clone();
+        let text = format!("\"{}\"", uuid::Uuid::from_bytes(*new_component_type));
+        self.push_edit(range, text)
+    }
+
+    /// Changes the component type names that refer to `component_type` to refer to
+    /// `new_component_type` instead
+    pub fn replace_type_map_component_type(
+        &mut self,
+        component_type: &ComponentTypeUuid,
+        new_component_type: &ComponentTypeUuid,
+    ) -> Result<()> {
+        if !self.type_map.values().any(|t| t == component_type) {
+            return Err(RonPatchError::NotFound);
+        }
+        let range = self.type_map_span.clone().ok_or(RonPatchError::NotFound)?;
+        let old = format!("\"{}\"", uuid::Uuid::from_bytes(*component_type));
+        let new = format!("\"{}\"", uuid::Uuid::from_bytes(*new_component_type));
+        // The UUID may be written in another form, i.e. upper case
+        if !self.source[range.clone()].contains(&old) {
+            return Err(RonPatchError::Unsupported);
+        }
+        let text = self.source[range.clone()].replace(&old, &new);
+        self.push_edit(range, text)
+    }
+
+    /// Adds a component override to an entity that already has overrides in the prefab ref
+    pub fn insert_override(
+        &mut self,
+        prefab_ref: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        diff: &str,
+    ) -> Result<()> {
+        let entity_override = self
+            .find_prefab_ref(prefab_ref)
+            .and_then(|r| r.entity_overrides.iter().find(|o| o.entity_id == *entity))
+            .ok_or(RonPatchError::Unsupported)?;
+        let list = entity_override.component_overrides.clone();
+        let last = entity_override
+            .component_override_items
+            .last()
+            .map(|c| c.item.span.clone());
+        let text = format!(
+            "(\n    component_type: \"{}\",\n    diff: {},\n)",
+            uuid::Uuid::from_bytes(*component_type),
+            indent_continuation_lines(diff, "    ")
+        );
+        self.insert_list_item(&list, last, &text)
+    }
+
+    /// Removes a component override. Removing the last override of an entity is unsupported since
+    /// it would leave an empty entity override behind.
+    pub fn remove_override(
+        &mut self,
+        prefab_ref: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+    ) -> Result<()> {
+        let entity_override = self
+            .find_prefab_ref(prefab_ref)
+            .and_then(|r| r.entity_overrides.iter().find(|o| o.entity_id == *entity))
+            .ok_or(RonPatchError::NotFound)?;
+        if entity_override.component_override_items.len() < 2 {
+            return Err(RonPatchError::Unsupported);
+        }
+        let span = entity_override
+            .component_override_items
+            .iter()
+            .find(|c| c.component_type == *component_type)
+            .ok_or(RonPatchError::NotFound)?
+            .item
+            .span
+            .clone();
+        self.remove_list_item(span)
+    }
+
+    /// Produces the edited source text
+    pub fn finish(mut self) -> String {
+        self.edits.sort_by_key(|edit| edit.range.start);
+        let mut output = String::with_capacity(self.source.len());
+        let mut pos = 0;
+        for edit in &self.edits {
+            output.push_str(&self.source[pos..edit.range.start]);
+            output.push_str(&edit.text);
+            output.push_str(&edit.tail);
+            pos = edit.range.end;
+        }
+        output.push_str(&self.source[pos..]);
+        output
+    }
+
+    fn find_entity(
+        &self,
+        entity: &EntityUuid,
+    ) -> Option<&EntitySpans> {
+        self.entities.iter().find(|e| e.id == *entity)
+    }
+
+    fn find_template(
+        &self,
+        template: &TemplateUuid,
+    ) -> Option<&TemplateSpans> {
+        self.template_items.iter().find(|t| t.id == *template)
+    }
+
+    fn find_prefab_ref(
+        &self,
+        prefab_ref: &PrefabUuid,
+    ) -> Option<&PrefabRefSpans> {
+        self.prefab_refs
+            .iter()
+            .chain(&self.base_prefab)
+            .find(|r| r.prefab_id == *prefab_ref)
+    }
+
+    fn find_component(
+        &self,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+    ) -> Option<&ComponentSpans> {
+        self.find_entity(entity).and_then(|e| {
+            e.component_items
+                .iter()
+                .find(|c| c.component_type == *component_type)
+        })
+    }
+
+    fn find_template_component(
+        &self,
+        template: &TemplateUuid,
+        component_type: &ComponentTypeUuid,
+    ) -> Option<&ComponentSpans> {
+        self.find_template(template).and_then(|t| {
+            t.component_items
+                .iter()
+                .find(|c| c.component_type == *component_type)
+        })
+    }
+
+    fn find_template_diff(
+        &self,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+    ) -> Option<&ComponentSpans> {
+        self.find_entity(entity).and_then(|e| {
+            e.template_diff_items
+                .iter()
+                .find(|c| c.component_type == *component_type)
+        })
+    }
+
+    fn find_component_override(
+        &self,
+        prefab_ref: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+    ) -> Option<&ComponentSpans> {
+        self.find_prefab_ref(prefab_ref)
+            .and_then(|r| r.entity_overrides.iter().find(|o| o.entity_id == *entity))
+            .and_then(|o| {
+                o.component_override_items
+                    .iter()
+                    .find(|c| c.component_type == *component_type)
+            })
+    }
+
+    fn dedented_text(
+        &self,
+        range: Range<usize>,
+    ) -> String {
+        let indent = line_indent(self.source, range.start);
+        let text = &self.source[range];
+        let mut lines = text.lines();
+        let mut output = lines.next().unwrap_or("").to_string();
+        for line in lines {
+            output.push('\n');
+            output.push_str(line.strip_prefix(indent).unwrap_or(line));
+        }
+        output
+    }
+
+    fn push_edit(
+        &mut self,
+        range: Range<usize>,
+        text: String,
+    ) -> Result<()> {
+        self.push_edit_with_tail(range, text, String::new(), false)
+    }
+
+    fn push_edit_with_tail(
+        &mut self,
+        range: Range<usize>,
+        text: String,
+        tail: String,
+        is_list_insert: bool,
+    ) -> Result<()> {
+        let text = self.with_line_endings(text);
+        let tail = self.with_line_endings(tail);
+
+        // Several items inserted into the same list end up in a single edit
+        if is_list_insert {
+            if let Some(edit) = self
+                .edits
+                .iter_mut()
+                .find(|edit| edit.is_list_insert && edit.range == range)
+            {
+                edit.text.push_str(&text);
+                return Ok(());
+            }
+        }
+
+        let overlaps = self.edits.iter().any(|edit| {
+            // Two edits starting at the same point are ambiguous, so treat them as overlapping too
+            (range.start < edit.range.end && edit.range.start < range.end)
+                || (range.start == edit.range.start)
+        });
+        if overlaps {
+            return Err(RonPatchError::OverlappingEdit);
+        }
+        self.edits.push(Edit {
+            range,
+            text,
+            tail,
+            is_list_insert,
+        });
+        Ok(())
+    }
+
+    // Inserted text is built with `\n` line breaks, match the source if it uses `\r\n`
+    fn with_line_endings(
+        &self,
+        text: String,
+    ) -> String {
+        if self.source.contains("\r\n") && text.contains('\n') {
+            text.replace("\r\n", "\n").replace('\n', "\r\n")
+        } else {
+            text
+        }
+    }
+
+    // Replaces the data of a component or template component
+    fn replace_data(
+        &mut self,
+        component: &ComponentSpans,
+        data: &str,
+    ) -> Result<()> {
+        if !component.has_value {
+            // There's no data to replace in a marker component
+            return if is_unit(data) {
+                Ok(())
+            } else {
+                Err(RonPatchError::Unsupported)
+            };
+        }
+        self.replace(component.item.value.clone(), data)
+    }
+
+    fn replace(
+        &mut self,
+        range: Range<usize>,
+        text: &str,
+    ) -> Result<()> {
+        let indent = line_indent(self.source, range.start);
+        let text = indent_continuation_lines(text, indent);
+        self.push_edit(range, text)
+    }
+
+    fn insert_list_item(
+        &mut self,
+        list: &ListSpan,
+        last_item: Option<Range<usize>>,
+        text: &str,
+    ) -> Result<()> {
+        let close_indent = line_indent(self.source, list.close);
+        match last_item {
+            Some(last_item) => {
+                let indent = line_indent(self.source, last_item.start);
+                let text = indent_continuation_lines(text, indent);
+                let mut scanner = Scanner::new(self.source);
+                scanner.pos = last_item.end;
+                scanner.skip_ws()?;
+                if scanner.peek() == Some(b',') {
+                    let pos = scanner.pos + 1;
+                    let text = format!("\n{}{},", indent, text);
+                    self.push_edit_with_tail(pos..pos, text, String::new(), true)
+                } else {
+                    let pos = last_item.end;
+                    let text = format!(",\n{}{}", indent, text);
+                    self.push_edit_with_tail(pos..pos, text, String::new(), true)
+                }
+            }
+            None => {
+                let indent = format!("{}    ", close_indent);
+                let text = indent_continuation_lines(text, &indent);
+                let text = format!("\n{}{},", indent, text);
+                let tail = format!("\n{}", close_indent);
+                self.push_edit_with_tail(list.open + 1..list.close, text, tail, true)
+            }
+        }
+    }
+
+    fn remove_list_item(
+        &mut self,
+        span: Range<usize>,
+    ) -> Result<()> {
+        let bytes = self.source.as_bytes();
+
+        // Take the trailing comma with the item
+        let mut scanner = Scanner::new(self.source);
+        scanner.pos = span.end;
+        scanner.skip_ws()?;
+        let mut end = if scanner.peek() == Some(b',') {
+            scanner.pos + 1
+        } else {
+            span.end
+        };
+
+        // If the item is on its own line(s), remove the whole line(s)
+        let mut start = span.start;
+        while start > 0 && (bytes[start - 1] == b' ' || bytes[start - 1] == b'\t') {
+            start -= 1;
+        }
+        let starts_line = start == 0 || bytes[start - 1] == b'\n';
+        let mut line_end = end;
+        while line_end < bytes.len() && (bytes[line_end] == b' ' || bytes[line_end] == b'\t') {
+            line_end += 1;
+        }
+        if line_end < bytes.len() && bytes[line_end] == b'\r' {
+            line_end += 1;
+        }
+        let ends_line = line_end >= bytes.len() || bytes[line_end] == b'\n';
+        if starts_line && ends_line {
+            end = (line_end + 1).min(bytes.len());
+        } else {
+            start = span.start;
+        }
+
+        self.push_edit(start..end, String::new())
+    }
+}
+
+fn parse_entity(
+    scanner: &mut Scanner,
+    start: usize,
+    type_map: &TypeMap,
+) -> Result<EntitySpans> {
+    let mut id = None;
+    let mut template = None;
+    let mut template_diffs = None;
+    let mut template_diff_items = vec![];
+    let mut components = None;
+    let mut component_items = vec![];
+    scanner.struct_start()?;
+    while let Some(field) = scanner.next_field()? {
+        match field {
+            "id" => id = Some(scanner.uuid()?),
+            "template" => template = Some(scanner.uuid()?),
+            "template_diffs" => {
+                let start = scanner.pos;
+                scanner.list(|scanner| {
+                    template_diff_items.push(parse_component_item(
+                        scanner,
+                        type_map,
+                        "component_type",
+                        "diff",
+                        false,
+                    )?);
+                    Ok(())
+                })?;
+                template_diffs = Some(start..scanner.last_token_end);
+            }
+            "components" => {
+                components = Some(scanner.list(|scanner| {
+                    component_items.push(parse_component_item(
+                        scanner, type_map, "type", "data", true,
+                    )?);
+                    Ok(())
+                })?)
+            }
+            _ => scanner.skip_value()?,
+        }
+        scanner.field_end()?;
+    }
+    // Close the newtype variant
+    scanner.skip_ws()?;
+    scanner.expect(b')')?;
+
+    Ok(EntitySpans {
+        id: id.ok_or(RonPatchError::Parse(start, "missing entity id"))?,
+        span: start..scanner.pos,
+        template,
+        template_diffs,
+        template_diff_items,
+        components: components.ok_or(RonPatchError::Parse(start, "missing components"))?,
+        component_items,
+    })
+}
+
+// The templates map, i.e. `{ "<template uuid>": [ <components> ], }`
+fn parse_templates(
+    scanner: &mut Scanner,
+    type_map: &TypeMap,
+) -> Result<Vec<TemplateSpans>> {
+    let mut templates = vec![];
+    scanner.skip_ws()?;
+    scanner.expect(b'{')?;
+    loop {
+        scanner.skip_ws()?;
+        if scanner.peek() == Some(b'}') {
+            scanner.pos += 1;
+            scanner.last_token_end = scanner.pos;
+            return Ok(templates);
+        }
+        let id = scanner.uuid()?;
+        scanner.skip_ws()?;
+        scanner.expect(b':')?;
+        let mut component_items = vec![];
+        scanner.list(|scanner| {
+            component_items.push(parse_component_item(
+                scanner, type_map, "type", "data", true,
+            )?);
+            Ok(())
+        })?;
+        templates.push(TemplateSpans {
+            id,
+            component_items,
+        });
+        scanner.field_end()?;
+    }
+}
+
+// `newtype` is true for prefab refs in the object list, which are wrapped in a `PrefabRef` variant
+fn parse_prefab_ref(
+    scanner: &mut Scanner,
+    start: usize,
+    type_map: &TypeMap,
+    newtype: bool,
+) -> Result<PrefabRefSpans> {
+    let mut prefab_id = None;
+    let mut parameter_values = None;
+    let mut transform = None;
+    let mut entity_overrides = vec![];
+    scanner.struct_start()?;
+    while let Some(field) = scanner.next_field()? {
+        match field {
+            "prefab_id" => prefab_id = Some(scanner.uuid()?),
+            "parameter_values" => {
+                let start = scanner.pos;
+                scanner.skip_value()?;
+                parameter_values = Some(start..scanner.last_token_end);
+            }
+            "transform" => {
+                let start = scanner.pos;
+                scanner.skip_value()?;
+                transform = Some(start..scanner.last_token_end);
+            }
+            "entity_overrides" => {
+                scanner.list(|scanner| {
+                    entity_overrides.push(parse_entity_override(scanner, type_map)?);
+                    Ok(())
+                })?;
+            }
+            _ => scanner.skip_value()?,
+        }
+        scanner.field_end()?;
+    }
+    if newtype {
+        scanner.skip_ws()?;
+        scanner.expect(b')')?;
+    }
+
+    Ok(PrefabRefSpans {
+        prefab_id: prefab_id.ok_or(RonPatchError::Parse(start, "missing prefab_id"))?,
+        span: start..scanner.pos,
+        parameter_values,
+        transform,
+        entity_overrides,
+    })
+}
+
+fn parse_entity_override(
+    scanner: &mut Scanner,
+    type_map: &TypeMap,
+) -> Result<EntityOverrideSpans> {
+    let start = scanner.pos;
+    let mut entity_id = None;
+    let mut component_overrides = None;
+    let mut component_override_items = vec![];
+    scanner.struct_start()?;
+    while let Some(field) = scanner.next_field()? {
+        match field {
+            "entity_id" => entity_id = Some(scanner.uuid()?),
+            "component_overrides" => {
+                component_overrides = Some(scanner.list(|scanner| {
+                    component_override_items.push(parse_component_item(
+                        scanner,
+                        type_map,
+                        "component_type",
+                        "diff",
+                        false,
+                    )?);
+                    Ok(())
+                })?)
+            }
+            _ => scanner.skip_value()?,
+        }
+        scanner.field_end()?;
+    }
+
+    Ok(EntityOverrideSpans {
+        entity_id: entity_id.ok_or(RonPatchError::Parse(start, "missing entity_id"))?,
+        component_overrides: component_overrides
+            .ok_or(RonPatchError::Parse(start, "missing component_overrides"))?,
+        component_override_items,
+    })
+}
+
+// Components and component overrides are both a (type uuid, value) struct
+fn parse_component_item(
+    scanner: &mut Scanner,
+    type_map: &TypeMap,
+    type_field: &str,
+    value_field: &str,
+    value_optional: bool,
+) -> Result<ComponentSpans> {
+    let start = scanner.pos;
+    let mut component_type = None;
+    let mut value = None;
+    scanner.struct_start()?;
+    while let Some(field) = scanner.next_field()? {
+        if field == type_field {
+            let type_start = scanner.pos;
+            component_type = Some((scanner.component_type(type_map)?, type_start..scanner.pos));
+        } else if field == value_field {
+            let value_start = scanner.pos;
+            scanner.skip_value()?;
+            value = Some(value_start..scanner.last_token_end);
+        } else {
+            scanner.skip_value()?;
+        }
+        scanner.field_end()?;
+    }
+
+    let (component_type, type_span) =
+        component_type.ok_or(RonPatchError::Parse(start, "missing component type"))?;
+    let has_value = value.is_some();
+    let value = match value {
+        Some(value) => value,
+        None if value_optional => scanner.pos..scanner.pos,
+        None => return Err(RonPatchError::Parse(start, "missing component value")),
+    };
+    Ok(ComponentSpans {
+        component_type,
+        type_span,
+        item: ItemSpan {
+            span: start..scanner.pos,
+            value,
+        },
+        has_value,
+    })
+}
+
+// A map of component type names to UUIDs, i.e. `{ "Position": "...", }`
+fn parse_type_map(scanner: &mut Scanner) -> Result<TypeMap> {
+    let mut type_map = TypeMap::new();
+    scanner.skip_ws()?;
+    scanner.expect(b'{')?;
+    loop {
+        scanner.skip_ws()?;
+        if scanner.peek() == Some(b'}') {
+            scanner.pos += 1;
+            scanner.last_token_end = scanner.pos;
+            return Ok(type_map);
+        }
+        let name = scanner.string()?.to_string();
+        scanner.skip_ws()?;
+        scanner.expect(b':')?;
+        type_map.insert(name, scanner.uuid()?);
+        scanner.field_end()?;
+    }
+}
+
+// The whitespace at the start of the line containing `pos`
+fn line_indent(
+    source: &str,
+    pos: usize,
+) -> &str {
+    let line_start = source[..pos].rfind('\n').map(|x| x + 1).unwrap_or(0);
+    let line = &source[line_start..];
+    let indent_len = line
+        .bytes()
+        .take_while(|b| *b == b' ' || *b == b'\t')
+        .count();
+    &line[..indent_len]
+}
+
+// The struct name a RON document starts with, i.e. `Prefab`, if it has one
+pub(crate) fn document_struct_name(source: &str) -> Option<&str> {
+    let mut scanner = Scanner::new(source);
+    scanner.skip_ws().ok()?;
+    scanner.ident()
+}
+
+// Multi-line text is inserted after existing indentation on the first line, so only the following
+// lines need to be indented
+pub(crate) fn indent_continuation_lines(
+    text: &str,
+    indent: &str,
+) -> String {
+    let mut lines = text.lines();
+    let mut output = lines.next().unwrap_or("").to_string();
+    for line in lines {
+        output.push('\n');
+        if !line.is_empty() {
+            output.push_str(indent);
+        }
+        output.push_str(line);
+    }
+    output
+}
+
+// Reads the contents of a RON string literal, either quoted or raw
+fn unescape_string(
+    source: &str,
+    range: Range<usize>,
+) -> Result<String> {
+    let text = &source[range.clone()];
+    let not_a_string = RonPatchError::Parse(range.start, "expected string");
+
+    if let Some(raw) = text.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        return raw[hashes..]
+            .strip_prefix('"')
+            .and_then(|body| body.get(..body.len().checked_sub(hashes + 1)?))
+            .map(|body| body.to_string())
+            .ok_or(not_a_string);
+    }
+
+    let body = text
+        .strip_prefix('"')
+        .and_then(|body| body.strip_suffix('"'))
+        .ok_or(not_a_string)?;
+    let invalid_escape = || RonPatchError::Parse(range.start, "invalid escape in string");
+    let mut output = String::with_capacity(body.len());
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            output.push(c);
+            continue;
+        }
+        let escaped = match chars.next() {
+            Some('\'') => '\'',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                u8::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(|b| b.is_ascii())
+                    .ok_or_else(invalid_escape)? as char
+            }
+            Some('u') => {
+                let rest = chars.as_str();
+                let hex = rest
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.find('}').map(|end| &rest[..end]))
+                    .ok_or_else(invalid_escape)?;
+                let c = u32::from_str_radix(hex, 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                    .ok_or_else(invalid_escape)?;
+                chars = rest[hex.len() + 2..].chars();
+                c
+            }
+            _ => return Err(invalid_escape()),
+        };
+        output.push(escaped);
+    }
+    Ok(output)
+}
+
+struct Scanner<'a> {
+    source: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    // End of the last token consumed, used to trim trailing whitespace/comments from value spans
+    last_token_end: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(source: &'a str) -> Self {
+        Scanner {
+            source,
+            bytes: source.as_bytes(),
+            pos: 0,
+            last_token_end: 0,
+        }
+    }
+
+    fn err<T>(
+        &self,
+        message: &'static str,
+    ) -> Result<T> {
+        Err(RonPatchError::Parse(self.pos, message))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).cloned()
+    }
+
+    fn skip_ws(&mut self) -> Result<()> {
+        loop {
+            match self.peek() {
+                Some(b) if b.is_ascii_whitespace() => self.pos += 1,
+                Some(b'/') if self.bytes.get(self.pos + 1) == Some(&b'/') => {
+                    while self.peek().map(|b| b != b'\n').unwrap_or(false) {
+                        self.pos += 1;
+                    }
+                }
+                Some(b'/') if self.bytes.get(self.pos + 1) == Some(&b'*') => {
+                    match self.source[self.pos + 2..].find("*/") {
+                        Some(end) => self.pos += end + 4,
+                        None => return self.err("unterminated block comment"),
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn expect(
+        &mut self,
+        b: u8,
+    ) -> Result<()> {
+        self.skip_ws()?;
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            self.last_token_end = self.pos;
+            Ok(())
+        } else {
+            self.err("unexpected character")
+        }
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        self.skip_ws().ok()?;
+        let start = self.pos;
+        if self.bytes[self.pos..].starts_with(b"r#") {
+            self.pos += 2;
+        }
+        match self.peek() {
+            Some(b) if b.is_ascii_alphabetic() || b == b'_' => {}
+            _ => {
+                self.pos = start;
+                return None;
+            }
+        }
+        while self
+            .peek()
+            .map(|b| b.is_ascii_alphanumeric() || b == b'_')
+            .unwrap_or(false)
+        {
+            self.pos += 1;
+        }
+        self.last_token_end = self.pos;
+        Some(self.source[start..self.pos].trim_start_matches("r#"))
+    }
+
+    // Consumes an optional struct name followed by the opening parenthesis
+    fn struct_start(&mut self) -> Result<()> {
+        self.ident();
+        self.expect(b'(')
+    }
+
+    // Returns the next field name and consumes the colon, or None if the struct is finished
+    fn next_field(&mut self) -> Result<Option<&'a str>> {
+        self.skip_ws()?;
+        if self.peek() == Some(b')') {
+            self.pos += 1;
+            self.last_token_end = self.pos;
+            return Ok(None);
+        }
+        let field = match self.ident() {
+            Some(field) => field,
+            None => return self.err("expected field name"),
+        };
+        self.expect(b':')?;
+        self.skip_ws()?;
+        Ok(Some(field))
+    }
+
+    fn field_end(&mut self) -> Result<()> {
+        self.skip_ws()?;
+        if self.peek() == Some(b',') {
+            self.pos += 1;
+        }
+        Ok(())
+    }
+
+    fn list<F: FnMut(&mut Self) -> Result<()>>(
+        &mut self,
+        mut item_fn: F,
+    ) -> Result<ListSpan> {
+        self.skip_ws()?;
+        let open = self.pos;
+        self.expect(b'[')?;
+        loop {
+            self.skip_ws()?;
+            if self.peek() == Some(b']') {
+                let close = self.pos;
+                self.pos += 1;
+                self.last_token_end = self.pos;
+                return Ok(ListSpan { open, close });
+            }
+            (item_fn)(self)?;
+            self.field_end()?;
+        }
+    }
+
+    // The contents of a string without escapes
+    fn string(&mut self) -> Result<&'a str> {
+        self.skip_ws()?;
+        let start = self.pos;
+        self.skip_string()?;
+        Ok(&self.source[start + 1..self.pos - 1])
+    }
+
+    fn uuid(&mut self) -> Result<uuid::Bytes> {
+        self.skip_ws()?;
+        let start = self.pos;
+        match uuid::Uuid::parse_str(self.string()?) {
+            Ok(uuid) => Ok(*uuid.as_bytes()),
+            Err(_) => Err(RonPatchError::Parse(start, "invalid uuid")),
+        }
+    }
+
+    // A component type UUID or a name from the type map
+    fn component_type(
+        &mut self,
+        type_map: &TypeMap,
+    ) -> Result<ComponentTypeUuid> {
+        self.skip_ws()?;
+        let start = self.pos;
+        match resolve_component_type(type_map, self.string()?) {
+            Some(component_type) => Ok(component_type),
+            None => Err(RonPatchError::Parse(start, "unknown component type")),
+        }
+    }
+
+    fn skip_string(&mut self) -> Result<()> {
+        if self.peek() != Some(b'"') {
+            return self.err("expected string");
+        }
+        self.pos += 1;
+        loop {
+            match self.peek() {
+                None => return self.err("unterminated string"),
+                Some(b'\\') => self.pos += 2,
+                Some(b'"') => {
+                    self.pos += 1;
+                    self.last_token_end = self.pos;
+                    return Ok(());
+                }
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+
+    // Handles r"..." and r#"..."#
+    fn try_skip_raw_string(&mut self) -> Result<bool> {
+        let rest = &self.bytes[self.pos..];
+        if rest.first() != Some(&b'r') {
+            return Ok(false);
+        }
+        let hashes = rest[1..].iter().take_while(|b| **b == b'#').count();
+        if rest.get(1 + hashes) != Some(&b'"') {
+            return Ok(false);
+        }
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        let body_start = self.pos + 2 + hashes;
+        match self.source[body_start..].find(&terminator) {
+            Some(end) => {
+                self.pos = body_start + end + terminator.len();
+                self.last_token_end = self.pos;
+                Ok(true)
+            }
+            None => self.err("unterminated raw string"),
+        }
+    }
+
+    // Skips a single RON value of any kind, stopping at the comma or closing bracket that follows
+    fn skip_value(&mut self) -> Result<()> {
+        let mut depth = 0;
+        let start = self.pos;
+        loop {
+            self.skip_ws()?;
+            match self.peek() {
+                None => return self.err("unexpected end of input"),
+                Some(b'(') | Some(b'[') | Some(b'{') => {
+                    depth += 1;
+                    self.pos += 1;
+                }
+                Some(b')') | Some(b']') | Some(b'}') => {
+                    if depth == 0 {
+                        break;
+                    }
+                    depth -= 1;
+                    self.pos += 1;
+                }
+                Some(b',') if depth == 0 => break,
+                Some(b'"') => self.skip_string()?,
+                Some(b'\'') => {
+                    self.pos += 1;
+                    if self.peek() == Some(b'\\') {
+                        self.pos += 1;
+                    }
+                    self.pos += 1;
+                    self.expect(b'\'')?;
+                }
+                Some(_) => {
+                    if !self.try_skip_raw_string()? && self.ident().is_none() {
+                        self.pos += 1;
+                    }
+                }
+            }
+            self.last_token_end = self.pos;
+        }
+        if self.pos == start {
+            return self.err("expected value");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTITY_A: EntityUuid = [0x01; 16];
+    const ENTITY_B: EntityUuid = [0x02; 16];
+    const POSITION: ComponentTypeUuid = [0xa1; 16];
+    const VELOCITY: ComponentTypeUuid = [0xa2; 16];
+
+    const POSITION_DATA: &str = "(x: 1.0, /* ) ] */ y: 2.0)";
+    const VELOCITY_DATA: &str = r##"(name: r#"a ) ] " b"#, quote: "\" ) ]", c: ')')"##;
+    const NESTED_DATA: &str = "(a: [(1, [2, 3]), (4, [])], b: ((1)))";
+
+    // Comments, strings and nesting that would confuse a scanner only counting brackets. The
+    // second entity's component list has no trailing comma.
+    const SOURCE: &str = r##"// A prefab ) ]
+Prefab(
+    id: "10101010-1010-1010-1010-101010101010",
+    objects: [
+        /* Entity(PrefabEntity(id: "03030303-0303-0303-0303-030303030303", components: [])), */
+        Entity(PrefabEntity(
+            id: "01010101-0101-0101-0101-010101010101",
+            components: [
+                EntityComponent(
+                    type: "a1a1a1a1-a1a1-a1a1-a1a1-a1a1a1a1a1a1",
+                    data: (x: 1.0, /* ) ] */ y: 2.0),
+                ),
+                // EntityComponent(type: "a3a3a3a3-a3a3-a3a3-a3a3-a3a3a3a3a3a3"),
+                EntityComponent(
+                    type: "a2a2a2a2-a2a2-a2a2-a2a2-a2a2a2a2a2a2",
+                    data: (name: r#"a ) ] " b"#, quote: "\" ) ]", c: ')'), // ) ]
+                ),
+            ],
+        )),
+        Entity(PrefabEntity(
+            id: "02020202-0202-0202-0202-020202020202",
+            components: [
+                EntityComponent(
+                    type: "a1a1a1a1-a1a1-a1a1-a1a1-a1a1a1a1a1a1",
+                    data: (a: [(1, [2, 3]), (4, [])], b: ((1))),
+                )
+            ]
+        )),
+    ],
+)
+"##;
+
+    fn crlf(source: &str) -> String {
+        source.replace('\n', "\r\n")
+    }
+
+    fn parse(source: &str) -> RonPrefabDocument<'_> {
+        RonPrefabDocument::parse(source).unwrap()
+    }
+
+    #[test]
+    fn unchanged_documents_round_trip_byte_for_byte() {
+        assert_eq!(parse(SOURCE).finish(), SOURCE);
+        let source = crlf(SOURCE);
+        assert_eq!(parse(&source).finish(), source);
+    }
+
+    #[test]
+    fn scans_past_comments_strings_and_nesting() {
+        for source in &[SOURCE.to_string(), crlf(SOURCE)] {
+            let document = parse(source);
+            assert_eq!(document.entities(), vec![ENTITY_A, ENTITY_B]);
+            assert_eq!(
+                document.component_types(&ENTITY_A),
+                vec![POSITION, VELOCITY]
+            );
+            assert_eq!(document.component_types(&ENTITY_B), vec![POSITION]);
+            assert_eq!(
+                document.component_data_text(&ENTITY_A, &POSITION).unwrap(),
+                POSITION_DATA
+            );
+            assert_eq!(
+                document.component_data_text(&ENTITY_A, &VELOCITY).unwrap(),
+                VELOCITY_DATA
+            );
+            assert_eq!(
+                document.component_data_text(&ENTITY_B, &POSITION).unwrap(),
+                NESTED_DATA
+            );
+        }
+    }
+
+    #[test]
+    fn editing_one_component_leaves_the_rest_untouched() {
+        let mut document = parse(SOURCE);
+        document
+            .replace_component_data(&ENTITY_A, &VELOCITY, "(name: \"b\")")
+            .unwrap();
+        let expected = SOURCE.replace(VELOCITY_DATA, "(name: \"b\")");
+        assert_eq!(document.finish(), expected);
+
+        // Continuation lines are indented to match the replaced value
+        let mut document = parse(SOURCE);
+        document
+            .replace_component_data(&ENTITY_B, &POSITION, "(\n    a: [],\n)")
+            .unwrap();
+        let expected = SOURCE.replace(
+            NESTED_DATA,
+            "(\n                        a: [],\n                    )",
+        );
+        assert_eq!(document.finish(), expected);
+    }
+
+    #[test]
+    fn inserting_after_an_item_without_a_trailing_comma() {
+        let mut document = parse(SOURCE);
+        document
+            .insert_component(&ENTITY_B, &VELOCITY, "(x: 0.0)")
+            .unwrap();
+        let output = document.finish();
+
+        let document = parse(&output);
+        assert_eq!(
+            document.component_types(&ENTITY_B),
+            vec![POSITION, VELOCITY]
+        );
+        assert_eq!(
+            document.component_data_text(&ENTITY_B, &VELOCITY).unwrap(),
+            "(x: 0.0)"
+        );
+        assert_eq!(
+            document.component_data_text(&ENTITY_B, &POSITION).unwrap(),
+            NESTED_DATA
+        );
+        assert_eq!(
+            document.component_types(&ENTITY_A),
+            vec![POSITION, VELOCITY]
+        );
+    }
+
+    #[test]
+    fn edits_keep_crlf_line_endings() {
+        let source = crlf(SOURCE);
+        let mut document = parse(&source);
+        document
+            .replace_component_data(&ENTITY_A, &POSITION, "(\n    x: 3.0,\n)")
+            .unwrap();
+        document
+            .insert_component(&ENTITY_B, &VELOCITY, "(x: 0.0)")
+            .unwrap();
+        let output = document.finish();
+
+        assert_eq!(output.matches('\n').count(), output.matches("\r\n").count());
+        let document = parse(&output);
+        assert_eq!(
+            document.component_data_text(&ENTITY_A, &POSITION).unwrap(),
+            "(\n    x: 3.0,\n)"
+        );
+        assert_eq!(
+            document.component_data_text(&ENTITY_A, &VELOCITY).unwrap(),
+            VELOCITY_DATA
+        );
+        assert_eq!(
+            document.component_types(&ENTITY_B),
+            vec![POSITION, VELOCITY]
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Extracts the IDs in a prefab without deserializing any component data, so that asset databases
+//! can index large numbers of prefabs quickly.
+use crate::ron_patch::{RonPatchError, RonPrefabDocument};
+use crate::{ComponentTypeUuid, EntityUuid, PrefabUuid, StorageDeserializer};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer};
+use std::cell::RefCell;
+
+/// The IDs found in a prefab, in the order they were first encountered
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PrefabSummary {
+    pub prefab_id: PrefabUuid,
+    pub entities: Vec<EntityUuid>,
+    /// Referenced prefabs, including the prefab this prefab extends
+    pub prefab_refs: Vec<PrefabUuid>,
+    /// The prefab this prefab extends, if any
+    pub base_prefab: Option<PrefabUuid>,
+    /// Component types used by entities and by overrides, without duplicates
+    pub component_types: Vec<ComponentTypeUuid>,
+}
+
+impl PrefabSummary {
+    fn add_component_type(
+        &mut self,
+        component_type: &ComponentTypeUuid,
+    ) {
+        if !self.component_types.contains(component_type) {
+            self.component_types.push(*component_type);
+        }
+    }
+}
+
+/// A `StorageDeserializer` that records a `PrefabSummary` and skips over component data and
+/// diffs. Use this to scan prefabs in formats other than RON, i.e. with `json::from_slice`.
+#[derive(Default)]
+pub struct PrefabScanner {
+    summary: RefCell<PrefabSummary>,
+}
+
+impl PrefabScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn into_summary(self) -> PrefabSummary {
+        self.summary.into_inner()
+    }
+}
+
+impl StorageDeserializer for PrefabScanner {
+    fn begin_prefab(
+        &self,
+        prefab: &PrefabUuid,
+    ) {
+        self.summary.borrow_mut().prefab_id = *prefab;
+    }
+
+    fn begin_entity_object(
+        &self,
+        _prefab: &PrefabUuid,
+        entity: &EntityUuid,
+    ) {
+        self.summary.borrow_mut().entities.push(*entity);
+    }
+
+    fn end_entity_object(
+        &self,
+        _prefab: &PrefabUuid,
+        _entity: &EntityUuid,
+    ) {
+    }
+
+    fn deserialize_component<'de, D: Deserializer<'de>>(
+        &self,
+        _prefab: &PrefabUuid,
+        _entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        self.summary.borrow_mut().add_component_type(component_type);
+        IgnoredAny::deserialize(deserializer)?;
+        Ok(())
+    }
+
+    fn begin_prefab_ref(
+        &self,
+        _prefab: &PrefabUuid,
+        target_prefab: &PrefabUuid,
+    ) {
+        self.summary.borrow_mut().prefab_refs.push(*target_prefab);
+    }
+
+    fn end_prefab_ref(
+        &self,
+        _prefab: &PrefabUuid,
+        _target_prefab: &PrefabUuid,
+    ) {
+    }
+
+    fn set_base_prefab(
+        &self,
+        _prefab: &PrefabUuid,
+        base_prefab: &PrefabUuid,
+    ) {
+        self.summary.borrow_mut().base_prefab = Some(*base_prefab);
+    }
+
+    fn apply_component_diff<'de, D: Deserializer<'de>>(
+        &self,
+        _parent_prefab: &PrefabUuid,
+        _prefab_ref: &PrefabUuid,
+        _entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        self.summary.borrow_mut().add_component_type(component_type);
+        IgnoredAny::deserialize(deserializer)?;
+        Ok(())
+    }
+}
+
+/// Scans a RON prefab. Component data and diffs are only checked for balanced brackets, so this
+/// is much faster than loading the prefab.
+pub fn scan_prefab(bytes: &[u8]) -> Result<PrefabSummary, RonPatchError> {
+    let source = std::str::from_utf8(bytes)
+        .map_err(|e| RonPatchError::Parse(e.valid_up_to(), "invalid UTF-8"))?;
+    let doc = RonPrefabDocument::parse(source)?;
+
+    let mut summary = PrefabSummary {
+        prefab_id: doc.prefab_id(),
+        base_prefab: doc.base_prefab(),
+        ..Default::default()
+    };
+    for entity in doc.entities() {
+        for component_type in doc.component_types(&entity) {
+            summary.add_component_type(&component_type);
+        }
+        summary.entities.push(entity);
+    }
+    for prefab_ref in doc.base_prefab().into_iter().chain(doc.prefab_refs()) {
+        for (_, component_types) in doc.prefab_ref_overrides(&prefab_ref) {
+            for component_type in component_types {
+                summary.add_component_type(&component_type);
+            }
+        }
+        summary.prefab_refs.push(prefab_ref);
+    }
+
+    Ok(summary)
+}
@@ -1,8 +1,13 @@
-use crate::{PrefabUuid, EntityUuid, ComponentTypeUuid};
+use crate::blobs::{BlobData, BlobId};
+use crate::type_map::{build_type_map, type_names, ComponentTypeKey};
+use crate::{
+    PrefabUuid, EntityUuid, ComponentTypeUuid, PrefabParameter, PrefabRefTransform, TemplateUuid,
+};
 use serde::{
     Serialize, Serializer,
-    ser::{SerializeSeq, SerializeStruct},
+    ser::{self, SerializeSeq, SerializeStruct},
 };
+use std::collections::{BTreeMap, BTreeSet};
 
 pub struct PrefabSerializer<'a, SS: StorageSerializer> {
     storage: &'a SS,
@@ -40,30 +45,155 @@ pub trait StorageSerializer {
         entity: &EntityUuid,
         component: &ComponentTypeUuid,
     ) -> Result<S::Ok, S::Error>;
+    /// The parameters declared by the prefab. Not written if empty.
+    fn parameters(&self) -> Vec<PrefabParameter> {
+        vec![]
+    }
+    /// The parameter values (as RON text) set by a prefab reference. Not written if empty.
+    fn prefab_ref_parameter_values(
+        &self,
+        _uuid: &PrefabUuid,
+    ) -> BTreeMap<String, String> {
+        BTreeMap::new()
+    }
+    /// The offset a prefab reference applies to the root entities of the referenced prefab. Not
+    /// written if it is the identity.
+    fn prefab_ref_transform(
+        &self,
+        _uuid: &PrefabUuid,
+    ) -> PrefabRefTransform {
+        PrefabRefTransform::IDENTITY
+    }
+    /// The entity layers of the prefab, with the entities each contains. Not written if empty.
+    fn layers(&self) -> BTreeMap<String, Vec<EntityUuid>> {
+        BTreeMap::new()
+    }
+    /// The children of each entity that has any, in order. Not written if empty.
+    fn hierarchy(&self) -> BTreeMap<EntityUuid, Vec<EntityUuid>> {
+        BTreeMap::new()
+    }
+    /// The blobs stored in the prefab's blob section. Not written if empty.
+    fn blobs(&self) -> Vec<(BlobId, &BlobData)> {
+        vec![]
+    }
+    /// The name to write a component type as in text formats, instead of its UUID. Names are
+    /// listed in the file's `type_map`.
+    fn component_type_name(
+        &self,
+        _component_type: &ComponentTypeUuid,
+    ) -> Option<String> {
+        None
+    }
+    /// True if the component type is a zero-sized marker. Marker components are written with only
+    /// their type and no `data`, and are read back with a unit deserializer.
+    fn is_marker_component(
+        &self,
+        _component_type: &ComponentTypeUuid,
+    ) -> bool {
+        false
+    }
+    /// The prefab this prefab extends. It must also be listed by `prefab_refs`, which is where its
+    /// overrides, parameter values and transform come from, but it's written in the prefab's
+    /// `extends` field instead of as an object.
+    fn base_prefab(&self) -> Option<PrefabUuid> {
+        None
+    }
+    /// The entity templates of the prefab. Not written if empty.
+    fn templates(&self) -> Vec<TemplateUuid> {
+        vec![]
+    }
+    fn template_component_types(
+        &self,
+        _template: &TemplateUuid,
+    ) -> Vec<ComponentTypeUuid> {
+        vec![]
+    }
+    fn serialize_template_component<S: Serializer>(
+        &self,
+        _serializer: S,
+        _template: &TemplateUuid,
+        _component: &ComponentTypeUuid,
+    ) -> Result<S::Ok, S::Error> {
+        Err(ser::Error::custom("entity templates are not supported"))
+    }
+    /// The template an entity instantiates, if any. The entity's components are then written
+    /// as-is, on top of the template's.
+    fn entity_template(
+        &self,
+        _entity: &EntityUuid,
+    ) -> Option<TemplateUuid> {
+        None
+    }
+    /// The template components an entity changes with a diff
+    fn template_diff_component_types(
+        &self,
+        _entity: &EntityUuid,
+    ) -> Vec<ComponentTypeUuid> {
+        vec![]
+    }
+    fn serialize_template_diff<S: Serializer>(
+        &self,
+        _serializer: S,
+        _entity: &EntityUuid,
+        _component: &ComponentTypeUuid,
+    ) -> Result<S::Ok, S::Error> {
+        Err(ser::Error::custom("entity templates are not supported"))
+    }
 }
 
+type TypeNames<'a> = BTreeMap<ComponentTypeUuid, &'a str>;
+
 #[derive(Serialize)]
 struct PrefabEntity<'a, SS: StorageSerializer> {
     id: uuid::Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    template: Option<uuid::Uuid>,
+    #[serde(
+        bound(serialize = "SS: StorageSerializer"),
+        skip_serializing_if = "<[_]>::is_empty"
+    )]
+    template_diffs: &'a [TemplateDiff<'a, SS>],
     #[serde(bound(serialize = "SS: StorageSerializer"))]
     components: &'a [EntityComponent<'a, SS>],
 }
 #[derive(Serialize)]
-struct EntityComponent<'a, SS: StorageSerializer> {
-    r#type: uuid::Uuid,
+struct TemplateDiff<'a, SS: StorageSerializer> {
+    component_type: ComponentTypeKey<'a>,
     #[serde(bound(serialize = "SS: StorageSerializer"))]
+    diff: TemplateDiffSerializer<'a, SS>,
+}
+struct TemplateDiffSerializer<'a, SS: StorageSerializer> {
+    storage: &'a SS,
+    entity: EntityUuid,
+    component_type: ComponentTypeUuid,
+}
+#[derive(Serialize)]
+struct EntityComponent<'a, SS: StorageSerializer> {
+    r#type: ComponentTypeKey<'a>,
+    #[serde(
+        bound(serialize = "SS: StorageSerializer"),
+        skip_serializing_if = "EntityComponentSerializer::is_marker"
+    )]
     data: EntityComponentSerializer<'a, SS>,
 }
 
+// What a component in the file belongs to
+#[derive(Clone, Copy)]
+enum ComponentOwner {
+    Entity(EntityUuid),
+    Template(TemplateUuid),
+}
+
 struct EntityComponentSerializer<'a, SS: StorageSerializer> {
     storage: &'a SS,
-    id: EntityUuid,
+    owner: ComponentOwner,
     component: ComponentTypeUuid,
 }
 
 struct EntityPrefabObjectSerializer<'a, SS: StorageSerializer> {
     storage: &'a SS,
     id: EntityUuid,
+    type_names: &'a TypeNames<'a>,
 }
 
 struct ComponentOverrideDiff<'a, SS: StorageSerializer> {
@@ -74,7 +204,7 @@ struct ComponentOverrideDiff<'a, SS: StorageSerializer> {
 }
 #[derive(Serialize)]
 struct ComponentOverride<'a, SS: StorageSerializer> {
-    component_type: uuid::Uuid,
+    component_type: ComponentTypeKey<'a>,
     #[serde(bound(serialize = "SS: StorageSerializer"))]
     diff: ComponentOverrideDiff<'a, SS>,
 }
@@ -88,18 +218,51 @@ struct EntityOverride<'a, SS: StorageSerializer> {
 #[derive(Serialize)]
 struct PrefabRef<'a, SS: StorageSerializer> {
     prefab_id: uuid::Uuid,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    parameter_values: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "PrefabRefTransform::is_identity")]
+    transform: PrefabRefTransform,
     #[serde(bound(serialize = "SS: StorageSerializer"))]
     entity_overrides: &'a [EntityOverride<'a, SS>],
 }
 struct PrefabRefObjectSerializer<'a, SS: StorageSerializer> {
     storage: &'a SS,
     id: PrefabUuid,
+    type_names: &'a TypeNames<'a>,
 }
 struct ObjectArraySerializer<'a, SS: StorageSerializer> {
     storage: &'a SS,
+    type_names: &'a TypeNames<'a>,
+}
+
+impl<'a, SS: StorageSerializer> EntityComponentSerializer<'a, SS> {
+    fn is_marker(&self) -> bool {
+        self.storage.is_marker_component(&self.component)
+    }
 }
 
 impl<'a, SS: StorageSerializer> Serialize for EntityComponentSerializer<'a, SS> {
+    fn serialize<S>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match &self.owner {
+            ComponentOwner::Entity(entity) => {
+                self.storage
+                    .serialize_entity_component(serializer, entity, &self.component)
+            }
+            ComponentOwner::Template(template) => {
+                self.storage
+                    .serialize_template_component(serializer, template, &self.component)
+            }
+        }
+    }
+}
+
+impl<'a, SS: StorageSerializer> Serialize for TemplateDiffSerializer<'a, SS> {
     fn serialize<S>(
         &self,
         serializer: S,
@@ -108,10 +271,33 @@ impl<'a, SS: StorageSerializer> Serialize for EntityComponentSerializer<'a, SS>
         S: Serializer,
     {
         self.storage
-            .serialize_entity_component(serializer, &self.id, &self.component)
+            .serialize_template_diff(serializer, &self.entity, &self.component_type)
     }
 }
 
+// The components of a template, or of an entity, as written in the file
+fn entity_components<'a, SS: StorageSerializer>(
+    storage: &'a SS,
+    owner: ComponentOwner,
+    component_types: Vec<ComponentTypeUuid>,
+    type_names: &'a TypeNames<'a>,
+) -> Vec<EntityComponent<'a, SS>> {
+    component_types
+        .into_iter()
+        .map(|c| EntityComponent {
+            r#type: ComponentTypeKey {
+                component_type: c,
+                type_names,
+            },
+            data: EntityComponentSerializer {
+                storage,
+                owner,
+                component: c,
+            },
+        })
+        .collect()
+}
+
 impl<'a, SS: StorageSerializer> Serialize for EntityPrefabObjectSerializer<'a, SS> {
     fn serialize<S>(
         &self,
@@ -126,19 +312,32 @@ impl<'a, SS: StorageSerializer> Serialize for EntityPrefabObjectSerializer<'a, S
             "Entity",
             &PrefabEntity {
                 id: uuid::Uuid::from_bytes(self.id),
-                components: &self
+                template: self
                     .storage
-                    .component_types(&self.id)
-                    .iter()
-                    .map(|c| EntityComponent {
-                        r#type: uuid::Uuid::from_bytes(*c),
-                        data: EntityComponentSerializer {
+                    .entity_template(&self.id)
+                    .map(uuid::Uuid::from_bytes),
+                template_diffs: &self
+                    .storage
+                    .template_diff_component_types(&self.id)
+                    .into_iter()
+                    .map(|component_type| TemplateDiff {
+                        component_type: ComponentTypeKey {
+                            component_type,
+                            type_names: self.type_names,
+                        },
+                        diff: TemplateDiffSerializer {
                             storage: self.storage,
-                            id: self.id,
-                            component: *c,
+                            entity: self.id,
+                            component_type,
                         },
                     })
                     .collect::<Vec<_>>(),
+                components: &entity_components(
+                    self.storage,
+                    ComponentOwner::Entity(self.id),
+                    self.storage.component_types(&self.id),
+                    self.type_names,
+                ),
             },
         )
     }
@@ -169,34 +368,61 @@ impl<'a, SS: StorageSerializer> Serialize for PrefabRefObjectSerializer<'a, SS>
     where
         S: Serializer,
     {
-        serializer.serialize_newtype_variant(
-            "PrefabObject",
-            0,
-            "PrefabRef",
-            &PrefabRef {
-                prefab_id: uuid::Uuid::from_bytes(self.id),
-                entity_overrides: &self
-                    .storage
-                    .prefab_ref_overrides(&self.id)
-                    .iter()
-                    .map(|(entity, component_types)| EntityOverride {
-                        entity_id: uuid::Uuid::from_bytes(*entity),
-                        component_overrides: component_types
-                            .iter()
-                            .map(|component_type| ComponentOverride {
-                                component_type: uuid::Uuid::from_bytes(*component_type),
-                                diff: ComponentOverrideDiff {
-                                    storage: self.storage,
-                                    prefab_ref: self.id,
-                                    entity: *entity,
-                                    component_type: *component_type,
-                                },
-                            })
-                            .collect::<Vec<_>>(),
-                    })
-                    .collect::<Vec<_>>(),
-            },
-        )
+        self.with_prefab_ref(|prefab_ref| {
+            serializer.serialize_newtype_variant("PrefabObject", 0, "PrefabRef", prefab_ref)
+        })
+    }
+}
+
+// The prefab this prefab extends, written as a PrefabRef outside of the objects
+struct BasePrefabSerializer<'a, SS: StorageSerializer>(PrefabRefObjectSerializer<'a, SS>);
+
+impl<'a, SS: StorageSerializer> Serialize for BasePrefabSerializer<'a, SS> {
+    fn serialize<S>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.0
+            .with_prefab_ref(|prefab_ref| prefab_ref.serialize(serializer))
+    }
+}
+
+impl<'a, SS: StorageSerializer> PrefabRefObjectSerializer<'a, SS> {
+    fn with_prefab_ref<R>(
+        &self,
+        f: impl FnOnce(&PrefabRef<SS>) -> R,
+    ) -> R {
+        f(&PrefabRef {
+            prefab_id: uuid::Uuid::from_bytes(self.id),
+            parameter_values: self.storage.prefab_ref_parameter_values(&self.id),
+            transform: self.storage.prefab_ref_transform(&self.id),
+            entity_overrides: &self
+                .storage
+                .prefab_ref_overrides(&self.id)
+                .iter()
+                .map(|(entity, component_types)| EntityOverride {
+                    entity_id: uuid::Uuid::from_bytes(*entity),
+                    component_overrides: component_types
+                        .iter()
+                        .map(|component_type| ComponentOverride {
+                            component_type: ComponentTypeKey {
+                                component_type: *component_type,
+                                type_names: self.type_names,
+                            },
+                            diff: ComponentOverrideDiff {
+                                storage: self.storage,
+                                prefab_ref: self.id,
+                                entity: *entity,
+                                component_type: *component_type,
+                            },
+                        })
+                        .collect::<Vec<_>>(),
+                })
+                .collect::<Vec<_>>(),
+        })
     }
 }
 
@@ -209,13 +435,16 @@ impl<'a, SS: StorageSerializer> Serialize for ObjectArraySerializer<'a, SS> {
         S: Serializer,
     {
         let entities = self.storage.entities();
-        let prefab_refs = self.storage.prefab_refs();
+        let base_prefab = self.storage.base_prefab();
+        let mut prefab_refs = self.storage.prefab_refs();
+        prefab_refs.retain(|prefab_ref| Some(*prefab_ref) != base_prefab);
         let mut seq = serializer.serialize_seq(Some(entities.len() + prefab_refs.len()))?;
         for s in prefab_refs
             .iter()
             .map(|prefab_ref| PrefabRefObjectSerializer {
                 storage: self.storage,
                 id: *prefab_ref,
+                type_names: self.type_names,
             })
         {
             seq.serialize_element(&s)?;
@@ -223,6 +452,7 @@ impl<'a, SS: StorageSerializer> Serialize for ObjectArraySerializer<'a, SS> {
         for s in entities.iter().map(|entity| EntityPrefabObjectSerializer {
             storage: self.storage,
             id: *entity,
+            type_names: self.type_names,
         }) {
             seq.serialize_element(&s)?;
         }
@@ -231,6 +461,82 @@ impl<'a, SS: StorageSerializer> Serialize for ObjectArraySerializer<'a, SS> {
     }
 }
 
+pub struct VariantSerializer<'a, SS: StorageSerializer> {
+    storage: &'a SS,
+    prefab_id: PrefabUuid,
+}
+impl<'a, SS: StorageSerializer> VariantSerializer<'a, SS> {
+    pub fn new(
+        prefab_id: PrefabUuid,
+        storage: &'a SS,
+    ) -> Self {
+        Self { storage, prefab_id }
+    }
+}
+
+impl<'a, SS: StorageSerializer> Serialize for VariantSerializer<'a, SS> {
+    fn serialize<S>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let base_prefab = self
+            .storage
+            .base_prefab()
+            .ok_or_else(|| ser::Error::custom("a prefab variant must extend a base prefab"))?;
+        // Everything but the base would be lost
+        let storage = self.storage;
+        if !storage.entities().is_empty()
+            || storage.prefab_refs() != [base_prefab]
+            || !storage.parameters().is_empty()
+            || !storage.layers().is_empty()
+            || !storage.hierarchy().is_empty()
+            || !storage.templates().is_empty()
+            || !storage.blobs().is_empty()
+        {
+            return Err(ser::Error::custom(
+                "a prefab variant can only override the entities of its base prefab",
+            ));
+        }
+
+        // Component types are always written as UUIDs
+        let type_names = TypeNames::new();
+        let mut s = serializer.serialize_struct("PrefabVariant", 2)?;
+        s.serialize_field("id", &uuid::Uuid::from_bytes(self.prefab_id))?;
+        s.serialize_field(
+            "base",
+            &BasePrefabSerializer(PrefabRefObjectSerializer {
+                storage: self.storage,
+                id: base_prefab,
+                type_names: &type_names,
+            }),
+        )?;
+        s.end()
+    }
+}
+
+impl<'a, SS: StorageSerializer> PrefabSerializer<'a, SS> {
+    // Every component type written by the prefab's templates, entities and overrides
+    fn component_types(&self) -> BTreeSet<ComponentTypeUuid> {
+        let mut component_types = BTreeSet::new();
+        for template in self.storage.templates() {
+            component_types.extend(self.storage.template_component_types(&template));
+        }
+        for entity in self.storage.entities() {
+            component_types.extend(self.storage.component_types(&entity));
+            component_types.extend(self.storage.template_diff_component_types(&entity));
+        }
+        for prefab_ref in self.storage.prefab_refs() {
+            for (_, overrides) in self.storage.prefab_ref_overrides(&prefab_ref) {
+                component_types.extend(overrides);
+            }
+        }
+        component_types
+    }
+}
+
 impl<'a, SS: StorageSerializer> Serialize for PrefabSerializer<'a, SS> {
     fn serialize<S>(
         &self,
@@ -239,14 +545,121 @@ impl<'a, SS: StorageSerializer> Serialize for PrefabSerializer<'a, SS> {
     where
         S: Serializer,
     {
-        let mut s = serializer.serialize_struct("Prefab", 2)?;
+        let parameters = self.storage.parameters();
+        let layers: BTreeMap<String, Vec<uuid::Uuid>> = self
+            .storage
+            .layers()
+            .into_iter()
+            .map(|(name, entities)| {
+                (
+                    name,
+                    entities.into_iter().map(uuid::Uuid::from_bytes).collect(),
+                )
+            })
+            .collect();
+        let hierarchy: BTreeMap<uuid::Uuid, Vec<uuid::Uuid>> = self
+            .storage
+            .hierarchy()
+            .into_iter()
+            .map(|(parent, children)| {
+                (
+                    uuid::Uuid::from_bytes(parent),
+                    children.into_iter().map(uuid::Uuid::from_bytes).collect(),
+                )
+            })
+            .collect();
+        let blobs: BTreeMap<uuid::Uuid, &BlobData> = self
+            .storage
+            .blobs()
+            .into_iter()
+            .map(|(blob, data)| (uuid::Uuid::from_bytes(blob), data))
+            .collect();
+        // Binary formats always write UUIDs, so they don't need the table
+        let type_map = if serializer.is_human_readable() {
+            build_type_map(self.component_types().into_iter().map(|component_type| {
+                (
+                    component_type,
+                    self.storage.component_type_name(&component_type),
+                )
+            }))
+        } else {
+            Default::default()
+        };
+        let type_names = type_names(&type_map);
+        let templates: BTreeMap<uuid::Uuid, Vec<EntityComponent<SS>>> = self
+            .storage
+            .templates()
+            .into_iter()
+            .map(|template| {
+                (
+                    uuid::Uuid::from_bytes(template),
+                    entity_components(
+                        self.storage,
+                        ComponentOwner::Template(template),
+                        self.storage.template_component_types(&template),
+                        &type_names,
+                    ),
+                )
+            })
+            .collect();
+        let mut s = serializer.serialize_struct("Prefab", 9)?;
         s.serialize_field("id", &uuid::Uuid::from_bytes(self.prefab_id))?;
+        if type_map.is_empty() {
+            s.skip_field("type_map")?;
+        } else {
+            let type_map: BTreeMap<&str, uuid::Uuid> = type_map
+                .iter()
+                .map(|(name, component_type)| {
+                    (name.as_str(), uuid::Uuid::from_bytes(*component_type))
+                })
+                .collect();
+            s.serialize_field("type_map", &type_map)?;
+        }
+        if parameters.is_empty() {
+            s.skip_field("parameters")?;
+        } else {
+            s.serialize_field("parameters", &parameters)?;
+        }
+        if layers.is_empty() {
+            s.skip_field("layers")?;
+        } else {
+            s.serialize_field("layers", &layers)?;
+        }
+        if hierarchy.is_empty() {
+            s.skip_field("hierarchy")?;
+        } else {
+            s.serialize_field("hierarchy", &hierarchy)?;
+        }
+        match self.storage.base_prefab() {
+            Some(base_prefab) => s.serialize_field(
+                "extends",
+                &BasePrefabSerializer(PrefabRefObjectSerializer {
+                    storage: self.storage,
+                    id: base_prefab,
+                    type_names: &type_names,
+                }),
+            )?,
+            None => s.skip_field("extends")?,
+        }
+        // Before the objects, which instantiate them
+        if templates.is_empty() {
+            s.skip_field("templates")?;
+        } else {
+            s.serialize_field("templates", &templates)?;
+        }
         s.serialize_field(
             "objects",
             &ObjectArraySerializer {
                 storage: self.storage,
+                type_names: &type_names,
             },
         )?;
+        // Blobs are written last so the objects stay near the top of text formats
+        if blobs.is_empty() {
+            s.skip_field("blobs")?;
+        } else {
+            s.serialize_field("blobs", &blobs)?;
+        }
         s.end()
     }
 }
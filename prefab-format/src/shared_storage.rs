@@ -0,0 +1,299 @@
+//! Deserializing several prefabs into the same storage at the same time, i.e. to load a directory
+//! of prefabs on a thread pool.
+//!
+//! `StorageDeserializer` methods take `&self`, so storage keeps what it receives behind interior
+//! mutability, usually a `RefCell`. That can't be shared between threads, and a storage that
+//! holds a single prefab at a time would mix up prefabs that are deserialized concurrently.
+//! `SharedStorage` gives every prefab its own storage behind a lock, so any storage type can be
+//! used from several threads.
+use crate::blobs::{BlobData, BlobId};
+use crate::{
+    ComponentTypeUuid, EntityUuid, PrefabParameter, PrefabRefTransform, PrefabUuid,
+    StorageDeserializer, TemplateUuid,
+};
+use serde::Deserializer;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A `StorageDeserializer` that can be shared by threads deserializing different prefabs. Calls
+/// are passed on to a separate `S` for each prefab, created with `Default` when the prefab is
+/// first seen. Calls for the same prefab wait for each other, calls for different prefabs don't.
+/// It is `Sync` as long as `S` is `Send`.
+pub struct SharedStorage<S> {
+    prefabs: Mutex<HashMap<PrefabUuid, Arc<Mutex<S>>>>,
+}
+
+// Must stay Sync for any storage that is Send, even ones that aren't Sync themselves like storage
+// keeping what it receives in a RefCell
+const _: fn() = || {
+    fn assert_sync<T: Sync>() {}
+    fn assert_shared_storage_sync<S: Send>() {
+        assert_sync::<SharedStorage<S>>();
+    }
+    assert_shared_storage_sync::<std::cell::RefCell<()>>();
+};
+
+impl<S> Default for SharedStorage<S> {
+    fn default() -> Self {
+        SharedStorage {
+            prefabs: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<S: Default> SharedStorage<S> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The storage of each prefab that was deserialized
+    pub fn into_storages(self) -> HashMap<PrefabUuid, S> {
+        self.prefabs
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|(prefab, storage)| {
+                let storage = Arc::try_unwrap(storage)
+                    .ok()
+                    .expect("prefab storage is only shared while it is in use");
+                (prefab, storage.into_inner().unwrap())
+            })
+            .collect()
+    }
+
+    fn with_storage<R>(
+        &self,
+        prefab: &PrefabUuid,
+        f: impl FnOnce(&S) -> R,
+    ) -> R {
+        // Only the map is locked while looking up the storage, so other prefabs aren't held up
+        let storage = self
+            .prefabs
+            .lock()
+            .unwrap()
+            .entry(*prefab)
+            .or_default()
+            .clone();
+        let storage = storage.lock().unwrap();
+        f(&storage)
+    }
+}
+
+impl<S: StorageDeserializer + Default> StorageDeserializer for SharedStorage<S> {
+    fn begin_prefab(
+        &self,
+        prefab: &PrefabUuid,
+    ) {
+        self.with_storage(prefab, |storage| storage.begin_prefab(prefab))
+    }
+    fn begin_entity_object(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+    ) {
+        self.with_storage(prefab, |storage| {
+            storage.begin_entity_object(prefab, entity)
+        })
+    }
+    fn end_entity_object(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+    ) {
+        self.with_storage(prefab, |storage| storage.end_entity_object(prefab, entity))
+    }
+    fn deserialize_component<'de, D: Deserializer<'de>>(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        self.with_storage(prefab, |storage| {
+            storage.deserialize_component(prefab, entity, component_type, deserializer)
+        })
+    }
+    fn begin_prefab_ref(
+        &self,
+        prefab: &PrefabUuid,
+        target_prefab: &PrefabUuid,
+    ) {
+        self.with_storage(prefab, |storage| {
+            storage.begin_prefab_ref(prefab, target_prefab)
+        })
+    }
+    fn end_prefab_ref(
+        &self,
+        prefab: &PrefabUuid,
+        target_prefab: &PrefabUuid,
+    ) {
+        self.with_storage(prefab, |storage| {
+            storage.end_prefab_ref(prefab, target_prefab)
+        })
+    }
+    fn apply_component_diff<'de, D: Deserializer<'de>>(
+        &self,
+        parent_prefab: &PrefabUuid,
+        prefab_ref: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        self.with_storage(parent_prefab, |storage| {
+            storage.apply_component_diff(
+                parent_prefab,
+                prefab_ref,
+                entity,
+                component_type,
+                deserializer,
+            )
+        })
+    }
+    fn declare_parameter(
+        &self,
+        prefab: &PrefabUuid,
+        parameter: PrefabParameter,
+    ) {
+        self.with_storage(prefab, |storage| {
+            storage.declare_parameter(prefab, parameter)
+        })
+    }
+    fn set_parameter_value(
+        &self,
+        parent_prefab: &PrefabUuid,
+        prefab_ref: &PrefabUuid,
+        name: &str,
+        value: &str,
+    ) {
+        self.with_storage(parent_prefab, |storage| {
+            storage.set_parameter_value(parent_prefab, prefab_ref, name, value)
+        })
+    }
+    fn set_prefab_ref_transform(
+        &self,
+        parent_prefab: &PrefabUuid,
+        prefab_ref: &PrefabUuid,
+        transform: &PrefabRefTransform,
+    ) {
+        self.with_storage(parent_prefab, |storage| {
+            storage.set_prefab_ref_transform(parent_prefab, prefab_ref, transform)
+        })
+    }
+    fn declare_layer(
+        &self,
+        prefab: &PrefabUuid,
+        name: &str,
+        entities: &[EntityUuid],
+    ) {
+        self.with_storage(prefab, |storage| {
+            storage.declare_layer(prefab, name, entities)
+        })
+    }
+    fn declare_children(
+        &self,
+        prefab: &PrefabUuid,
+        parent: &EntityUuid,
+        children: &[EntityUuid],
+    ) {
+        self.with_storage(prefab, |storage| {
+            storage.declare_children(prefab, parent, children)
+        })
+    }
+    fn declare_component_type_name(
+        &self,
+        prefab: &PrefabUuid,
+        name: &str,
+        component_type: &ComponentTypeUuid,
+    ) {
+        self.with_storage(prefab, |storage| {
+            storage.declare_component_type_name(prefab, name, component_type)
+        })
+    }
+    fn set_base_prefab(
+        &self,
+        prefab: &PrefabUuid,
+        base_prefab: &PrefabUuid,
+    ) {
+        self.with_storage(prefab, |storage| {
+            storage.set_base_prefab(prefab, base_prefab)
+        })
+    }
+    fn declare_blob(
+        &self,
+        prefab: &PrefabUuid,
+        blob: &BlobId,
+        data: BlobData,
+    ) {
+        self.with_storage(prefab, |storage| storage.declare_blob(prefab, blob, data))
+    }
+    fn deserialize_template_component<'de, D: Deserializer<'de>>(
+        &self,
+        prefab: &PrefabUuid,
+        template: &TemplateUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        self.with_storage(prefab, |storage| {
+            storage.deserialize_template_component(prefab, template, component_type, deserializer)
+        })
+    }
+    fn instantiate_template(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+        template: &TemplateUuid,
+    ) {
+        self.with_storage(prefab, |storage| {
+            storage.instantiate_template(prefab, entity, template)
+        })
+    }
+    fn apply_template_diff<'de, D: Deserializer<'de>>(
+        &self,
+        prefab: &PrefabUuid,
+        entity: &EntityUuid,
+        component_type: &ComponentTypeUuid,
+        deserializer: D,
+    ) -> Result<(), D::Error> {
+        self.with_storage(prefab, |storage| {
+            storage.apply_template_diff(prefab, entity, component_type, deserializer)
+        })
+    }
+}
+
+/// Why a file passed to `load_prefab_files_parallel` couldn't be loaded
+#[cfg(feature = "parallel")]
+#[derive(Debug)]
+pub enum LoadFileError<E> {
+    Io(std::io::Error),
+    Format(E),
+}
+
+/// Reads prefab files on the rayon thread pool and deserializes each with `load`, i.e.
+/// `|bytes, storage| prefab_format::json::from_slice(bytes, storage)`. The storage is shared by
+/// all the threads, which is why it must be `Sync`. Storage that isn't can be wrapped in
+/// `SharedStorage`.
+///
+/// A file that fails to load doesn't stop the others. Returns the failures in the order of
+/// `paths`.
+#[cfg(feature = "parallel")]
+pub fn load_prefab_files_parallel<S, F, E>(
+    paths: &[std::path::PathBuf],
+    storage: &S,
+    load: F,
+) -> Vec<(std::path::PathBuf, LoadFileError<E>)>
+where
+    S: StorageDeserializer + Sync,
+    F: Fn(&[u8], &S) -> Result<(), E> + Sync,
+    E: Send,
+{
+    use rayon::prelude::*;
+    paths
+        .par_iter()
+        .filter_map(|path| {
+            let result = std::fs::read(path)
+                .map_err(LoadFileError::Io)
+                .and_then(|bytes| load(&bytes, storage).map_err(LoadFileError::Format));
+            result.err().map(|e| (path.clone(), e))
+        })
+        .collect()
+}
@@ -0,0 +1,75 @@
+use crate::PrefabUuid;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::BuildHasher;
+
+// Prefab refs only hold the UUID of the referenced prefab. These traits are how loaders ask the
+// application (an asset database, a directory index, a network server) for the source data of a
+// referenced prefab.
+
+/// Provides the source data of prefabs by UUID
+pub trait PrefabSource {
+    type Error;
+
+    fn fetch_prefab(
+        &mut self,
+        prefab: PrefabUuid,
+    ) -> Result<Vec<u8>, Self::Error>;
+}
+
+impl<F, E> PrefabSource for F
+where
+    F: FnMut(PrefabUuid) -> Result<Vec<u8>, E>,
+{
+    type Error = E;
+
+    fn fetch_prefab(
+        &mut self,
+        prefab: PrefabUuid,
+    ) -> Result<Vec<u8>, E> {
+        self(prefab)
+    }
+}
+
+/// Returned by the `PrefabSource` implementation for maps when a prefab isn't in the map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrefabNotFound(pub PrefabUuid);
+
+/// Prefab sources already in memory, i.e. for tests or tools that read a whole directory up front
+impl<S: BuildHasher> PrefabSource for HashMap<PrefabUuid, Vec<u8>, S> {
+    type Error = PrefabNotFound;
+
+    fn fetch_prefab(
+        &mut self,
+        prefab: PrefabUuid,
+    ) -> Result<Vec<u8>, PrefabNotFound> {
+        self.get(&prefab).cloned().ok_or(PrefabNotFound(prefab))
+    }
+}
+
+/// Like `PrefabSource`, but fetching may await, i.e. on a request to an asset server
+pub trait AsyncPrefabSource {
+    type Error;
+    type Fetch: Future<Output = Result<Vec<u8>, Self::Error>>;
+
+    fn fetch_prefab(
+        &mut self,
+        prefab: PrefabUuid,
+    ) -> Self::Fetch;
+}
+
+impl<F, Fut, E> AsyncPrefabSource for F
+where
+    F: FnMut(PrefabUuid) -> Fut,
+    Fut: Future<Output = Result<Vec<u8>, E>>,
+{
+    type Error = E;
+    type Fetch = Fut;
+
+    fn fetch_prefab(
+        &mut self,
+        prefab: PrefabUuid,
+    ) -> Fut {
+        self(prefab)
+    }
+}
@@ -0,0 +1,54 @@
+//! An offset that a prefab ref applies to the root entities of the prefab it instantiates, so the
+//! same prefab can be placed several times within a parent prefab:
+//!
+//! ```text
+//! PrefabRef(PrefabRef(
+//!     prefab_id: "...",
+//!     transform: (
+//!         position: (10.0, 0.0, 0.0),
+//!         rotation: (0.0, 0.0, 0.0, 1.0),
+//!         scale: (1.0, 1.0, 1.0),
+//!     ),
+//!     entity_overrides: [],
+//! )),
+//! ```
+//!
+//! Fields that are left out keep their identity value. This crate doesn't know about the engine's
+//! transform component, so the offset is only stored here and is composed with the instance's
+//! transforms by a hook when the prefab is cooked.
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrefabRefTransform {
+    pub position: [f32; 3],
+    /// A quaternion, as x, y, z, w
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl PrefabRefTransform {
+    pub const IDENTITY: PrefabRefTransform = PrefabRefTransform {
+        position: [0.0, 0.0, 0.0],
+        rotation: [0.0, 0.0, 0.0, 1.0],
+        scale: [1.0, 1.0, 1.0],
+    };
+
+    pub fn from_position(position: [f32; 3]) -> Self {
+        PrefabRefTransform {
+            position,
+            ..Self::IDENTITY
+        }
+    }
+
+    /// True if applying the offset doesn't move anything. Identity offsets aren't written.
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+}
+
+impl Default for PrefabRefTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
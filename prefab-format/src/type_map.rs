@@ -0,0 +1,102 @@
+//! Component types are identified by UUID, which makes prefab files hard to review. Text formats
+//! can instead name each component type, with a `type_map` table in the file header giving the
+//! UUID for each name. Either form is accepted when loading.
+use crate::ComponentTypeUuid;
+use serde::{
+    de::{self, DeserializeSeed},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::collections::BTreeMap;
+
+/// The names a prefab file uses for component types, with the UUID of each
+pub type TypeMap = BTreeMap<String, ComponentTypeUuid>;
+
+/// Looks up a component type written either as a UUID or as a name from the type map
+pub fn resolve_component_type(
+    type_map: &TypeMap,
+    key: &str,
+) -> Option<ComponentTypeUuid> {
+    match uuid::Uuid::parse_str(key) {
+        Ok(uuid) => Some(*uuid.as_bytes()),
+        Err(_) => type_map.get(key).cloned(),
+    }
+}
+
+/// Builds the type map to write for the given component types, skipping types without a name.
+/// If several types have the same name, none of them are written by name.
+pub fn build_type_map(
+    component_types: impl IntoIterator<Item = (ComponentTypeUuid, Option<String>)>
+) -> TypeMap {
+    let mut type_map = TypeMap::new();
+    let mut ambiguous = Vec::new();
+    for (component_type, name) in component_types {
+        let name = match name {
+            Some(name) => name,
+            None => continue,
+        };
+        match type_map.get(&name) {
+            Some(existing) if *existing != component_type => ambiguous.push(name),
+            _ => {
+                type_map.insert(name, component_type);
+            }
+        }
+    }
+    for name in ambiguous {
+        type_map.remove(&name);
+    }
+    type_map
+}
+
+/// The name for each component type in a type map
+pub(crate) fn type_names(type_map: &TypeMap) -> BTreeMap<ComponentTypeUuid, &str> {
+    type_map
+        .iter()
+        .map(|(name, component_type)| (*component_type, name.as_str()))
+        .collect()
+}
+
+/// Writes a component type by name if it has one and the format is human readable
+pub(crate) struct ComponentTypeKey<'a> {
+    pub component_type: ComponentTypeUuid,
+    pub type_names: &'a BTreeMap<ComponentTypeUuid, &'a str>,
+}
+impl<'a> Serialize for ComponentTypeKey<'a> {
+    fn serialize<S>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.type_names.get(&self.component_type) {
+            Some(name) if serializer.is_human_readable() => serializer.serialize_str(name),
+            _ => uuid::Uuid::from_bytes(self.component_type).serialize(serializer),
+        }
+    }
+}
+
+/// Reads a component type written either as a UUID or as a name from the type map
+pub(crate) struct ComponentTypeKeySeed<'a>(pub &'a TypeMap);
+impl<'de, 'a> DeserializeSeed<'de> for ComponentTypeKeySeed<'a> {
+    type Value = ComponentTypeUuid;
+
+    fn deserialize<D>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // Binary formats always store the UUID
+        if !deserializer.is_human_readable() {
+            return Ok(*uuid::Uuid::deserialize(deserializer)?.as_bytes());
+        }
+        let key = String::deserialize(deserializer)?;
+        resolve_component_type(self.0, &key).ok_or_else(|| {
+            de::Error::invalid_value(
+                de::Unexpected::Str(&key),
+                &"a component type UUID or a name from the prefab's type_map",
+            )
+        })
+    }
+}
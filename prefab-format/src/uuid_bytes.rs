@@ -0,0 +1,217 @@
+//! Serde helpers for `uuid::Bytes` values (PrefabUuid, EntityUuid, ComponentTypeUuid).
+//!
+//! Human-readable formats get a hyphenated UUID string, which is required for formats like JSON
+//! that only allow string map keys. Binary formats keep the plain 16-byte tuple so existing
+//! cooked data stays compatible. Either representation is accepted when deserializing.
+//!
+//! Use with `#[serde(with = "prefab_format::uuid_bytes")]`, `uuid_bytes::vec` for lists of UUIDs,
+//! or `uuid_bytes::map` for maps keyed by UUID.
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+pub fn serialize<S: Serializer>(
+    bytes: &uuid::Bytes,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    UuidBytes(*bytes).serialize(serializer)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<uuid::Bytes, D::Error> {
+    UuidBytes::deserialize(deserializer).map(|x| x.0)
+}
+
+/// A `uuid::Bytes` wrapper that implements the (de)serialization described in the module docs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct UuidBytes(pub uuid::Bytes);
+
+impl Serialize for UuidBytes {
+    fn serialize<S>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&uuid::Uuid::from_bytes(self.0).to_string())
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for UuidBytes {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct UuidBytesVisitor;
+        impl<'de> Visitor<'de> for UuidBytesVisitor {
+            type Value = UuidBytes;
+
+            fn expecting(
+                &self,
+                formatter: &mut std::fmt::Formatter,
+            ) -> std::fmt::Result {
+                formatter.write_str("a UUID string or 16 bytes")
+            }
+
+            fn visit_str<E>(
+                self,
+                value: &str,
+            ) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                uuid::Uuid::parse_str(value)
+                    .map(|uuid| UuidBytes(*uuid.as_bytes()))
+                    .map_err(E::custom)
+            }
+
+            fn visit_bytes<E>(
+                self,
+                value: &[u8],
+            ) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                uuid::Uuid::from_slice(value)
+                    .map(|uuid| UuidBytes(*uuid.as_bytes()))
+                    .map_err(E::custom)
+            }
+
+            fn visit_seq<A>(
+                self,
+                mut seq: A,
+            ) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut bytes = uuid::Bytes::default();
+                for (idx, byte) in bytes.iter_mut().enumerate() {
+                    *byte = seq
+                        .next_element()?
+                        .ok_or_else(|| de::Error::invalid_length(idx, &self))?;
+                }
+                Ok(UuidBytes(bytes))
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(UuidBytesVisitor)
+        } else {
+            deserializer.deserialize_tuple(16, UuidBytesVisitor)
+        }
+    }
+}
+
+/// (De)serializes a `Vec<uuid::Bytes>`, using `UuidBytes` for the elements
+pub mod vec {
+    use super::UuidBytes;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        uuids: &[uuid::Bytes],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(uuids.iter().map(|x| UuidBytes(*x)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D
+    ) -> Result<Vec<uuid::Bytes>, D::Error> {
+        let uuids = Vec::<UuidBytes>::deserialize(deserializer)?;
+        Ok(uuids.into_iter().map(|x| x.0).collect())
+    }
+}
+
+/// (De)serializes a `HashMap` keyed by `uuid::Bytes`, using `UuidBytes` for the keys. Entries are
+/// written in key order rather than hash order, so saving the same map always gives the same
+/// output.
+pub mod map {
+    use super::UuidBytes;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::HashMap;
+    use std::hash::BuildHasher;
+
+    pub fn serialize<V: Serialize, H: BuildHasher, S: Serializer>(
+        map: &HashMap<uuid::Bytes, V, H>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut entries: Vec<_> = map.iter().map(|(k, v)| (UuidBytes(*k), v)).collect();
+        entries.sort_by_key(|(k, _)| *k);
+        serializer.collect_map(entries)
+    }
+
+    pub fn deserialize<'de, V, H, D>(
+        deserializer: D
+    ) -> Result<HashMap<uuid::Bytes, V, H>, D::Error>
+    where
+        V: Deserialize<'de>,
+        H: BuildHasher + Default,
+        D: Deserializer<'de>,
+    {
+        let map = HashMap::<UuidBytes, V, H>::deserialize(deserializer)?;
+        Ok(map.into_iter().map(|(k, v)| (k.0, v)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Uuids {
+        #[serde(with = "crate::uuid_bytes")]
+        id: uuid::Bytes,
+        #[serde(with = "crate::uuid_bytes::vec")]
+        list: Vec<uuid::Bytes>,
+        #[serde(with = "crate::uuid_bytes::map")]
+        names: HashMap<uuid::Bytes, String>,
+    }
+
+    const A: uuid::Bytes = [0x01; 16];
+    const B: uuid::Bytes = [0x02; 16];
+
+    fn uuids() -> Uuids {
+        let mut names = HashMap::new();
+        names.insert(A, "a".to_string());
+        names.insert(B, "b".to_string());
+        Uuids {
+            id: A,
+            list: vec![A, B],
+            names,
+        }
+    }
+
+    #[test]
+    fn writes_strings_in_human_readable_formats() {
+        let ron = ron::ser::to_string(&uuids()).unwrap();
+        assert!(ron.contains("id:\"01010101-0101-0101-0101-010101010101\""));
+        assert!(ron.contains("\"02020202-0202-0202-0202-020202020202\":\"b\""));
+        assert_eq!(ron::de::from_str::<Uuids>(&ron).unwrap(), uuids());
+    }
+
+    // Files saved before UUIDs were written as strings have them as tuples of 16 bytes, including
+    // the keys of entity maps
+    #[test]
+    fn reads_uuids_written_as_bytes() {
+        let a = "(1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1)";
+        let b = "(2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2)";
+        let legacy = format!(
+            "(id:{a},list:[{a},{b}],names:{{{a}:\"a\",{b}:\"b\"}})",
+            a = a,
+            b = b
+        );
+        assert_eq!(ron::de::from_str::<Uuids>(&legacy).unwrap(), uuids());
+    }
+
+    #[test]
+    fn rejects_short_byte_sequences() {
+        let legacy = "(id:(1,1,1),list:[],names:{})";
+        assert!(ron::de::from_str::<Uuids>(legacy).is_err());
+    }
+}
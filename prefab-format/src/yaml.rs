@@ -0,0 +1,60 @@
+//! Helpers for reading and writing prefabs as YAML.
+//!
+//! Prefab objects are written as single-entry maps (`Entity: { id: ..., components: [...] }`).
+//! An empty list may also be left blank (`components:`), which YAML reads as null.
+use crate::{PrefabUuid, StorageDeserializer, StorageSerializer};
+use serde::{Serialize, Serializer};
+
+/// Reads a YAML prefab from a string, calling into `storage` as objects are encountered
+pub fn from_str<S: StorageDeserializer>(
+    string: &str,
+    storage: &S,
+) -> Result<(), serde_yaml::Error> {
+    // serde_yaml resolves anchors/aliases while building the value tree, so go through a Value
+    // rather than streaming
+    let value: serde_yaml::Value = serde_yaml::from_str(string)?;
+    crate::deserialize(value, storage)
+}
+
+/// Reads a YAML prefab from a byte slice, calling into `storage` as objects are encountered
+pub fn from_slice<S: StorageDeserializer>(
+    bytes: &[u8],
+    storage: &S,
+) -> Result<(), serde_yaml::Error> {
+    let value: serde_yaml::Value = serde_yaml::from_slice(bytes)?;
+    crate::deserialize(value, storage)
+}
+
+struct SerializablePrefab<'a, SS: StorageSerializer> {
+    storage: &'a SS,
+    prefab_id: PrefabUuid,
+}
+
+impl<'a, SS: StorageSerializer> Serialize for SerializablePrefab<'a, SS> {
+    fn serialize<S>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        crate::serialize(serializer, self.storage, self.prefab_id)
+    }
+}
+
+/// Serializes the prefab provided by `storage` to a YAML string
+pub fn to_string<SS: StorageSerializer>(
+    storage: &SS,
+    prefab_id: PrefabUuid,
+) -> Result<String, serde_yaml::Error> {
+    serde_yaml::to_string(&SerializablePrefab { storage, prefab_id })
+}
+
+/// Writes the prefab provided by `storage` as YAML
+pub fn to_writer<W: std::io::Write, SS: StorageSerializer>(
+    writer: W,
+    storage: &SS,
+    prefab_id: PrefabUuid,
+) -> Result<(), serde_yaml::Error> {
+    serde_yaml::to_writer(writer, &SerializablePrefab { storage, prefab_id })
+}